@@ -0,0 +1,85 @@
+//! aarch64 registers.
+//!
+//! aarch64 uses the generic syscall table (see generic.rs). Arguments are in
+//! x0-x5 and the number in x8; the return value replaces x0.
+
+use super::generic::to_x86_64;
+use super::SyscallRegs;
+use nix::sys::ptrace;
+use nix::unistd::Pid;
+
+/// AUDIT_ARCH reported by PTRACE_GET_SYSCALL_INFO and seen by seccomp filters
+/// for native tracees; 32-bit ARM tracees report another value and aren't translated.
+pub const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+/// Register set holding the syscall number the kernel will run (linux/elf.h).
+const NT_ARM_SYSTEM_CALL: libc::c_int = 0x404;
+
+/// The general-purpose registers of a stopped tracee.
+#[derive(Debug, Clone, Copy)]
+pub struct Registers(libc::user_regs_struct);
+
+impl Registers {
+    pub fn read(pid: Pid) -> nix::Result<Self> {
+        ptrace::getregs(pid).map(Registers)
+    }
+
+    /// Syscall number as the tracee's ABI numbers it.
+    pub fn syscall_number(&self) -> u64 {
+        self.0.regs[8]
+    }
+
+    /// For kernels without PTRACE_GET_SYSCALL_INFO. Nothing in the registers
+    /// marks an entry here, so a stop we weren't expecting is taken as one.
+    pub fn looks_like_entry(&self) -> bool {
+        true
+    }
+
+    /// The stop in canonical form, given the AUDIT_ARCH the kernel reported
+    /// for it if any. None for syscalls we don't trace.
+    ///
+    /// At an exit stop x0 holds the return value, not the first argument;
+    /// the caller keeps the arguments seen at entry.
+    pub fn syscall(&self, _pid: Pid, audit_arch: Option<u32>) -> Option<SyscallRegs> {
+        if audit_arch.is_some_and(|arch| arch != AUDIT_ARCH) {
+            return None;
+        }
+        let regs = &self.0.regs;
+        Some(SyscallRegs {
+            orig_rax: to_x86_64(regs[8])?,
+            rax: regs[0],
+            rdi: regs[0],
+            rsi: regs[1],
+            rdx: regs[2],
+            r10: regs[3],
+            r8: regs[4],
+            r9: regs[5],
+        })
+    }
+
+    /// At a syscall exit stop, replace the syscall's return value.
+    pub fn set_return(mut self, pid: Pid, value: i64) -> nix::Result<()> {
+        self.0.regs[0] = value as u64;
+        ptrace::setregs(pid, self.0)
+    }
+
+    /// At a syscall entry stop, make the kernel skip the syscall. Returns the
+    /// syscall number the exit stop will show.
+    pub fn skip_syscall(self, pid: Pid) -> nix::Result<u64> {
+        // The number to run is kept apart from x8; -1 makes the kernel skip it
+        let mut nr: libc::c_int = -1;
+        let mut iov = libc::iovec {
+            iov_base: (&mut nr as *mut libc::c_int).cast(),
+            iov_len: std::mem::size_of::<libc::c_int>(),
+        };
+        let ret = unsafe {
+            libc::ptrace(
+                libc::PTRACE_SETREGSET,
+                pid.as_raw(),
+                NT_ARM_SYSTEM_CALL,
+                &mut iov as *mut libc::iovec,
+            )
+        };
+        nix::errno::Errno::result(ret).map(|_| self.0.regs[8])
+    }
+}
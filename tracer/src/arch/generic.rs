@@ -0,0 +1,96 @@
+//! The generic syscall table (asm-generic/unistd.h), shared by aarch64 and
+//! riscv64. It has only the *at variants of path syscalls: no open, stat,
+//! rename or pipe.
+
+/// Native numbers of the given canonical (x86_64) syscall numbers.
+pub fn native_syscalls(canonical: &[u64]) -> Vec<u64> {
+    (0..512)
+        .filter(|&nr| to_x86_64(nr).is_some_and(|c| canonical.contains(&c)))
+        .collect()
+}
+
+/// The x86_64 number of a generic-table syscall we trace, if any.
+pub fn to_x86_64(nr: u64) -> Option<u64> {
+    Some(match nr {
+        5 => 188,        // setxattr
+        6 => 189,        // lsetxattr
+        7 => 190,        // fsetxattr
+        14 => 197,       // removexattr
+        15 => 198,       // lremovexattr
+        16 => 199,       // fremovexattr
+        19 => 290,       // eventfd2
+        23 => 32,        // dup
+        24 => 292,       // dup3
+        25 => 72,        // fcntl
+        34 => 258,       // mkdirat
+        35 => 263,       // unlinkat
+        36 => 266,       // symlinkat
+        37 => 265,       // linkat
+        38 => 264,       // renameat
+        45 => 76,        // truncate
+        46 => 77,        // ftruncate
+        47 => 285,       // fallocate
+        48 => 269,       // faccessat
+        49 => 80,        // chdir
+        50 => 81,        // fchdir
+        51 => 161,       // chroot
+        52 => 91,        // fchmod
+        53 => 268,       // fchmodat
+        54 => 260,       // fchownat
+        55 => 93,        // fchown
+        56 => 257,       // openat
+        57 => 3,         // close
+        59 => 293,       // pipe2
+        61 => 217,       // getdents64
+        63 => 0,         // read
+        64 => 1,         // write
+        65 => 19,        // readv
+        66 => 20,        // writev
+        67 => 17,        // pread64
+        68 => 18,        // pwrite64
+        69 => 295,       // preadv
+        70 => 296,       // pwritev
+        71 => 40,        // sendfile
+        74 => 289,       // signalfd4
+        75 => 278,       // vmsplice
+        76 => 275,       // splice
+        77 => 276,       // tee
+        78 => 267,       // readlinkat
+        79 => 262,       // newfstatat
+        80 => 5,         // fstat
+        82 => 74,        // fsync
+        83 => 75,        // fdatasync
+        84 => 277,       // sync_file_range
+        85 => 283,       // timerfd_create
+        88 => 280,       // utimensat
+        97 => 272,       // unshare
+        198 => 41,       // socket
+        200 => 49,       // bind
+        201 => 50,       // listen
+        202 => 43,       // accept
+        203 => 42,       // connect
+        206 => 44,       // sendto
+        207 => 45,       // recvfrom
+        211 => 46,       // sendmsg
+        212 => 47,       // recvmsg
+        215 => 11,       // munmap
+        220 => 56,       // clone (flags is the first argument everywhere)
+        221 => 59,       // execve
+        222 => 9,        // mmap
+        226 => 10,       // mprotect
+        227 => 26,       // msync
+        242 => 288,      // accept4
+        243 => 299,      // recvmmsg
+        268 => 308,      // setns
+        269 => 307,      // sendmmsg
+        276 => 316,      // renameat2
+        279 => 319,      // memfd_create
+        281 => 322,      // execveat
+        285 => 326,      // copy_file_range
+        286 => 327,      // preadv2
+        287 => 328,      // pwritev2
+        291 => 332,      // statx
+        425..=452 => nr, // io_uring, clone3, close_range, openat2, ...: shared numbering
+        _ => return None,
+    })
+}
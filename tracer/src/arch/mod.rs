@@ -0,0 +1,45 @@
+//! Register access for the CPU the tracer is built for.
+//!
+//! The syscall handlers are written against one canonical form of a syscall
+//! stop: x86_64 syscall numbers, and arguments under the names of the x86_64
+//! registers that carry them. Each architecture reads a stop's registers and
+//! translates them into a `SyscallRegs`, the same way compat.rs translates
+//! i386 tracees on an x86_64 kernel.
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64;
+#[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
+mod generic;
+#[cfg(target_arch = "riscv64")]
+mod riscv64;
+#[cfg(target_arch = "x86_64")]
+mod x86_64;
+
+#[cfg(target_arch = "aarch64")]
+pub use aarch64::{Registers, AUDIT_ARCH};
+#[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
+pub use generic::native_syscalls;
+#[cfg(target_arch = "riscv64")]
+pub use riscv64::{Registers, AUDIT_ARCH};
+#[cfg(target_arch = "x86_64")]
+pub use x86_64::{native_syscalls, Registers, AUDIT_ARCH};
+
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+)))]
+compile_error!("roar-tracer supports x86_64, aarch64 and riscv64 Linux");
+
+/// A syscall stop in canonical (x86_64) form.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SyscallRegs {
+    pub orig_rax: u64, // syscall number
+    pub rax: u64,      // return value, at the exit stop
+    pub rdi: u64,      // arguments 1 to 6
+    pub rsi: u64,
+    pub rdx: u64,
+    pub r10: u64,
+    pub r8: u64,
+    pub r9: u64,
+}
@@ -0,0 +1,70 @@
+//! riscv64 registers.
+//!
+//! riscv64 uses the generic syscall table (see generic.rs). Arguments are in
+//! a0-a5 and the number in a7; the return value replaces a0.
+
+use super::generic::to_x86_64;
+use super::SyscallRegs;
+use nix::sys::ptrace;
+use nix::unistd::Pid;
+
+/// AUDIT_ARCH reported by PTRACE_GET_SYSCALL_INFO and seen by seccomp filters
+/// for native tracees.
+pub const AUDIT_ARCH: u32 = 0xc000_00f3;
+
+/// The general-purpose registers of a stopped tracee.
+#[derive(Debug, Clone, Copy)]
+pub struct Registers(libc::user_regs_struct);
+
+impl Registers {
+    pub fn read(pid: Pid) -> nix::Result<Self> {
+        ptrace::getregs(pid).map(Registers)
+    }
+
+    /// Syscall number as the tracee's ABI numbers it.
+    pub fn syscall_number(&self) -> u64 {
+        self.0.a7
+    }
+
+    /// For kernels without PTRACE_GET_SYSCALL_INFO. Nothing in the registers
+    /// marks an entry here, so a stop we weren't expecting is taken as one.
+    pub fn looks_like_entry(&self) -> bool {
+        true
+    }
+
+    /// The stop in canonical form, given the AUDIT_ARCH the kernel reported
+    /// for it if any. None for syscalls we don't trace.
+    ///
+    /// At an exit stop a0 holds the return value, not the first argument;
+    /// the caller keeps the arguments seen at entry.
+    pub fn syscall(&self, _pid: Pid, audit_arch: Option<u32>) -> Option<SyscallRegs> {
+        if audit_arch.is_some_and(|arch| arch != AUDIT_ARCH) {
+            return None;
+        }
+        let regs = &self.0;
+        Some(SyscallRegs {
+            orig_rax: to_x86_64(regs.a7)?,
+            rax: regs.a0,
+            rdi: regs.a0,
+            rsi: regs.a1,
+            rdx: regs.a2,
+            r10: regs.a3,
+            r8: regs.a4,
+            r9: regs.a5,
+        })
+    }
+
+    /// At a syscall exit stop, replace the syscall's return value.
+    pub fn set_return(mut self, pid: Pid, value: i64) -> nix::Result<()> {
+        self.0.a0 = value as u64;
+        ptrace::setregs(pid, self.0)
+    }
+
+    /// At a syscall entry stop, make the kernel skip the syscall. Returns the
+    /// syscall number the exit stop will show.
+    pub fn skip_syscall(mut self, pid: Pid) -> nix::Result<u64> {
+        // The kernel reads a7 again after the entry stop; -1 is no syscall
+        self.0.a7 = u64::MAX;
+        ptrace::setregs(pid, self.0).map(|()| u64::MAX)
+    }
+}
@@ -0,0 +1,89 @@
+//! x86_64 registers, including stops of i386 and x32 tracees.
+
+use super::SyscallRegs;
+use crate::compat;
+use nix::sys::ptrace;
+use nix::unistd::Pid;
+
+/// AUDIT_ARCH of native tracees, as seccomp filters see it.
+pub const AUDIT_ARCH: u32 = compat::AUDIT_ARCH_X86_64;
+
+/// Native numbers of the given canonical syscall numbers; on x86_64 they're the same.
+pub fn native_syscalls(canonical: &[u64]) -> Vec<u64> {
+    canonical.to_vec()
+}
+
+/// The general-purpose registers of a stopped tracee.
+#[derive(Debug, Clone, Copy)]
+pub struct Registers(libc::user_regs_struct);
+
+impl Registers {
+    pub fn read(pid: Pid) -> nix::Result<Self> {
+        ptrace::getregs(pid).map(Registers)
+    }
+
+    /// Syscall number as the tracee's ABI numbers it.
+    pub fn syscall_number(&self) -> u64 {
+        self.0.orig_rax
+    }
+
+    /// For kernels without PTRACE_GET_SYSCALL_INFO: the kernel sets rax to
+    /// -ENOSYS on syscall entry.
+    pub fn looks_like_entry(&self) -> bool {
+        self.0.rax as i64 == -(libc::ENOSYS as i64)
+    }
+
+    /// The stop in canonical form, given the AUDIT_ARCH the kernel reported
+    /// for it if any. None for syscalls we don't trace.
+    pub fn syscall(&self, pid: Pid, audit_arch: Option<u32>) -> Option<SyscallRegs> {
+        // 32-bit children use their own syscall numbers and argument registers
+        if self.is_i386(audit_arch) {
+            compat::i386_regs(pid, &self.0)
+        } else if self.0.orig_rax & compat::X32_SYSCALL_BIT != 0 {
+            compat::x32_regs(&self.0)
+        } else {
+            Some(SyscallRegs {
+                orig_rax: self.0.orig_rax,
+                rax: self.0.rax,
+                rdi: self.0.rdi,
+                rsi: self.0.rsi,
+                rdx: self.0.rdx,
+                r10: self.0.r10,
+                r8: self.0.r8,
+                r9: self.0.r9,
+            })
+        }
+    }
+
+    /// An i386 or x32 sendmsg or sendmmsg, which `syscall` leaves untranslated.
+    pub fn compat_send(&self, pid: Pid, audit_arch: Option<u32>) -> Option<compat::CompatSend> {
+        if self.is_i386(audit_arch) {
+            compat::i386_send(pid, &self.0)
+        } else if self.0.orig_rax & compat::X32_SYSCALL_BIT != 0 {
+            compat::x32_send(pid, &self.0)
+        } else {
+            None
+        }
+    }
+
+    fn is_i386(&self, audit_arch: Option<u32>) -> bool {
+        match audit_arch {
+            Some(arch) => arch == compat::AUDIT_ARCH_I386,
+            None => self.0.cs == compat::I386_USER_CS,
+        }
+    }
+
+    /// At a syscall exit stop, replace the syscall's return value.
+    pub fn set_return(mut self, pid: Pid, value: i64) -> nix::Result<()> {
+        self.0.rax = value as u64;
+        ptrace::setregs(pid, self.0)
+    }
+
+    /// At a syscall entry stop, make the kernel skip the syscall. Returns the
+    /// syscall number the exit stop will show.
+    pub fn skip_syscall(mut self, pid: Pid) -> nix::Result<u64> {
+        // An invalid number (-1) is skipped on both x86_64 and i386
+        self.0.orig_rax = u64::MAX;
+        ptrace::setregs(pid, self.0).map(|()| self.0.orig_rax)
+    }
+}
@@ -0,0 +1,75 @@
+//! Syscall categories (--trace), to record only some subsystems.
+//!
+//! Syscalls in no category below keep the fd table, cwd and the like up to
+//! date and are always decoded. The rest are only decoded, and with seccomp
+//! only stop the tracee, when one of their categories is traced. Reads and
+//! writes belong to both file and network, and count as whichever the fd
+//! turns out to be.
+
+use crate::{
+    SYS_ACCEPT, SYS_ACCEPT4, SYS_ACCESS, SYS_BIND, SYS_CHMOD, SYS_CHOWN, SYS_CONNECT,
+    SYS_COPY_FILE_RANGE, SYS_EXECVE, SYS_EXECVEAT, SYS_FACCESSAT, SYS_FACCESSAT2, SYS_FALLOCATE,
+    SYS_FCHMOD, SYS_FCHMODAT, SYS_FCHMODAT2, SYS_FCHOWN, SYS_FCHOWNAT, SYS_FDATASYNC,
+    SYS_FREMOVEXATTR, SYS_FSETXATTR, SYS_FSTAT, SYS_FSYNC, SYS_FTRUNCATE, SYS_FUTIMESAT,
+    SYS_GETDENTS, SYS_GETDENTS64, SYS_LCHOWN, SYS_LINK, SYS_LINKAT, SYS_LISTEN, SYS_LREMOVEXATTR,
+    SYS_LSETXATTR, SYS_LSTAT, SYS_MKDIR, SYS_MKDIRAT, SYS_MMAP, SYS_MPROTECT, SYS_MSYNC,
+    SYS_MUNMAP, SYS_NEWFSTATAT, SYS_OPEN, SYS_OPENAT, SYS_OPENAT2, SYS_PREAD64, SYS_PREADV,
+    SYS_PREADV2, SYS_PWRITE64, SYS_PWRITEV, SYS_PWRITEV2, SYS_READ, SYS_READLINK, SYS_READLINKAT,
+    SYS_READV, SYS_RECVFROM, SYS_REMOVEXATTR, SYS_RENAME, SYS_RENAMEAT, SYS_RENAMEAT2, SYS_RMDIR,
+    SYS_SENDFILE, SYS_SENDMMSG, SYS_SENDMSG, SYS_SENDTO, SYS_SETXATTR, SYS_SOCKET, SYS_SPLICE,
+    SYS_STAT, SYS_STATX, SYS_SYMLINK, SYS_SYMLINKAT, SYS_SYNC_FILE_RANGE, SYS_TEE, SYS_TRUNCATE,
+    SYS_UNLINK, SYS_UNLINKAT, SYS_UTIME, SYS_UTIMENSAT, SYS_UTIMES, SYS_VMSPLICE, SYS_WRITE,
+    SYS_WRITEV,
+};
+use clap::ValueEnum;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceCategory {
+    /// Opens, reads, writes, mappings, renames, links, deletions, directories
+    File,
+    /// Sockets: connections, DNS lookups and bytes sent and received
+    Network,
+    /// Execs, with their argv and binary
+    Process,
+    /// stat, access, readlink, and chmod/chown/utimes/xattr changes
+    Metadata,
+}
+
+impl TraceCategory {
+    pub const ALL: [TraceCategory; 4] = [
+        TraceCategory::File,
+        TraceCategory::Network,
+        TraceCategory::Process,
+        TraceCategory::Metadata,
+    ];
+
+    /// The categories syscall `nr` belongs to; empty for bookkeeping syscalls.
+    pub(crate) fn of(nr: u64) -> &'static [TraceCategory] {
+        use TraceCategory::*;
+        match nr {
+            SYS_READ | SYS_PREAD64 | SYS_READV | SYS_PREADV | SYS_PREADV2 | SYS_WRITE
+            | SYS_PWRITE64 | SYS_WRITEV | SYS_PWRITEV | SYS_PWRITEV2 | SYS_SENDFILE
+            | SYS_SPLICE => &[File, Network],
+            SYS_OPEN | SYS_OPENAT | SYS_OPENAT2 | SYS_MMAP | SYS_MPROTECT | SYS_MSYNC
+            | SYS_MUNMAP | SYS_TEE | SYS_VMSPLICE | SYS_COPY_FILE_RANGE | SYS_FSYNC
+            | SYS_FDATASYNC | SYS_SYNC_FILE_RANGE | SYS_TRUNCATE | SYS_FTRUNCATE
+            | SYS_FALLOCATE | SYS_GETDENTS | SYS_GETDENTS64 | SYS_RENAME | SYS_RENAMEAT
+            | SYS_RENAMEAT2 | SYS_MKDIR | SYS_MKDIRAT | SYS_RMDIR | SYS_LINK | SYS_LINKAT
+            | SYS_UNLINK | SYS_UNLINKAT | SYS_SYMLINK | SYS_SYMLINKAT => &[File],
+            // recvmsg is left out: it can pass file fds, which the fd table must learn of
+            SYS_SOCKET | SYS_CONNECT | SYS_BIND | SYS_LISTEN | SYS_ACCEPT | SYS_ACCEPT4
+            | SYS_SENDTO | SYS_RECVFROM | SYS_SENDMSG | SYS_SENDMMSG => &[Network],
+            // Exec events still come, so only the exec records are lost
+            SYS_EXECVE | SYS_EXECVEAT => &[Process],
+            SYS_STAT | SYS_FSTAT | SYS_LSTAT | SYS_NEWFSTATAT | SYS_STATX | SYS_ACCESS
+            | SYS_FACCESSAT | SYS_FACCESSAT2 | SYS_READLINK | SYS_READLINKAT | SYS_CHMOD
+            | SYS_FCHMOD | SYS_FCHMODAT | SYS_FCHMODAT2 | SYS_CHOWN | SYS_FCHOWN | SYS_LCHOWN
+            | SYS_FCHOWNAT | SYS_UTIME | SYS_UTIMES | SYS_FUTIMESAT | SYS_UTIMENSAT
+            | SYS_SETXATTR | SYS_LSETXATTR | SYS_FSETXATTR | SYS_REMOVEXATTR | SYS_LREMOVEXATTR
+            | SYS_FREMOVEXATTR => &[Metadata],
+            _ => &[],
+        }
+    }
+}
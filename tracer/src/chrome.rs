@@ -0,0 +1,93 @@
+//! Chrome trace-event export (--chrome-trace), for chrome://tracing and Perfetto.
+//!
+//! Each process is a track spanning its lifetime, named after its command.
+//! Execs, forks and the first access of each kind a process makes to each
+//! path are instant events on it; repeats are left out, since a build reads
+//! the same headers thousands of times and the viewer would drown in them.
+
+use crate::{EventRecord, TraceEvent, TraceReport};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Events collected from `Tracer::on_event`, written out once the trace ends.
+#[derive(Debug, Default)]
+pub struct ChromeTrace {
+    events: Vec<(f64, i32, Value)>, // (timestamp, pid, serialized TraceEvent)
+    seen: HashSet<(i32, String)>,   // (pid, serialized event) already kept
+}
+
+impl ChromeTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, record: &EventRecord) {
+        let Ok(event) = serde_json::to_value(record.event) else {
+            return;
+        };
+        let repeatable = matches!(
+            record.event,
+            TraceEvent::Exec { .. } | TraceEvent::Fork { .. } | TraceEvent::Exit { .. }
+        );
+        if !repeatable && !self.seen.insert((record.pid, event.to_string())) {
+            return;
+        }
+        self.events.push((record.timestamp, record.pid, event));
+    }
+
+    /// Write the trace-event JSON, with process lifetimes taken from `report`.
+    pub fn write(&self, report: &TraceReport, path: &str) -> io::Result<()> {
+        // Microseconds since the trace started
+        let ts = |t: f64| ((t - report.start_time) * 1e6).max(0.0);
+        let mut trace_events = Vec::new();
+
+        for process in &report.processes {
+            let pid = process.pid;
+            let command = process.command.join(" ");
+            let name = match process.command.first() {
+                Some(program) => format!("{} ({})", program, pid),
+                None => pid.to_string(),
+            };
+            trace_events.push(json!({
+                "name": "process_name", "ph": "M", "pid": pid, "tid": pid,
+                "args": { "name": name },
+            }));
+            let end = process.end_time.unwrap_or(report.end_time);
+            trace_events.push(json!({
+                "name": command, "cat": "process", "ph": "X", "pid": pid, "tid": pid,
+                "ts": ts(process.start_time), "dur": ts(end) - ts(process.start_time),
+                "args": {
+                    "parent_pid": process.parent_pid,
+                    "exit_code": process.exit_code,
+                    "term_signal": process.term_signal,
+                },
+            }));
+        }
+
+        for (timestamp, pid, event) in &self.events {
+            let kind = event["event"].as_str().unwrap_or_default();
+            let name = match (&event["path"], &event["command"], &event["child_pid"]) {
+                (Value::String(path), _, _) => format!("{} {}", kind, path),
+                (_, Value::Array(argv), _) => {
+                    let argv: Vec<&str> = argv.iter().filter_map(Value::as_str).collect();
+                    format!("{} {}", kind, argv.join(" "))
+                }
+                (_, _, Value::Number(child)) => format!("{} {}", kind, child),
+                _ => kind.to_string(),
+            };
+            trace_events.push(json!({
+                "name": name, "cat": kind, "ph": "i", "s": "t", "pid": pid, "tid": pid,
+                "ts": ts(*timestamp), "args": event,
+            }));
+        }
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(
+            &mut writer,
+            &json!({ "traceEvents": trace_events, "displayTimeUnit": "ms" }),
+        )?;
+        writer.flush()
+    }
+}
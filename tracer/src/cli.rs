@@ -0,0 +1,304 @@
+//! Command-line interface.
+//!
+//! The original interface was purely positional (`roar-tracer <output> <cmd>...`);
+//! that form is still accepted whenever the first argument is not a flag, as is
+//! `roar-tracer -p <pid> <output>` for attaching.
+
+use crate::config::Config;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use roar_tracer::{Backend, Compression, OutputFormat, TraceCategory};
+use std::ffi::OsString;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "roar-tracer",
+    version,
+    about = "ptrace-based syscall tracer for roar provenance tracking",
+    override_usage = "roar-tracer [OPTIONS] -o <FILE> [--] <COMMAND>...\n       \
+                      roar-tracer [OPTIONS] -o <FILE> --attach <PID>\n       \
+                      roar-tracer <FILE> <COMMAND>...\n       \
+                      roar-tracer -p <PID> <FILE>"
+)]
+pub struct Cli {
+    /// Write the trace summary to FILE (default: a file in the temp directory).
+    /// With -, it goes to stdout and the command's stdout is sent to stderr
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<String>,
+
+    /// Write the trace summary to file descriptor N, inherited from the
+    /// parent process; the traced command doesn't get it
+    #[arg(long, value_name = "N", conflicts_with = "output")]
+    pub output_fd: Option<i32>,
+
+    /// Output file format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Json)]
+    pub format: OutputFormat,
+
+    /// Read options from the TOML file FILE (default: roar.toml in the
+    /// current directory, if there is one); flags given here win
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<String>,
+
+    /// Stream each event as a JSON line to FILE while tracing
+    #[arg(long, value_name = "FILE")]
+    pub events: Option<String>,
+
+    /// Keep at most N paths in memory for the files, stat_files and
+    /// missing_files lists, for long traces; accesses to further paths are
+    /// only counted, under path_limit, and logged to --spill and --events
+    #[arg(long, value_name = "N")]
+    pub max_paths: Option<usize>,
+
+    /// Log the accesses left out by --max-paths to FILE, as JSON lines like
+    /// --events
+    #[arg(long, value_name = "FILE", requires = "max_paths")]
+    pub spill: Option<String>,
+
+    /// Compress the trace and the event log (default: by file extension,
+    /// .gz or .zst)
+    #[arg(long, value_enum, value_name = "ALGORITHM")]
+    pub compress: Option<Compression>,
+
+    /// Also write a timeline of processes and file accesses to FILE, in the
+    /// Chrome trace-event format (open it in Perfetto or chrome://tracing)
+    #[arg(long, value_name = "FILE")]
+    pub chrome_trace: Option<String>,
+
+    /// Also send the process tree as OpenTelemetry spans to the OTLP/HTTP
+    /// collector at URL, e.g. http://localhost:4318
+    #[arg(long, value_name = "URL")]
+    pub otlp_endpoint: Option<String>,
+
+    /// Also write SLSA provenance to FILE: an in-toto statement naming the
+    /// files written as subjects and the files read as dependencies, with
+    /// SHA-256 digests
+    #[arg(long, value_name = "FILE")]
+    pub provenance: Option<String>,
+
+    /// Trace an already-running process instead of launching a command
+    #[arg(short = 'p', long, value_name = "PID", conflicts_with = "command")]
+    pub attach: Option<i32>,
+
+    /// How to observe the command: ptrace records everything; fanotify only
+    /// records files opened, read and written, with far less overhead; ebpf
+    /// (if built with the ebpf feature) records syscalls without stopping it
+    #[arg(long, value_enum, default_value_t = Backend::Ptrace, conflicts_with = "attach")]
+    pub backend: Backend,
+
+    /// Which syscalls to record, as a comma-separated list of categories;
+    /// the others aren't decoded, and with --seccomp don't stop the command
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        value_name = "CATEGORIES",
+        default_values_t = TraceCategory::ALL
+    )]
+    pub trace: Vec<TraceCategory>,
+
+    /// Only report paths under DIR, or whose directory is a symlink into it;
+    /// checked before --include and --exclude
+    #[arg(long, value_name = "DIR")]
+    pub root: Option<String>,
+
+    /// Omit paths matching GLOB from the output (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Keep paths matching GLOB even if they are excluded (repeatable); with
+    /// --exclude '**', only paths matching an --include are reported
+    #[arg(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+
+    /// Exclude everything under /proc, /sys and /dev by name, /dev/shm shared
+    /// memory included (pseudo-filesystems are left out by default anyway)
+    #[arg(long)]
+    pub exclude_default: bool,
+
+    /// Also report paths on pseudo-filesystems (proc, sysfs, devtmpfs, cgroup,
+    /// ... as listed in /proc/self/mountinfo), which are left out by default
+    #[arg(long)]
+    pub no_default_ignores: bool,
+
+    /// Exclude shared libraries and data under /lib, /usr/lib and /usr/share
+    #[arg(long)]
+    pub exclude_libs: bool,
+
+    /// Report I/O on memfds and eventfd/timerfd/signalfd fds under pseudo-paths
+    /// such as memfd:[name] and anon:[eventfd]
+    #[arg(long)]
+    pub anon_fds: bool,
+
+    /// Record the SHA-256 of each file as it was when first opened for reading
+    #[arg(long)]
+    pub hash_reads: bool,
+
+    /// Record the SHA-256 of each written file once the trace ends
+    #[arg(long)]
+    pub hash_writes: bool,
+
+    /// Resolve symlinks in the directory part of recorded paths (default: lexical only)
+    #[arg(long)]
+    pub resolve_symlinks: bool,
+
+    /// Never mask the value of environment variables matching GLOB (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    pub env_allow: Vec<String>,
+
+    /// Replace the value of environment variables matching GLOB with a hash,
+    /// in addition to names containing TOKEN, SECRET, KEY, PASSWORD, PASSWD or
+    /// CREDENTIAL (repeatable)
+    #[arg(long, visible_alias = "redact-env", value_name = "GLOB")]
+    pub env_deny: Vec<String>,
+
+    /// After DURATION, send SIGTERM to every traced process, then SIGKILL 5s
+    /// later; the trace is still written, marked timed_out, and the exit code
+    /// is 124. DURATION is in seconds, or has a suffix s, m, h or d as in
+    /// timeout(1)
+    #[arg(long, value_name = "DURATION", conflicts_with = "attach", value_parser = parse_duration)]
+    pub timeout: Option<f64>,
+
+    /// Rewrite the output file with the trace so far every DURATION, marked
+    /// partial, so a crash or OOM kill still leaves a usable trace
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub flush_interval: Option<f64>,
+
+    /// Let traced processes keep running if the tracer itself dies
+    #[arg(long)]
+    pub no_exit_kill: bool,
+
+    /// Make network connections and sends fail with EPERM instead of only
+    /// recording them, to check that a command is hermetic
+    #[arg(long)]
+    pub deny_network: bool,
+
+    /// With --deny-network, still allow connections to loopback addresses
+    #[arg(long, requires = "deny_network")]
+    pub allow_localhost: bool,
+
+    /// With --deny-network, still allow unix sockets whose path matches GLOB,
+    /// or @NAME for the abstract namespace (repeatable)
+    #[arg(long, value_name = "GLOB", requires = "deny_network")]
+    pub allow_unix: Vec<String>,
+
+    /// Trace only the command itself and its threads, not the processes it
+    /// starts
+    #[arg(long, conflicts_with = "seccomp")]
+    pub no_follow_forks: bool,
+
+    /// Stop tracing a process once it execs PROGRAM, matched by file name
+    /// (e.g. cc1), and don't trace what it starts; the exec is still
+    /// recorded (repeatable)
+    #[arg(long, value_name = "PROGRAM", conflicts_with = "seccomp")]
+    pub no_follow: Vec<String>,
+
+    /// Stop the traced command only at the syscalls the tracer records, using a
+    /// seccomp filter (faster; setuid programs it runs can't gain privileges
+    /// unless the tracer runs as root)
+    #[arg(long, conflicts_with = "attach")]
+    pub seccomp: bool,
+
+    /// Include syscall counts and tracer overhead statistics in the output
+    #[arg(long)]
+    pub stats: bool,
+
+    /// Command to trace, optionally preceded by `--`
+    #[arg(
+        value_name = "COMMAND",
+        trailing_var_arg = true,
+        allow_hyphen_values = true,
+        required_unless_present = "attach"
+    )]
+    pub command: Vec<String>,
+}
+
+impl Cli {
+    /// Parse the process arguments, accepting the legacy positional form, and
+    /// fill in what they leave unset from the config file.
+    pub fn parse_args() -> Self {
+        let args: Vec<OsString> = std::env::args_os().collect();
+        let matches = Self::command().get_matches_from(legacy_to_flags(args));
+        let mut cli = Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        let applied = Config::load(cli.config.as_deref())
+            .and_then(|config| config.map_or(Ok(()), |c| c.apply(&mut cli, &matches)));
+        if let Err(e) = applied {
+            eprintln!("roar-tracer: bad config file {}", e);
+            std::process::exit(2);
+        }
+        cli
+    }
+}
+
+/// Seconds in "1.5", "90s", "10m", "2h" or "1d".
+fn parse_duration(value: &str) -> Result<f64, String> {
+    let (number, unit) = match value.char_indices().last() {
+        Some((i, 's')) => (&value[..i], 1.0),
+        Some((i, 'm')) => (&value[..i], 60.0),
+        Some((i, 'h')) => (&value[..i], 3600.0),
+        Some((i, 'd')) => (&value[..i], 86400.0),
+        _ => (value, 1.0),
+    };
+    match number.parse::<f64>().map(|n| n * unit) {
+        Ok(secs) if secs > 0.0 && secs.is_finite() => Ok(secs),
+        _ => Err(format!(
+            "expected a positive duration such as 30, 90s, 10m or 2h, got '{}'",
+            value
+        )),
+    }
+}
+
+/// Rewrite `roar-tracer <output> <cmd>...` as `roar-tracer -o <output> -- <cmd>...`,
+/// and `roar-tracer -p <pid> <output>` as `roar-tracer -p <pid> -o <output>`.
+fn legacy_to_flags(mut args: Vec<OsString>) -> Vec<OsString> {
+    let is_legacy_attach = args.len() == 4
+        && matches!(args[1].to_str(), Some("-p" | "--attach"))
+        && args[3]
+            .to_str()
+            .is_some_and(|output| !output.starts_with('-'));
+    if is_legacy_attach {
+        args.insert(3, "-o".into());
+        return args;
+    }
+
+    let is_legacy = args.len() >= 3
+        && args[1]
+            .to_str()
+            .is_some_and(|first| !first.starts_with('-'));
+    if !is_legacy {
+        return args;
+    }
+
+    let mut rewritten = Vec::with_capacity(args.len() + 2);
+    let mut rest = args.into_iter();
+    rewritten.extend(rest.next()); // program name
+    rewritten.push("-o".into());
+    rewritten.extend(rest.next()); // output file
+    rewritten.push("--".into());
+    rewritten.extend(rest);
+    rewritten
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        for (value, secs) in [
+            ("30", 30.0),
+            ("1.5", 1.5),
+            ("90s", 90.0),
+            ("10m", 600.0),
+            ("2h", 7200.0),
+            ("1d", 86400.0),
+            ("0.5m", 30.0),
+        ] {
+            assert_eq!(parse_duration(value), Ok(secs), "{}", value);
+        }
+        for value in [
+            "", "s", "0", "0s", "-5", "-1m", "NaN", "nan", "inf", "infm", "1e309", "10x", "m10",
+        ] {
+            assert!(parse_duration(value).is_err(), "{}", value);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! i386 and x32 syscalls made by processes running under the x86_64 kernel.
+//!
+//! Compat stops are translated into the equivalent x86_64 stop (syscall number
+//! and argument registers) so the regular handlers can process them unchanged.
+//! socketcall, which passes its arguments in memory, is unpacked into the
+//! socket call it stands for. Calls whose structures are laid out differently
+//! in memory (old_mmap, sendmsg/recvmsg's msghdr) are deliberately left
+//! untranslated; sendmsg and sendmmsg still have their destinations read,
+//! so that --deny-network applies to them.
+
+use crate::arch::SyscallRegs;
+use nix::sys::ptrace;
+use nix::unistd::Pid;
+
+/// AUDIT_ARCH values reported by PTRACE_GET_SYSCALL_INFO.
+pub const AUDIT_ARCH_X86_64: u32 = 0xc000_003e;
+pub const AUDIT_ARCH_I386: u32 = 0x4000_0003;
+
+/// Code segment selector of 32-bit user mode, for kernels without syscall info.
+pub const I386_USER_CS: u64 = 0x23;
+
+/// Syscall numbers with this bit set use the x32 ABI (x86_64 numbers, 32-bit pointers).
+pub const X32_SYSCALL_BIT: u64 = 0x4000_0000;
+
+/// The x86_64 number of an i386 syscall we trace, if any.
+pub fn i386_to_x86_64(nr: u64) -> Option<u64> {
+    Some(match nr {
+        3 => 0,          // read
+        4 => 1,          // write
+        5 => 2,          // open
+        6 => 3,          // close
+        9 => 86,         // link
+        10 => 87,        // unlink
+        11 => 59,        // execve
+        12 => 80,        // chdir
+        15 => 90,        // chmod
+        16 | 198 => 94,  // lchown, lchown32
+        30 => 132,       // utime
+        33 => 21,        // access
+        38 => 82,        // rename
+        39 => 83,        // mkdir
+        40 => 84,        // rmdir
+        41 => 32,        // dup
+        42 => 22,        // pipe
+        55 | 221 => 72,  // fcntl, fcntl64
+        61 => 161,       // chroot
+        63 => 33,        // dup2
+        83 => 88,        // symlink
+        85 => 89,        // readlink
+        91 => 11,        // munmap
+        92 | 193 => 76,  // truncate, truncate64
+        93 | 194 => 77,  // ftruncate, ftruncate64
+        94 => 91,        // fchmod
+        95 | 207 => 93,  // fchown, fchown32
+        106 | 195 => 4,  // stat, stat64
+        107 | 196 => 6,  // lstat, lstat64
+        108 | 197 => 5,  // fstat, fstat64
+        118 => 74,       // fsync
+        120 => 56,       // clone (flags is the first argument on both)
+        125 => 10,       // mprotect
+        133 => 81,       // fchdir
+        141 => 78,       // getdents
+        144 => 26,       // msync
+        145 => 19,       // readv
+        146 => 20,       // writev
+        148 => 75,       // fdatasync
+        180 => 17,       // pread64
+        181 => 18,       // pwrite64
+        182 | 212 => 92, // chown, chown32
+        187 | 239 => 40, // sendfile, sendfile64
+        192 => 9,        // mmap2 (page offset, which we don't use)
+        220 => 217,      // getdents64
+        226 => 188,      // setxattr
+        227 => 189,      // lsetxattr
+        228 => 190,      // fsetxattr
+        235 => 197,      // removexattr
+        236 => 198,      // lremovexattr
+        237 => 199,      // fremovexattr
+        271 => 235,      // utimes
+        295 => 257,      // openat
+        296 => 258,      // mkdirat
+        298 => 260,      // fchownat
+        299 => 261,      // futimesat
+        300 => 262,      // fstatat64
+        301 => 263,      // unlinkat
+        302 => 264,      // renameat
+        303 => 265,      // linkat
+        304 => 266,      // symlinkat
+        305 => 267,      // readlinkat
+        306 => 268,      // fchmodat
+        307 => 269,      // faccessat
+        310 => 272,      // unshare
+        313 => 275,      // splice
+        314 => 277,      // sync_file_range
+        315 => 276,      // tee
+        316 => 278,      // vmsplice
+        320 => 280,      // utimensat
+        321 => 282,      // signalfd
+        322 => 283,      // timerfd_create
+        323 => 284,      // eventfd
+        324 => 285,      // fallocate
+        327 => 289,      // signalfd4
+        328 => 290,      // eventfd2
+        330 => 292,      // dup3
+        331 => 293,      // pipe2
+        333 => 295,      // preadv
+        334 => 296,      // pwritev
+        346 => 308,      // setns
+        353 => 316,      // renameat2
+        356 => 319,      // memfd_create
+        358 => 322,      // execveat
+        359 => 41,       // socket
+        361 => 49,       // bind
+        362 => 42,       // connect
+        363 => 50,       // listen
+        364 => 288,      // accept4
+        369 => 44,       // sendto
+        371 => 45,       // recvfrom
+        377 => 326,      // copy_file_range
+        378 => 327,      // preadv2
+        379 => 328,      // pwritev2
+        383 => 332,      // statx
+        425..=452 => nr, // io_uring, clone3, close_range, openat2, ...: shared numbering
+        _ => return None,
+    })
+}
+
+/// i386 socketcall(call, args): one syscall multiplexing the socket calls.
+const SOCKETCALL: u64 = 102;
+
+/// socketcall's sendmsg and sendmmsg calls.
+const SOCKETCALL_SENDMSG: u64 = 16;
+const SOCKETCALL_SENDMMSG: u64 = 20;
+
+/// Size of the i386 and x32 struct mmsghdr: a msghdr of seven 32-bit fields,
+/// then msg_len.
+const MMSGHDR_SIZE: u64 = 32;
+
+/// The x86_64 number of a socketcall call we trace, and how many arguments
+/// it takes. send and recv are sendto and recvfrom without an address.
+fn socketcall_to_x86_64(call: u64) -> Option<(u64, usize)> {
+    Some(match call {
+        1 => (41, 3),   // socket
+        2 => (49, 3),   // bind
+        3 => (42, 3),   // connect
+        4 => (50, 2),   // listen
+        5 => (43, 3),   // accept
+        9 => (44, 4),   // send
+        10 => (45, 4),  // recv
+        11 => (44, 6),  // sendto
+        12 => (45, 6),  // recvfrom
+        18 => (288, 4), // accept4
+        _ => return None,
+    })
+}
+
+/// Rewrite the registers of an i386 stop as the x86_64 stop they correspond to.
+/// Returns None for syscalls we don't trace.
+pub fn i386_regs(pid: Pid, regs: &libc::user_regs_struct) -> Option<SyscallRegs> {
+    // Return values are 32-bit: sign-extend -errno, but not addresses above 2GB
+    let ret = regs.rax as u32;
+    let rax = if ret > (-4096i32) as u32 {
+        ret as i32 as i64 as u64
+    } else {
+        ret as u64
+    };
+    if regs.orig_rax == SOCKETCALL {
+        return socketcall_regs(pid, regs.rbx, regs.rcx, rax);
+    }
+    // i386 passes arguments in ebx, ecx, edx, esi, edi, ebp
+    Some(SyscallRegs {
+        orig_rax: i386_to_x86_64(regs.orig_rax)?,
+        rax,
+        rdi: regs.rbx,
+        rsi: regs.rcx,
+        rdx: regs.rdx,
+        r10: regs.rsi,
+        r8: regs.rdi,
+        r9: regs.rbp,
+    })
+}
+
+/// The first `count` of socketcall's arguments, an array of 32-bit values in
+/// tracee memory.
+fn socketcall_args(pid: Pid, args_ptr: u64, count: usize) -> Option<[u64; 6]> {
+    let mut args = [0u64; 6];
+    for (i, arg) in args.iter_mut().enumerate().take(count) {
+        let addr = (args_ptr + i as u64 * 4) as *mut libc::c_void;
+        *arg = ptrace::read(pid, addr).ok()? as u64 & 0xffff_ffff;
+    }
+    Some(args)
+}
+
+/// Unpack socketcall(call, args) into the socket call it stands for.
+fn socketcall_regs(pid: Pid, call: u64, args_ptr: u64, rax: u64) -> Option<SyscallRegs> {
+    let (nr, count) = socketcall_to_x86_64(call)?;
+    let args = socketcall_args(pid, args_ptr, count)?;
+    Some(SyscallRegs {
+        orig_rax: nr,
+        rax,
+        rdi: args[0],
+        rsi: args[1],
+        rdx: args[2],
+        r10: args[3],
+        r8: args[4],
+        r9: args[5],
+    })
+}
+
+/// Rewrite the registers of an x32 stop (x86_64 numbering plus the x32 bit).
+/// x32-only numbers (512 and up) use compat structures and are not traced.
+pub fn x32_regs(regs: &libc::user_regs_struct) -> Option<SyscallRegs> {
+    let nr = regs.orig_rax & !X32_SYSCALL_BIT;
+    if nr >= 512 {
+        return None;
+    }
+    Some(SyscallRegs {
+        orig_rax: nr,
+        rax: regs.rax,
+        rdi: regs.rdi,
+        rsi: regs.rsi,
+        rdx: regs.rdx,
+        r10: regs.r10,
+        r8: regs.r8,
+        r9: regs.r9,
+    })
+}
+
+/// An i386 or x32 sendmsg or sendmmsg: left untranslated, but where it sends
+/// to is still checked against --deny-network.
+#[derive(Debug)]
+pub struct CompatSend {
+    pub operation: &'static str,
+    pub fd: u64,
+    pub destinations: Vec<(u64, u64)>, // msg_name and msg_namelen of each message
+}
+
+/// The i386 sendmsg or sendmmsg entering at this stop, made directly or
+/// through socketcall.
+pub fn i386_send(pid: Pid, regs: &libc::user_regs_struct) -> Option<CompatSend> {
+    let (mmsg, args) = match regs.orig_rax {
+        370 => (false, [regs.rbx, regs.rcx, regs.rdx]), // sendmsg
+        345 => (true, [regs.rbx, regs.rcx, regs.rdx]),  // sendmmsg
+        SOCKETCALL if matches!(regs.rbx, SOCKETCALL_SENDMSG | SOCKETCALL_SENDMMSG) => {
+            let args = socketcall_args(pid, regs.rcx, 3).unwrap_or_default();
+            (regs.rbx == SOCKETCALL_SENDMMSG, [args[0], args[1], args[2]])
+        }
+        _ => return None,
+    };
+    Some(compat_send(pid, mmsg, args))
+}
+
+/// The x32 sendmsg or sendmmsg entering at this stop. Like the rest of the
+/// x32-only numbers (512 and up), they take compat structures.
+pub fn x32_send(pid: Pid, regs: &libc::user_regs_struct) -> Option<CompatSend> {
+    let mmsg = match regs.orig_rax & !X32_SYSCALL_BIT {
+        518 => false, // sendmsg
+        538 => true,  // sendmmsg
+        _ => return None,
+    };
+    Some(compat_send(pid, mmsg, [regs.rdi, regs.rsi, regs.rdx]))
+}
+
+/// sendmsg(fd, msg, flags) or sendmmsg(fd, msgvec, vlen, flags), with
+/// 32-bit msghdrs starting with msg_name and msg_namelen.
+fn compat_send(pid: Pid, mmsg: bool, [fd, msg, vlen]: [u64; 3]) -> CompatSend {
+    let msg_name = |msghdr: u64| {
+        ptrace::read(pid, msghdr as *mut libc::c_void)
+            .map(|word| {
+                let word = word as u64;
+                (word & 0xffff_ffff, word >> 32)
+            })
+            .unwrap_or((0, 0))
+    };
+    let (operation, destinations) = if mmsg {
+        let count = (vlen as u32).min(1024) as u64;
+        let names = (0..count).map(|i| msg_name(msg + i * MMSGHDR_SIZE));
+        ("sendmmsg", names.collect())
+    } else {
+        ("sendmsg", vec![msg_name(msg)])
+    };
+    CompatSend {
+        operation,
+        fd: fd & 0xffff_ffff,
+        destinations,
+    }
+}
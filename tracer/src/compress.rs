@@ -0,0 +1,93 @@
+//! Compressed output (--compress).
+//!
+//! Traces of big builds run to hundreds of megabytes of JSON, most of it
+//! repeated path prefixes, so both the report and the event log can be
+//! streamed through gzip or zstd as they are written.
+
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+use std::fmt;
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    /// Write uncompressed
+    #[default]
+    None,
+    /// gzip (.gz)
+    Gzip,
+    /// Zstandard (.zst), faster and smaller than gzip
+    Zstd,
+}
+
+impl Compression {
+    /// The compression a file name asks for by its extension.
+    pub fn from_path(path: &str) -> Self {
+        if path.ends_with(".gz") {
+            Compression::Gzip
+        } else if path.ends_with(".zst") {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    pub(crate) fn writer<W: Write>(self, inner: W) -> io::Result<Compressor<W>> {
+        Ok(match self {
+            Compression::None => Compressor::Plain(inner),
+            Compression::Gzip => {
+                Compressor::Gzip(GzEncoder::new(inner, flate2::Compression::default()))
+            }
+            Compression::Zstd => Compressor::Zstd(zstd::Encoder::new(inner, 0)?),
+        })
+    }
+}
+
+/// A writer compressing into `W`. Must be finished to write the trailer.
+pub(crate) enum Compressor<W: Write> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Compressor<W> {
+    /// End the compressed stream and hand back the underlying writer, flushed.
+    pub fn finish(self) -> io::Result<W> {
+        let mut inner = match self {
+            Compressor::Plain(inner) => inner,
+            Compressor::Gzip(encoder) => encoder.finish()?,
+            Compressor::Zstd(encoder) => encoder.finish()?,
+        };
+        inner.flush()?;
+        Ok(inner)
+    }
+}
+
+impl<W: Write> fmt::Debug for Compressor<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Compressor::Plain(_) => "Plain",
+            Compressor::Gzip(_) => "Gzip",
+            Compressor::Zstd(_) => "Zstd",
+        })
+    }
+}
+
+impl<W: Write> Write for Compressor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Compressor::Plain(inner) => inner.write(buf),
+            Compressor::Gzip(encoder) => encoder.write(buf),
+            Compressor::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    // Compressors emit everything written so far, so a reader can follow along
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Compressor::Plain(inner) => inner.flush(),
+            Compressor::Gzip(encoder) => encoder.flush(),
+            Compressor::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Tracing policy from a TOML file (--config, or roar.toml in the current
+//! directory), so a team can share one instead of long command lines.
+//!
+//! Keys are the long flag names with underscores. Flags given on the command
+//! line win over the file; lists (include, exclude, env_allow, env_deny,
+//! no_follow) from both are combined.
+//!
+//! ```toml
+//! format = "json-compact"
+//! compress = "zstd"
+//! backend = "ptrace"
+//! trace = ["file", "process"]
+//! root = "."
+//! exclude = ["/usr/**"]
+//! exclude_libs = true
+//! default_ignores = true   # false is --no-default-ignores
+//! env_deny = ["*_DSN"]
+//! ```
+
+use crate::cli::Cli;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, ValueEnum};
+use serde::Deserialize;
+use std::path::Path;
+
+/// Looked for in the current directory when --config isn't given.
+const DEFAULT_CONFIG: &str = "roar.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    #[serde(skip)]
+    path: String,
+    format: Option<String>,
+    compress: Option<String>,
+    backend: Option<String>,
+    trace: Option<Vec<String>>,
+    root: Option<String>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    exclude_default: bool,
+    exclude_libs: bool,
+    default_ignores: Option<bool>,
+    env_allow: Vec<String>,
+    #[serde(alias = "redact_env")]
+    env_deny: Vec<String>,
+    no_follow: Vec<String>,
+}
+
+impl Config {
+    /// Read `path`, or roar.toml if there is one; None when neither applies.
+    pub fn load(path: Option<&str>) -> Result<Option<Self>, String> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(DEFAULT_CONFIG).is_file() => DEFAULT_CONFIG,
+            None => return Ok(None),
+        };
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let config: Config = toml::from_str(&text).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(Some(Config {
+            path: path.to_string(),
+            ..config
+        }))
+    }
+
+    /// Fill in what the command line left unset.
+    pub fn apply(self, cli: &mut Cli, matches: &ArgMatches) -> Result<(), String> {
+        let invalid = |e: String| format!("{}: {}", self.path, e);
+        let from_command_line =
+            |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+        if let (Some(format), false) = (&self.format, from_command_line("format")) {
+            cli.format = value_enum("format", format).map_err(invalid)?;
+        }
+        if let (Some(backend), false) = (&self.backend, from_command_line("backend")) {
+            cli.backend = value_enum("backend", backend).map_err(invalid)?;
+        }
+        if let (Some(trace), false) = (&self.trace, from_command_line("trace")) {
+            cli.trace = trace
+                .iter()
+                .map(|category| value_enum("trace category", category))
+                .collect::<Result<_, _>>()
+                .map_err(invalid)?;
+        }
+        if let (Some(compress), None) = (&self.compress, cli.compress) {
+            cli.compress = Some(value_enum("compress", compress).map_err(invalid)?);
+        }
+        if cli.root.is_none() {
+            cli.root = self.root;
+        }
+        prepend(&mut cli.include, self.include);
+        prepend(&mut cli.exclude, self.exclude);
+        prepend(&mut cli.env_allow, self.env_allow);
+        prepend(&mut cli.env_deny, self.env_deny);
+        prepend(&mut cli.no_follow, self.no_follow);
+        cli.exclude_default |= self.exclude_default;
+        cli.exclude_libs |= self.exclude_libs;
+        cli.no_default_ignores |= self.default_ignores == Some(false);
+        Ok(())
+    }
+}
+
+fn value_enum<T: ValueEnum>(key: &str, value: &str) -> Result<T, String> {
+    T::from_str(value, false).map_err(|_| {
+        let valid: Vec<String> = T::value_variants()
+            .iter()
+            .filter_map(|v| v.to_possible_value())
+            .map(|v| v.get_name().to_string())
+            .collect();
+        format!(
+            "invalid {} '{}' (expected one of {})",
+            key,
+            value,
+            valid.join(", ")
+        )
+    })
+}
+
+/// Put the file's entries before the command line's.
+fn prepend(list: &mut Vec<String>, mut from_file: Vec<String>) {
+    from_file.append(list);
+    *list = from_file;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{CommandFactory, FromArgMatches};
+    use roar_tracer::OutputFormat;
+
+    /// Load `text` as a config file.
+    fn load(name: &str, text: &str) -> Result<Option<Config>, String> {
+        let path = std::env::temp_dir().join(format!(
+            "roar-tracer-config-{}-{}.toml",
+            name,
+            std::process::id()
+        ));
+        std::fs::write(&path, text).expect("write config");
+        let config = Config::load(Some(&path.to_string_lossy()));
+        let _ = std::fs::remove_file(path);
+        config
+    }
+
+    /// Parse `args` and fill in from `config`, as parse_args does.
+    fn apply(config: Config, args: &[&str]) -> Result<Cli, String> {
+        let args = ["roar-tracer", "-o", "out.json"].iter().chain(args);
+        let matches = Cli::command().get_matches_from(args.chain(&["--", "true"]));
+        let mut cli = Cli::from_arg_matches(&matches).expect("cli");
+        config.apply(&mut cli, &matches).map(|()| cli)
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let err = load("unknown", "exclude = []\nexlude_libs = true\n").expect_err("unknown key");
+        assert!(err.contains("unknown field `exlude_libs`"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_values_of_the_wrong_type() {
+        let err = load("type", "exclude = \"/usr/**\"\n").expect_err("string for a list");
+        assert!(err.contains("invalid type"), "{}", err);
+
+        let config = load("value", "format = \"yaml\"\n")
+            .expect("load")
+            .expect("config");
+        let err = apply(config, &[]).expect_err("unknown format");
+        assert!(err.contains("invalid format 'yaml'"), "{}", err);
+    }
+
+    #[test]
+    fn command_line_flags_win() {
+        let text = "format = \"msgpack\"\nroot = \"/src\"\nexclude = [\"/usr/**\"]\n";
+        let config = load("precedence", text).expect("load").expect("config");
+        let cli = apply(config, &["--format", "csv", "--exclude", "/tmp/**"]).expect("apply");
+        assert!(matches!(cli.format, OutputFormat::Csv));
+        assert_eq!(cli.root.as_deref(), Some("/src"));
+        assert_eq!(cli.exclude, ["/usr/**", "/tmp/**"]);
+
+        let config = load("defaults", text).expect("load").expect("config");
+        let cli = apply(config, &[]).expect("apply");
+        assert!(matches!(cli.format, OutputFormat::Msgpack));
+    }
+
+    #[test]
+    fn reports_a_missing_file() {
+        let err = Config::load(Some("/nonexistent/roar.toml")).expect_err("missing file");
+        assert!(err.starts_with("/nonexistent/roar.toml: "), "{}", err);
+    }
+}
@@ -0,0 +1,48 @@
+//! One row per file (--format csv or tsv), for spreadsheets and pandas.
+//!
+//! Columns are path, read, written, first_pid and bytes (read plus written).
+//! Rows are sorted by path. Fields holding the delimiter, a quote or a
+//! newline are quoted as RFC 4180 has it, in TSV too.
+
+use crate::TraceReport;
+use std::io::{self, Write};
+
+const HEADER: [&str; 5] = ["path", "read", "written", "first_pid", "bytes"];
+
+pub(crate) fn write<W: Write>(
+    report: &TraceReport,
+    writer: &mut W,
+    delimiter: char,
+) -> io::Result<()> {
+    write_row(writer, delimiter, HEADER.map(String::from))?;
+    let mut files: Vec<_> = report.files.iter().collect();
+    files.sort_by(|a, b| a.0.cmp(b.0));
+    for (path, stats) in files {
+        write_row(
+            writer,
+            delimiter,
+            [
+                path.clone(),
+                stats.read.to_string(),
+                stats.written.to_string(),
+                stats.first_pid.to_string(),
+                (stats.bytes_read + stats.bytes_written).to_string(),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+fn write_row<W: Write>(writer: &mut W, delimiter: char, fields: [String; 5]) -> io::Result<()> {
+    let fields: Vec<String> = fields
+        .into_iter()
+        .map(|field| {
+            if field.contains([delimiter, '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        })
+        .collect();
+    writeln!(writer, "{}", fields.join(&delimiter.to_string()))
+}
@@ -0,0 +1,28 @@
+//! Content digests of traced files (--hash-reads, --hash-writes, --provenance)
+//! and of redacted environment values.
+
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// Hex SHA-256 of a regular file's contents, or None if it can't be read.
+/// Other files (/dev/null, FIFOs) are skipped: reading them would block or never end.
+pub(crate) fn sha256_file(path: impl AsRef<Path>) -> Option<String> {
+    let mut file = File::open(path).ok()?;
+    if !file.metadata().ok()?.is_file() {
+        return None;
+    }
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).ok()?;
+    Some(hex(&hasher.finalize()))
+}
+
+/// Hex SHA-256 of a byte string.
+pub(crate) fn sha256(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
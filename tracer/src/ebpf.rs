@@ -0,0 +1,1263 @@
+//! eBPF probes for the syscall backend that doesn't stop the command (--backend ebpf).
+//!
+//! The command runs in a cgroup v2 directory of its own. Programs on the
+//! raw_syscalls tracepoints look the current cgroup up in a map holding that
+//! cgroup's id and, for the syscalls the trace decodes, copy the number, the
+//! arguments and the paths or structs they point at into a ring buffer, then
+//! the return value; one on sched_process_fork reports new tasks. The trace
+//! loop hands these to the same handlers the ptrace backend uses.
+//!
+//! Nothing is stopped, so memory is only what was copied at entry: argv and
+//! syscall outputs are read from the live process when their record is read,
+//! if it still has them. The programs are assembled here rather than built
+//! from C, so no compiler or libbpf is needed. Loading them takes CAP_BPF and
+//! CAP_PERFMON (or root), and the tracepoints are found through tracefs.
+//! Syscall numbers are x86_64's.
+
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use crate::arch::SyscallRegs;
+use crate::fanotify::mount_point;
+use crate::{
+    MAX_TRACEE_STRING, SYS_ACCESS, SYS_BIND, SYS_CHMOD, SYS_CHOWN, SYS_CLONE, SYS_CLONE3,
+    SYS_CONNECT, SYS_EXECVE, SYS_EXECVEAT, SYS_FACCESSAT, SYS_FACCESSAT2, SYS_FCHMODAT,
+    SYS_FCHMODAT2, SYS_FCHOWNAT, SYS_FUTIMESAT, SYS_LCHOWN, SYS_LINK, SYS_LINKAT, SYS_LREMOVEXATTR,
+    SYS_LSETXATTR, SYS_LSTAT, SYS_MEMFD_CREATE, SYS_MKDIR, SYS_MKDIRAT, SYS_NEWFSTATAT, SYS_OPEN,
+    SYS_OPENAT, SYS_OPENAT2, SYS_READLINK, SYS_READLINKAT, SYS_REMOVEXATTR, SYS_RENAME,
+    SYS_RENAMEAT, SYS_RENAMEAT2, SYS_RMDIR, SYS_SENDTO, SYS_SETXATTR, SYS_STAT, SYS_STATX,
+    SYS_SYMLINK, SYS_SYMLINKAT, SYS_TRUNCATE, SYS_UNLINK, SYS_UNLINKAT, SYS_UTIME, SYS_UTIMENSAT,
+    SYS_UTIMES,
+};
+
+// Lifecycle syscalls the backend needs whether or not they are decoded
+pub const SYS_FORK: u64 = 57;
+pub const SYS_VFORK: u64 = 58;
+pub const SYS_EXIT: u64 = 60;
+pub const SYS_EXIT_GROUP: u64 = 231;
+pub const LIFECYCLE: [u64; 8] = [
+    SYS_CLONE,
+    SYS_CLONE3,
+    SYS_FORK,
+    SYS_VFORK,
+    SYS_EXECVE,
+    SYS_EXECVEAT,
+    SYS_EXIT,
+    SYS_EXIT_GROUP,
+];
+
+// bpf(2) commands, map and program types and helper ids (linux/bpf.h)
+const BPF_MAP_CREATE: libc::c_long = 0;
+const BPF_MAP_LOOKUP_ELEM: libc::c_long = 1;
+const BPF_MAP_UPDATE_ELEM: libc::c_long = 2;
+const BPF_PROG_LOAD: libc::c_long = 5;
+const MAP_TYPE_HASH: u32 = 1;
+const MAP_TYPE_ARRAY: u32 = 2;
+const MAP_TYPE_PERCPU_ARRAY: u32 = 6;
+const MAP_TYPE_RINGBUF: u32 = 27;
+const PROG_TYPE_TRACEPOINT: u32 = 5;
+const HELPER_MAP_LOOKUP_ELEM: i32 = 1;
+const HELPER_GET_CURRENT_PID_TGID: i32 = 14;
+const HELPER_GET_CURRENT_CGROUP_ID: i32 = 80;
+const HELPER_PROBE_READ_USER: i32 = 112;
+const HELPER_PROBE_READ_USER_STR: i32 = 114;
+const HELPER_RINGBUF_OUTPUT: i32 = 130;
+const HELPER_RINGBUF_RESERVE: i32 = 131;
+const HELPER_RINGBUF_SUBMIT: i32 = 132;
+
+// perf_event_open(2) for tracepoints (linux/perf_event.h)
+const PERF_TYPE_TRACEPOINT: u32 = 2;
+const PERF_ATTR_SIZE: usize = 128;
+const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 8;
+const PERF_EVENT_IOC_ENABLE: libc::c_ulong = 0x2400;
+const PERF_EVENT_IOC_SET_BPF: libc::c_ulong = 0x4004_2408;
+
+// Instruction opcodes: class | size or operation | source
+const LD_IMM64: u8 = 0x18;
+const LDX_W: u8 = 0x61;
+const LDX_DW: u8 = 0x79;
+const ST_W: u8 = 0x62;
+const STX_W: u8 = 0x63;
+const STX_DW: u8 = 0x7b;
+const ATOMIC_DW: u8 = 0xdb; // imm 0: fetch-less add
+const ADD_K: u8 = 0x07;
+const ADD_X: u8 = 0x0f;
+const AND_K: u8 = 0x57;
+const RSH_K: u8 = 0x77;
+const MOV_K: u8 = 0xb7;
+const MOV_X: u8 = 0xbf;
+const JA: u8 = 0x05;
+const JEQ_K: u8 = 0x15;
+const JNE_K: u8 = 0x55;
+const CALL: u8 = 0x85;
+const EXIT: u8 = 0x95;
+const PSEUDO_MAP_FD: u8 = 1;
+
+const R0: u8 = 0;
+const R1: u8 = 1;
+const R2: u8 = 2;
+const R3: u8 = 3;
+const R4: u8 = 4;
+const R6: u8 = 6; // the tracepoint context, or the length of the record
+const R7: u8 = 7; // the syscall number
+const R8: u8 = 8; // what to copy, from the syscalls map
+const R9: u8 = 9; // the record
+const R10: u8 = 10; // frame pointer
+
+// Records in the ring buffer. An entry: kind, status of the struct copy,
+// pid_tgid, nr, args[6], status of each string copy, the struct, then the
+// strings back to back. A string copy takes up its status (its length with
+// the NUL, or -errno) masked with its slot size - 1, which the programs can
+// add without branching; a copy that fills all of that may be cut short.
+const KIND_ENTER: u32 = 0;
+const KIND_EXIT: u32 = 1;
+const KIND_FORK: u32 = 2;
+const KIND_ARGV: u32 = 3;
+const ARGS: usize = 24;
+const STATUS: [usize; 3] = [72, 76, 4];
+const PEEK: usize = 80;
+const PEEK_LEN: usize = 128; // a sockaddr_un; open_how and clone_args flags fit too
+const PATHS: usize = PEEK + PEEK_LEN;
+const PATH_LEN: usize = MAX_TRACEE_STRING; // slot sizes are powers of two
+const ENTER_MAX: usize = PATHS + 2 * PATH_LEN;
+const EXIT_SIZE: usize = 32; // kind, pid_tgid, nr, ret
+const FORK_SIZE: usize = 16; // kind, parent tid, child tid
+
+// Ahead of an execve entry: kind, pid_tgid, count, each copy's status, the strings
+const ARGV_MAX: usize = 32;
+const ARG_LEN: usize = 512;
+const ARGV_COUNT: usize = 16;
+const ARGV_STATUS: usize = 24;
+const ARGV_STRINGS: usize = ARGV_STATUS + 4 * ARGV_MAX;
+const ARGV_MAX_SIZE: usize = ARGV_STRINGS + ARGV_MAX * ARG_LEN;
+/// Records are put together in a per-CPU buffer, then copied out at their length.
+const SCRATCH_SIZE: usize = if ENTER_MAX > ARGV_MAX_SIZE {
+    ENTER_MAX
+} else {
+    ARGV_MAX_SIZE
+};
+
+/// Records the reader has not caught up with yet; more are counted as lost.
+const RING_SIZE: u32 = 8 << 20;
+const RING_BUSY: u32 = 1 << 31;
+const RING_DISCARD: u32 = 1 << 30;
+const RING_HEADER: usize = 8;
+
+/// Which arguments of a syscall point at memory its entry handler reads:
+/// up to two strings, then one struct, by argument index.
+fn captures(nr: u64) -> [Option<usize>; 3] {
+    match nr {
+        SYS_OPEN | SYS_MEMFD_CREATE | SYS_EXECVE | SYS_MKDIR | SYS_RMDIR | SYS_UNLINK
+        | SYS_READLINK | SYS_TRUNCATE | SYS_STAT | SYS_LSTAT | SYS_ACCESS | SYS_CHMOD
+        | SYS_CHOWN | SYS_LCHOWN | SYS_UTIME | SYS_UTIMES | SYS_SETXATTR | SYS_LSETXATTR
+        | SYS_REMOVEXATTR | SYS_LREMOVEXATTR => [Some(0), None, None],
+        SYS_OPENAT | SYS_EXECVEAT | SYS_UNLINKAT | SYS_MKDIRAT | SYS_READLINKAT | SYS_RENAME
+        | SYS_FCHMODAT | SYS_FCHMODAT2 | SYS_FCHOWNAT | SYS_FUTIMESAT | SYS_UTIMENSAT
+        | SYS_NEWFSTATAT | SYS_STATX | SYS_FACCESSAT | SYS_FACCESSAT2 => [Some(1), None, None],
+        SYS_OPENAT2 => [Some(1), None, Some(2)],
+        SYS_RENAMEAT | SYS_RENAMEAT2 => [Some(3), None, None],
+        SYS_LINK | SYS_SYMLINK => [Some(0), Some(1), None],
+        SYS_LINKAT => [Some(1), Some(3), None],
+        SYS_SYMLINKAT => [Some(0), Some(2), None],
+        SYS_CONNECT | SYS_BIND => [None, None, Some(1)],
+        SYS_SENDTO => [None, None, Some(4)],
+        SYS_CLONE3 => [None, None, Some(0)],
+        _ => [None; 3],
+    }
+}
+
+/// The syscalls map value: one argument index per byte, 0xff for none.
+fn capture_spec(nr: u64) -> u32 {
+    captures(nr)
+        .iter()
+        .rev()
+        .fold(0, |spec, arg| spec << 8 | arg.map_or(0xff, |i| i as u32))
+}
+
+/// Tracee memory copied at the entry of a syscall, by address.
+#[derive(Debug, Default, Clone)]
+pub struct Captured {
+    strings: Vec<(u64, String)>,
+    bytes: Option<(u64, Vec<u8>)>,
+    argv: Option<(u64, Vec<String>)>,
+}
+
+impl Captured {
+    pub fn string(&self, addr: u64) -> Option<&str> {
+        self.strings
+            .iter()
+            .find(|(a, _)| *a == addr)
+            .map(|(_, s)| s.as_str())
+    }
+
+    pub fn bytes(&self, addr: u64, len: usize) -> Option<&[u8]> {
+        match &self.bytes {
+            Some((a, bytes)) if *a == addr => bytes.get(..len),
+            _ => None,
+        }
+    }
+
+    /// The strings of the NULL-terminated array at `addr` (an execve's argv).
+    pub fn string_array(&self, addr: u64) -> Option<&[String]> {
+        match &self.argv {
+            Some((a, strings)) if *a == addr => Some(strings),
+            _ => None,
+        }
+    }
+}
+
+/// A syscall as it entered.
+#[derive(Debug, Clone)]
+pub struct Syscall {
+    pub tid: i32,
+    pub nr: u64,
+    pub args: [u64; 6],
+    pub memory: Captured,
+}
+
+impl Syscall {
+    pub fn regs(&self) -> SyscallRegs {
+        let [rdi, rsi, rdx, r10, r8, r9] = self.args;
+        SyscallRegs {
+            orig_rax: self.nr,
+            rax: 0,
+            rdi,
+            rsi,
+            rdx,
+            r10,
+            r8,
+            r9,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Record {
+    Enter(Syscall),
+    Exit {
+        tid: i32,
+        nr: u64,
+        ret: i64,
+    },
+    Fork {
+        parent: i32,
+        child: i32,
+    },
+    /// The argv of the execve `tid` enters next; None if too long to copy whole.
+    Argv {
+        tid: i32,
+        argv: Option<Vec<String>>,
+    },
+}
+
+fn u32_at(bytes: &[u8], off: usize) -> Option<u32> {
+    Some(u32::from_ne_bytes(
+        bytes.get(off..off + 4)?.try_into().ok()?,
+    ))
+}
+
+fn u64_at(bytes: &[u8], off: usize) -> Option<u64> {
+    Some(u64::from_ne_bytes(
+        bytes.get(off..off + 8)?.try_into().ok()?,
+    ))
+}
+
+/// The string copied at `off` into a slot of `slot` bytes, and where the
+/// next one starts. None for a copy that faulted or may have been cut short.
+fn copied_string(bytes: &[u8], off: usize, status: i32, slot: usize) -> (Option<&[u8]>, usize) {
+    let next = off + (status as usize & (slot - 1));
+    if status <= 0 || status as usize >= slot - 1 {
+        return (None, next);
+    }
+    (bytes.get(off..off + status as usize - 1), next)
+}
+
+impl Record {
+    fn parse(bytes: &[u8]) -> Option<Record> {
+        // The low half of pid_tgid is the thread id
+        let tid = u64_at(bytes, 8)? as u32 as i32;
+        match u32_at(bytes, 0)? {
+            KIND_ENTER => {
+                let nr = u64_at(bytes, 16)?;
+                let mut args = [0; 6];
+                for (i, arg) in args.iter_mut().enumerate() {
+                    *arg = u64_at(bytes, ARGS + 8 * i)?;
+                }
+                let mut memory = Captured::default();
+                let [first, second, peek] = captures(nr);
+                // A struct copy reports 0 or -errno
+                if let Some(arg) = peek {
+                    if u32_at(bytes, STATUS[2])? == 0 {
+                        let copied = bytes.get(PEEK..PEEK + PEEK_LEN)?;
+                        memory.bytes = Some((args[arg], copied.to_vec()));
+                    }
+                }
+                let mut off = PATHS;
+                for (slot, arg) in [first, second].into_iter().enumerate() {
+                    let Some(arg) = arg else {
+                        continue;
+                    };
+                    let status = u32_at(bytes, STATUS[slot])? as i32;
+                    let (string, next) = copied_string(bytes, off, status, PATH_LEN);
+                    if let Some(Ok(string)) = string.map(|s| String::from_utf8(s.to_vec())) {
+                        memory.strings.push((args[arg], string));
+                    }
+                    off = next;
+                }
+                Some(Record::Enter(Syscall {
+                    tid,
+                    nr,
+                    args,
+                    memory,
+                }))
+            }
+            KIND_EXIT => Some(Record::Exit {
+                tid,
+                nr: u64_at(bytes, 16)?,
+                ret: u64_at(bytes, 24)? as i64,
+            }),
+            KIND_FORK => Some(Record::Fork {
+                parent: u32_at(bytes, 8)? as i32,
+                child: u32_at(bytes, 12)? as i32,
+            }),
+            KIND_ARGV => {
+                // One past ARGV_MAX when there were more
+                let count = u32_at(bytes, ARGV_COUNT)? as usize;
+                if count > ARGV_MAX {
+                    return Some(Record::Argv { tid, argv: None });
+                }
+                let mut argv = Vec::with_capacity(count);
+                let mut off = ARGV_STRINGS;
+                for i in 0..count {
+                    let status = u32_at(bytes, ARGV_STATUS + 4 * i)? as i32;
+                    let (arg, next) = copied_string(bytes, off, status, ARG_LEN);
+                    let Some(arg) = arg else {
+                        return Some(Record::Argv { tid, argv: None });
+                    };
+                    // Like read_string_array_from_tracee, which skips these
+                    if let Ok(arg) = String::from_utf8(arg.to_vec()) {
+                        argv.push(arg);
+                    }
+                    off = next;
+                }
+                Some(Record::Argv {
+                    tid,
+                    argv: Some(argv),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+// =============================================================================
+// Assembling programs
+// =============================================================================
+
+#[derive(Debug, Clone, Copy)]
+struct Label(usize);
+
+/// eBPF instructions, with forward jumps to labels patched in at the end.
+#[derive(Debug, Default)]
+struct Asm {
+    insns: Vec<(u8, u8, i16, i32)>, // code, src << 4 | dst, off, imm
+    labels: Vec<usize>,
+    jumps: Vec<(usize, Label)>,
+}
+
+impl Asm {
+    fn op(&mut self, code: u8, dst: u8, src: u8, off: i16, imm: i32) {
+        self.insns.push((code, src << 4 | dst, off, imm));
+    }
+
+    fn label(&mut self) -> Label {
+        self.labels.push(usize::MAX);
+        Label(self.labels.len() - 1)
+    }
+
+    fn bind(&mut self, label: Label) {
+        self.labels[label.0] = self.insns.len();
+    }
+
+    fn jump(&mut self, code: u8, dst: u8, imm: i32, to: Label) {
+        self.jumps.push((self.insns.len(), to));
+        self.op(code, dst, 0, 0, imm);
+    }
+
+    fn call(&mut self, helper: i32) {
+        self.op(CALL, 0, 0, 0, helper);
+    }
+
+    fn load_map(&mut self, dst: u8, map: &OwnedFd) {
+        self.op(LD_IMM64, dst, PSEUDO_MAP_FD, 0, map.as_raw_fd());
+        self.op(0, 0, 0, 0, 0);
+    }
+
+    /// r0 = the value under the key at `key` on the stack, or NULL.
+    fn lookup(&mut self, map: &OwnedFd, key: i16) {
+        self.load_map(R1, map);
+        self.op(MOV_X, R2, R10, 0, 0);
+        self.op(ADD_K, R2, 0, 0, key as i32);
+        self.call(HELPER_MAP_LOOKUP_ELEM);
+    }
+
+    /// Go to `out` unless the current task is in the traced cgroup.
+    fn in_cgroup(&mut self, maps: &Maps, out: Label) {
+        self.call(HELPER_GET_CURRENT_CGROUP_ID);
+        self.op(STX_DW, R10, R0, -16, 0);
+        self.lookup(&maps.cgroups, -16);
+        self.jump(JEQ_K, R0, 0, out);
+    }
+
+    /// Start a record of `kind` in r9: this CPU's scratch buffer, or go to `out`.
+    fn scratch(&mut self, maps: &Maps, kind: u32, out: Label) {
+        self.op(ST_W, R10, 0, -8, 0);
+        self.lookup(&maps.scratch, -8);
+        self.jump(JEQ_K, R0, 0, out);
+        self.op(MOV_X, R9, R0, 0, 0);
+        self.stamp(kind);
+    }
+
+    /// r9 = a `size` byte record of `kind` in the ring buffer, or go to `out`.
+    fn reserve(&mut self, maps: &Maps, kind: u32, size: usize, out: Label) {
+        let reserved = self.label();
+        self.load_map(R1, &maps.events);
+        self.op(MOV_K, R2, 0, 0, size as i32);
+        self.op(MOV_K, R3, 0, 0, 0);
+        self.call(HELPER_RINGBUF_RESERVE);
+        self.jump(JNE_K, R0, 0, reserved);
+        self.count_lost(maps);
+        self.jump(JA, 0, 0, out);
+        self.bind(reserved);
+        self.op(MOV_X, R9, R0, 0, 0);
+        self.stamp(kind);
+    }
+
+    fn stamp(&mut self, kind: u32) {
+        self.op(ST_W, R9, 0, 0, kind as i32);
+        self.call(HELPER_GET_CURRENT_PID_TGID);
+        self.op(STX_DW, R9, R0, 8, 0);
+    }
+
+    /// One more record the ring buffer had no room for.
+    fn count_lost(&mut self, maps: &Maps) {
+        let counted = self.label();
+        self.op(ST_W, R10, 0, -8, 0);
+        self.lookup(&maps.lost, -8);
+        self.jump(JEQ_K, R0, 0, counted);
+        self.op(MOV_K, R1, 0, 0, 1);
+        self.op(ATOMIC_DW, R0, R1, 0, 0);
+        self.bind(counted);
+    }
+
+    fn submit(&mut self) {
+        self.op(MOV_X, R1, R9, 0, 0);
+        self.op(MOV_K, R2, 0, 0, 0);
+        self.call(HELPER_RINGBUF_SUBMIT);
+    }
+
+    /// Copy the first r6 bytes of the scratch record to the ring buffer.
+    fn output(&mut self, maps: &Maps) {
+        let sent = self.label();
+        self.load_map(R1, &maps.events);
+        self.op(MOV_X, R2, R9, 0, 0);
+        self.op(MOV_X, R3, R6, 0, 0);
+        self.op(MOV_K, R4, 0, 0, 0);
+        self.call(HELPER_RINGBUF_OUTPUT);
+        self.jump(JEQ_K, R0, 0, sent);
+        self.count_lost(maps);
+        self.bind(sent);
+    }
+
+    /// Copy the string at r3 to offset r6 of the record in r9, its status to
+    /// `status`, and move r6 past it.
+    fn copy_string(&mut self, slot: usize, status: usize) {
+        self.op(MOV_X, R1, R9, 0, 0);
+        self.op(ADD_X, R1, R6, 0, 0);
+        self.op(MOV_K, R2, 0, 0, slot as i32 - 1);
+        self.call(HELPER_PROBE_READ_USER_STR);
+        self.op(STX_W, R9, R0, status as i16, 0);
+        self.op(AND_K, R0, 0, 0, slot as i32 - 1);
+        self.op(ADD_X, R6, R0, 0, 0);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        for &(at, to) in &self.jumps {
+            self.insns[at].2 = (self.labels[to.0] as isize - at as isize - 1) as i16;
+        }
+        let mut bytes = Vec::with_capacity(self.insns.len() * 8);
+        for (code, regs, off, imm) in self.insns {
+            bytes.extend_from_slice(&[code, regs]);
+            bytes.extend_from_slice(&off.to_le_bytes());
+            bytes.extend_from_slice(&imm.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+/// Offsets of the tracepoint fields the programs read.
+#[derive(Debug)]
+struct Fields {
+    id: i16,
+    args: i16,
+    ret: i16,
+    parent_pid: i16,
+    child_pid: i16,
+}
+
+/// raw_syscalls/sys_enter: an entry record for each decoded syscall.
+fn enter_program(maps: &Maps, fields: &Fields) -> Vec<u8> {
+    let mut a = Asm::default();
+    let out = a.label();
+    a.op(MOV_X, R6, R1, 0, 0);
+    a.in_cgroup(maps, out);
+    a.op(LDX_DW, R7, R6, fields.id, 0);
+    argv_record(&mut a, maps, fields);
+    a.op(STX_W, R10, R7, -4, 0);
+    a.lookup(&maps.syscalls, -4);
+    a.jump(JEQ_K, R0, 0, out);
+    a.op(LDX_W, R8, R0, 0, 0);
+    a.scratch(maps, KIND_ENTER, out);
+    a.op(STX_DW, R9, R7, 16, 0);
+    for i in 0..6 {
+        a.op(LDX_DW, R1, R6, fields.args + 8 * i, 0);
+        a.op(STX_DW, R9, R1, ARGS as i16 + 8 * i, 0);
+    }
+    // From here on r6 is the length of the record
+    a.op(MOV_K, R6, 0, 0, PATHS as i32);
+    for slot in [2, 0, 1] {
+        // r3 = the argument whose index is in byte `slot` of the spec
+        let (copy, done) = (a.label(), a.label());
+        a.op(MOV_X, R3, R8, 0, 0);
+        a.op(RSH_K, R3, 0, 0, 8 * slot as i32);
+        a.op(AND_K, R3, 0, 0, 0xff);
+        for i in 0..6 {
+            let next = a.label();
+            a.jump(JNE_K, R3, i, next);
+            a.op(LDX_DW, R3, R9, ARGS as i16 + 8 * i as i16, 0);
+            a.jump(JA, 0, 0, copy);
+            a.bind(next);
+        }
+        a.jump(JA, 0, 0, done);
+        a.bind(copy);
+        if slot == 2 {
+            a.op(MOV_X, R1, R9, 0, 0);
+            a.op(ADD_K, R1, 0, 0, PEEK as i32);
+            a.op(MOV_K, R2, 0, 0, PEEK_LEN as i32);
+            a.call(HELPER_PROBE_READ_USER);
+            a.op(STX_W, R9, R0, STATUS[slot] as i16, 0);
+        } else {
+            a.copy_string(PATH_LEN, STATUS[slot]);
+        }
+        a.bind(done);
+    }
+    a.output(maps);
+    a.bind(out);
+    a.op(MOV_K, R0, 0, 0, 0);
+    a.op(EXIT, 0, 0, 0, 0);
+    a.finish()
+}
+
+/// For an execve, an argv record ahead of the entry: the strings of up to
+/// ARGV_MAX arguments, and a count past ARGV_MAX if there are more.
+fn argv_record(a: &mut Asm, maps: &Maps, fields: &Fields) {
+    let (execve, copy, done, restore, after) =
+        (a.label(), a.label(), a.label(), a.label(), a.label());
+    a.jump(JEQ_K, R7, SYS_EXECVE as i32, execve);
+    a.jump(JNE_K, R7, SYS_EXECVEAT as i32, after);
+    a.op(LDX_DW, R8, R6, fields.args + 16, 0);
+    a.jump(JA, 0, 0, copy);
+    a.bind(execve);
+    a.op(LDX_DW, R8, R6, fields.args + 8, 0);
+    a.bind(copy);
+    // r6 is the record length meanwhile; the context waits on the stack
+    a.op(STX_DW, R10, R6, -32, 0);
+    a.scratch(maps, KIND_ARGV, restore);
+    a.op(ST_W, R9, 0, ARGV_COUNT as i16, 0);
+    a.op(MOV_K, R6, 0, 0, ARGV_STRINGS as i32);
+    for i in 0..=ARGV_MAX {
+        // r3 = argv[i], through the stack
+        a.op(MOV_X, R1, R10, 0, 0);
+        a.op(ADD_K, R1, 0, 0, -24);
+        a.op(MOV_K, R2, 0, 0, 8);
+        a.op(MOV_X, R3, R8, 0, 0);
+        a.op(ADD_K, R3, 0, 0, 8 * i as i32);
+        a.call(HELPER_PROBE_READ_USER);
+        a.jump(JNE_K, R0, 0, done);
+        a.op(LDX_DW, R3, R10, -24, 0);
+        a.jump(JEQ_K, R3, 0, done);
+        a.op(ST_W, R9, 0, ARGV_COUNT as i16, i as i32 + 1);
+        if i < ARGV_MAX {
+            a.copy_string(ARG_LEN, ARGV_STATUS + 4 * i);
+        }
+    }
+    a.bind(done);
+    a.output(maps);
+    a.bind(restore);
+    a.op(LDX_DW, R6, R10, -32, 0);
+    a.bind(after);
+}
+
+/// raw_syscalls/sys_exit: the return value of each decoded syscall.
+fn exit_program(maps: &Maps, fields: &Fields) -> Vec<u8> {
+    let mut a = Asm::default();
+    let out = a.label();
+    a.op(MOV_X, R6, R1, 0, 0);
+    a.in_cgroup(maps, out);
+    a.op(LDX_DW, R7, R6, fields.id, 0);
+    a.op(STX_W, R10, R7, -4, 0);
+    a.lookup(&maps.syscalls, -4);
+    a.jump(JEQ_K, R0, 0, out);
+    a.reserve(maps, KIND_EXIT, EXIT_SIZE, out);
+    a.op(STX_DW, R9, R7, 16, 0);
+    a.op(LDX_DW, R1, R6, fields.ret, 0);
+    a.op(STX_DW, R9, R1, 24, 0);
+    a.submit();
+    a.bind(out);
+    a.op(MOV_K, R0, 0, 0, 0);
+    a.op(EXIT, 0, 0, 0, 0);
+    a.finish()
+}
+
+/// sched/sched_process_fork: the parent and child of every new task.
+fn fork_program(maps: &Maps, fields: &Fields) -> Vec<u8> {
+    let mut a = Asm::default();
+    let out = a.label();
+    a.op(MOV_X, R6, R1, 0, 0);
+    a.in_cgroup(maps, out);
+    a.reserve(maps, KIND_FORK, FORK_SIZE, out);
+    a.op(LDX_W, R1, R6, fields.parent_pid, 0);
+    a.op(STX_W, R9, R1, 8, 0);
+    a.op(LDX_W, R1, R6, fields.child_pid, 0);
+    a.op(STX_W, R9, R1, 12, 0);
+    a.submit();
+    a.bind(out);
+    a.op(MOV_K, R0, 0, 0, 0);
+    a.op(EXIT, 0, 0, 0, 0);
+    a.finish()
+}
+
+// =============================================================================
+// Loading and attaching
+// =============================================================================
+
+/// A bpf(2) attribute union, zero past the fields set.
+struct Attr([u8; 128]);
+
+impl Attr {
+    fn new() -> Self {
+        Attr([0; 128])
+    }
+
+    fn u32(mut self, off: usize, value: u32) -> Self {
+        self.0[off..off + 4].copy_from_slice(&value.to_ne_bytes());
+        self
+    }
+
+    fn u64(mut self, off: usize, value: u64) -> Self {
+        self.0[off..off + 8].copy_from_slice(&value.to_ne_bytes());
+        self
+    }
+
+    fn name(mut self, off: usize, name: &str) -> Self {
+        let name = &name.as_bytes()[..name.len().min(15)];
+        self.0[off..off + name.len()].copy_from_slice(name);
+        self
+    }
+
+    fn call(&self, cmd: libc::c_long) -> io::Result<i32> {
+        let ret = unsafe { libc::syscall(libc::SYS_bpf, cmd, self.0.as_ptr(), self.0.len()) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(ret as i32)
+    }
+
+    fn fd(&self, cmd: libc::c_long) -> io::Result<OwnedFd> {
+        self.call(cmd).map(|fd| unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+}
+
+fn create_map(kind: u32, key: u32, value: u32, entries: u32, name: &str) -> io::Result<OwnedFd> {
+    Attr::new()
+        .u32(0, kind)
+        .u32(4, key)
+        .u32(8, value)
+        .u32(12, entries)
+        .name(28, name)
+        .fd(BPF_MAP_CREATE)
+}
+
+fn update_map(map: &OwnedFd, key: &[u8], value: &[u8]) -> io::Result<()> {
+    Attr::new()
+        .u32(0, map.as_raw_fd() as u32)
+        .u64(8, key.as_ptr() as u64)
+        .u64(16, value.as_ptr() as u64)
+        .call(BPF_MAP_UPDATE_ELEM)
+        .map(|_| ())
+}
+
+/// Load a tracepoint program; if the verifier refuses it, load it again for
+/// the log that says why.
+fn load_program(code: &[u8], name: &str) -> io::Result<OwnedFd> {
+    let license = c"GPL";
+    let attr = |log: &mut [u8]| {
+        Attr::new()
+            .u32(0, PROG_TYPE_TRACEPOINT)
+            .u32(4, (code.len() / 8) as u32)
+            .u64(8, code.as_ptr() as u64)
+            .u64(16, license.as_ptr() as u64)
+            .u32(24, !log.is_empty() as u32)
+            .u32(28, log.len() as u32)
+            .u64(32, log.as_mut_ptr() as u64)
+            .name(48, name)
+    };
+    match attr(&mut []).fd(BPF_PROG_LOAD) {
+        Err(e)
+            if e.raw_os_error() == Some(libc::EACCES) || e.raw_os_error() == Some(libc::EINVAL) =>
+        {
+            let mut log = vec![0u8; 1 << 20];
+            let result = attr(&mut log).fd(BPF_PROG_LOAD);
+            let log = String::from_utf8_lossy(&log);
+            let log = log.trim_end_matches('\0').trim_end();
+            let reason = log.lines().last().unwrap_or_default();
+            result.map_err(|_| {
+                io::Error::new(e.kind(), format!("{} program: {}: {}", name, e, reason))
+            })
+        }
+        result => result.map_err(|e| io::Error::new(e.kind(), format!("{} program: {}", name, e))),
+    }
+}
+
+/// The fstype field of a /proc/self/mountinfo line.
+fn fstype(line: &str) -> Option<&str> {
+    line.split(" - ").nth(1)?.split(' ').next()
+}
+
+fn find_mount(fstype_name: &str) -> io::Result<Option<PathBuf>> {
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")?;
+    Ok(mountinfo
+        .lines()
+        .find(|line| fstype(line) == Some(fstype_name))
+        .and_then(mount_point)
+        .map(PathBuf::from))
+}
+
+/// Where tracefs is. It isn't mounted here if nothing has: that changes the host.
+fn tracefs() -> io::Result<PathBuf> {
+    find_mount("tracefs")?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "tracefs is not mounted (mount -t tracefs nodev /sys/kernel/tracing)",
+        )
+    })
+}
+
+/// The id and format of a tracepoint, e.g. "raw_syscalls/sys_enter".
+fn tracepoint(tracefs: &Path, name: &str) -> io::Result<(u64, String)> {
+    let dir = tracefs.join("events").join(name);
+    let read = |file: &str| {
+        std::fs::read_to_string(dir.join(file))
+            .map_err(|e| io::Error::new(e.kind(), format!("tracepoint {}: {}", name, e)))
+    };
+    let id = read("id")?
+        .trim()
+        .parse()
+        .map_err(|_| io::Error::other(format!("tracepoint {}: bad id", name)))?;
+    Ok((id, read("format")?))
+}
+
+/// The offset of `field` in a tracepoint's record, from its format file.
+fn field_offset(format: &str, field: &str) -> io::Result<i16> {
+    format
+        .lines()
+        .find_map(|line| {
+            let mut parts = line.trim().split(';').map(str::trim);
+            let decl = parts.next()?.strip_prefix("field:")?;
+            let name = decl.rsplit(' ').next()?;
+            if name.split('[').next()? != field {
+                return None;
+            }
+            parts.find_map(|part| part.strip_prefix("offset:")?.parse().ok())
+        })
+        .ok_or_else(|| io::Error::other(format!("tracepoint field {} not found", field)))
+}
+
+/// Run `program` at each hit of a tracepoint, until the returned fd is closed.
+fn attach(program: &OwnedFd, tracepoint_id: u64) -> io::Result<OwnedFd> {
+    let mut attr = [0u8; PERF_ATTR_SIZE];
+    attr[0..4].copy_from_slice(&PERF_TYPE_TRACEPOINT.to_ne_bytes());
+    attr[4..8].copy_from_slice(&(PERF_ATTR_SIZE as u32).to_ne_bytes());
+    attr[8..16].copy_from_slice(&tracepoint_id.to_ne_bytes());
+    attr[16..24].copy_from_slice(&1u64.to_ne_bytes()); // sample_period
+
+    // The program runs for the tracepoint on every CPU, whichever the event is on
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_perf_event_open,
+            attr.as_ptr(),
+            -1,
+            0,
+            -1,
+            PERF_FLAG_FD_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let event = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+    for (request, arg) in [
+        (PERF_EVENT_IOC_SET_BPF, program.as_raw_fd()),
+        (PERF_EVENT_IOC_ENABLE, 0),
+    ] {
+        if unsafe { libc::ioctl(event.as_raw_fd(), request, arg) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(event)
+}
+
+struct Maps {
+    cgroups: OwnedFd,  // cgroup id -> 1
+    syscalls: OwnedFd, // syscall number -> capture_spec
+    events: OwnedFd,   // the ring buffer
+    scratch: OwnedFd,  // per CPU: the record being put together
+    lost: OwnedFd,     // [0]: records dropped for want of room
+}
+
+/// The ring buffer's consumer position page and its data, mapped twice in a
+/// row so a record that wraps around reads as one.
+struct RingBuffer {
+    consumer: *mut libc::c_void,
+    producer: *mut libc::c_void,
+    page: usize,
+    mask: u64,
+}
+
+impl RingBuffer {
+    fn map(fd: &OwnedFd) -> io::Result<Self> {
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let size = RING_SIZE as usize;
+        let map = |len, prot, offset| {
+            let ptr = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    len,
+                    prot,
+                    libc::MAP_SHARED,
+                    fd.as_raw_fd(),
+                    offset,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(ptr)
+        };
+        let consumer = map(page, libc::PROT_READ | libc::PROT_WRITE, 0)?;
+        let producer = match map(page + 2 * size, libc::PROT_READ, page as libc::off_t) {
+            Ok(ptr) => ptr,
+            Err(e) => {
+                unsafe { libc::munmap(consumer, page) };
+                return Err(e);
+            }
+        };
+        Ok(RingBuffer {
+            consumer,
+            producer,
+            page,
+            mask: size as u64 - 1,
+        })
+    }
+
+    /// Every record submitted since the last call.
+    fn records(&mut self) -> Vec<Record> {
+        // Safety: both positions are u64s at the start of their page, which
+        // stays mapped as long as self; the kernel only writes the producer's
+        let consumer = unsafe { &*(self.consumer as *const AtomicU64) };
+        let producer = unsafe { &*(self.producer as *const AtomicU64) };
+        let data = unsafe { (self.producer as *const u8).add(self.page) };
+        let mut records = Vec::new();
+        let mut pos = consumer.load(Ordering::Acquire);
+        while pos < producer.load(Ordering::Acquire) {
+            let header = unsafe { data.add((pos & self.mask) as usize) };
+            let len = unsafe { &*(header as *const AtomicU32) }.load(Ordering::Acquire);
+            if len & RING_BUSY != 0 {
+                break;
+            }
+            let size = (len & !(RING_BUSY | RING_DISCARD)) as usize;
+            if len & RING_DISCARD == 0 {
+                let bytes = unsafe { std::slice::from_raw_parts(header.add(RING_HEADER), size) };
+                records.extend(Record::parse(bytes));
+            }
+            pos += ((RING_HEADER + size + 7) & !7) as u64;
+            consumer.store(pos, Ordering::Release);
+        }
+        records
+    }
+}
+
+impl Drop for RingBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.consumer, self.page);
+            libc::munmap(self.producer, self.page + 2 * RING_SIZE as usize);
+        }
+    }
+}
+
+/// The loaded, attached programs and what they report into.
+pub struct Probe {
+    // Closing the perf events detaches the programs, so they go first
+    _events: Vec<OwnedFd>,
+    _programs: Vec<OwnedFd>,
+    ring: RingBuffer,
+    maps: Maps,
+    argvs: HashMap<i32, Vec<String>>, // by tid, until its execve entry is read
+}
+
+impl Probe {
+    /// Report the `syscalls` of every task in the cgroup with id `cgroup`.
+    pub fn new(cgroup: u64, syscalls: &[u64]) -> io::Result<Self> {
+        // The syscall numbers seen by the tracepoints are the native ones
+        if cfg!(not(target_arch = "x86_64")) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the eBPF backend only supports x86_64",
+            ));
+        }
+        let tracefs = tracefs()?;
+        let (enter_id, enter) = tracepoint(&tracefs, "raw_syscalls/sys_enter")?;
+        let (exit_id, exit) = tracepoint(&tracefs, "raw_syscalls/sys_exit")?;
+        let (fork_id, fork) = tracepoint(&tracefs, "sched/sched_process_fork")?;
+        let fields = Fields {
+            id: field_offset(&enter, "id")?,
+            args: field_offset(&enter, "args")?,
+            ret: field_offset(&exit, "ret")?,
+            parent_pid: field_offset(&fork, "parent_pid")?,
+            child_pid: field_offset(&fork, "child_pid")?,
+        };
+
+        let maps = Maps {
+            cgroups: create_map(MAP_TYPE_HASH, 8, 1, 1, "roar_cgroups")?,
+            syscalls: create_map(MAP_TYPE_HASH, 4, 4, 1024, "roar_syscalls")?,
+            events: create_map(MAP_TYPE_RINGBUF, 0, 0, RING_SIZE, "roar_events")?,
+            scratch: create_map(
+                MAP_TYPE_PERCPU_ARRAY,
+                4,
+                SCRATCH_SIZE as u32,
+                1,
+                "roar_scratch",
+            )?,
+            lost: create_map(MAP_TYPE_ARRAY, 4, 8, 1, "roar_lost")?,
+        };
+        update_map(&maps.cgroups, &cgroup.to_ne_bytes(), &[1])?;
+        for &nr in syscalls {
+            update_map(
+                &maps.syscalls,
+                &(nr as u32).to_ne_bytes(),
+                &capture_spec(nr).to_ne_bytes(),
+            )?;
+        }
+        let ring = RingBuffer::map(&maps.events)?;
+
+        let programs = vec![
+            load_program(&enter_program(&maps, &fields), "roar_sys_enter")?,
+            load_program(&exit_program(&maps, &fields), "roar_sys_exit")?,
+            load_program(&fork_program(&maps, &fields), "roar_fork")?,
+        ];
+        let events = [enter_id, exit_id, fork_id]
+            .into_iter()
+            .zip(&programs)
+            .map(|(id, program)| attach(program, id))
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(Probe {
+            _events: events,
+            _programs: programs,
+            ring,
+            maps,
+            argvs: HashMap::new(),
+        })
+    }
+
+    /// Block until there are records, `pidfd`'s process exits or `timeout_ms`
+    /// passes. Signals interrupt the wait so the caller can look at its flags.
+    pub fn wait(&self, pidfd: Option<&OwnedFd>, timeout_ms: i32) {
+        let mut fds: Vec<libc::pollfd> = [Some(&self.maps.events), pidfd]
+            .into_iter()
+            .flatten()
+            .map(|fd| libc::pollfd {
+                fd: fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+        unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout_ms) };
+    }
+
+    /// Every record submitted since the last call, in the order submitted,
+    /// with execve argvs moved into the memory of their entries.
+    pub fn records(&mut self) -> Vec<Record> {
+        let mut records = self.ring.records();
+        records.retain_mut(|record| match record {
+            Record::Argv { tid, argv } => {
+                match argv.take() {
+                    Some(argv) => self.argvs.insert(*tid, argv),
+                    None => self.argvs.remove(tid),
+                };
+                false
+            }
+            Record::Enter(call) => {
+                if let Some(argv) = self.argvs.remove(&call.tid) {
+                    let array = call.args[if call.nr == SYS_EXECVE { 1 } else { 2 }];
+                    call.memory.argv = Some((array, argv));
+                }
+                true
+            }
+            _ => true,
+        });
+        records
+    }
+
+    /// How many records were dropped because the ring buffer was full.
+    pub fn lost(&self) -> u64 {
+        let (key, mut value) = (0u32, 0u64);
+        let found = Attr::new()
+            .u32(0, self.maps.lost.as_raw_fd() as u32)
+            .u64(8, &key as *const u32 as u64)
+            .u64(16, &mut value as *mut u64 as u64)
+            .call(BPF_MAP_LOOKUP_ELEM);
+        found.map_or(0, |_| value)
+    }
+}
+
+/// A cgroup v2 directory for the traced command, under the tracer's own,
+/// removed when dropped (if it is empty by then).
+pub struct Cgroup {
+    path: PathBuf,
+    procs: CString,
+    id: u64,
+}
+
+impl Cgroup {
+    pub fn create() -> io::Result<Self> {
+        let root = find_mount("cgroup2")?
+            .ok_or_else(|| io::Error::other("no cgroup v2 hierarchy is mounted"))?;
+        let own = std::fs::read_to_string("/proc/self/cgroup")?;
+        let own = own
+            .lines()
+            .find_map(|line| line.strip_prefix("0::"))
+            .unwrap_or("/");
+        let path = root
+            .join(own.trim_start_matches('/'))
+            .join(format!("roar-{}", std::process::id()));
+        std::fs::create_dir(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        // Its id is the directory's inode number
+        let id = std::fs::metadata(&path)?.ino();
+        let procs = CString::new(path.join("cgroup.procs").as_os_str().as_encoded_bytes())
+            .map_err(io::Error::other)?;
+        Ok(Cgroup { path, procs, id })
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Move the calling process in. Only async-signal-safe calls, so the
+    /// command can do it between fork and exec.
+    pub fn enter(&self) -> io::Result<()> {
+        let fd = unsafe { libc::open(self.procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let written = unsafe { libc::write(fd, b"0".as_ptr().cast(), 1) };
+        let result = if written == 1 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        };
+        unsafe { libc::close(fd) };
+        result
+    }
+
+    /// Whether any process is still in the cgroup.
+    pub fn is_populated(&self) -> bool {
+        std::fs::read_to_string(self.path.join("cgroup.events"))
+            .map(|events| events.lines().any(|line| line == "populated 1"))
+            .unwrap_or(false)
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SYS_READ;
+
+    const SYS_ENTER_FORMAT: &str = "name: sys_enter
+ID: 24
+format:
+\tfield:unsigned short common_type;\toffset:0;\tsize:2;\tsigned:0;
+\tfield:int common_pid;\toffset:4;\tsize:4;\tsigned:1;
+
+\tfield:long id;\toffset:8;\tsize:8;\tsigned:1;
+\tfield:unsigned long args[6];\toffset:16;\tsize:48;\tsigned:0;
+";
+
+    #[test]
+    fn finds_tracepoint_fields() {
+        assert_eq!(field_offset(SYS_ENTER_FORMAT, "id").expect("id"), 8);
+        assert_eq!(field_offset(SYS_ENTER_FORMAT, "args").expect("args"), 16);
+        assert!(field_offset(SYS_ENTER_FORMAT, "common").is_err());
+    }
+
+    #[test]
+    fn encodes_what_to_copy() {
+        assert_eq!(capture_spec(SYS_READ), 0xffffff);
+        assert_eq!(capture_spec(SYS_OPENAT2), 0x02ff01);
+        assert_eq!(capture_spec(SYS_LINKAT), 0xff0301);
+    }
+
+    /// An entry record with the given copy statuses, its strings packed after
+    /// the struct the way the program packs them.
+    fn entry(nr: u64, args: [u64; 6], statuses: [i32; 3], strings: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = vec![0u8; PATHS];
+        bytes[0..4].copy_from_slice(&KIND_ENTER.to_ne_bytes());
+        bytes[8..16].copy_from_slice(&(100u64 << 32 | 101).to_ne_bytes());
+        bytes[16..24].copy_from_slice(&nr.to_ne_bytes());
+        for (i, arg) in args.iter().enumerate() {
+            bytes[ARGS + 8 * i..ARGS + 8 * i + 8].copy_from_slice(&arg.to_ne_bytes());
+        }
+        for (slot, status) in statuses.iter().enumerate() {
+            bytes[STATUS[slot]..STATUS[slot] + 4].copy_from_slice(&status.to_ne_bytes());
+        }
+        for (string, status) in strings.iter().zip(statuses) {
+            let mut copied = string.to_vec();
+            copied.resize(status as usize & (PATH_LEN - 1), 0);
+            bytes.extend_from_slice(&copied);
+        }
+        bytes
+    }
+
+    #[test]
+    fn parses_an_entry_with_its_memory() {
+        let bytes = entry(
+            SYS_LINKAT,
+            [3, 0x1000, 4, 0x2000, 0, 0],
+            [4, 4, 0],
+            &[b"old\0", b"new\0"],
+        );
+        let Some(Record::Enter(call)) = Record::parse(&bytes) else {
+            panic!("not an entry");
+        };
+        assert_eq!((call.tid, call.nr), (101, SYS_LINKAT));
+        assert_eq!(call.regs().r10, 0x2000);
+        assert_eq!(call.memory.string(0x1000), Some("old"));
+        assert_eq!(call.memory.string(0x2000), Some("new"));
+    }
+
+    #[test]
+    fn leaves_faulted_and_cut_short_strings_for_a_live_read() {
+        let bytes = entry(
+            SYS_LINKAT,
+            [3, 0x1000, 4, 0x2000, 0, 0],
+            [-14, 3, 0],
+            &[b"", b"ne\0"],
+        );
+        let Some(Record::Enter(call)) = Record::parse(&bytes) else {
+            panic!("not an entry");
+        };
+        assert_eq!(call.memory.string(0x1000), None);
+        assert_eq!(call.memory.string(0x2000), Some("ne"));
+
+        let long = vec![b'a'; PATH_LEN - 1];
+        let bytes = entry(
+            SYS_OPENAT,
+            [3, 0x1000, 0, 0, 0, 0],
+            [PATH_LEN as i32 - 1, 0, 0],
+            &[&long],
+        );
+        let Some(Record::Enter(call)) = Record::parse(&bytes) else {
+            panic!("not an entry");
+        };
+        assert_eq!(call.memory.string(0x1000), None);
+    }
+
+    #[test]
+    fn parses_copied_structs() {
+        let mut bytes = entry(SYS_CONNECT, [3, 0x3000, 16, 0, 0, 0], [0, 0, 0], &[]);
+        bytes[PEEK..PEEK + 2].copy_from_slice(&2u16.to_ne_bytes());
+        let Some(Record::Enter(call)) = Record::parse(&bytes) else {
+            panic!("not an entry");
+        };
+        assert_eq!(call.memory.bytes(0x3000, 2), Some(&2u16.to_ne_bytes()[..]));
+        assert_eq!(call.memory.bytes(0x3000, PEEK_LEN + 1), None);
+        assert_eq!(call.memory.bytes(0x3008, 2), None);
+    }
+
+    fn argv_record_of(count: u32, args: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = vec![0u8; ARGV_STRINGS];
+        bytes[0..4].copy_from_slice(&KIND_ARGV.to_ne_bytes());
+        bytes[8..16].copy_from_slice(&7u64.to_ne_bytes());
+        bytes[ARGV_COUNT..ARGV_COUNT + 4].copy_from_slice(&count.to_ne_bytes());
+        for (i, arg) in args.iter().enumerate() {
+            let status = (arg.len() as u32).to_ne_bytes();
+            bytes[ARGV_STATUS + 4 * i..ARGV_STATUS + 4 * i + 4].copy_from_slice(&status);
+            bytes.extend_from_slice(arg);
+        }
+        bytes
+    }
+
+    #[test]
+    fn parses_argv() {
+        let parsed = Record::parse(&argv_record_of(3, &[b"ls\0", b"\xff\0", b"-l\0"]));
+        let Some(Record::Argv { tid: 7, argv }) = parsed else {
+            panic!("not an argv");
+        };
+        assert_eq!(argv, Some(vec!["ls".to_string(), "-l".to_string()]));
+
+        let parsed = Record::parse(&argv_record_of(ARGV_MAX as u32 + 1, &[]));
+        assert!(matches!(parsed, Some(Record::Argv { argv: None, .. })));
+    }
+
+    #[test]
+    fn parses_exits_and_forks() {
+        let mut exit = vec![0u8; EXIT_SIZE];
+        exit[0..4].copy_from_slice(&KIND_EXIT.to_ne_bytes());
+        exit[8..16].copy_from_slice(&7u64.to_ne_bytes());
+        exit[16..24].copy_from_slice(&SYS_OPENAT.to_ne_bytes());
+        exit[24..32].copy_from_slice(&(-2i64).to_ne_bytes());
+        assert!(matches!(
+            Record::parse(&exit),
+            Some(Record::Exit {
+                tid: 7,
+                nr: SYS_OPENAT,
+                ret: -2
+            })
+        ));
+
+        let mut fork = vec![0u8; FORK_SIZE];
+        fork[0..4].copy_from_slice(&KIND_FORK.to_ne_bytes());
+        fork[8..12].copy_from_slice(&7u32.to_ne_bytes());
+        fork[12..16].copy_from_slice(&8u32.to_ne_bytes());
+        assert!(matches!(
+            Record::parse(&fork),
+            Some(Record::Fork {
+                parent: 7,
+                child: 8
+            })
+        ));
+        assert!(Record::parse(&fork[..12]).is_none());
+    }
+}
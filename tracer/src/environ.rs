@@ -0,0 +1,97 @@
+//! Environment redaction and per-process environment diffs.
+//!
+//! Values of sensitive variables are replaced before anything is serialized;
+//! the key stays so traces can still be compared on which variables were set,
+//! and the replacement carries a hash of the value so they can be compared on
+//! whether it changed. The hash is unsalted: a guessable value (a short
+//! password) can be recovered from it, a random token can't.
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::collections::HashMap;
+
+/// Variable names whose values are masked unless allowed with --env-allow.
+pub const DEFAULT_DENY: &[&str] = &[
+    "*TOKEN*",
+    "*SECRET*",
+    "*KEY*",
+    "*PASSWORD*",
+    "*PASSWD*",
+    "*CREDENTIAL*",
+];
+
+/// Replacement for a redacted value: the first 64 bits of its SHA-256.
+fn redacted_value(value: &str) -> String {
+    format!(
+        "<redacted sha256:{}>",
+        &crate::digest::sha256(value.as_bytes())[..16]
+    )
+}
+
+#[derive(Debug)]
+pub struct EnvRedactor {
+    allow: GlobSet,
+    deny: GlobSet,
+}
+
+impl Default for EnvRedactor {
+    fn default() -> Self {
+        EnvRedactor::new(&[], &[]).expect("default env patterns are valid")
+    }
+}
+
+impl EnvRedactor {
+    /// Build a redactor from user patterns; `deny` extends DEFAULT_DENY.
+    pub fn new(allow: &[String], deny: &[String]) -> Result<Self, globset::Error> {
+        let defaults = DEFAULT_DENY.iter().map(|p| p.to_string());
+        let deny: Vec<String> = defaults.chain(deny.iter().cloned()).collect();
+        Ok(EnvRedactor {
+            allow: build_set(allow)?,
+            deny: build_set(&deny)?,
+        })
+    }
+
+    /// Whether a variable's value must be masked. Allows override denies.
+    pub fn is_denied(&self, name: &str) -> bool {
+        !self.allow.is_match(name) && self.deny.is_match(name)
+    }
+
+    /// Mask denied values in place, returning the names that were masked.
+    pub fn redact(&self, env: &mut HashMap<String, String>) -> Vec<String> {
+        let mut redacted = Vec::new();
+        for (name, value) in env.iter_mut() {
+            if self.is_denied(name) {
+                *value = redacted_value(value);
+                redacted.push(name.clone());
+            }
+        }
+        redacted
+    }
+}
+
+/// Variables of `env` that are new or changed relative to `parent`, and the
+/// names of parent variables that `env` no longer has.
+pub fn diff(
+    env: &HashMap<String, String>,
+    parent: &HashMap<String, String>,
+) -> (HashMap<String, String>, Vec<String>) {
+    let changed = env
+        .iter()
+        .filter(|(name, value)| parent.get(*name) != Some(*value))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    let mut unset: Vec<String> = parent
+        .keys()
+        .filter(|name| !env.contains_key(*name))
+        .cloned()
+        .collect();
+    unset.sort();
+    (changed, unset)
+}
+
+fn build_set(patterns: &[String]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(GlobBuilder::new(pattern).case_insensitive(true).build()?);
+    }
+    builder.build()
+}
@@ -0,0 +1,59 @@
+//! Errors that stop a trace from starting or running.
+//!
+//! Problems the trace can carry on past (a tracee vanishing mid-call, one
+//! thread refusing to be seized) are not errors; they show up in the report
+//! or, with --stats, in its ptrace error count.
+
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum TraceError {
+    /// A pid to attach to was zero or negative.
+    InvalidPid(i32),
+    /// Forking the traced command failed.
+    Fork(io::Error),
+    /// The forked command exited or could not be seized before it was traced.
+    Seize(io::Error),
+    /// No thread of the process could be attached to.
+    Attach(i32),
+    /// A SIGINT/SIGTERM/SIGALRM handler the trace loop relies on could not be installed.
+    SignalHandler(io::Error),
+    /// The config asks a backend other than ptrace for something only ptrace can do.
+    Unsupported(&'static str),
+    /// The fanotify backend could not watch the filesystems (usually for lack of CAP_SYS_ADMIN).
+    Fanotify(io::Error),
+    /// The eBPF backend could not load or attach its probes, or set up the command's cgroup.
+    #[cfg(feature = "ebpf")]
+    Ebpf(io::Error),
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceError::InvalidPid(pid) => write!(f, "invalid pid: {}", pid),
+            TraceError::Fork(e) => write!(f, "fork failed: {}", e),
+            TraceError::Seize(e) => write!(f, "failed to start tracing: {}", e),
+            TraceError::Attach(pid) => write!(f, "failed to attach to process {}", pid),
+            TraceError::SignalHandler(e) => write!(f, "failed to install signal handler: {}", e),
+            TraceError::Unsupported(option) => write!(f, "{} needs the ptrace backend", option),
+            TraceError::Fanotify(e) => write!(f, "failed to set up fanotify: {}", e),
+            #[cfg(feature = "ebpf")]
+            TraceError::Ebpf(e) => write!(f, "failed to set up eBPF probes: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TraceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TraceError::Fork(e)
+            | TraceError::Seize(e)
+            | TraceError::SignalHandler(e)
+            | TraceError::Fanotify(e) => Some(e),
+            #[cfg(feature = "ebpf")]
+            TraceError::Ebpf(e) => Some(e),
+            TraceError::InvalidPid(_) | TraceError::Attach(_) | TraceError::Unsupported(_) => None,
+        }
+    }
+}
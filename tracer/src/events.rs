@@ -0,0 +1,169 @@
+//! Trace events and the streaming JSON Lines event log.
+//!
+//! Each event is written as one JSON object per line while the trace runs, so
+//! a tracer that is killed mid-run still leaves a usable (if partial) record;
+//! a compressed log then lacks its trailer, but decompresses up to the last
+//! flush. Library users get the same records through `Tracer::on_event`.
+
+use crate::compress::{Compression, Compressor};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+
+/// How long buffered events may sit in memory before being flushed to a regular file.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TraceEvent<'a> {
+    Open {
+        path: &'a str,
+        fd: i32,
+    },
+    Read {
+        path: &'a str,
+    },
+    Write {
+        path: &'a str,
+    },
+    Rename {
+        path: &'a str,
+    },
+    Stat {
+        path: &'a str,
+    },
+    Missing {
+        path: &'a str,
+    },
+    Metadata {
+        path: &'a str,
+    },
+    Mkdir {
+        path: &'a str,
+    },
+    Rmdir {
+        path: &'a str,
+    },
+    Delete {
+        path: &'a str,
+    },
+    List {
+        path: &'a str,
+    },
+    Sync {
+        path: &'a str,
+    },
+    Connect {
+        path: &'a str,
+    },
+    Link {
+        path: &'a str,
+        target: &'a str,
+    },
+    Readlink {
+        path: &'a str,
+        target: &'a str,
+    },
+    Fork {
+        child_pid: i32,
+    },
+    Exec {
+        command: &'a [String],
+    },
+    Exit {
+        exit_code: Option<i32>,
+        signal: Option<i32>,
+    },
+}
+
+/// An event as it is logged: what happened, to which pid, and when (Unix time).
+#[derive(Debug, Serialize)]
+pub struct EventRecord<'a> {
+    pub timestamp: f64,
+    pub pid: i32,
+    #[serde(flatten)]
+    pub event: &'a TraceEvent<'a>,
+}
+
+/// Callback given every event as it happens (`Tracer::on_event`).
+pub type EventHook = Box<dyn FnMut(&EventRecord)>;
+
+#[derive(Debug)]
+pub struct EventLog {
+    writer: Option<Compressor<BufWriter<File>>>, // taken to finish the stream on drop
+    // Pipes are flushed per event and never allowed to block the trace loop
+    is_pipe: bool,
+    last_flush: Instant,
+    dropped: u64,
+}
+
+impl EventLog {
+    /// Append to `path`; a compressed log gets a new gzip member or zstd frame.
+    pub fn open(path: &str, compression: Compression) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let is_pipe = file.metadata()?.file_type().is_fifo();
+
+        if is_pipe {
+            // A reader that stops draining the pipe must not stall the tracee;
+            // with O_NONBLOCK we drop events instead of hanging in write().
+            let fd = file.as_raw_fd();
+            let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+            if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(EventLog {
+            writer: Some(compression.writer(BufWriter::new(file))?),
+            is_pipe,
+            last_flush: Instant::now(),
+            dropped: 0,
+        })
+    }
+
+    pub fn emit(&mut self, record: &EventRecord) {
+        let line = match serde_json::to_vec(record) {
+            Ok(mut line) => {
+                line.push(b'\n');
+                line
+            }
+            Err(_) => return,
+        };
+
+        let Some(writer) = self.writer.as_mut() else {
+            return;
+        };
+        if writer.write_all(&line).is_err() {
+            self.dropped += 1;
+            return;
+        }
+        if self.is_pipe || self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush();
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if self.writer.as_mut().is_some_and(|w| w.flush().is_err()) {
+            self.dropped += 1;
+        }
+        self.last_flush = Instant::now();
+    }
+}
+
+impl Drop for EventLog {
+    fn drop(&mut self) {
+        if self.writer.take().is_some_and(|w| w.finish().is_err()) {
+            self.dropped += 1;
+        }
+        if self.dropped > 0 {
+            eprintln!(
+                "Warning: {} event log write(s) failed or would have blocked",
+                self.dropped
+            );
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! fanotify watcher for the file-only backend (--backend fanotify).
+//!
+//! Nothing is stopped: every mount in the tracer's mount namespace, which the
+//! launched command shares, is marked for open, read and write events. Each
+//! event carries the pid responsible and an fd for the file, whose path is
+//! read back from /proc/self/fd. The kernel merges repeated events on a file,
+//! and none are sent for directories, sockets, renames or deletions.
+//!
+//! A process may be gone by the time its events are read, so which processes
+//! descend from the command is followed as it happens, through the fork
+//! notifications of the proc connector.
+
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+pub use libc::{FAN_ACCESS, FAN_MODIFY, FAN_OPEN, FAN_OPEN_EXEC};
+
+const EVENT_MASK: u64 = FAN_ACCESS | FAN_MODIFY | FAN_OPEN | FAN_OPEN_EXEC;
+
+// struct fanotify_event_metadata: event_len, vers, reserved, metadata_len, mask, fd, pid
+const METADATA_LEN: usize = 24;
+
+/// One file access by a process.
+#[derive(Debug)]
+pub struct Event {
+    pub pid: i32,
+    pub mask: u64, // FAN_* bits, possibly several merged
+    pub path: String,
+}
+
+/// A fanotify group watching every mount.
+#[derive(Debug)]
+pub struct Notifier(OwnedFd);
+
+fn u32_at(bytes: &[u8], off: usize) -> u32 {
+    u32::from_ne_bytes(bytes[off..off + 4].try_into().unwrap_or([0; 4]))
+}
+
+fn u64_at(bytes: &[u8], off: usize) -> u64 {
+    u64::from_ne_bytes(bytes[off..off + 8].try_into().unwrap_or([0; 8]))
+}
+
+impl Notifier {
+    /// Mark every mount we can see. Needs CAP_SYS_ADMIN.
+    pub fn new() -> io::Result<Self> {
+        // Unlimited queue: an overflow would silently lose file accesses
+        let flags = libc::FAN_CLASS_NOTIF
+            | libc::FAN_CLOEXEC
+            | libc::FAN_NONBLOCK
+            | libc::FAN_UNLIMITED_QUEUE;
+        let event_flags = libc::O_RDONLY | libc::O_LARGEFILE | libc::O_CLOEXEC;
+        let fd = unsafe { libc::fanotify_init(flags, event_flags as libc::c_uint) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let notifier = Notifier(unsafe { OwnedFd::from_raw_fd(fd) });
+
+        // Some pseudo-filesystems refuse marks; only give up if nothing is watched
+        let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")?;
+        let mut result = Err(io::Error::from(io::ErrorKind::NotFound));
+        for mount in mountinfo.lines().filter_map(mount_point) {
+            let marked = notifier.mark(&mount);
+            if result.is_err() {
+                result = marked;
+            }
+        }
+        result.map(|()| notifier)
+    }
+
+    fn mark(&self, mount: &str) -> io::Result<()> {
+        let path = std::ffi::CString::new(mount).map_err(io::Error::other)?;
+        let ret = unsafe {
+            libc::fanotify_mark(
+                self.0.as_raw_fd(),
+                libc::FAN_MARK_ADD | libc::FAN_MARK_MOUNT,
+                EVENT_MASK,
+                libc::AT_FDCWD,
+                path.as_ptr(),
+            )
+        };
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// Block until there are events or `pidfd`'s process exits. Signals
+    /// interrupt the wait so the caller can look at its flags.
+    pub fn wait(&self, pidfd: &OwnedFd) {
+        let mut fds = [self.0.as_raw_fd(), pidfd.as_raw_fd()].map(|fd| libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        });
+        unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) };
+    }
+
+    /// Every event queued so far.
+    pub fn events(&self) -> Vec<Event> {
+        let mut events = Vec::new();
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = unsafe { libc::read(self.0.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+            if n <= 0 {
+                // EAGAIN once drained; EINTR leaves the rest for the next call
+                return events;
+            }
+            let mut off = 0;
+            while off + METADATA_LEN <= n as usize {
+                let bytes = &buf[off..];
+                let event_len = u32_at(bytes, 0) as usize;
+                let fd = u32_at(bytes, 16) as i32;
+                if fd >= 0 {
+                    let file = unsafe { OwnedFd::from_raw_fd(fd) };
+                    if let Ok(path) = std::fs::read_link(format!("/proc/self/fd/{}", fd)) {
+                        let path = path.to_string_lossy();
+                        events.push(Event {
+                            pid: u32_at(bytes, 20) as i32,
+                            mask: u64_at(bytes, 8),
+                            // An unlinked file still reports where it was
+                            path: path.strip_suffix(" (deleted)").unwrap_or(&path).to_string(),
+                        });
+                    }
+                    drop(file);
+                }
+                if event_len < METADATA_LEN {
+                    break;
+                }
+                off += event_len;
+            }
+        }
+    }
+}
+
+// Proc connector (linux/connector.h, linux/cn_proc.h)
+const NETLINK_CONNECTOR: i32 = 11;
+const CN_IDX_PROC: u32 = 1;
+const CN_VAL_PROC: u32 = 1;
+const PROC_CN_MCAST_LISTEN: u32 = 1;
+const PROC_EVENT_FORK: u32 = 1;
+// nlmsghdr (16 bytes), then cn_msg (20), then proc_event: what, cpu, timestamp, data
+const NLMSG_HDR_LEN: usize = 16;
+const EVENT_WHAT: usize = NLMSG_HDR_LEN + 20;
+const EVENT_DATA: usize = EVENT_WHAT + 16;
+
+/// Fork notifications for every process on the system. Needs CAP_NET_ADMIN.
+#[derive(Debug)]
+pub struct ForkWatcher(OwnedFd);
+
+impl ForkWatcher {
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_DGRAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                NETLINK_CONNECTOR,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let watcher = ForkWatcher(unsafe { OwnedFd::from_raw_fd(fd) });
+
+        let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_groups = CN_IDX_PROC;
+        let addr_ptr: *const libc::sockaddr_nl = &addr;
+        let addr_len = std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t;
+        if unsafe { libc::bind(fd, addr_ptr.cast(), addr_len) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // nlmsghdr { len, type = NLMSG_DONE, flags, seq, pid }, then
+        // cn_msg { idx, val, seq, ack, len, flags } and the listen op
+        let mut msg = Vec::with_capacity(NLMSG_HDR_LEN + 24);
+        msg.extend_from_slice(&((NLMSG_HDR_LEN + 24) as u32).to_ne_bytes());
+        msg.extend_from_slice(&(libc::NLMSG_DONE as u16).to_ne_bytes());
+        msg.extend_from_slice(&[0; 10]);
+        for word in [CN_IDX_PROC, CN_VAL_PROC, 0, 0] {
+            msg.extend_from_slice(&word.to_ne_bytes());
+        }
+        msg.extend_from_slice(&4u16.to_ne_bytes());
+        msg.extend_from_slice(&0u16.to_ne_bytes());
+        msg.extend_from_slice(&PROC_CN_MCAST_LISTEN.to_ne_bytes());
+        if unsafe { libc::send(fd, msg.as_ptr().cast(), msg.len(), 0) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(watcher)
+    }
+
+    /// (parent, child) pids of every process forked since the last call, in
+    /// order. New threads are left out.
+    pub fn forks(&self) -> Vec<(i32, i32)> {
+        let mut forks = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n =
+                unsafe { libc::recv(self.0.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+            if n <= 0 {
+                return forks;
+            }
+            let msg = &buf[..n as usize];
+            if msg.len() < EVENT_DATA + 16 || u32_at(msg, EVENT_WHAT) != PROC_EVENT_FORK {
+                continue;
+            }
+            // parent_pid, parent_tgid, child_pid, child_tgid
+            let parent = u32_at(msg, EVENT_DATA + 4) as i32;
+            let (child_tid, child) = (u32_at(msg, EVENT_DATA + 8), u32_at(msg, EVENT_DATA + 12));
+            if child_tid == child {
+                forks.push((parent, child as i32));
+            }
+        }
+    }
+}
+
+/// A pidfd, readable once the process exits (Linux 5.3+).
+pub fn pidfd_open(pid: i32) -> io::Result<OwnedFd> {
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as i32) })
+}
+
+/// The mount point field of a /proc/self/mountinfo line, unescaped.
+pub(crate) fn mount_point(line: &str) -> Option<String> {
+    let field = line.split(' ').nth(4)?;
+    // Spaces, tabs, newlines and backslashes are written as \ooo
+    let mut path = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            path.push(c);
+            continue;
+        }
+        let octal: String = chars.by_ref().take(3).collect();
+        match u8::from_str_radix(&octal, 8) {
+            Ok(byte) => path.push(byte as char),
+            Err(_) => {
+                path.push('\\');
+                path.push_str(&octal);
+            }
+        }
+    }
+    Some(path)
+}
@@ -0,0 +1,219 @@
+//! Glob-based path filtering applied before paths reach the output sets.
+//!
+//! Filtering only affects what is reported: the fd table keeps tracking
+//! excluded files so attribution through dup'd or inherited fds still works.
+//!
+//! Pseudo-filesystems (/proc, /sys, /dev, cgroups, ...) are found from
+//! mountinfo rather than by name, so a tmpfs mounted inside one, such as
+//! /dev/shm, is still reported.
+//!
+//! A root (--root) scopes the trace to one directory. It is checked before
+//! the globs, by prefix, against the root as given and with its symlinks
+//! resolved; the tracer also admits paths whose directory resolves into it.
+
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// Pseudo-filesystems covered by --exclude-default.
+pub const DEFAULT_EXCLUDES: &[&str] = &["/proc/**", "/sys/**", "/dev/**"];
+
+/// Filesystem types whose files are kernel interfaces rather than data a
+/// command could depend on.
+const PSEUDO_FS_TYPES: &[&str] = &[
+    "autofs",
+    "binfmt_misc",
+    "bpf",
+    "cgroup",
+    "cgroup2",
+    "configfs",
+    "debugfs",
+    "devpts",
+    "devtmpfs",
+    "efivarfs",
+    "fusectl",
+    "mqueue",
+    "nsfs",
+    "proc",
+    "pstore",
+    "rpc_pipefs",
+    "securityfs",
+    "selinuxfs",
+    "sysfs",
+    "tracefs",
+];
+
+/// Shared libraries and data files covered by --exclude-libs.
+pub const LIBRARY_EXCLUDES: &[&str] = &[
+    "/lib/**",
+    "/lib64/**",
+    "/usr/lib/**",
+    "/usr/lib64/**",
+    "/usr/share/**",
+];
+
+/// The active patterns, reported in the output so consumers know the trace was filtered.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FilterSummary {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub ignored_mounts: Vec<String>, // pseudo-filesystem mount points left out
+    pub root: Option<String>,        // only paths under this directory are reported
+}
+
+#[derive(Debug)]
+pub struct PathFilter {
+    include: GlobSet,
+    exclude: GlobSet,
+    // Every mount point, deepest first, and whether it is a pseudo-filesystem;
+    // empty unless pseudo-filesystems are ignored
+    mounts: Vec<(String, bool)>,
+    roots: Vec<String>, // the --root directory as given and canonical; empty for no scope
+    summary: FilterSummary,
+}
+
+impl Default for PathFilter {
+    fn default() -> Self {
+        PathFilter {
+            include: GlobSet::empty(),
+            exclude: GlobSet::empty(),
+            mounts: Vec::new(),
+            roots: Vec::new(),
+            summary: FilterSummary::default(),
+        }
+    }
+}
+
+impl PathFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, globset::Error> {
+        Ok(PathFilter {
+            include: build_set(include)?,
+            exclude: build_set(exclude)?,
+            mounts: Vec::new(),
+            roots: Vec::new(),
+            summary: FilterSummary {
+                include: include.to_vec(),
+                exclude: exclude.to_vec(),
+                ignored_mounts: Vec::new(),
+                root: None,
+            },
+        })
+    }
+
+    /// Also leave out paths on pseudo-filesystems, as mounted for this process.
+    /// Without a readable /proc/self/mountinfo, nothing more is left out.
+    pub fn ignore_pseudo_filesystems(mut self) -> Self {
+        let Ok(mountinfo) = std::fs::read_to_string("/proc/self/mountinfo") else {
+            return self;
+        };
+        let mut mounts: Vec<(String, bool)> = mountinfo.lines().filter_map(parse_mount).collect();
+        // A later mount over the same point hides the earlier one
+        mounts.reverse();
+        let mut seen = HashSet::new();
+        mounts.retain(|(point, _)| seen.insert(point.clone()));
+        // Mounts inside a pseudo-filesystem are part of it (the tmpfs holding
+        // /sys/fs/cgroup), except under /dev, which has real storage like /dev/shm
+        mounts.sort_by_key(|(point, _)| point.len());
+        for i in 0..mounts.len() {
+            let (point, _) = &mounts[i];
+            let parent_pseudo = mounts[..i]
+                .iter()
+                .rev()
+                .find(|(parent, _)| is_within(point, parent))
+                .is_some_and(|(parent, pseudo)| *pseudo && !is_within(parent, "/dev"));
+            mounts[i].1 |= parent_pseudo;
+        }
+        mounts.reverse();
+        self.summary.ignored_mounts = mounts
+            .iter()
+            .filter(|(_, pseudo)| *pseudo)
+            .map(|(point, _)| point.clone())
+            .collect();
+        self.summary.ignored_mounts.sort();
+        self.mounts = mounts;
+        self
+    }
+
+    /// Only report paths under `root`; a relative one is taken from the
+    /// current directory.
+    pub fn within(mut self, root: &str) -> Self {
+        let given = std::path::absolute(root)
+            .map(|p| p.to_string_lossy().trim_end_matches('/').to_string())
+            .unwrap_or_else(|_| root.to_string());
+        let given = if given.is_empty() {
+            "/".to_string()
+        } else {
+            given
+        };
+        // Paths from /proc (cwd, fd links) are canonical, paths from tracees as written
+        let canonical = std::fs::canonicalize(&given).map(|p| p.to_string_lossy().to_string());
+        self.roots = vec![given.clone()];
+        if let Some(canonical) = canonical.ok().filter(|c| *c != given) {
+            self.roots.push(canonical);
+        }
+        self.summary.root = Some(given);
+        self
+    }
+
+    /// Whether a path is under the root, if there is one. Pipes, sockets and
+    /// other pseudo-paths are never out of scope.
+    pub fn in_scope(&self, path: &str) -> bool {
+        self.roots.is_empty()
+            || !path.starts_with('/')
+            || self.roots.iter().any(|root| is_within(path, root))
+    }
+
+    /// Whether a path should be reported. Includes override excludes and
+    /// ignored pseudo-filesystems, but not the root.
+    pub fn allows(&self, path: &str) -> bool {
+        if !self.in_scope(path) {
+            return false;
+        }
+        if self.exclude.is_empty() && self.mounts.is_empty() {
+            return true;
+        }
+        self.include.is_match(path) || !(self.exclude.is_match(path) || self.on_pseudo_fs(path))
+    }
+
+    /// Whether the deepest mount holding `path` is a pseudo-filesystem.
+    fn on_pseudo_fs(&self, path: &str) -> bool {
+        self.mounts
+            .iter()
+            .find(|(point, _)| is_within(path, point))
+            .is_some_and(|(_, pseudo)| *pseudo)
+    }
+
+    pub fn summary(&self) -> &FilterSummary {
+        &self.summary
+    }
+}
+
+/// Whether `path` is `dir` or below it.
+fn is_within(path: &str, dir: &str) -> bool {
+    dir == "/"
+        || path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// (mount point, is a pseudo-filesystem) from a mountinfo line. /dev counts
+/// whatever backs it, since containers often use a tmpfs there.
+fn parse_mount(line: &str) -> Option<(String, bool)> {
+    let point = crate::fanotify::mount_point(line)?;
+    let fs_type = line.split(' ').skip_while(|f| *f != "-").nth(1)?;
+    let pseudo = PSEUDO_FS_TYPES.contains(&fs_type) || point == "/dev";
+    Some((point, pseudo))
+}
+
+fn build_set(patterns: &[String]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(compile(pattern)?);
+    }
+    builder.build()
+}
+
+fn compile(pattern: &str) -> Result<Glob, globset::Error> {
+    // `*` stays within one path component; use `**` to cross directories
+    GlobBuilder::new(pattern).literal_separator(true).build()
+}
@@ -0,0 +1,4989 @@
+//! ptrace-based syscall tracer for roar provenance tracking.
+//!
+//! ```no_run
+//! use roar_tracer::{Tracer, TracerConfig};
+//! use std::process::Command;
+//!
+//! let report = Tracer::new(TracerConfig::default())
+//!     .on_event(|record| println!("{} {:?}", record.pid, record.event))
+//!     .run(Command::new("make"))?;
+//! println!("{} files written", report.written_files.len());
+//! # Ok::<(), roar_tracer::TraceError>(())
+//! ```
+//!
+//! The trace loop waits on any child of the calling process and installs
+//! process-wide signal handlers, so only one trace may run per process at a
+//! time, and other children of the caller must not be waited for meanwhile.
+
+mod arch;
+mod category;
+mod chrome;
+#[cfg(target_arch = "x86_64")]
+mod compat;
+mod compress;
+mod csv;
+mod digest;
+#[cfg(feature = "ebpf")]
+mod ebpf;
+mod environ;
+mod error;
+mod events;
+mod fanotify;
+mod filter;
+mod net;
+mod otlp;
+mod output;
+mod paths;
+mod provenance;
+mod report;
+mod seccomp;
+mod sqlite;
+mod stats;
+mod strace;
+mod uring;
+
+pub use category::TraceCategory;
+pub use chrome::ChromeTrace;
+pub use compress::Compression;
+pub use environ::EnvRedactor;
+pub use error::TraceError;
+pub use events::{EventHook, EventLog, EventRecord, TraceEvent};
+pub use filter::{FilterSummary, PathFilter, DEFAULT_EXCLUDES, LIBRARY_EXCLUDES};
+pub use net::{NetworkConnection, NetworkPolicy, NetworkTraffic};
+pub use otlp::export as export_otlp;
+pub use output::{check_output_path, OutputFormat};
+pub use provenance::write as write_provenance;
+pub use report::{
+    ExecRecord, FileMetadata, FileStats, PathLimit, ProcessInfo, ResourceUsage, SharedMemory,
+    TraceReport, SCHEMA_VERSION,
+};
+pub use stats::TracerStats;
+pub use strace::{StraceLog, SyscallHook, SyscallRecord};
+
+use arch::{Registers, SyscallRegs};
+use clap::ValueEnum;
+use fanotify::{ForkWatcher, Notifier};
+use net::{PendingSocketCall, PendingTransfer};
+use nix::sys::ptrace;
+use nix::sys::signal::Signal;
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{fork, ForkResult, Pid};
+use paths::SymlinkResolver;
+use seccomp::Filter;
+use stats::StatsCollector;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::os::fd::OwnedFd;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicIsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use uring::Ring;
+
+// Syscall numbers for x86_64. Stops of other ABIs are translated to these
+// (see arch and compat), so these are the only numbers the handlers see.
+const SYS_READ: u64 = 0;
+const SYS_WRITE: u64 = 1;
+const SYS_OPEN: u64 = 2;
+const SYS_CLOSE: u64 = 3;
+const SYS_STAT: u64 = 4;
+const SYS_FSTAT: u64 = 5; // fstat(fd, statbuf)
+const SYS_LSTAT: u64 = 6;
+const SYS_MMAP: u64 = 9;
+const SYS_MPROTECT: u64 = 10; // mprotect(addr, len, prot)
+const SYS_MUNMAP: u64 = 11; // munmap(addr, len)
+const SYS_MSYNC: u64 = 26; // msync(addr, len, flags)
+const SYS_DUP: u64 = 32; // dup(oldfd)
+const SYS_DUP2: u64 = 33; // dup2(oldfd, newfd)
+const SYS_PREAD64: u64 = 17; // positional read (used by pyarrow, etc.)
+const SYS_PWRITE64: u64 = 18; // positional write
+const SYS_READV: u64 = 19; // scatter read
+const SYS_WRITEV: u64 = 20; // gather write
+const SYS_ACCESS: u64 = 21; // access(path, mode)
+const SYS_PIPE: u64 = 22; // pipe(int fds[2])
+const SYS_SENDFILE: u64 = 40; // zero-copy file-to-file/socket
+const SYS_SOCKET: u64 = 41; // socket(domain, type, protocol)
+const SYS_CONNECT: u64 = 42; // connect(sockfd, addr, addrlen)
+const SYS_ACCEPT: u64 = 43; // accept(sockfd, addr, addrlen)
+const SYS_SENDTO: u64 = 44; // sendto(sockfd, buf, len, flags, dest_addr, addrlen)
+const SYS_RECVFROM: u64 = 45; // recvfrom(sockfd, buf, len, flags, src_addr, addrlen)
+const SYS_SENDMSG: u64 = 46; // sendmsg(sockfd, msghdr, flags)
+const SYS_RECVMSG: u64 = 47; // recvmsg(sockfd, msghdr, flags)
+const SYS_BIND: u64 = 49; // bind(sockfd, addr, addrlen)
+const SYS_LISTEN: u64 = 50; // listen(sockfd, backlog)
+const SYS_CLONE: u64 = 56; // clone(flags, stack, ...)
+const SYS_EXECVE: u64 = 59; // execve(filename, argv, envp)
+const SYS_FCNTL: u64 = 72; // fcntl(fd, cmd, arg)
+const SYS_FSYNC: u64 = 74; // fsync(fd)
+const SYS_FDATASYNC: u64 = 75; // fdatasync(fd)
+const SYS_TRUNCATE: u64 = 76; // truncate(path, length)
+const SYS_FTRUNCATE: u64 = 77; // ftruncate(fd, length)
+const SYS_GETDENTS: u64 = 78; // getdents(fd, dirp, count)
+const SYS_CHDIR: u64 = 80; // chdir(path)
+const SYS_FCHDIR: u64 = 81; // fchdir(fd)
+const SYS_RENAME: u64 = 82; // rename(oldpath, newpath)
+const SYS_MKDIR: u64 = 83; // mkdir(path, mode)
+const SYS_RMDIR: u64 = 84; // rmdir(path)
+const SYS_LINK: u64 = 86; // link(oldpath, newpath)
+const SYS_UNLINK: u64 = 87; // unlink(path)
+const SYS_SYMLINK: u64 = 88; // symlink(target, linkpath)
+const SYS_READLINK: u64 = 89; // readlink(path, buf, bufsiz)
+const SYS_CHMOD: u64 = 90; // chmod(path, mode)
+const SYS_FCHMOD: u64 = 91; // fchmod(fd, mode)
+const SYS_CHOWN: u64 = 92; // chown(path, owner, group)
+const SYS_FCHOWN: u64 = 93; // fchown(fd, owner, group)
+const SYS_LCHOWN: u64 = 94; // lchown(path, owner, group)
+const SYS_UTIME: u64 = 132; // utime(path, times)
+const SYS_CHROOT: u64 = 161; // chroot(path)
+const SYS_GETDENTS64: u64 = 217; // getdents64(fd, dirp, count)
+const SYS_SETXATTR: u64 = 188; // setxattr(path, name, value, size, flags)
+const SYS_LSETXATTR: u64 = 189; // lsetxattr(path, ...)
+const SYS_FSETXATTR: u64 = 190; // fsetxattr(fd, ...)
+const SYS_REMOVEXATTR: u64 = 197; // removexattr(path, name)
+const SYS_LREMOVEXATTR: u64 = 198; // lremovexattr(path, name)
+const SYS_FREMOVEXATTR: u64 = 199; // fremovexattr(fd, name)
+const SYS_UTIMES: u64 = 235; // utimes(path, times)
+const SYS_OPENAT: u64 = 257;
+const SYS_MKDIRAT: u64 = 258; // mkdirat(dirfd, path, mode)
+const SYS_FCHOWNAT: u64 = 260; // fchownat(dirfd, path, owner, group, flags)
+const SYS_FUTIMESAT: u64 = 261; // futimesat(dirfd, path, times)
+const SYS_NEWFSTATAT: u64 = 262; // newfstatat(dirfd, path, statbuf, flags)
+const SYS_UNLINKAT: u64 = 263; // unlinkat(dirfd, path, flags)
+const SYS_SIGNALFD: u64 = 282; // signalfd(fd, mask, size)
+const SYS_TIMERFD_CREATE: u64 = 283; // timerfd_create(clockid, flags)
+const SYS_EVENTFD: u64 = 284; // eventfd(initval)
+const SYS_FALLOCATE: u64 = 285; // fallocate(fd, mode, offset, len)
+const SYS_ACCEPT4: u64 = 288; // accept4(sockfd, addr, addrlen, flags)
+const SYS_SIGNALFD4: u64 = 289; // signalfd4(fd, mask, size, flags)
+const SYS_EVENTFD2: u64 = 290; // eventfd2(initval, flags)
+const SYS_DUP3: u64 = 292; // dup3(oldfd, newfd, flags)
+const SYS_PIPE2: u64 = 293; // pipe2(int fds[2], flags)
+const SYS_RECVMMSG: u64 = 299; // recvmmsg(sockfd, mmsghdr vec, vlen, flags, timeout)
+const SYS_RENAMEAT: u64 = 264; // renameat(olddirfd, oldpath, newdirfd, newpath)
+const SYS_LINKAT: u64 = 265; // linkat(olddirfd, oldpath, newdirfd, newpath, flags)
+const SYS_SYMLINKAT: u64 = 266; // symlinkat(target, newdirfd, linkpath)
+const SYS_READLINKAT: u64 = 267; // readlinkat(dirfd, path, buf, bufsiz)
+const SYS_FCHMODAT: u64 = 268; // fchmodat(dirfd, path, mode)
+const SYS_FACCESSAT: u64 = 269; // faccessat(dirfd, path, mode)
+const SYS_UNSHARE: u64 = 272; // unshare(flags)
+const SYS_SPLICE: u64 = 275; // splice(fd_in, off_in, fd_out, off_out, len, flags)
+const SYS_TEE: u64 = 276; // tee(fd_in, fd_out, len, flags)
+const SYS_SYNC_FILE_RANGE: u64 = 277; // sync_file_range(fd, offset, nbytes, flags)
+const SYS_VMSPLICE: u64 = 278; // vmsplice(fd, iov, nr_segs, flags)
+const SYS_UTIMENSAT: u64 = 280; // utimensat(dirfd, path, times, flags)
+const SYS_PREADV: u64 = 295; // positional scatter read
+const SYS_PWRITEV: u64 = 296; // positional gather write
+const SYS_SENDMMSG: u64 = 307; // sendmmsg(sockfd, mmsghdr vec, vlen, flags)
+const SYS_SETNS: u64 = 308; // setns(fd, nstype)
+const SYS_RENAMEAT2: u64 = 316; // renameat2 with flags
+const SYS_EXECVEAT: u64 = 322; // execveat(dirfd, pathname, argv, envp, flags)
+const SYS_COPY_FILE_RANGE: u64 = 326; // efficient file copy
+const SYS_PREADV2: u64 = 327; // preadv with flags
+const SYS_PWRITEV2: u64 = 328; // pwritev with flags
+const SYS_MEMFD_CREATE: u64 = 319; // memfd_create(name, flags)
+const SYS_STATX: u64 = 332; // statx(dirfd, path, flags, mask, statxbuf)
+const SYS_CLONE3: u64 = 435; // clone3(struct clone_args *, size)
+const SYS_CLOSE_RANGE: u64 = 436; // close_range(first, last, flags)
+const SYS_IO_URING_SETUP: u64 = 425; // io_uring_setup(entries, params)
+const SYS_IO_URING_ENTER: u64 = 426; // io_uring_enter(fd, to_submit, min_complete, flags, ...)
+const SYS_IO_URING_REGISTER: u64 = 427; // io_uring_register(fd, opcode, arg, nr_args)
+const SYS_OPENAT2: u64 = 437; // openat2(dirfd, path, struct open_how *, size)
+const SYS_FACCESSAT2: u64 = 439; // faccessat2(dirfd, path, mode, flags)
+const SYS_FCHMODAT2: u64 = 452; // fchmodat2(dirfd, path, mode, flags)
+
+/// Names of the syscalls we handle, for the --stats per-syscall counts.
+const SYSCALL_NAMES: &[(u64, &str)] = &[
+    (SYS_READ, "read"),
+    (SYS_WRITE, "write"),
+    (SYS_OPEN, "open"),
+    (SYS_CLOSE, "close"),
+    (SYS_STAT, "stat"),
+    (SYS_FSTAT, "fstat"),
+    (SYS_LSTAT, "lstat"),
+    (SYS_MMAP, "mmap"),
+    (SYS_MPROTECT, "mprotect"),
+    (SYS_MUNMAP, "munmap"),
+    (SYS_MSYNC, "msync"),
+    (SYS_DUP, "dup"),
+    (SYS_DUP2, "dup2"),
+    (SYS_PREAD64, "pread64"),
+    (SYS_PWRITE64, "pwrite64"),
+    (SYS_READV, "readv"),
+    (SYS_WRITEV, "writev"),
+    (SYS_ACCESS, "access"),
+    (SYS_PIPE, "pipe"),
+    (SYS_SENDFILE, "sendfile"),
+    (SYS_SOCKET, "socket"),
+    (SYS_CONNECT, "connect"),
+    (SYS_ACCEPT, "accept"),
+    (SYS_SENDTO, "sendto"),
+    (SYS_RECVFROM, "recvfrom"),
+    (SYS_SENDMSG, "sendmsg"),
+    (SYS_RECVMSG, "recvmsg"),
+    (SYS_BIND, "bind"),
+    (SYS_LISTEN, "listen"),
+    (SYS_CLONE, "clone"),
+    (SYS_EXECVE, "execve"),
+    (SYS_FCNTL, "fcntl"),
+    (SYS_FSYNC, "fsync"),
+    (SYS_FDATASYNC, "fdatasync"),
+    (SYS_TRUNCATE, "truncate"),
+    (SYS_FTRUNCATE, "ftruncate"),
+    (SYS_GETDENTS, "getdents"),
+    (SYS_CHDIR, "chdir"),
+    (SYS_FCHDIR, "fchdir"),
+    (SYS_RENAME, "rename"),
+    (SYS_MKDIR, "mkdir"),
+    (SYS_RMDIR, "rmdir"),
+    (SYS_LINK, "link"),
+    (SYS_UNLINK, "unlink"),
+    (SYS_SYMLINK, "symlink"),
+    (SYS_READLINK, "readlink"),
+    (SYS_CHMOD, "chmod"),
+    (SYS_FCHMOD, "fchmod"),
+    (SYS_CHOWN, "chown"),
+    (SYS_FCHOWN, "fchown"),
+    (SYS_LCHOWN, "lchown"),
+    (SYS_UTIME, "utime"),
+    (SYS_CHROOT, "chroot"),
+    (SYS_GETDENTS64, "getdents64"),
+    (SYS_SETXATTR, "setxattr"),
+    (SYS_LSETXATTR, "lsetxattr"),
+    (SYS_FSETXATTR, "fsetxattr"),
+    (SYS_REMOVEXATTR, "removexattr"),
+    (SYS_LREMOVEXATTR, "lremovexattr"),
+    (SYS_FREMOVEXATTR, "fremovexattr"),
+    (SYS_UTIMES, "utimes"),
+    (SYS_OPENAT, "openat"),
+    (SYS_MKDIRAT, "mkdirat"),
+    (SYS_FCHOWNAT, "fchownat"),
+    (SYS_FUTIMESAT, "futimesat"),
+    (SYS_NEWFSTATAT, "newfstatat"),
+    (SYS_UNLINKAT, "unlinkat"),
+    (SYS_SIGNALFD, "signalfd"),
+    (SYS_TIMERFD_CREATE, "timerfd_create"),
+    (SYS_EVENTFD, "eventfd"),
+    (SYS_FALLOCATE, "fallocate"),
+    (SYS_ACCEPT4, "accept4"),
+    (SYS_SIGNALFD4, "signalfd4"),
+    (SYS_EVENTFD2, "eventfd2"),
+    (SYS_DUP3, "dup3"),
+    (SYS_PIPE2, "pipe2"),
+    (SYS_RECVMMSG, "recvmmsg"),
+    (SYS_RENAMEAT, "renameat"),
+    (SYS_LINKAT, "linkat"),
+    (SYS_SYMLINKAT, "symlinkat"),
+    (SYS_READLINKAT, "readlinkat"),
+    (SYS_FCHMODAT, "fchmodat"),
+    (SYS_FACCESSAT, "faccessat"),
+    (SYS_UNSHARE, "unshare"),
+    (SYS_SPLICE, "splice"),
+    (SYS_TEE, "tee"),
+    (SYS_SYNC_FILE_RANGE, "sync_file_range"),
+    (SYS_VMSPLICE, "vmsplice"),
+    (SYS_UTIMENSAT, "utimensat"),
+    (SYS_PREADV, "preadv"),
+    (SYS_PWRITEV, "pwritev"),
+    (SYS_SENDMMSG, "sendmmsg"),
+    (SYS_SETNS, "setns"),
+    (SYS_RENAMEAT2, "renameat2"),
+    (SYS_EXECVEAT, "execveat"),
+    (SYS_COPY_FILE_RANGE, "copy_file_range"),
+    (SYS_PREADV2, "preadv2"),
+    (SYS_PWRITEV2, "pwritev2"),
+    (SYS_MEMFD_CREATE, "memfd_create"),
+    (SYS_STATX, "statx"),
+    (SYS_CLONE3, "clone3"),
+    (SYS_CLOSE_RANGE, "close_range"),
+    (SYS_IO_URING_SETUP, "io_uring_setup"),
+    (SYS_IO_URING_ENTER, "io_uring_enter"),
+    (SYS_IO_URING_REGISTER, "io_uring_register"),
+    (SYS_OPENAT2, "openat2"),
+    (SYS_FACCESSAT2, "faccessat2"),
+    (SYS_FCHMODAT2, "fchmodat2"),
+];
+
+// openat2 open_how.resolve flags
+const RESOLVE_IN_ROOT: u64 = 0x10;
+
+// close_range flags
+const CLOSE_RANGE_CLOEXEC: u64 = 1 << 2;
+
+// =============================================================================
+// Data Structures - the ones written out are in report.rs
+// =============================================================================
+
+/// How a syscall touched a path; applied at exit once the call is known to have succeeded.
+#[derive(Debug, Clone)]
+enum Access {
+    Read,
+    Write,
+    Rename,
+    Stat,
+    Metadata,
+    Mkdir,
+    Rmdir,
+    Delete,
+    List,
+    Sync,
+    Link { target: String },
+    Named { owner: i32, fd: i32 }, // an unnamed file behind owner's fd got this path
+}
+
+/// A live file-backed mapping, kept so later mprotect/msync calls can be attributed.
+#[derive(Debug, Clone)]
+struct FileMapping {
+    start: u64,
+    end: u64,
+    path: String,
+    shared: bool,
+}
+
+/// The files one process opened, read and wrote, until they go in its ProcessInfo.
+#[derive(Debug, Clone, Default)]
+struct ProcessFiles {
+    opened: BTreeSet<String>,
+    read: BTreeSet<String>,
+    written: BTreeSet<String>,
+    first_access: f64,
+    last_access: f64,
+}
+
+/// An open seen at syscall entry, completed at exit once the fd is known.
+#[derive(Debug, Clone)]
+struct PendingOpen {
+    path: String,
+    flags: u64,
+    mode: u32,
+    // O_CREAT of a path that was absent at entry: success means the open made it
+    creates: bool,
+}
+
+impl PendingOpen {
+    fn new(path: String, flags: u64, mode: u64) -> Self {
+        let creates = flags & libc::O_CREAT as u64 != 0
+            && (flags & libc::O_EXCL as u64 != 0 || !std::path::Path::new(&path).exists());
+        PendingOpen {
+            path,
+            flags,
+            mode: (mode & 0o7777) as u32,
+            creates,
+        }
+    }
+}
+
+/// An io_uring call seen at entry, completed at exit.
+#[derive(Debug)]
+enum PendingUring {
+    Setup {
+        params: u64,
+    },
+    Map {
+        fd: i32,
+        offset: u64,
+    },
+    Register {
+        fd: i32,
+        opcode: u32,
+        arg: u64,
+        nr: u32,
+    },
+    Enter {
+        ring: (i32, i32),
+    },
+}
+
+/// An operation submitted through io_uring whose outcome only its CQE tells.
+#[derive(Debug, Clone)]
+enum UringOp {
+    // file_index: 0 for a regular fd, else the direct descriptor slot + 1 (or ALLOC)
+    Open { open: PendingOpen, file_index: u32 },
+    Access(Access, String),
+    Io(Vec<(Access, String)>), // reads, writes and syncs; res is the bytes moved
+}
+
+struct TracerState {
+    processes: HashMap<i32, ProcessInfo>,
+    // Descriptor tables are shared between tasks created with CLONE_FILES,
+    // so pids map to a table id rather than owning their fds directly.
+    fd_tables: HashMap<u64, HashMap<i32, String>>, // table id -> (fd -> path)
+    fd_table_ids: HashMap<i32, u64>,               // pid -> table id
+    next_fd_table_id: u64,
+    // Raw number of the syscall each pid is inside of, used to tell entry from
+    // exit when syscall_info_supported is false, and its registers at entry
+    in_syscall: HashMap<i32, (u64, Option<SyscallRegs>)>,
+    syscall_info_supported: bool,
+    pending_opens: HashMap<i32, PendingOpen>,
+    pending_execs: HashMap<i32, (Option<String>, Vec<String>)>, // pid -> (filename, argv)
+    pending_sockets: HashMap<i32, PendingSocketCall>,
+    pending_accesses: HashMap<i32, Vec<(Access, String)>>, // pid -> accesses awaiting exit
+    active_pids: HashSet<i32>,
+    // New children whose first stop arrived before the parent's fork event; held until it does
+    early_children: HashSet<i32>,
+
+    // Track file access
+    started: Instant,
+    start_time: f64, // `started` in seconds since the epoch
+    now: f64,        // seconds since `started`, refreshed at every syscall stop
+
+    // Per-file access stats; opened/read/written_files are derived from these
+    files: HashMap<String, FileStats>,
+
+    // Track metadata probes (stat/access) and probes/opens that found nothing
+    stat_files: HashSet<String>,
+    missing_files: HashSet<String>,
+    failed_opens: HashMap<String, i32>,
+
+    // Track permission, ownership, timestamp and xattr changes
+    metadata_changed_files: HashSet<String>,
+
+    // Track directories created and removed (mkdir/rmdir)
+    created_dirs: HashSet<String>,
+    removed_dirs: HashSet<String>,
+
+    // Track files unlinked; a later open, rename or link onto the path takes it out again
+    deleted_files: HashSet<String>,
+
+    // Track directories enumerated (ls, glob, os.listdir): their contents are an input
+    listed_dirs: HashSet<String>,
+
+    // Track files flushed to disk (fsync, fdatasync, sync_file_range): commit points
+    synced_files: HashSet<String>,
+    unix_sockets: HashSet<String>, // filesystem sockets connected to
+
+    // Track links created, and symlinks the tracee looked through
+    links: HashMap<String, String>,
+    readlinks: HashMap<String, String>,
+    pending_readlinks: HashMap<i32, (String, u64)>, // pid -> (path, buffer address)
+
+    // File-backed mappings per address space; CLONE_VM tasks map to the owning pid
+    mappings: HashMap<i32, Vec<FileMapping>>, // owner pid -> mappings
+    address_spaces: HashMap<i32, i32>,        // pid -> owner pid, absent if its own
+    pending_mmaps: HashMap<i32, (String, u64, bool)>, // pid -> (path, len, shared)
+
+    // Root directories of chrooted processes, inherited across fork
+    roots: HashMap<i32, String>, // pid -> root in the tracer's view
+
+    // Working directories as of each process's last chdir/fchdir, so relative
+    // paths resolve against the cwd of the moment; CLONE_FS tasks map to the owner
+    cwds: HashMap<i32, String>,   // owner pid -> cwd in the tracer's view
+    fs_owners: HashMap<i32, i32>, // pid -> owner pid, absent if its own
+
+    // Track socket connect/bind/accept calls
+    network_connections: Vec<NetworkConnection>,
+    resolved_hostnames: BTreeSet<String>, // names asked for in DNS queries to port 53
+    network_traffic: HashMap<String, NetworkTraffic>,
+    pending_transfers: HashMap<i32, PendingTransfer>, // pid -> socket I/O awaiting its byte count
+
+    process_files: HashMap<i32, ProcessFiles>, // pid -> files for its ProcessInfo
+
+    // Track pipe I/O per process. Anonymous pipes are labeled "pipe:[<inode>]",
+    // named FIFOs keep their path; both ends of a pipe share the label.
+    pipe_reads: HashMap<i32, HashSet<String>>,
+    pipe_writes: HashMap<i32, HashSet<String>>,
+
+    // Track POSIX shared memory under /dev/shm apart from files: it is
+    // inter-process state, not an input or output of the command
+    shared_memory: HashMap<String, SharedMemory>,
+    fifos: HashSet<String>,
+    pending_pipes: HashMap<i32, u64>, // pid -> address of the fds array
+    pending_dups: HashMap<i32, i32>,  // pid -> fd being duplicated
+    pending_closes: HashMap<i32, (i32, i32)>, // pid -> inclusive fd range being closed
+    pending_recvmsgs: HashMap<i32, u64>, // pid -> msghdr/mmsghdr address
+    // Labels of fds sent with SCM_RIGHTS, keyed by the open file's inode, so the
+    // receiver gets the sender's label even for sockets and unnamed files
+    passed_fds: HashMap<(u64, u64), String>, // (dev, ino) -> sender's label
+
+    // io_uring instances, whose SQEs are read from ring memory at io_uring_enter.
+    // Rings live in an address space, so they're keyed by mm owner and ring fd
+    rings: HashMap<(i32, i32), Ring<UringOp>>, // (mm owner, ring fd) -> ring
+    registered_rings: HashMap<(i32, u32), i32>, // (pid, index) -> ring fd
+    pending_urings: HashMap<i32, PendingUring>, // pid -> io_uring call awaiting exit
+
+    // Optional per-event callback (Tracer::on_event, e.g. the --events log)
+    on_event: Option<EventHook>,
+    // Optional periodic report of the trace so far (Tracer::on_checkpoint)
+    checkpoint: Option<Checkpoint>,
+    // Optional per-syscall callback (Tracer::on_syscall, --format strace), and
+    // each pid's current syscall with its arguments rendered at entry
+    on_syscall: Option<SyscallHook>,
+    syscall_lines: HashMap<i32, (&'static str, String)>,
+
+    // Set with --resolve-symlinks; aliases map each spelling seen to the recorded path
+    symlinks: Option<SymlinkResolver>,
+    path_aliases: HashMap<String, String>,
+    scope_resolver: SymlinkResolver, // for paths outside --root that may resolve into it
+
+    // Which paths are reported (--include/--exclude)
+    filter: PathFilter,
+    anon_fds: bool,    // report memfd:[..] and anon:[..] pseudo-paths (--anon-fds)
+    hash_reads: bool,  // digest files when first opened for reading (--hash-reads)
+    hash_writes: bool, // digest written files once the trace ends (--hash-writes)
+    trace: Vec<TraceCategory>, // syscall categories decoded (--trace)
+    // With --max-paths, how many paths are held in memory and how many
+    // accesses went to the spill log (--spill) instead
+    path_limit: Option<PathLimit>,
+    spill: Option<EventLog>,
+
+    // With --deny-network, connects and sends it rejects are skipped and fail
+    network_policy: Option<NetworkPolicy>,
+    denied_syscalls: HashMap<i32, i32>, // pid -> errno for the skipped syscall's exit
+
+    // Set as soon as the root is forked or attached, independent of /proc capture
+    root_pid: Option<i32>,
+    exit_kill: bool,
+    seccomp: bool,      // launched tracees only stop at syscalls we trace (--seccomp)
+    follow_forks: bool, // trace the processes tracees start, not only their threads
+    no_follow: Vec<String>, // programs whose processes are detached at exec (--no-follow)
+    timeout: Option<Duration>,
+    env_redactor: EnvRedactor,
+    stats: Option<StatsCollector>, // only with --stats
+    interrupted: bool,
+    timed_out: bool,
+}
+
+/// Callback given the report so far (`Tracer::on_checkpoint`).
+type CheckpointHook = Box<dyn FnMut(&TraceReport)>;
+
+/// When and how to hand out the report-so-far while tracing.
+struct Checkpoint {
+    interval: Duration,
+    last: Instant,
+    start_time: f64,
+    hook: CheckpointHook,
+}
+
+impl TracerState {
+    fn new(options: TracerConfig, on_event: Option<EventHook>) -> Self {
+        let mut trace = options.trace;
+        // Network calls can't be denied without being decoded
+        if options.deny_network.is_some() && !trace.contains(&TraceCategory::Network) {
+            trace.push(TraceCategory::Network);
+        }
+        trace.sort();
+        trace.dedup();
+        TracerState {
+            processes: HashMap::new(),
+            fd_tables: HashMap::new(),
+            fd_table_ids: HashMap::new(),
+            next_fd_table_id: 0,
+            in_syscall: HashMap::new(),
+            syscall_info_supported: true,
+            pending_opens: HashMap::new(),
+            pending_execs: HashMap::new(),
+            pending_sockets: HashMap::new(),
+            pending_accesses: HashMap::new(),
+            active_pids: HashSet::new(),
+            early_children: HashSet::new(),
+            started: Instant::now(),
+            start_time: now_secs(),
+            now: 0.0,
+            files: HashMap::new(),
+            stat_files: HashSet::new(),
+            missing_files: HashSet::new(),
+            failed_opens: HashMap::new(),
+            metadata_changed_files: HashSet::new(),
+            created_dirs: HashSet::new(),
+            removed_dirs: HashSet::new(),
+            deleted_files: HashSet::new(),
+            listed_dirs: HashSet::new(),
+            synced_files: HashSet::new(),
+            unix_sockets: HashSet::new(),
+            links: HashMap::new(),
+            readlinks: HashMap::new(),
+            pending_readlinks: HashMap::new(),
+            mappings: HashMap::new(),
+            address_spaces: HashMap::new(),
+            pending_mmaps: HashMap::new(),
+            roots: HashMap::new(),
+            cwds: HashMap::new(),
+            fs_owners: HashMap::new(),
+            network_connections: Vec::new(),
+            resolved_hostnames: BTreeSet::new(),
+            network_traffic: HashMap::new(),
+            pending_transfers: HashMap::new(),
+            process_files: HashMap::new(),
+            pipe_reads: HashMap::new(),
+            pipe_writes: HashMap::new(),
+            shared_memory: HashMap::new(),
+            fifos: HashSet::new(),
+            pending_pipes: HashMap::new(),
+            pending_dups: HashMap::new(),
+            pending_closes: HashMap::new(),
+            pending_recvmsgs: HashMap::new(),
+            passed_fds: HashMap::new(),
+            rings: HashMap::new(),
+            registered_rings: HashMap::new(),
+            pending_urings: HashMap::new(),
+            on_event,
+            checkpoint: None,
+            on_syscall: None,
+            syscall_lines: HashMap::new(),
+            filter: options.filter,
+            anon_fds: options.anon_fds,
+            hash_reads: options.hash_reads,
+            hash_writes: options.hash_writes,
+            trace,
+            path_limit: options.max_paths.map(|max_paths| PathLimit {
+                max_paths,
+                spilled_events: 0,
+            }),
+            spill: options.spill,
+            network_policy: options.deny_network,
+            denied_syscalls: HashMap::new(),
+            symlinks: options.resolve_symlinks.then(SymlinkResolver::default),
+            path_aliases: HashMap::new(),
+            scope_resolver: SymlinkResolver::default(),
+            root_pid: None,
+            exit_kill: options.exit_kill,
+            // Untraced processes can't run under the filter: its traps fail with ENOSYS
+            seccomp: options.seccomp && options.follow_forks && options.no_follow.is_empty(),
+            follow_forks: options.follow_forks,
+            no_follow: options.no_follow,
+            timeout: options.timeout,
+            env_redactor: options.env_redactor,
+            stats: options.stats.then(StatsCollector::new),
+            interrupted: false,
+            timed_out: false,
+        }
+    }
+
+    /// Give the checkpoint hook a report of the trace so far, if one is due.
+    fn checkpoint(&mut self) {
+        let Some(checkpoint) = self.checkpoint.as_mut() else {
+            return;
+        };
+        if checkpoint.last.elapsed() < checkpoint.interval {
+            return;
+        }
+        let start_time = checkpoint.start_time;
+        let report = build_report(self, start_time, 0, true);
+        if let Some(checkpoint) = self.checkpoint.as_mut() {
+            (checkpoint.hook)(&report);
+            // Timed from the end, so a slow write can't leave the trace checkpointing nonstop
+            checkpoint.last = Instant::now();
+        }
+    }
+
+    /// Start the on_syscall line for the syscall `pid` is entering.
+    fn begin_syscall_line(&mut self, pid: Pid, regs: &SyscallRegs) {
+        if self.on_syscall.is_none() {
+            return;
+        }
+        let Some(&(_, name)) = SYSCALL_NAMES.iter().find(|(nr, _)| *nr == regs.orig_rax) else {
+            return;
+        };
+        let args = strace::render_args(
+            regs.orig_rax,
+            regs,
+            |addr| read_string_from_tracee(pid, addr),
+            |addr| read_string_array_from_tracee(pid, addr),
+        );
+        self.syscall_lines.insert(pid.as_raw(), (name, args));
+    }
+
+    /// Hand the syscall `pid` is leaving to on_syscall, with its result.
+    fn end_syscall_line(&mut self, pid: i32, result: i64) {
+        let (Some(hook), Some((name, args))) =
+            (self.on_syscall.as_mut(), self.syscall_lines.remove(&pid))
+        else {
+            return;
+        };
+        hook(&SyscallRecord {
+            timestamp: now_secs(),
+            pid,
+            name,
+            args: &args,
+            result,
+        });
+    }
+
+    fn emit(&mut self, pid: i32, event: &TraceEvent) {
+        if let Some(hook) = self.on_event.as_mut() {
+            hook(&EventRecord {
+                timestamp: now_secs(),
+                pid,
+                event,
+            });
+        }
+    }
+
+    fn record_open(&mut self, pid: i32, fd: i32, open: PendingOpen) {
+        // The fd table tracks every file, filtered or not, so dup'd fds keep working
+        self.fd_table_mut(pid).insert(fd, open.path.clone());
+        self.record_opened(pid, fd, open);
+    }
+
+    /// A successful open; fd is -1 for io_uring direct descriptors, which have none.
+    fn record_opened(&mut self, pid: i32, fd: i32, open: PendingOpen) {
+        let Some(path) = self.admit(open.path) else {
+            return;
+        };
+        let event = TraceEvent::Open { path: &path, fd };
+        self.emit(pid, &event);
+        if is_shared_memory(&path) {
+            let shm = self.shared_memory.entry(path).or_default();
+            shm.openers.insert(pid);
+            shm.created |= open.creates;
+            shm.unlinked = false;
+            return;
+        }
+        if self.spilled(pid, &event) {
+            return;
+        }
+        self.deleted_files.remove(&path);
+        self.process_files(pid).opened.insert(path.clone());
+        // The tracee's fd reaches the file whatever its root or mount namespace
+        let opened = match fd {
+            -1 => PathBuf::from(&path),
+            fd => PathBuf::from(format!("/proc/{}/fd/{}", pid, fd)),
+        };
+        let digest = (self.hash_reads
+            && is_first_read_open(&self.files, &path, open.flags, open.creates))
+        .then(|| digest::sha256_file(&opened))
+        .flatten();
+        let metadata = self
+            .needs_metadata(&path)
+            .then(|| file_metadata(&opened, open.creates, self.start_time))
+            .flatten();
+        let stats = self.file_stats(pid, path);
+        stats.opened = true;
+        if digest.is_some() {
+            stats.read_sha256 = digest;
+        }
+        if metadata.is_some() {
+            stats.metadata = metadata;
+        }
+        stats.open_flags.extend(open_flag_names(open.flags));
+        if open.creates {
+            stats.created = true;
+            stats.mode = Some(open.mode);
+        }
+    }
+
+    /// An open seen by the fanotify backend, which learns neither fd nor flags.
+    fn record_watched_open(&mut self, pid: i32, path: String) {
+        let Some(path) = self.admit(path) else {
+            return;
+        };
+        let event = TraceEvent::Open {
+            path: &path,
+            fd: -1,
+        };
+        self.emit(pid, &event);
+        if is_shared_memory(&path) {
+            self.shared_memory
+                .entry(path)
+                .or_default()
+                .openers
+                .insert(pid);
+        } else if !self.spilled(pid, &event) {
+            self.process_files(pid).opened.insert(path.clone());
+            // fanotify doesn't say how the file was opened, so any first open counts
+            let digest = (self.hash_reads && !self.files.contains_key(&path))
+                .then(|| digest::sha256_file(&path))
+                .flatten();
+            let metadata = self
+                .needs_metadata(&path)
+                .then(|| file_metadata(Path::new(&path), false, self.start_time))
+                .flatten();
+            let stats = self.file_stats(pid, path);
+            stats.opened = true;
+            if digest.is_some() {
+                stats.read_sha256 = digest;
+            }
+            if metadata.is_some() {
+                stats.metadata = metadata;
+            }
+        }
+    }
+
+    fn traces(&self, category: TraceCategory) -> bool {
+        self.trace.contains(&category)
+    }
+
+    /// Whether syscall `nr` is decoded: it is bookkeeping or in a traced category.
+    fn decodes(&self, nr: u64) -> bool {
+        let categories = TraceCategory::of(nr);
+        categories.is_empty() || categories.iter().any(|c| self.traces(*c))
+    }
+
+    /// A read of `bytes` (0 where the syscall doesn't say, e.g. mmap).
+    fn record_read(&mut self, pid: i32, path: String, bytes: u64) {
+        // Reads and writes of sockets are network I/O, anything else file I/O
+        if !net::is_socket_label(&path) && !self.traces(TraceCategory::File) {
+            return;
+        }
+        let Some(path) = self.admit(path) else {
+            return;
+        };
+        let event = TraceEvent::Read { path: &path };
+        self.emit(pid, &event);
+        // Socket I/O is covered by network_connections, pipe I/O by pipe_reads
+        if self.is_pipe(&path) {
+            self.pipe_reads.entry(pid).or_default().insert(path);
+        } else if is_shared_memory(&path) {
+            self.shared_memory
+                .entry(path)
+                .or_default()
+                .readers
+                .insert(pid);
+        } else if !net::is_socket_label(&path) && !self.spilled(pid, &event) {
+            self.process_files(pid).read.insert(path.clone());
+            let stats = self.file_stats(pid, path);
+            stats.read = true;
+            stats.read_count += 1;
+            stats.bytes_read += bytes;
+        }
+    }
+
+    fn record_write(&mut self, pid: i32, path: String, bytes: u64) {
+        if !net::is_socket_label(&path) && !self.traces(TraceCategory::File) {
+            return;
+        }
+        let Some(path) = self.admit(path) else {
+            return;
+        };
+        let event = TraceEvent::Write { path: &path };
+        self.emit(pid, &event);
+        if self.is_pipe(&path) {
+            self.pipe_writes.entry(pid).or_default().insert(path);
+        } else if is_shared_memory(&path) {
+            self.shared_memory
+                .entry(path)
+                .or_default()
+                .writers
+                .insert(pid);
+        } else if !net::is_socket_label(&path) && !self.spilled(pid, &event) {
+            self.process_files(pid).written.insert(path.clone());
+            let stats = self.file_stats(pid, path);
+            stats.written = true;
+            stats.write_count += 1;
+            stats.bytes_written += bytes;
+        }
+    }
+
+    /// Apply --resolve-symlinks and the path filter to a path about to be recorded.
+    fn admit(&mut self, path: String) -> Option<String> {
+        // Unnamed files have nothing to record until linkat names them; memfds,
+        // like eventfds and timerfds, are reported under their pseudo-path on request
+        let shown = self.anon_fds && is_anon_fd(&path);
+        if (is_unnamed_file(&path) || is_anon_fd(&path)) && !shown {
+            return None;
+        }
+        let path = match self.symlinks.as_mut() {
+            Some(resolver) if path.starts_with('/') => match resolver.resolve(&path) {
+                Some(resolved) => {
+                    self.path_aliases.insert(path, resolved.clone());
+                    resolved
+                }
+                None => path,
+            },
+            _ => path,
+        };
+        // Outside the root, a path can still lead into it through a symlinked directory
+        let path = if self.filter.in_scope(&path) {
+            path
+        } else {
+            let resolved = self.scope_resolver.resolve(&path)?;
+            if !self.filter.in_scope(&resolved) {
+                return None;
+            }
+            self.path_aliases.insert(path, resolved.clone());
+            resolved
+        };
+        self.filter.allows(&path).then_some(path)
+    }
+
+    /// Stats entry for a path, stamped with the current syscall's time.
+    fn file_stats(&mut self, pid: i32, path: String) -> &mut FileStats {
+        let now = self.now;
+        let stats = self.files.entry(path).or_insert(FileStats {
+            first_access: now,
+            first_pid: pid,
+            ..FileStats::default()
+        });
+        stats.last_access = now;
+        stats
+    }
+
+    /// Past max_paths, whether an access to a path not already held went to
+    /// the spill log (if any) instead of into memory, where only its count is kept.
+    fn spilled(&mut self, pid: i32, event: &TraceEvent) -> bool {
+        let Some(limit) = self.path_limit.as_ref() else {
+            return false;
+        };
+        let held = match event {
+            TraceEvent::Stat { path } => self.stat_files.contains(*path),
+            TraceEvent::Missing { path } => self.missing_files.contains(*path),
+            TraceEvent::Open { path, .. }
+            | TraceEvent::Read { path }
+            | TraceEvent::Write { path }
+            | TraceEvent::Rename { path }
+            | TraceEvent::Link { path, .. } => self.files.contains_key(*path),
+            _ => true,
+        };
+        let paths = self.files.len() + self.stat_files.len() + self.missing_files.len();
+        if held || paths < limit.max_paths {
+            return false;
+        }
+        if let Some(log) = self.spill.as_mut() {
+            log.emit(&EventRecord {
+                timestamp: now_secs(),
+                pid,
+                event,
+            });
+        }
+        if let Some(limit) = self.path_limit.as_mut() {
+            limit.spilled_events += 1;
+        }
+        true
+    }
+
+    /// Whether no open of the path has been stat'ed yet.
+    fn needs_metadata(&self, path: &str) -> bool {
+        self.files.get(path).is_none_or(|s| s.metadata.is_none())
+    }
+
+    fn process_files(&mut self, pid: i32) -> &mut ProcessFiles {
+        let now = self.now;
+        let files = self.process_files.entry(pid).or_insert(ProcessFiles {
+            first_access: now,
+            ..ProcessFiles::default()
+        });
+        files.last_access = now;
+        files
+    }
+
+    /// Whether an fd-table entry is an anonymous pipe or a named FIFO.
+    fn is_pipe(&self, path: &str) -> bool {
+        path.starts_with("pipe:[") || self.fifos.contains(path)
+    }
+
+    /// Stash an access seen at syscall entry until the exit tells us whether it happened.
+    fn defer_access(&mut self, pid: i32, access: Access, path: String) {
+        self.pending_accesses
+            .entry(pid)
+            .or_default()
+            .push((access, path));
+    }
+
+    /// An access whose syscall succeeded, moving `bytes` if it's a read or write.
+    fn record_access(&mut self, pid: i32, access: Access, path: String, bytes: u64) {
+        match access {
+            Access::Read => self.record_read(pid, path, bytes),
+            Access::Write => self.record_write(pid, path, bytes),
+            Access::Rename => self.record_rename(pid, path),
+            Access::Stat => self.record_stat(pid, path),
+            Access::Metadata => self.record_metadata_change(pid, path),
+            Access::Mkdir => self.record_mkdir(pid, path),
+            Access::Rmdir => self.record_rmdir(pid, path),
+            Access::Delete => self.record_delete(pid, path),
+            Access::List => self.record_list(pid, path),
+            Access::Sync => self.record_sync(pid, path),
+            Access::Link { target } => self.record_link(pid, path, target),
+            Access::Named { owner, fd } => self.record_named(pid, path, owner, fd),
+        }
+    }
+
+    /// A new link is a written entry; its target is what it depends on.
+    fn record_link(&mut self, pid: i32, path: String, target: String) {
+        let Some(path) = self.admit(path) else {
+            return;
+        };
+        let event = TraceEvent::Link {
+            path: &path,
+            target: &target,
+        };
+        self.emit(pid, &event);
+        if self.spilled(pid, &event) {
+            return;
+        }
+        self.deleted_files.remove(&path);
+        self.process_files(pid).written.insert(path.clone());
+        self.file_stats(pid, path.clone()).written = true;
+        self.links.insert(path, target);
+    }
+
+    /// An O_TMPFILE or memfd file was linked into place: it is the file its
+    /// writes really went to, and later writes through the fd go there too.
+    fn record_named(&mut self, pid: i32, path: String, owner: i32, fd: i32) {
+        self.fd_table_mut(owner).insert(fd, path.clone());
+        let Some(path) = self.admit(path) else {
+            return;
+        };
+        let event = TraceEvent::Write { path: &path };
+        self.emit(pid, &event);
+        if self.spilled(pid, &event) {
+            return;
+        }
+        self.process_files(pid).written.insert(path.clone());
+        let stats = self.file_stats(pid, path);
+        stats.created = true;
+        stats.written = true;
+        stats.write_count += 1;
+    }
+
+    fn record_readlink(&mut self, pid: i32, path: String, target: String) {
+        let Some(path) = self.admit(path) else {
+            return;
+        };
+        self.emit(
+            pid,
+            &TraceEvent::Readlink {
+                path: &path,
+                target: &target,
+            },
+        );
+        self.readlinks.insert(path, target);
+    }
+
+    fn record_mkdir(&mut self, pid: i32, path: String) {
+        let Some(path) = self.admit(path) else {
+            return;
+        };
+        self.emit(pid, &TraceEvent::Mkdir { path: &path });
+        self.removed_dirs.remove(&path);
+        self.created_dirs.insert(path);
+    }
+
+    fn record_rmdir(&mut self, pid: i32, path: String) {
+        let Some(path) = self.admit(path) else {
+            return;
+        };
+        self.emit(pid, &TraceEvent::Rmdir { path: &path });
+        self.removed_dirs.insert(path);
+    }
+
+    fn record_delete(&mut self, pid: i32, path: String) {
+        let Some(path) = self.admit(path) else {
+            return;
+        };
+        self.emit(pid, &TraceEvent::Delete { path: &path });
+        if is_shared_memory(&path) {
+            self.shared_memory.entry(path).or_default().unlinked = true;
+            return;
+        }
+        self.deleted_files.insert(path);
+    }
+
+    fn record_list(&mut self, pid: i32, path: String) {
+        let Some(path) = self.admit(path) else {
+            return;
+        };
+        if self.listed_dirs.insert(path.clone()) {
+            // One event per directory, not per getdents batch
+            self.emit(pid, &TraceEvent::List { path: &path });
+        }
+    }
+
+    fn record_sync(&mut self, pid: i32, path: String) {
+        let Some(path) = self.admit(path) else {
+            return;
+        };
+        // Only files: fsync on a pipe or socket is a no-op
+        if self.is_pipe(&path) || net::is_socket_label(&path) {
+            return;
+        }
+        if self.synced_files.insert(path.clone()) {
+            self.emit(pid, &TraceEvent::Sync { path: &path });
+        }
+    }
+
+    fn record_unix_socket(&mut self, pid: i32, path: String) {
+        let Some(path) = self.admit(path) else {
+            return;
+        };
+        if self.unix_sockets.insert(path.clone()) {
+            self.emit(pid, &TraceEvent::Connect { path: &path });
+        }
+    }
+
+    fn record_metadata_change(&mut self, pid: i32, path: String) {
+        let Some(path) = self.admit(path) else {
+            return;
+        };
+        self.emit(pid, &TraceEvent::Metadata { path: &path });
+        self.metadata_changed_files.insert(path);
+    }
+
+    fn record_stat(&mut self, pid: i32, path: String) {
+        let Some(path) = self.admit(path) else {
+            return;
+        };
+        let event = TraceEvent::Stat { path: &path };
+        self.emit(pid, &event);
+        if self.spilled(pid, &event) {
+            return;
+        }
+        self.stat_files.insert(path);
+    }
+
+    /// A path that was looked for and found absent (ENOENT).
+    fn record_missing(&mut self, pid: i32, path: String) {
+        let Some(path) = self.admit(path) else {
+            return;
+        };
+        let event = TraceEvent::Missing { path: &path };
+        self.emit(pid, &event);
+        if self.spilled(pid, &event) {
+            return;
+        }
+        self.missing_files.insert(path);
+    }
+
+    fn record_failed_open(&mut self, path: String, errno: i32) {
+        if let Some(path) = self.admit(path) {
+            self.failed_opens.insert(path, errno);
+        }
+    }
+
+    /// The destination of a rename is effectively written.
+    fn record_rename(&mut self, pid: i32, path: String) {
+        let Some(path) = self.admit(path) else {
+            return;
+        };
+        let event = TraceEvent::Rename { path: &path };
+        self.emit(pid, &event);
+        if self.spilled(pid, &event) {
+            return;
+        }
+        self.deleted_files.remove(&path);
+        self.process_files(pid).written.insert(path.clone());
+        self.file_stats(pid, path).written = true;
+    }
+
+    /// Look up the path behind a pid's fd, going through its (possibly shared) table.
+    fn fd_path(&self, pid: i32, fd: i32) -> Option<String> {
+        let table_id = self.fd_table_ids.get(&pid)?;
+        self.fd_tables.get(table_id)?.get(&fd).cloned()
+    }
+
+    /// Get the fd table for a pid, creating an empty private one if it has none yet.
+    fn fd_table_mut(&mut self, pid: i32) -> &mut HashMap<i32, String> {
+        let table_id = match self.fd_table_ids.get(&pid) {
+            Some(id) => *id,
+            None => {
+                let id = self.alloc_fd_table(HashMap::new());
+                self.fd_table_ids.insert(pid, id);
+                id
+            }
+        };
+        self.fd_tables.entry(table_id).or_default()
+    }
+
+    /// The pid whose entry in `mappings` describes this pid's address space.
+    fn mm_owner(&self, pid: i32) -> i32 {
+        self.address_spaces.get(&pid).copied().unwrap_or(pid)
+    }
+
+    /// The pid whose entry in `cwds` holds this pid's working directory.
+    fn fs_owner(&self, pid: i32) -> i32 {
+        self.fs_owners.get(&pid).copied().unwrap_or(pid)
+    }
+
+    fn alloc_fd_table(&mut self, table: HashMap<i32, String>) -> u64 {
+        let id = self.next_fd_table_id;
+        self.next_fd_table_id += 1;
+        self.fd_tables.insert(id, table);
+        id
+    }
+}
+
+// =============================================================================
+// Reading tracee memory
+// =============================================================================
+
+/// Longest path or argument read from a tracee.
+const MAX_TRACEE_STRING: usize = 4096;
+
+#[cfg(feature = "ebpf")]
+thread_local! {
+    /// What the eBPF probes copied from tracee memory at the entry of the
+    /// syscall being handled. It is read first: the process has moved on.
+    static CAPTURED: std::cell::RefCell<ebpf::Captured> = Default::default();
+}
+
+/// Copy up to `buf.len()` bytes at `addr` with process_vm_readv, one syscall
+/// instead of a PTRACE_PEEKDATA per word. Stops short at an unmapped page;
+/// None if nothing could be read (or the kernel refuses, e.g. under some LSMs).
+fn vm_read(pid: Pid, addr: u64, buf: &mut [u8]) -> Option<usize> {
+    let local = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    let remote = libc::iovec {
+        iov_base: addr as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let n = unsafe { libc::process_vm_readv(pid.as_raw(), &local, 1, &remote, 1, 0) };
+    (n > 0).then_some(n as usize)
+}
+
+/// Bytes from `addr` to the end of its page, so a read never crosses into an
+/// unmapped one.
+fn page_remainder(addr: u64) -> usize {
+    4096 - (addr % 4096) as usize
+}
+
+fn read_string_from_tracee(pid: Pid, addr: u64) -> Option<String> {
+    if addr == 0 {
+        return None;
+    }
+    #[cfg(feature = "ebpf")]
+    if let Some(string) = CAPTURED.with_borrow(|c| c.string(addr).map(String::from)) {
+        return Some(string);
+    }
+
+    let mut bytes = Vec::new();
+    let mut buf = [0u8; 4096];
+    let mut current = addr;
+    while bytes.len() <= MAX_TRACEE_STRING {
+        let chunk = &mut buf[..page_remainder(current)];
+        let Some(n) = vm_read(pid, current, chunk) else {
+            // Only the first read may fail over to ptrace: a later page that
+            // can't be read ends the string for both
+            return if bytes.is_empty() {
+                peek_string_from_tracee(pid, addr)
+            } else {
+                None
+            };
+        };
+        if let Some(nul) = chunk[..n].iter().position(|&b| b == 0) {
+            bytes.extend_from_slice(&chunk[..nul]);
+            if bytes.len() > MAX_TRACEE_STRING {
+                return None;
+            }
+            stats::count_read(bytes.len());
+            return String::from_utf8(bytes).ok();
+        }
+        bytes.extend_from_slice(&chunk[..n]);
+        current += n as u64;
+    }
+    None // Safety limit
+}
+
+/// read_string_from_tracee a word at a time with PTRACE_PEEKDATA.
+fn peek_string_from_tracee(pid: Pid, addr: u64) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut current = addr;
+
+    loop {
+        let word = match ptrace::read(pid, current as *mut libc::c_void) {
+            Ok(w) => w,
+            Err(_) => return None,
+        };
+
+        for byte in word.to_ne_bytes() {
+            if byte == 0 {
+                stats::count_read(bytes.len());
+                return String::from_utf8(bytes).ok();
+            }
+            bytes.push(byte);
+            if bytes.len() > MAX_TRACEE_STRING {
+                return None; // Safety limit
+            }
+        }
+        current += 8;
+    }
+}
+
+/// Read `len` raw bytes from tracee memory (structs like sockaddr).
+fn read_bytes_from_tracee(pid: Pid, addr: u64, len: usize) -> Option<Vec<u8>> {
+    if addr == 0 {
+        return None;
+    }
+    #[cfg(feature = "ebpf")]
+    if let Some(bytes) = CAPTURED.with_borrow(|c| c.bytes(addr, len).map(<[u8]>::to_vec)) {
+        return Some(bytes);
+    }
+
+    let mut bytes = vec![0; len];
+    if vm_read(pid, addr, &mut bytes) == Some(len) {
+        stats::count_read(len);
+        return Some(bytes);
+    }
+
+    bytes.clear();
+    let mut current = addr;
+    while bytes.len() < len {
+        let word = ptrace::read(pid, current as *mut libc::c_void).ok()?;
+        bytes.extend_from_slice(&word.to_ne_bytes());
+        current += 8;
+    }
+    bytes.truncate(len);
+    stats::count_read(len);
+    Some(bytes)
+}
+
+/// Read a plain C struct (iovec, open_how, ...) laid out as in a 64-bit tracee.
+fn read_struct_from_tracee<T: Copy>(pid: Pid, addr: u64) -> Option<T> {
+    let bytes = read_bytes_from_tracee(pid, addr, std::mem::size_of::<T>())?;
+    // Safety: only instantiated with libc structs of integers and pointers,
+    // for which any bit pattern is valid
+    Some(unsafe { std::ptr::read_unaligned(bytes.as_ptr().cast::<T>()) })
+}
+
+/// Read a NULL-terminated array of string pointers (argv/envp) from tracee memory.
+fn read_string_array_from_tracee(pid: Pid, addr: u64) -> Vec<String> {
+    let mut strings = Vec::new();
+    if addr == 0 {
+        return strings;
+    }
+    #[cfg(feature = "ebpf")]
+    if let Some(strings) = CAPTURED.with_borrow(|c| c.string_array(addr).map(<[String]>::to_vec)) {
+        return strings;
+    }
+
+    let mut current = addr;
+    while strings.len() < 4096 {
+        let Some(ptr) = read_bytes_from_tracee(pid, current, 8)
+            .and_then(|word| word.try_into().ok())
+            .map(u64::from_ne_bytes)
+        else {
+            break;
+        };
+        if ptr == 0 {
+            break;
+        }
+        if let Some(s) = read_string_from_tracee(pid, ptr) {
+            strings.push(s);
+        }
+        current += 8;
+    }
+    strings
+}
+
+// =============================================================================
+// Process info capture
+// =============================================================================
+
+fn capture_process_info(pid: Pid, state: &mut TracerState, parent_pid: Option<i32>) {
+    let pid_raw = pid.as_raw();
+
+    // Read command line
+    let cmdline_path = format!("/proc/{}/cmdline", pid_raw);
+    let command = std::fs::read_to_string(&cmdline_path)
+        .map(|s| {
+            s.split('\0')
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // Read environment
+    let environ_path = format!("/proc/{}/environ", pid_raw);
+    let env: HashMap<String, String> = std::fs::read_to_string(&environ_path)
+        .map(|s| {
+            s.split('\0')
+                .filter_map(|entry| {
+                    let mut parts = entry.splitn(2, '=');
+                    match (parts.next(), parts.next()) {
+                        (Some(k), Some(v)) if !k.is_empty() => Some((k.to_string(), v.to_string())),
+                        _ => None,
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // Keep the exec history and lifetime across re-captures (exec recaptures the same pid)
+    let previous = state.processes.remove(&pid_raw);
+    let start_time = previous
+        .as_ref()
+        .map(|p| p.start_time)
+        .unwrap_or_else(now_secs);
+    let previous_io_uring = previous.as_ref().is_some_and(|p| p.io_uring_used);
+    let execs = previous.map(|p| p.execs).unwrap_or_default();
+
+    state.processes.insert(
+        pid_raw,
+        ProcessInfo {
+            pid: pid_raw,
+            parent_pid,
+            command,
+            env,
+            env_unset: Vec::new(),
+            execs,
+            start_time,
+            end_time: None,
+            exit_code: None,
+            term_signal: None,
+            cwd: read_cwd(pid_raw),
+            chroot: state.roots.get(&pid_raw).cloned(),
+            io_uring_used: previous_io_uring,
+            mnt_ns: read_mnt_ns(pid_raw),
+            detached: false,
+            thread_of: None,
+            rusage: None,
+            opened_files: BTreeSet::new(),
+            read_files: BTreeSet::new(),
+            written_files: BTreeSet::new(),
+            first_access: None,
+            last_access: None,
+        },
+    );
+}
+
+/// Record a thread `creator` started. It shares its process's command line
+/// and environment, so those come from the process's entry rather than /proc,
+/// and its environment is left empty in the report.
+fn capture_thread_info(tid: i32, creator: i32, state: &mut TracerState) {
+    let Some(process) = state.processes.get(&creator) else {
+        capture_process_info(Pid::from_raw(tid), state, Some(creator));
+        return;
+    };
+    let thread = ProcessInfo {
+        pid: tid,
+        parent_pid: Some(creator),
+        command: process.command.clone(),
+        env: HashMap::new(),
+        env_unset: Vec::new(),
+        execs: Vec::new(),
+        start_time: now_secs(),
+        end_time: None,
+        exit_code: None,
+        term_signal: None,
+        cwd: process.cwd.clone(),
+        chroot: state.roots.get(&tid).cloned(),
+        io_uring_used: false,
+        mnt_ns: process.mnt_ns,
+        detached: false,
+        thread_of: Some(process.thread_of.unwrap_or(creator)),
+        rusage: None,
+        opened_files: BTreeSet::new(),
+        read_files: BTreeSet::new(),
+        written_files: BTreeSet::new(),
+        first_access: None,
+        last_access: None,
+    };
+    state.processes.insert(tid, thread);
+}
+
+/// Working directory of a process, as the tracer sees it.
+fn read_cwd(pid: i32) -> Option<String> {
+    let cwd = std::fs::read_link(format!("/proc/{}/cwd", pid)).ok()?;
+    Some(cwd.to_string_lossy().to_string())
+}
+
+/// Mount namespace inode of a process ("mnt:[4026531841]" -> 4026531841).
+fn read_mnt_ns(pid: i32) -> Option<u64> {
+    let link = std::fs::read_link(format!("/proc/{}/ns/mnt", pid)).ok()?;
+    link.to_str()?
+        .strip_prefix("mnt:[")?
+        .strip_suffix(']')?
+        .parse()
+        .ok()
+}
+
+/// Parent pid as reported by /proc/<pid>/status (the real parent, not the tracer).
+fn read_parent_pid(pid: i32) -> Option<i32> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("PPid:"))
+        .and_then(|ppid| ppid.trim().parse().ok())
+        .filter(|&ppid| ppid > 0)
+}
+
+/// Record how a process finished. The first report wins: PTRACE_EVENT_EXIT
+/// arrives before the final wait status and both describe the same exit.
+fn record_process_exit(
+    pid: Pid,
+    exit_code: Option<i32>,
+    term_signal: Option<i32>,
+    state: &mut TracerState,
+) {
+    let pid_raw = pid.as_raw();
+    if !state.processes.contains_key(&pid_raw) {
+        // The child exited before we saw its fork event; record what we can
+        capture_process_info(pid, state, read_parent_pid(pid_raw));
+    }
+    if let Some(process) = state.processes.get_mut(&pid_raw) {
+        if process.end_time.is_none() {
+            process.end_time = Some(now_secs());
+            process.exit_code = exit_code;
+            process.term_signal = term_signal;
+            // Only there at PTRACE_EVENT_EXIT; once reaped, /proc has nothing left
+            process.rusage = read_rusage(pid_raw);
+        }
+    }
+}
+
+/// A tracee is gone: record how it ended and drop what it held.
+fn record_exited(pid: Pid, exit_code: Option<i32>, signal: Option<i32>, state: &mut TracerState) {
+    let pid_raw = pid.as_raw();
+    state.active_pids.remove(&pid_raw);
+    record_process_exit(pid, exit_code, signal, state);
+    release_fd_table(pid_raw, state);
+    release_mappings(pid_raw, state);
+    release_cwd(pid_raw, state);
+    state.roots.remove(&pid_raw);
+    state.in_syscall.remove(&pid_raw);
+    state.emit(pid_raw, &TraceEvent::Exit { exit_code, signal });
+}
+
+/// CPU time, peak memory and storage I/O of a process that is about to exit.
+fn read_rusage(pid: i32) -> Option<ResourceUsage> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The fields after the command name, which can hold spaces and parentheses
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) } as f64;
+    // utime and stime are fields 14 and 15, counting the pid as 1
+    let cpu_time = |i: usize| Some(fields.get(i - 3)?.parse::<u64>().ok()? as f64 / ticks);
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).unwrap_or_default();
+    let io = std::fs::read_to_string(format!("/proc/{}/io", pid)).unwrap_or_default();
+    let field = |text: &str, name: &str| {
+        text.lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|value| value.split_whitespace().next()?.parse().ok())
+            .unwrap_or(0)
+    };
+    Some(ResourceUsage {
+        user_time: cpu_time(14)?,
+        system_time: cpu_time(15)?,
+        max_rss_kb: field(&status, "VmHWM:"),
+        read_bytes: field(&io, "read_bytes:"),
+        write_bytes: field(&io, "write_bytes:"),
+    })
+}
+
+// =============================================================================
+// FD table management
+// =============================================================================
+
+fn clone_fd_table(parent_pid: i32, child_pid: i32, clone_flags: u64, state: &mut TracerState) {
+    if clone_flags & libc::CLONE_FILES as u64 != 0 {
+        // Threads (and CLONE_FILES children) share the parent's table: opens and
+        // closes in either task are visible to both.
+        state.fd_table_mut(parent_pid);
+        let table_id = state.fd_table_ids[&parent_pid];
+        state.fd_table_ids.insert(child_pid, table_id);
+    } else {
+        let parent_table = state.fd_table_mut(parent_pid).clone();
+        let table_id = state.alloc_fd_table(parent_table);
+        state.fd_table_ids.insert(child_pid, table_id);
+    }
+}
+
+/// Give a pid a private copy of its fd table (execve unshares CLONE_FILES tables).
+fn unshare_fd_table(pid: i32, state: &mut TracerState) {
+    let Some(&table_id) = state.fd_table_ids.get(&pid) else {
+        return;
+    };
+    let shared = state
+        .fd_table_ids
+        .iter()
+        .any(|(other, id)| *other != pid && *id == table_id);
+    if shared {
+        let table = state.fd_tables.get(&table_id).cloned().unwrap_or_default();
+        let new_id = state.alloc_fd_table(table);
+        state.fd_table_ids.insert(pid, new_id);
+    }
+}
+
+/// Detach a pid from its fd table, dropping the table once no task references it.
+fn release_fd_table(pid: i32, state: &mut TracerState) {
+    if let Some(table_id) = state.fd_table_ids.remove(&pid) {
+        if !state.fd_table_ids.values().any(|id| *id == table_id) {
+            state.fd_tables.remove(&table_id);
+        }
+    }
+}
+
+// =============================================================================
+// Memory mapping tracking
+// =============================================================================
+
+/// A new task shares its parent's address space with CLONE_VM, else gets a copy.
+fn clone_mappings(parent_pid: i32, child_pid: i32, clone_flags: u64, state: &mut TracerState) {
+    let owner = state.mm_owner(parent_pid);
+    if clone_flags & libc::CLONE_VM as u64 != 0 {
+        state.address_spaces.insert(child_pid, owner);
+    } else {
+        if let Some(mappings) = state.mappings.get(&owner).cloned() {
+            state.mappings.insert(child_pid, mappings);
+        }
+        // Ring memory is MAP_SHARED, so the child submits to the same rings
+        let rings: Vec<_> = (state.rings.iter())
+            .filter(|((o, _), _)| *o == owner)
+            .map(|((_, fd), ring)| (*fd, ring.clone()))
+            .collect();
+        for (fd, mut ring) in rings {
+            ring.pending.clear();
+            state.rings.insert((child_pid, fd), ring);
+        }
+    }
+}
+
+/// Drop a pid's view of its address space, and the mappings once no task uses them.
+fn release_mappings(pid: i32, state: &mut TracerState) {
+    let owner = state.mm_owner(pid);
+    state.address_spaces.remove(&pid);
+    let in_use = (owner != pid && state.active_pids.contains(&owner))
+        || state.address_spaces.values().any(|o| *o == owner);
+    state.registered_rings.retain(|(p, _), _| *p != pid);
+    if !in_use {
+        state.mappings.remove(&owner);
+        state.rings.retain(|(o, _), _| *o != owner);
+    }
+}
+
+/// Paths of shared file mappings overlapping [addr, addr + len).
+fn mapped_paths(pid: i32, addr: u64, len: u64, state: &TracerState) -> Vec<String> {
+    let end = addr.saturating_add(len);
+    let mut paths: Vec<String> = state
+        .mappings
+        .get(&state.mm_owner(pid))
+        .into_iter()
+        .flatten()
+        .filter(|m| m.shared && m.start < end && addr < m.end)
+        .map(|m| m.path.clone())
+        .collect();
+    paths.dedup();
+    paths
+}
+
+/// Forget mappings in [addr, addr + len), trimming or splitting partial overlaps.
+fn unmap_range(pid: i32, addr: u64, len: u64, state: &mut TracerState) {
+    let end = addr.saturating_add(len);
+    let owner = state.mm_owner(pid);
+    let Some(mappings) = state.mappings.get_mut(&owner) else {
+        return;
+    };
+    let mut kept = Vec::with_capacity(mappings.len());
+    for m in mappings.drain(..) {
+        if m.end <= addr || end <= m.start {
+            kept.push(m);
+            continue;
+        }
+        if m.start < addr {
+            kept.push(FileMapping {
+                end: addr,
+                ..m.clone()
+            });
+        }
+        if end < m.end {
+            kept.push(FileMapping { start: end, ..m });
+        }
+    }
+    *mappings = kept;
+}
+
+// =============================================================================
+// Working directory tracking
+// =============================================================================
+
+/// A new task shares its parent's working directory with CLONE_FS, else gets a copy.
+fn clone_cwd(parent_pid: i32, child_pid: i32, clone_flags: u64, state: &mut TracerState) {
+    let owner = state.fs_owner(parent_pid);
+    if clone_flags & libc::CLONE_FS as u64 != 0 {
+        state.fs_owners.insert(child_pid, owner);
+    } else if let Some(cwd) = state.cwds.get(&owner).cloned() {
+        state.cwds.insert(child_pid, cwd);
+    }
+}
+
+/// Give a pid a private copy of a working directory it shared (unshare(CLONE_FS)).
+fn unshare_cwd(pid: i32, state: &mut TracerState) {
+    if let Some(owner) = state.fs_owners.remove(&pid) {
+        if let Some(cwd) = state.cwds.get(&owner).cloned() {
+            state.cwds.insert(pid, cwd);
+        }
+    }
+}
+
+/// Record the cwd a successful chdir/fchdir left behind. The tracee is stopped,
+/// so /proc gives exactly the directory the kernel resolved.
+fn refresh_cwd(pid: i32, state: &mut TracerState) {
+    let Some(cwd) = read_cwd(pid) else {
+        return;
+    };
+    let owner = state.fs_owner(pid);
+    // Every task sharing it (CLONE_FS) moved too
+    let sharers: Vec<i32> = state
+        .processes
+        .keys()
+        .copied()
+        .filter(|&p| state.fs_owner(p) == owner && state.active_pids.contains(&p))
+        .collect();
+    for sharer in sharers {
+        if let Some(process) = state.processes.get_mut(&sharer) {
+            process.cwd = Some(cwd.clone());
+        }
+    }
+    state.cwds.insert(owner, cwd);
+}
+
+/// Drop a pid's working directory once no task shares it.
+fn release_cwd(pid: i32, state: &mut TracerState) {
+    let owner = state.fs_owner(pid);
+    state.fs_owners.remove(&pid);
+    let in_use = (owner != pid && state.active_pids.contains(&owner))
+        || state.fs_owners.values().any(|o| *o == owner);
+    if !in_use {
+        state.cwds.remove(&owner);
+    }
+}
+
+/// What /proc says an fd refers to, if it is a file, a pipe or an anonymous fd we label.
+fn proc_fd_label(pid: i32, fd: i32) -> Option<String> {
+    let target = std::fs::read_link(format!("/proc/{}/fd/{}", pid, fd)).ok()?;
+    let target = target.to_string_lossy().to_string();
+    if let Some(kind) = target.strip_prefix("anon_inode:") {
+        return anon_fd_label(kind.trim_matches(|c| c == '[' || c == ']'));
+    }
+    // memfds show up as "/memfd:<name> (deleted)"
+    if let Some(name) = target.strip_prefix("/memfd:") {
+        let name = name.strip_suffix(" (deleted)").unwrap_or(name);
+        return Some(format!("memfd:[{}]", name));
+    }
+    // O_TMPFILE files as "<dir>/#<inode> (deleted)" until linkat names them
+    if let Some((dir, name)) = target
+        .strip_suffix(" (deleted)")
+        .and_then(|t| t.rsplit_once('/'))
+    {
+        let inode = name.strip_prefix('#').unwrap_or_default();
+        if !inode.is_empty() && inode.bytes().all(|b| b.is_ascii_digit()) {
+            return Some(tmpfile_label(if dir.is_empty() { "/" } else { dir }));
+        }
+    }
+    // Skip other pseudo-files like "socket:[5678]"
+    (target.starts_with('/') || target.starts_with("pipe:[")).then_some(target)
+}
+
+/// Pseudo-path for an eventfd, timerfd or signalfd, e.g. "anon:[eventfd]".
+fn anon_fd_label(kind: &str) -> Option<String> {
+    matches!(kind, "eventfd" | "timerfd" | "signalfd").then(|| format!("anon:[{}]", kind))
+}
+
+/// Whether --hash-reads should digest a file at this open: the first readable
+/// one that leaves the contents as they were (no O_TRUNC, not just created).
+fn is_first_read_open(
+    files: &HashMap<String, FileStats>,
+    path: &str,
+    flags: u64,
+    creates: bool,
+) -> bool {
+    let flags = flags as i32;
+    files.get(path).is_none_or(|s| s.read_sha256.is_none())
+        && flags & libc::O_ACCMODE != libc::O_WRONLY
+        && flags & libc::O_TRUNC == 0
+        && !creates
+}
+
+/// Whether a syscall returns the number of bytes it read or wrote.
+fn moves_bytes(syscall_num: u64) -> bool {
+    matches!(
+        syscall_num,
+        SYS_READ
+            | SYS_PREAD64
+            | SYS_READV
+            | SYS_PREADV
+            | SYS_PREADV2
+            | SYS_WRITE
+            | SYS_PWRITE64
+            | SYS_WRITEV
+            | SYS_PWRITEV
+            | SYS_PWRITEV2
+            | SYS_SENDFILE
+            | SYS_SPLICE
+            | SYS_COPY_FILE_RANGE
+    )
+}
+
+/// stat of a just-opened file, through /proc/<pid>/fd/<n> where there is an fd.
+fn file_metadata(path: &Path, created: bool, start_time: f64) -> Option<FileMetadata> {
+    let meta = std::fs::metadata(path).ok()?;
+    let ctime = meta.ctime() as f64 + meta.ctime_nsec() as f64 / 1e9;
+    Some(FileMetadata {
+        size: meta.size(),
+        mtime: meta.mtime() as f64 + meta.mtime_nsec() as f64 / 1e9,
+        inode: meta.ino(),
+        device: meta.dev(),
+        // A file renamed into place keeps no O_CREAT, but the rename sets its ctime
+        existed_before: !created && ctime < start_time,
+    })
+}
+
+/// Whether a path is a POSIX shared memory object; glibc's shm_open and
+/// sem_open create them as files under /dev/shm.
+fn is_shared_memory(path: &str) -> bool {
+    path.strip_prefix("/dev/shm/")
+        .is_some_and(|name| !name.is_empty() && !name.contains('/'))
+}
+
+/// Whether an fd label is a memfd or anonymous-fd pseudo-path (see --anon-fds).
+fn is_anon_fd(label: &str) -> bool {
+    label.starts_with("memfd:[") || label.starts_with("anon:[")
+}
+
+/// The O_ACCMODE bits an fd was opened with, from /proc/<pid>/fdinfo.
+fn proc_fd_access_mode(pid: i32, fd: i32) -> Option<i32> {
+    let info = std::fs::read_to_string(format!("/proc/{}/fdinfo/{}", pid, fd)).ok()?;
+    let flags = info.lines().find_map(|l| l.strip_prefix("flags:"))?;
+    let flags = i32::from_str_radix(flags.trim(), 8).ok()?;
+    Some(flags & libc::O_ACCMODE)
+}
+
+/// Whether open flags ask for an O_TMPFILE (which includes O_DIRECTORY).
+fn is_tmpfile_open(flags: u64) -> bool {
+    let tmpfile = libc::O_TMPFILE as u64;
+    flags & tmpfile == tmpfile
+}
+
+/// Label of an unnamed O_TMPFILE file created in `dir`.
+fn tmpfile_label(dir: &str) -> String {
+    format!("tmpfile:[{}]", dir)
+}
+
+/// Whether an fd label stands for an O_TMPFILE or memfd file that has no name.
+fn is_unnamed_file(label: &str) -> bool {
+    label.starts_with("tmpfile:[") || label.starts_with("memfd:[")
+}
+
+/// The (pid, fd) of an unnamed file that linkat is naming, either as
+/// linkat(fd, "", ..., AT_EMPTY_PATH) or through a /proc/<pid>/fd/<n> path.
+fn unnamed_link_source(
+    pid: i32,
+    olddirfd: i32,
+    oldpath: &str,
+    flags: u64,
+    state: &TracerState,
+) -> Option<(i32, i32)> {
+    let (owner, fd) = if oldpath.is_empty() && flags & libc::AT_EMPTY_PATH as u64 != 0 {
+        (pid, olddirfd)
+    } else {
+        let (proc_dir, fd) = oldpath.strip_prefix("/proc/")?.split_once("/fd/")?;
+        let owner = match proc_dir {
+            "self" | "thread-self" => pid,
+            other => other.parse().ok()?,
+        };
+        (owner, fd.parse().ok()?)
+    };
+    let label = state.fd_path(owner, fd)?;
+    is_unnamed_file(&label).then_some((owner, fd))
+}
+
+/// Bring a pid's fd table in line with /proc after exec: close-on-exec fds are
+/// gone, and fds we never saw being created (e.g. from a foreign process) appear.
+fn refresh_fd_table(pid: i32, state: &mut TracerState) {
+    let live: HashSet<i32> = read_proc_ids(&format!("/proc/{}/fd", pid))
+        .into_iter()
+        .collect();
+    let table = state.fd_table_mut(pid);
+    table.retain(|fd, _| live.contains(fd));
+    let missing: Vec<i32> = live
+        .into_iter()
+        .filter(|fd| !table.contains_key(fd))
+        .collect();
+    for fd in missing {
+        if let Some(label) = proc_fd_label(pid, fd) {
+            state.fd_table_mut(pid).insert(fd, label);
+        }
+    }
+}
+
+/// Read the clone flags of the clone/clone3 call a tracee is stopped in.
+///
+/// Returns 0 for fork/vfork, which is the right answer for fd-table purposes.
+fn read_clone_flags(pid: Pid) -> u64 {
+    let Some(regs) = Registers::read(pid)
+        .ok()
+        .and_then(|raw| raw.syscall(pid, None))
+    else {
+        return 0;
+    };
+
+    match regs.orig_rax {
+        SYS_CLONE => regs.rdi,
+        // clone3(struct clone_args *args, size_t size): flags is the first u64 field
+        SYS_CLONE3 => ptrace::read(pid, regs.rdi as *mut libc::c_void)
+            .map(|w| w as u64)
+            .unwrap_or(0),
+        _ => 0,
+    }
+}
+
+// =============================================================================
+// Syscall handling
+// =============================================================================
+
+fn handle_syscall(pid: Pid, state: &mut TracerState) {
+    let pid_raw = pid.as_raw();
+
+    let raw = match Registers::read(pid) {
+        Ok(r) => r,
+        Err(_) => {
+            stats::count_error();
+            return;
+        }
+    };
+
+    // One timestamp per stop, shared by everything recorded for it
+    state.now = state.started.elapsed().as_secs_f64();
+    let info = syscall_info(pid, state);
+    let is_entry = match info.map(|i| i.op) {
+        Some(libc::PTRACE_SYSCALL_INFO_ENTRY | libc::PTRACE_SYSCALL_INFO_SECCOMP) => true,
+        Some(libc::PTRACE_SYSCALL_INFO_EXIT) => false,
+        _ => match state.in_syscall.get(&pid_raw) {
+            // Only an exit of the syscall we saw enter; anything else means a
+            // stop was missed, so resync by treating this one as a new entry.
+            Some(&(pending, _)) => pending != raw.syscall_number(),
+            // Not inside a syscall as far as we know (fresh pid, after attach or exec)
+            None => raw.looks_like_entry(),
+        },
+    };
+
+    // Foreign ABIs (i386, x32, aarch64, riscv64) become the equivalent x86_64 stop
+    let regs = raw.syscall(pid, info.map(|i| i.arch));
+    let regs = if is_entry {
+        state
+            .in_syscall
+            .insert(pid_raw, (raw.syscall_number(), regs));
+        regs
+    } else {
+        let entry = state
+            .in_syscall
+            .remove(&pid_raw)
+            .and_then(|(_, entry)| entry);
+        // A syscall skipped at entry: all that's left is to give it its error
+        if let Some(errno) = state.denied_syscalls.remove(&pid_raw) {
+            if raw.set_return(pid, -(errno as i64)).is_err() {
+                stats::count_error();
+            }
+            state.end_syscall_line(pid_raw, -(errno as i64));
+            return;
+        }
+        // Exit handlers get the arguments as they were at entry; aarch64 and
+        // riscv64 overwrite the first one with the return value
+        match (regs, entry) {
+            (Some(exit), Some(entry)) if exit.orig_rax == entry.orig_rax => Some(SyscallRegs {
+                rax: exit.rax,
+                ..entry
+            }),
+            _ => regs,
+        }
+    };
+    let Some(regs) = regs else {
+        // Not translated, but not to get past --deny-network either
+        #[cfg(target_arch = "x86_64")]
+        if is_entry && state.network_policy.is_some() {
+            if let Some(send) = raw.compat_send(pid, info.map(|i| i.arch)) {
+                deny_destinations(pid, send.fd, send.operation, send.destinations, state);
+            }
+        }
+        return;
+    };
+    let syscall_num = regs.orig_rax;
+
+    if is_entry {
+        if let Some(stats) = state.stats.as_mut() {
+            stats.count_syscall(syscall_num);
+        }
+        // Nothing is pending for its exit either, so that is left alone
+        if !state.decodes(syscall_num) {
+            return;
+        }
+        state.begin_syscall_line(pid, &regs);
+        handle_syscall_entry(pid, syscall_num, &regs, state);
+    } else {
+        handle_syscall_exit(pid, syscall_num, &regs, state);
+        state.end_syscall_line(pid_raw, regs.rax as i64);
+    }
+}
+
+/// PTRACE_GET_SYSCALL_INFO for the current stop, if the kernel supports it (5.3+).
+fn syscall_info(pid: Pid, state: &mut TracerState) -> Option<libc::ptrace_syscall_info> {
+    if !state.syscall_info_supported {
+        return None;
+    }
+    let mut info: libc::ptrace_syscall_info = unsafe { std::mem::zeroed() };
+    let ret = unsafe {
+        libc::ptrace(
+            libc::PTRACE_GET_SYSCALL_INFO,
+            pid.as_raw(),
+            std::mem::size_of::<libc::ptrace_syscall_info>(),
+            &mut info as *mut libc::ptrace_syscall_info,
+        )
+    };
+    if ret <= 0 {
+        if nix::errno::Errno::last() == nix::errno::Errno::EIO {
+            state.syscall_info_supported = false;
+            eprintln!(
+                "Warning: PTRACE_GET_SYSCALL_INFO is unavailable; \
+                 telling syscall entry from exit by the syscall number pending per thread"
+            );
+        }
+        return None;
+    }
+    Some(info)
+}
+
+fn handle_syscall_entry(pid: Pid, syscall_num: u64, regs: &SyscallRegs, state: &mut TracerState) {
+    let pid_raw = pid.as_raw();
+
+    // Blocked calls never run, so there is nothing else to record for them
+    if state.network_policy.is_some() && deny_network(pid, syscall_num, regs, state) {
+        return;
+    }
+
+    match syscall_num {
+        SYS_OPEN => {
+            // open(path, flags, mode): rdi=path, rsi=flags, rdx=mode
+            if let Some(path) = read_string_from_tracee(pid, regs.rdi) {
+                let abs_path = resolve_path(&path, pid_raw, state);
+                let open = PendingOpen::new(abs_path, regs.rsi, regs.rdx);
+                state.pending_opens.insert(pid_raw, open);
+            }
+        }
+        SYS_OPENAT => {
+            // openat(dirfd, path, flags, mode): rdi=dirfd, rsi=path, rdx=flags, r10=mode
+            if let Some(path) = read_string_from_tracee(pid, regs.rsi) {
+                let abs_path = resolve_path_at(regs.rdi as i32, &path, pid_raw, state);
+                let open = PendingOpen::new(abs_path, regs.rdx, regs.r10);
+                state.pending_opens.insert(pid_raw, open);
+            }
+        }
+        SYS_OPENAT2 => {
+            // openat2(dirfd, path, struct open_how *, size)
+            let (flags, mode, resolve) = read_open_how(pid, regs.rdx);
+            if let Some(path) = read_string_from_tracee(pid, regs.rsi) {
+                let abs_path = resolve_openat2_path(regs.rdi as i32, path, resolve, pid_raw, state);
+                let open = PendingOpen::new(abs_path, flags, mode);
+                state.pending_opens.insert(pid_raw, open);
+            }
+        }
+        SYS_MEMFD_CREATE => {
+            if let Some(name) = read_string_from_tracee(pid, regs.rdi) {
+                let open = PendingOpen::new(format!("memfd:[{}]", name), 0, 0);
+                state.pending_opens.insert(pid_raw, open);
+            }
+        }
+        SYS_EXECVE | SYS_EXECVEAT => {
+            // Read argv now: by the time the exec event arrives the old image
+            // is gone, and /proc/<pid>/cmdline is racy for short-lived processes.
+            // execve: rdi=filename, rsi=argv; execveat: rsi=pathname, rdx=argv
+            let (path_ptr, argv_ptr) = if syscall_num == SYS_EXECVE {
+                (regs.rdi, regs.rsi)
+            } else {
+                (regs.rsi, regs.rdx)
+            };
+            let filename =
+                read_string_from_tracee(pid, path_ptr).map(|p| resolve_path(&p, pid_raw, state));
+            let argv = read_string_array_from_tracee(pid, argv_ptr);
+            state.pending_execs.insert(pid_raw, (filename, argv));
+        }
+        SYS_IO_URING_SETUP => {
+            // io_uring_setup(entries, params): the ring layout is filled in on success
+            let setup = PendingUring::Setup { params: regs.rsi };
+            state.pending_urings.insert(pid_raw, setup);
+        }
+        SYS_IO_URING_REGISTER => {
+            let opcode = regs.rsi as u32;
+            let fd = ring_fd(
+                pid_raw,
+                regs.rdi as i32,
+                opcode & uring::REGISTER_USE_REGISTERED_RING != 0,
+                state,
+            );
+            let register = PendingUring::Register {
+                fd,
+                opcode: opcode & !uring::REGISTER_USE_REGISTERED_RING,
+                arg: regs.rdx,
+                nr: regs.r10 as u32,
+            };
+            state.pending_urings.insert(pid_raw, register);
+        }
+        SYS_IO_URING_ENTER => submit_uring(pid, regs, state),
+        SYS_RECVMSG | SYS_RECVMMSG => {
+            // Descriptors passed with SCM_RIGHTS are only known after the call
+            state.pending_recvmsgs.insert(pid_raw, regs.rsi);
+            let mmsgs = (syscall_num == SYS_RECVMMSG).then_some(regs.rsi);
+            begin_transfer(pid_raw, regs.rdi as i32, false, mmsgs, state);
+        }
+        SYS_RECVFROM => {
+            // recvfrom(sockfd, buf, len, flags, src_addr, addrlen): the sender is
+            // filled in on return, which matters for unconnected datagram sockets
+            begin_transfer(pid_raw, regs.rdi as i32, false, None, state);
+            if let Some(transfer) = state.pending_transfers.get_mut(&pid_raw) {
+                transfer.source = (regs.r8 != 0).then_some((regs.r8, regs.r9));
+            }
+        }
+        SYS_SENDTO => {
+            // sendto(sockfd, buf, len, flags, dest_addr, addrlen)
+            let message = (regs.rsi, regs.rdx);
+            record_dns_query(pid, regs.rdi as i32, message, (regs.r8, regs.r9), state);
+            begin_transfer(pid_raw, regs.rdi as i32, true, None, state);
+            if let Some(transfer) = state.pending_transfers.get_mut(&pid_raw) {
+                let len = (regs.r9 as usize).min(std::mem::size_of::<libc::sockaddr_storage>());
+                let scheme = net::label_scheme(&transfer.endpoint);
+                if let Some((_, dest)) = read_bytes_from_tracee(pid, regs.r8, len)
+                    .and_then(|bytes| net::format_sockaddr(scheme, &bytes))
+                {
+                    transfer.endpoint = dest;
+                }
+            }
+        }
+        SYS_SENDMSG => {
+            record_sent_fds(pid, regs.rsi, state);
+            record_dns_query_msg(pid, regs.rdi as i32, regs.rsi, state);
+            begin_transfer(pid_raw, regs.rdi as i32, true, None, state);
+        }
+        SYS_SENDMMSG => {
+            // sendmmsg(sockfd, msgvec, vlen, flags); struct mmsghdr is 64 bytes
+            for i in 0..(regs.rdx as u32).min(1024) as u64 {
+                record_sent_fds(pid, regs.rsi + i * 64, state);
+                record_dns_query_msg(pid, regs.rdi as i32, regs.rsi + i * 64, state);
+            }
+            begin_transfer(pid_raw, regs.rdi as i32, true, Some(regs.rsi), state);
+        }
+        SYS_PIPE | SYS_PIPE2 => {
+            // The fds are only written to the array on success
+            state.pending_pipes.insert(pid_raw, regs.rdi);
+        }
+        SYS_DUP | SYS_DUP2 | SYS_DUP3 => {
+            // dup*(oldfd, ...): the new fd is the return value, even for dup2/dup3
+            state.pending_dups.insert(pid_raw, regs.rdi as i32);
+        }
+        SYS_FCNTL if matches!(regs.rsi as i32, libc::F_DUPFD | libc::F_DUPFD_CLOEXEC) => {
+            // fcntl(fd, F_DUPFD[_CLOEXEC], min): like dup, onto the lowest free fd >= min
+            state.pending_dups.insert(pid_raw, regs.rdi as i32);
+        }
+        SYS_CLOSE => {
+            // close(fd): forget it once the call is through, so a reused fd
+            // number is never attributed to the file it used to be
+            let fd = regs.rdi as i32;
+            state.pending_closes.insert(pid_raw, (fd, fd));
+        }
+        SYS_CLOSE_RANGE if regs.rdx & CLOSE_RANGE_CLOEXEC == 0 => {
+            // close_range(first, last, flags); with CLOSE_RANGE_CLOEXEC the fds
+            // stay open until exec, where refresh_fd_table drops them
+            let last = regs.rsi.min(i32::MAX as u64) as i32;
+            state
+                .pending_closes
+                .insert(pid_raw, (regs.rdi as i32, last));
+        }
+        SYS_SOCKET => {
+            // socket(domain, type, protocol): label the fd once we know it
+            if let Some(scheme) = net::socket_scheme(regs.rdi as i32, regs.rsi as i32) {
+                state
+                    .pending_sockets
+                    .insert(pid_raw, PendingSocketCall::Socket { scheme });
+            }
+        }
+        SYS_CONNECT | SYS_BIND => {
+            // connect/bind(sockfd, addr, addrlen): rdi=fd, rsi=addr, rdx=addrlen
+            let fd = regs.rdi as i32;
+            let operation = if syscall_num == SYS_CONNECT {
+                "connect"
+            } else {
+                "bind"
+            };
+            if let Some((family, address)) = socket_address(pid, fd, regs.rsi, regs.rdx, state) {
+                state.pending_sockets.insert(
+                    pid_raw,
+                    PendingSocketCall::Address {
+                        operation,
+                        fd,
+                        family,
+                        address,
+                    },
+                );
+            }
+        }
+        SYS_LISTEN => {
+            // listen(sockfd, backlog): the socket is already labeled with its bind address
+            let listen = PendingSocketCall::Listen {
+                fd: regs.rdi as i32,
+            };
+            state.pending_sockets.insert(pid_raw, listen);
+        }
+        SYS_ACCEPT | SYS_ACCEPT4 => {
+            // accept(sockfd, addr, addrlen): the peer address is filled in on return
+            state.pending_sockets.insert(
+                pid_raw,
+                PendingSocketCall::Accept {
+                    fd: regs.rdi as i32,
+                    addr_ptr: regs.rsi,
+                    addrlen_ptr: regs.rdx,
+                },
+            );
+        }
+        SYS_READ | SYS_PREAD64 | SYS_READV | SYS_PREADV | SYS_PREADV2 => {
+            // All read variants have fd in rdi
+            let fd = regs.rdi as i32;
+            if let Some(path) = state.fd_path(pid_raw, fd) {
+                state.defer_access(pid_raw, Access::Read, path);
+            }
+            begin_transfer(pid_raw, fd, false, None, state);
+        }
+        SYS_WRITE | SYS_PWRITE64 | SYS_WRITEV | SYS_PWRITEV | SYS_PWRITEV2 => {
+            // All write variants have fd in rdi
+            let fd = regs.rdi as i32;
+            if let Some(path) = state.fd_path(pid_raw, fd) {
+                if syscall_num == SYS_WRITE && net::is_dns_label(&path) {
+                    record_dns_query(pid, fd, (regs.rsi, regs.rdx), (0, 0), state);
+                }
+                state.defer_access(pid_raw, Access::Write, path);
+            }
+            begin_transfer(pid_raw, fd, true, None, state);
+        }
+        SYS_SENDFILE => {
+            // sendfile(out_fd, in_fd, ...) - reads from in_fd (rsi), writes to out_fd (rdi)
+            let out_fd = regs.rdi as i32;
+            let in_fd = regs.rsi as i32;
+            if let Some(path) = state.fd_path(pid_raw, in_fd) {
+                state.defer_access(pid_raw, Access::Read, path);
+            }
+            if let Some(path) = state.fd_path(pid_raw, out_fd) {
+                state.defer_access(pid_raw, Access::Write, path);
+            }
+            begin_transfer(pid_raw, out_fd, true, None, state);
+        }
+        SYS_SPLICE | SYS_TEE => {
+            // splice(fd_in, off_in, fd_out, ...) moves data between a pipe and a file
+            // (or another pipe); tee(fd_in, fd_out, ...) copies between two pipes
+            let in_fd = regs.rdi as i32;
+            let out_fd = if syscall_num == SYS_SPLICE {
+                regs.rdx as i32
+            } else {
+                regs.rsi as i32
+            };
+            if let Some(path) = state.fd_path(pid_raw, in_fd) {
+                state.defer_access(pid_raw, Access::Read, path);
+            }
+            if let Some(path) = state.fd_path(pid_raw, out_fd) {
+                state.defer_access(pid_raw, Access::Write, path);
+            }
+        }
+        SYS_VMSPLICE => {
+            // vmsplice(fd, iov, ...) fills a pipe from memory through its write end,
+            // or drains it through its read end; both ends share a label
+            let fd = regs.rdi as i32;
+            if let Some(path) = state.fd_path(pid_raw, fd) {
+                let access = match proc_fd_access_mode(pid_raw, fd) {
+                    Some(libc::O_WRONLY) => Access::Write,
+                    _ => Access::Read,
+                };
+                state.defer_access(pid_raw, access, path);
+            }
+        }
+        SYS_COPY_FILE_RANGE => {
+            // copy_file_range(fd_in, ..., fd_out, ...) - reads from fd_in (rdi), writes to fd_out (r8)
+            let in_fd = regs.rdi as i32;
+            let out_fd = regs.r8 as i32;
+            if let Some(path) = state.fd_path(pid_raw, in_fd) {
+                state.defer_access(pid_raw, Access::Read, path);
+            }
+            if let Some(path) = state.fd_path(pid_raw, out_fd) {
+                state.defer_access(pid_raw, Access::Write, path);
+            }
+        }
+        SYS_MMAP => {
+            // mmap(addr, len, prot, flags, fd, offset)
+            // Args: rdi=addr, rsi=len, rdx=prot, r10=flags, r8=fd, r9=offset
+            let fd = regs.r8 as i64;
+            let prot = regs.rdx;
+            let flags = regs.r10;
+
+            // Mapping a ring fd tells us where the ring lives in the tracee
+            if state
+                .rings
+                .contains_key(&(state.mm_owner(pid_raw), fd as i32))
+            {
+                let map = PendingUring::Map {
+                    fd: fd as i32,
+                    offset: regs.r9,
+                };
+                state.pending_urings.insert(pid_raw, map);
+            }
+
+            // Only track if mapping a file (fd >= 0)
+            if fd >= 0 {
+                let fd_i32 = fd as i32;
+                if let Some(path) = state.fd_path(pid_raw, fd_i32) {
+                    // PROT_READ = 1, PROT_WRITE = 2
+                    // MAP_SHARED = 1, MAP_PRIVATE = 2
+                    let is_shared = flags & 1 != 0;
+
+                    // Any file-backed mmap is a read
+                    if prot & 1 != 0 {
+                        state.defer_access(pid_raw, Access::Read, path.clone());
+                    }
+                    // Only MAP_SHARED + PROT_WRITE is a real write (changes go to disk)
+                    // MAP_PRIVATE writes are copy-on-write and don't modify the file
+                    if is_shared && (prot & 2 != 0) {
+                        state.defer_access(pid_raw, Access::Write, path.clone());
+                    }
+                    // The mapped address is only known at exit
+                    state
+                        .pending_mmaps
+                        .insert(pid_raw, (path, regs.rsi, is_shared));
+                }
+            }
+        }
+        // mprotect(addr, len, prot): making a shared file mapping writable
+        // lets the tracee modify the file from then on
+        SYS_MPROTECT if regs.rdx & 2 != 0 => {
+            for path in mapped_paths(pid_raw, regs.rdi, regs.rsi, state) {
+                state.defer_access(pid_raw, Access::Write, path);
+            }
+        }
+        SYS_MSYNC => {
+            // msync(addr, len, flags): flushes dirty pages of shared mappings to the file
+            for path in mapped_paths(pid_raw, regs.rdi, regs.rsi, state) {
+                state.defer_access(pid_raw, Access::Write, path);
+            }
+        }
+        SYS_MUNMAP => {
+            // munmap(addr, len); the kernel never fails it for a valid range
+            unmap_range(pid_raw, regs.rdi, regs.rsi, state);
+        }
+        SYS_TRUNCATE => {
+            // truncate(path, length): resizing a file modifies it
+            if let Some(path) = read_string_from_tracee(pid, regs.rdi) {
+                let abs_path = resolve_path(&path, pid_raw, state);
+                state.defer_access(pid_raw, Access::Write, abs_path);
+            }
+        }
+        SYS_FTRUNCATE | SYS_FALLOCATE => {
+            // ftruncate(fd, length) / fallocate(fd, mode, offset, len): fd in rdi
+            if let Some(path) = state.fd_path(pid_raw, regs.rdi as i32) {
+                state.defer_access(pid_raw, Access::Write, path);
+            }
+        }
+        SYS_FSYNC | SYS_FDATASYNC | SYS_SYNC_FILE_RANGE => {
+            // fsync(fd) / fdatasync(fd) / sync_file_range(fd, ...): fd in rdi
+            if let Some(path) = state.fd_path(pid_raw, regs.rdi as i32) {
+                state.defer_access(pid_raw, Access::Sync, path);
+            }
+        }
+        SYS_GETDENTS | SYS_GETDENTS64 => {
+            // getdents64(fd, dirp, count): rdi=directory fd
+            if let Some(path) = state.fd_path(pid_raw, regs.rdi as i32) {
+                state.defer_access(pid_raw, Access::List, path);
+            }
+        }
+        SYS_MKDIR | SYS_RMDIR => {
+            // mkdir(path, mode) / rmdir(path): rdi=path
+            if let Some(path) = read_string_from_tracee(pid, regs.rdi) {
+                let abs_path = resolve_path(&path, pid_raw, state);
+                let access = if syscall_num == SYS_MKDIR {
+                    Access::Mkdir
+                } else {
+                    Access::Rmdir
+                };
+                state.defer_access(pid_raw, access, abs_path);
+            }
+        }
+        SYS_UNLINK => {
+            // unlink(path): rdi=path
+            if let Some(path) = read_string_from_tracee(pid, regs.rdi) {
+                let abs_path = resolve_path(&path, pid_raw, state);
+                state.defer_access(pid_raw, Access::Delete, abs_path);
+            }
+        }
+        SYS_UNLINKAT => {
+            // unlinkat(dirfd, path, flags): rdi=dirfd, rsi=path, rdx=flags.
+            // With AT_REMOVEDIR it is an rmdir.
+            if let Some(path) = read_string_from_tracee(pid, regs.rsi) {
+                let abs_path = resolve_path_at(regs.rdi as i32, &path, pid_raw, state);
+                let access = if regs.rdx & libc::AT_REMOVEDIR as u64 != 0 {
+                    Access::Rmdir
+                } else {
+                    Access::Delete
+                };
+                state.defer_access(pid_raw, access, abs_path);
+            }
+        }
+        SYS_MKDIRAT => {
+            // mkdirat(dirfd, path, mode): rdi=dirfd, rsi=path
+            if let Some(path) = read_string_from_tracee(pid, regs.rsi) {
+                let abs_path = resolve_path_at(regs.rdi as i32, &path, pid_raw, state);
+                state.defer_access(pid_raw, Access::Mkdir, abs_path);
+            }
+        }
+        SYS_SYMLINK | SYS_SYMLINKAT => {
+            // symlink(target, linkpath): rdi=target, rsi=linkpath
+            // symlinkat(target, newdirfd, linkpath): rdi=target, rsi=newdirfd, rdx=linkpath
+            let (dirfd, link_ptr) = if syscall_num == SYS_SYMLINK {
+                (libc::AT_FDCWD, regs.rsi)
+            } else {
+                (regs.rsi as i32, regs.rdx)
+            };
+            if let (Some(target), Some(link)) = (
+                read_string_from_tracee(pid, regs.rdi),
+                read_string_from_tracee(pid, link_ptr),
+            ) {
+                let abs_link = resolve_path_at(dirfd, &link, pid_raw, state);
+                // Relative symlink targets are relative to the link's directory
+                let abs_target = match std::path::Path::new(&abs_link).parent() {
+                    Some(dir) if !target.starts_with('/') => {
+                        dir.join(&target).to_string_lossy().to_string()
+                    }
+                    _ => target,
+                };
+                state.defer_access(pid_raw, Access::Link { target: abs_target }, abs_link);
+            }
+        }
+        SYS_LINK | SYS_LINKAT => {
+            // link(oldpath, newpath): rdi=oldpath, rsi=newpath
+            // linkat(olddirfd, oldpath, newdirfd, newpath, flags): rdi, rsi, rdx, r10
+            let (old, new) = if syscall_num == SYS_LINK {
+                (
+                    read_string_from_tracee(pid, regs.rdi)
+                        .map(|p| resolve_path_at(libc::AT_FDCWD, &p, pid_raw, state)),
+                    read_string_from_tracee(pid, regs.rsi)
+                        .map(|p| resolve_path_at(libc::AT_FDCWD, &p, pid_raw, state)),
+                )
+            } else {
+                let old = read_string_from_tracee(pid, regs.rsi);
+                if let Some((owner, fd)) = old
+                    .as_deref()
+                    .and_then(|p| unnamed_link_source(pid_raw, regs.rdi as i32, p, regs.r8, state))
+                {
+                    if let Some(new) = read_string_from_tracee(pid, regs.r10) {
+                        let new = resolve_path_at(regs.rdx as i32, &new, pid_raw, state);
+                        state.defer_access(pid_raw, Access::Named { owner, fd }, new);
+                    }
+                    return;
+                }
+                (
+                    old.map(|p| resolve_path_at(regs.rdi as i32, &p, pid_raw, state)),
+                    read_string_from_tracee(pid, regs.r10)
+                        .map(|p| resolve_path_at(regs.rdx as i32, &p, pid_raw, state)),
+                )
+            };
+            if let (Some(old), Some(new)) = (old, new) {
+                state.defer_access(pid_raw, Access::Link { target: old }, new);
+            }
+        }
+        SYS_READLINK | SYS_READLINKAT => {
+            // readlink(path, buf, bufsiz): rdi=path, rsi=buf
+            // readlinkat(dirfd, path, buf, bufsiz): rdi=dirfd, rsi=path, rdx=buf
+            let (dirfd, path_ptr, buf) = if syscall_num == SYS_READLINK {
+                (libc::AT_FDCWD, regs.rdi, regs.rsi)
+            } else {
+                (regs.rdi as i32, regs.rsi, regs.rdx)
+            };
+            if let Some(path) = read_string_from_tracee(pid, path_ptr) {
+                let abs_path = resolve_path_at(dirfd, &path, pid_raw, state);
+                state.pending_readlinks.insert(pid_raw, (abs_path, buf));
+            }
+        }
+        SYS_CHMOD | SYS_CHOWN | SYS_LCHOWN | SYS_UTIME | SYS_UTIMES | SYS_SETXATTR
+        | SYS_LSETXATTR | SYS_REMOVEXATTR | SYS_LREMOVEXATTR => {
+            // Path-based metadata changes: rdi=path
+            if let Some(path) = read_string_from_tracee(pid, regs.rdi) {
+                let abs_path = resolve_path(&path, pid_raw, state);
+                state.defer_access(pid_raw, Access::Metadata, abs_path);
+            }
+        }
+        SYS_FCHMOD | SYS_FCHOWN | SYS_FSETXATTR | SYS_FREMOVEXATTR => {
+            // fd-based metadata changes: rdi=fd
+            if let Some(path) = state.fd_path(pid_raw, regs.rdi as i32) {
+                state.defer_access(pid_raw, Access::Metadata, path);
+            }
+        }
+        SYS_FCHMODAT | SYS_FCHMODAT2 | SYS_FCHOWNAT | SYS_FUTIMESAT | SYS_UTIMENSAT => {
+            // *at(dirfd, path, ...): rdi=dirfd, rsi=path.
+            // utimensat with a NULL path (futimens) operates on dirfd itself.
+            let path = if regs.rsi == 0 && syscall_num == SYS_UTIMENSAT {
+                Some(String::new())
+            } else {
+                read_string_from_tracee(pid, regs.rsi)
+            };
+            if let Some(path) = path {
+                let abs_path = resolve_path_at(regs.rdi as i32, &path, pid_raw, state);
+                state.defer_access(pid_raw, Access::Metadata, abs_path);
+            }
+        }
+        SYS_STAT | SYS_LSTAT | SYS_ACCESS => {
+            // stat/lstat/access(path, ...): rdi=path
+            if let Some(path) = read_string_from_tracee(pid, regs.rdi) {
+                let abs_path = resolve_path(&path, pid_raw, state);
+                state.defer_access(pid_raw, Access::Stat, abs_path);
+            }
+        }
+        SYS_FSTAT => {
+            // fstat(fd, statbuf): only files, not the pipes and sockets of stdio
+            if let Some(path) = state
+                .fd_path(pid_raw, regs.rdi as i32)
+                .filter(|p| p.starts_with('/'))
+            {
+                state.defer_access(pid_raw, Access::Stat, path);
+            }
+        }
+        SYS_NEWFSTATAT | SYS_STATX | SYS_FACCESSAT | SYS_FACCESSAT2 => {
+            // *at(dirfd, path, ...): rdi=dirfd, rsi=path. glibc's fstat is
+            // newfstatat(fd, "", AT_EMPTY_PATH), which can name a pipe or socket.
+            if let Some(path) = read_string_from_tracee(pid, regs.rsi) {
+                let abs_path = resolve_path_at(regs.rdi as i32, &path, pid_raw, state);
+                if abs_path.starts_with('/') {
+                    state.defer_access(pid_raw, Access::Stat, abs_path);
+                }
+            }
+        }
+        SYS_RENAME => {
+            // rename(oldpath, newpath): rdi=oldpath, rsi=newpath
+            // The destination (newpath) is effectively written
+            if let Some(newpath) = read_string_from_tracee(pid, regs.rsi) {
+                let abs_path = resolve_path(&newpath, pid_raw, state);
+                state.defer_access(pid_raw, Access::Rename, abs_path);
+            }
+        }
+        SYS_RENAMEAT | SYS_RENAMEAT2 => {
+            // renameat(olddirfd, oldpath, newdirfd, newpath): rsi=oldpath, rdx=newdirfd,
+            // r10=newpath. The destination (newpath) is effectively written
+            if let Some(newpath) = read_string_from_tracee(pid, regs.r10) {
+                let abs_path = resolve_path_at(regs.rdx as i32, &newpath, pid_raw, state);
+                state.defer_access(pid_raw, Access::Rename, abs_path);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn handle_syscall_exit(pid: Pid, syscall_num: u64, regs: &SyscallRegs, state: &mut TracerState) {
+    let pid_raw = pid.as_raw();
+    let ret_val = regs.rax as i64;
+
+    // Reads, writes, mmaps and renames only count if the call succeeded.
+    // Errors come back as -errno; mmap addresses and byte counts are non-negative.
+    if let Some(accesses) = state.pending_accesses.remove(&pid_raw) {
+        if ret_val >= 0 {
+            let bytes = if moves_bytes(syscall_num) {
+                ret_val as u64
+            } else {
+                0
+            };
+            for (access, path) in accesses {
+                state.record_access(pid_raw, access, path, bytes);
+            }
+        } else if ret_val == -(libc::ENOENT as i64) {
+            // "Checked and absent" is itself a dependency for probes
+            for (access, path) in accesses {
+                if let Access::Stat = access {
+                    state.record_missing(pid_raw, path);
+                }
+            }
+        }
+    }
+
+    if let Some(transfer) = state.pending_transfers.remove(&pid_raw) {
+        if ret_val > 0 {
+            complete_transfer(pid, transfer, ret_val as u64, state);
+        }
+    }
+
+    match syscall_num {
+        SYS_CHROOT if ret_val == 0 => {
+            // The kernel reports the new root in our view of the filesystem
+            let root = std::fs::read_link(format!("/proc/{}/root", pid_raw))
+                .map(|p| p.to_string_lossy().to_string())
+                .ok()
+                .filter(|root| root != "/");
+            match &root {
+                Some(root) => state.roots.insert(pid_raw, root.clone()),
+                None => state.roots.remove(&pid_raw),
+            };
+            if let Some(process) = state.processes.get_mut(&pid_raw) {
+                process.chroot = root;
+            }
+        }
+        SYS_UNSHARE | SYS_SETNS if ret_val == 0 => {
+            // Paths from a new mount namespace may not mean the same thing to us;
+            // the namespace id lets consumers flag them
+            if let Some(process) = state.processes.get_mut(&pid_raw) {
+                process.mnt_ns = read_mnt_ns(pid_raw);
+            }
+            // unshare(flags): argument registers survive to the exit stop
+            if syscall_num == SYS_UNSHARE && regs.rdi & libc::CLONE_FS as u64 != 0 {
+                unshare_cwd(pid_raw, state);
+            }
+        }
+        SYS_CHDIR | SYS_FCHDIR if ret_val == 0 => refresh_cwd(pid_raw, state),
+        SYS_IO_URING_SETUP | SYS_IO_URING_REGISTER | SYS_IO_URING_ENTER => {
+            complete_uring(pid, ret_val, state);
+        }
+        SYS_MMAP => {
+            complete_uring(pid, ret_val, state);
+            if let Some((path, len, shared)) = state.pending_mmaps.remove(&pid_raw) {
+                if ret_val >= 0 {
+                    let start = ret_val as u64;
+                    let owner = state.mm_owner(pid_raw);
+                    // MAP_FIXED can replace whatever was mapped there before
+                    unmap_range(pid_raw, start, len, state);
+                    state.mappings.entry(owner).or_default().push(FileMapping {
+                        start,
+                        end: start.saturating_add(len),
+                        path,
+                        shared,
+                    });
+                }
+            }
+        }
+        SYS_RECVMSG | SYS_RECVMMSG => {
+            if let Some(msg_ptr) = state.pending_recvmsgs.remove(&pid_raw) {
+                // recvmsg returns a byte count, recvmmsg the number of messages
+                let messages = match syscall_num {
+                    SYS_RECVMSG if ret_val >= 0 => 1,
+                    SYS_RECVMMSG if ret_val > 0 => ret_val as u64,
+                    _ => 0,
+                };
+                for i in 0..messages {
+                    // struct mmsghdr is a msghdr followed by an unsigned int (padded to 64)
+                    record_received_fds(pid, msg_ptr + i * 64, state);
+                }
+            }
+        }
+        SYS_PIPE | SYS_PIPE2 => {
+            if let Some(fds_ptr) = state.pending_pipes.remove(&pid_raw) {
+                if ret_val == 0 {
+                    record_pipe_fds(pid, fds_ptr, state);
+                }
+            }
+        }
+        SYS_DUP | SYS_DUP2 | SYS_DUP3 | SYS_FCNTL => {
+            if let Some(old_fd) = state.pending_dups.remove(&pid_raw) {
+                if ret_val >= 0 {
+                    record_dup(pid_raw, old_fd, ret_val as i32, state);
+                }
+            }
+        }
+        SYS_OPEN | SYS_OPENAT | SYS_OPENAT2 => {
+            if let Some(open) = state.pending_opens.remove(&pid_raw) {
+                if ret_val >= 0 && is_tmpfile_open(open.flags) {
+                    // `path` is only the directory; the file has no name yet
+                    let label = tmpfile_label(&open.path);
+                    state.fd_table_mut(pid_raw).insert(ret_val as i32, label);
+                } else if ret_val >= 0 {
+                    let is_fifo = std::fs::metadata(&open.path)
+                        .map(|m| m.file_type().is_fifo())
+                        .unwrap_or(false);
+                    if is_fifo {
+                        state.fifos.insert(open.path.clone());
+                    }
+                    state.record_open(pid_raw, ret_val as i32, open);
+                } else if ret_val == -(libc::ENOENT as i64) {
+                    // Search paths (ld.so, config loaders) depend on earlier candidates being absent
+                    state.record_missing(pid_raw, open.path);
+                } else {
+                    state.record_failed_open(open.path, -ret_val as i32);
+                }
+            }
+        }
+        SYS_EVENTFD | SYS_EVENTFD2 | SYS_TIMERFD_CREATE | SYS_SIGNALFD | SYS_SIGNALFD4
+            if ret_val >= 0 =>
+        {
+            // signalfd with an existing fd returns it again; the label is the same
+            let kind = match syscall_num {
+                SYS_EVENTFD | SYS_EVENTFD2 => "eventfd",
+                SYS_TIMERFD_CREATE => "timerfd",
+                _ => "signalfd",
+            };
+            if let Some(label) = anon_fd_label(kind) {
+                state.fd_table_mut(pid_raw).insert(ret_val as i32, label);
+            }
+        }
+        SYS_MEMFD_CREATE => {
+            if let Some(open) = state.pending_opens.remove(&pid_raw) {
+                if ret_val >= 0 {
+                    state
+                        .fd_table_mut(pid_raw)
+                        .insert(ret_val as i32, open.path);
+                }
+            }
+        }
+        SYS_SOCKET | SYS_CONNECT | SYS_BIND | SYS_LISTEN | SYS_ACCEPT | SYS_ACCEPT4 => {
+            if let Some(call) = state.pending_sockets.remove(&pid_raw) {
+                handle_socket_exit(pid, call, ret_val, state);
+            }
+        }
+        SYS_READLINK | SYS_READLINKAT => {
+            // The target is not NUL-terminated: the return value is its length
+            if let Some((path, buf)) = state.pending_readlinks.remove(&pid_raw) {
+                if ret_val > 0 {
+                    if let Some(bytes) = read_bytes_from_tracee(pid, buf, ret_val as usize) {
+                        let target = String::from_utf8_lossy(&bytes).to_string();
+                        state.record_readlink(pid_raw, path, target);
+                    }
+                }
+            }
+        }
+        SYS_EXECVE | SYS_EXECVEAT => {
+            // A successful exec consumes its pending entry at PTRACE_EVENT_EXEC;
+            // anything left here belongs to a failed exec.
+            state.pending_execs.remove(&pid_raw);
+        }
+        SYS_CLOSE | SYS_CLOSE_RANGE => {
+            // Linux releases the fd even when close reports EINTR or EIO
+            if let Some((first, last)) = state.pending_closes.remove(&pid_raw) {
+                if ret_val != -(libc::EBADF as i64) && ret_val != -(libc::EINVAL as i64) {
+                    state
+                        .fd_table_mut(pid_raw)
+                        .retain(|fd, _| *fd < first || *fd > last);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn mark_io_uring(pid: i32, state: &mut TracerState) {
+    let Some(process) = state.processes.get_mut(&pid) else {
+        return;
+    };
+    if !process.io_uring_used {
+        process.io_uring_used = true;
+        eprintln!(
+            "Warning: process {} submits io_uring I/O we can't decode; some file I/O is not traced",
+            pid
+        );
+    }
+}
+
+/// The ring fd an io_uring_enter/register call refers to, looking up registered ring indices.
+fn ring_fd(pid: i32, fd: i32, registered: bool, state: &TracerState) -> i32 {
+    if !registered {
+        return fd;
+    }
+    let registered_fd = state.registered_rings.get(&(pid, fd as u32)).copied();
+    registered_fd.unwrap_or(-1)
+}
+
+/// Label of the file an SQE operates on: a registered file index or a plain fd.
+fn uring_file(
+    pid: i32,
+    ring: (i32, i32),
+    fd: i32,
+    fixed: bool,
+    state: &TracerState,
+) -> Option<String> {
+    if fixed {
+        state.rings.get(&ring)?.file(fd as u32)
+    } else {
+        state.fd_path(pid, fd)
+    }
+}
+
+/// At io_uring_enter: attribute the SQEs the kernel is about to consume.
+fn submit_uring(pid: Pid, regs: &SyscallRegs, state: &mut TracerState) {
+    let pid_raw = pid.as_raw();
+    let flags = regs.r10;
+    let fd = ring_fd(
+        pid_raw,
+        regs.rdi as i32,
+        flags & uring::ENTER_REGISTERED_RING != 0,
+        state,
+    );
+    let key = (state.mm_owner(pid_raw), fd);
+    let submitted = match state.rings.get(&key) {
+        // A kernel thread drains SQPOLL rings whenever it likes
+        Some(ring) if !ring.sqpoll() => ring.submissions(regs.rsi as u32, |addr, len| {
+            read_bytes_from_tracee(pid, addr, len)
+        }),
+        _ => None,
+    };
+    let Some(submitted) = submitted else {
+        mark_io_uring(pid_raw, state);
+        return;
+    };
+
+    for sqe in submitted {
+        decode_sqe(pid, key, &sqe, state);
+    }
+    state
+        .pending_urings
+        .insert(pid_raw, PendingUring::Enter { ring: key });
+}
+
+/// Turn one SQE into the accesses the equivalent syscall would have made.
+fn decode_sqe(pid: Pid, ring: (i32, i32), sqe: &uring::Sqe, state: &mut TracerState) {
+    use uring::*;
+    let pid_raw = pid.as_raw();
+    let path_at = |dirfd: i32, ptr: u64, state: &TracerState| {
+        read_string_from_tracee(pid, ptr).map(|p| resolve_path_at(dirfd, &p, pid_raw, state))
+    };
+
+    let op = match sqe.opcode {
+        OP_CLOSE => {
+            // Close of a direct descriptor frees its slot, else a plain fd
+            if sqe.file_index != 0 {
+                if let Some(ring) = state.rings.get_mut(&ring) {
+                    ring.set_file(sqe.file_index - 1, None);
+                }
+            } else {
+                state.fd_table_mut(pid_raw).remove(&sqe.fd);
+            }
+            None
+        }
+        // openat: fd=dirfd, addr=path, len=mode, op_flags=open flags
+        OP_OPENAT => path_at(sqe.fd, sqe.addr, state).map(|path| UringOp::Open {
+            open: PendingOpen::new(path, sqe.op_flags as u64, sqe.len as u64),
+            file_index: sqe.file_index,
+        }),
+        // openat2: fd=dirfd, addr=path, off=struct open_how *
+        OP_OPENAT2 => read_string_from_tracee(pid, sqe.addr).map(|path| {
+            let (flags, mode, resolve) = read_open_how(pid, sqe.off);
+            let path = resolve_openat2_path(sqe.fd, path, resolve, pid_raw, state);
+            UringOp::Open {
+                open: PendingOpen::new(path, flags, mode),
+                file_index: sqe.file_index,
+            }
+        }),
+        // statx: fd=dirfd, addr=path; only real files count, as for the syscall
+        OP_STATX => path_at(sqe.fd, sqe.addr, state)
+            .filter(|p| p.starts_with('/'))
+            .map(|path| UringOp::Access(Access::Stat, path)),
+        // renameat: fd=olddirfd, addr=oldpath, len=newdirfd, off=newpath
+        OP_RENAMEAT => path_at(sqe.len as i32, sqe.off, state)
+            .map(|path| UringOp::Access(Access::Rename, path)),
+        // unlinkat: fd=dirfd, addr=path, op_flags=AT_REMOVEDIR
+        OP_UNLINKAT => path_at(sqe.fd, sqe.addr, state).map(|path| {
+            let access = if sqe.op_flags & libc::AT_REMOVEDIR as u32 != 0 {
+                Access::Rmdir
+            } else {
+                Access::Delete
+            };
+            UringOp::Access(access, path)
+        }),
+        OP_MKDIRAT => {
+            path_at(sqe.fd, sqe.addr, state).map(|path| UringOp::Access(Access::Mkdir, path))
+        }
+        // symlinkat: addr=target, fd=newdirfd, off=linkpath
+        OP_SYMLINKAT => {
+            let target = read_string_from_tracee(pid, sqe.addr);
+            let link = path_at(sqe.fd, sqe.off, state);
+            target.zip(link).map(|(target, link)| {
+                // Relative symlink targets are relative to the link's directory
+                let target = match std::path::Path::new(&link).parent() {
+                    Some(dir) if !target.starts_with('/') => {
+                        dir.join(&target).to_string_lossy().to_string()
+                    }
+                    _ => target,
+                };
+                UringOp::Access(Access::Link { target }, link)
+            })
+        }
+        // linkat: fd=olddirfd, addr=oldpath, len=newdirfd, off=newpath
+        OP_LINKAT => {
+            let old = path_at(sqe.fd, sqe.addr, state);
+            let new = path_at(sqe.len as i32, sqe.off, state);
+            old.zip(new)
+                .map(|(target, link)| UringOp::Access(Access::Link { target }, link))
+        }
+        // Reads, writes and syncs on files already open
+        _ => {
+            let io: Vec<(Access, String)> = sqe
+                .file_io()
+                .into_iter()
+                .filter_map(|(io, fd, fixed)| {
+                    let access = match io {
+                        FileIo::Read => Access::Read,
+                        FileIo::Write => Access::Write,
+                        FileIo::Sync => Access::Sync,
+                    };
+                    Some((access, uring_file(pid_raw, ring, fd, fixed, state)?))
+                })
+                .collect();
+            (!io.is_empty()).then_some(UringOp::Io(io))
+        }
+    };
+
+    match op {
+        // No CQE comes back on success, so count it as soon as it's submitted
+        Some(UringOp::Access(access, path)) if sqe.flags & IOSQE_CQE_SKIP_SUCCESS != 0 => {
+            state.defer_access(pid_raw, access, path);
+        }
+        Some(UringOp::Io(io)) if sqe.flags & IOSQE_CQE_SKIP_SUCCESS != 0 => {
+            for (access, path) in io {
+                state.defer_access(pid_raw, access, path);
+            }
+        }
+        Some(op) => {
+            if let Some(ring) = state.rings.get_mut(&ring) {
+                ring.pending.insert(sqe.user_data, op);
+            }
+        }
+        None => {}
+    }
+}
+
+/// At the exit of io_uring_setup/register/enter or an mmap of a ring fd.
+fn complete_uring(pid: Pid, ret_val: i64, state: &mut TracerState) {
+    let pid_raw = pid.as_raw();
+    let Some(call) = state.pending_urings.remove(&pid_raw) else {
+        return;
+    };
+    let owner = state.mm_owner(pid_raw);
+    match call {
+        PendingUring::Setup { params } if ret_val >= 0 => {
+            let ring = read_bytes_from_tracee(pid, params, uring::PARAMS_SIZE)
+                .and_then(|p| Ring::from_params(&p));
+            match ring {
+                Some(ring) => {
+                    if ring.sqpoll() {
+                        mark_io_uring(pid_raw, state);
+                    }
+                    state.rings.insert((owner, ret_val as i32), ring);
+                }
+                None => mark_io_uring(pid_raw, state),
+            }
+        }
+        PendingUring::Map { fd, offset } if ret_val >= 0 => {
+            if let Some(ring) = state.rings.get_mut(&(owner, fd)) {
+                ring.map(offset, ret_val as u64);
+            }
+        }
+        PendingUring::Register {
+            fd,
+            opcode,
+            arg,
+            nr,
+        } if ret_val >= 0 => register_uring(pid, (owner, fd), opcode, arg, nr, state),
+        PendingUring::Enter { ring } => {
+            // Completions can be posted even when a wait is interrupted
+            let Some(completions) = state
+                .rings
+                .get(&ring)
+                .map(|r| r.completions(|addr, len| read_bytes_from_tracee(pid, addr, len)))
+            else {
+                return;
+            };
+            for cqe in completions {
+                let op = state
+                    .rings
+                    .get_mut(&ring)
+                    .and_then(|r| r.pending.remove(&cqe.user_data));
+                if let Some(op) = op {
+                    complete_uring_op(pid_raw, ring, op, cqe.res, state);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Record an io_uring operation once its CQE has told us the result.
+fn complete_uring_op(pid: i32, ring: (i32, i32), op: UringOp, res: i32, state: &mut TracerState) {
+    match op {
+        UringOp::Open { open, file_index } if res >= 0 => {
+            let slot = match file_index {
+                0 if is_tmpfile_open(open.flags) => {
+                    let label = tmpfile_label(&open.path);
+                    state.fd_table_mut(pid).insert(res, label);
+                    return;
+                }
+                0 => return state.record_open(pid, res, open),
+                uring::FILE_INDEX_ALLOC => res as u32,
+                index => index - 1,
+            };
+            // A direct descriptor lives in the ring's file table, not the fd table
+            if let Some(ring) = state.rings.get_mut(&ring) {
+                ring.set_file(slot, Some(open.path.clone()));
+            }
+            state.record_opened(pid, -1, open);
+        }
+        UringOp::Open { open, .. } if res == -libc::ENOENT => {
+            state.record_missing(pid, open.path);
+        }
+        UringOp::Open { open, .. } => state.record_failed_open(open.path, -res),
+        UringOp::Access(access, path) if res >= 0 => state.record_access(pid, access, path, 0),
+        UringOp::Access(Access::Stat, path) if res == -libc::ENOENT => {
+            state.record_missing(pid, path);
+        }
+        UringOp::Access(..) => {}
+        UringOp::Io(io) if res >= 0 => {
+            for (access, path) in io {
+                state.record_access(pid, access, path, res as u64);
+            }
+        }
+        UringOp::Io(_) => {}
+    }
+}
+
+/// Apply an io_uring_register call that changed the registered files or ring fds.
+fn register_uring(
+    pid: Pid,
+    ring: (i32, i32),
+    opcode: u32,
+    arg: u64,
+    nr: u32,
+    state: &mut TracerState,
+) {
+    let pid_raw = pid.as_raw();
+    let read_fds = |ptr: u64, count: u32, state: &TracerState| -> Vec<Option<String>> {
+        let bytes = read_bytes_from_tracee(pid, ptr, count as usize * 4).unwrap_or_default();
+        (bytes.chunks_exact(4))
+            .map(|b| i32::from_ne_bytes(b.try_into().unwrap_or([0xff; 4])))
+            .map(|fd| {
+                if fd < 0 {
+                    None
+                } else {
+                    state.fd_path(pid_raw, fd)
+                }
+            })
+            .collect()
+    };
+    let struct_bytes = |len: usize| read_bytes_from_tracee(pid, arg, len).unwrap_or_default();
+    let word32 = |b: &[u8], off: usize| {
+        (b.get(off..off + 4))
+            .and_then(|w| w.try_into().ok())
+            .map(u32::from_ne_bytes)
+            .unwrap_or(0)
+    };
+    let word64 = |b: &[u8], off: usize| {
+        (b.get(off..off + 8))
+            .and_then(|w| w.try_into().ok())
+            .map(u64::from_ne_bytes)
+            .unwrap_or(0)
+    };
+
+    // (first slot, labels) to store into the registered file table
+    let update = match opcode {
+        uring::REGISTER_FILES => Some((0, read_fds(arg, nr, state))),
+        uring::UNREGISTER_FILES => {
+            if let Some(ring) = state.rings.get_mut(&ring) {
+                ring.files.clear();
+            }
+            None
+        }
+        // struct io_uring_rsrc_register { nr, flags, resv2, data, tags }
+        uring::REGISTER_FILES2 => {
+            let reg = struct_bytes(32);
+            let count = word32(&reg, 0);
+            if let Some(ring) = state.rings.get_mut(&ring) {
+                ring.files.clear();
+            }
+            if word32(&reg, 4) & uring::RSRC_REGISTER_SPARSE != 0 {
+                Some((0, vec![None; count as usize]))
+            } else {
+                Some((0, read_fds(word64(&reg, 16), count, state)))
+            }
+        }
+        // struct io_uring_files_update { offset, resv, fds }
+        uring::REGISTER_FILES_UPDATE => {
+            let update = struct_bytes(16);
+            Some((word32(&update, 0), read_fds(word64(&update, 8), nr, state)))
+        }
+        // struct io_uring_rsrc_update2 { offset, resv, data, tags, nr, resv2 }
+        uring::REGISTER_FILES_UPDATE2 => {
+            let update = struct_bytes(32);
+            let count = word32(&update, 24);
+            Some((
+                word32(&update, 0),
+                read_fds(word64(&update, 8), count, state),
+            ))
+        }
+        // An array of struct io_uring_rsrc_update { offset, resv, data }; the kernel
+        // writes the index it picked back into offset
+        uring::REGISTER_RING_FDS | uring::UNREGISTER_RING_FDS => {
+            let updates = struct_bytes(nr as usize * 16);
+            for update in updates.chunks_exact(16) {
+                let index = word32(update, 0);
+                if opcode == uring::REGISTER_RING_FDS {
+                    let fd = word64(update, 8) as i32;
+                    state.registered_rings.insert((pid_raw, index), fd);
+                } else {
+                    state.registered_rings.remove(&(pid_raw, index));
+                }
+            }
+            None
+        }
+        _ => None,
+    };
+
+    if let (Some((first, labels)), Some(ring)) = (update, state.rings.get_mut(&ring)) {
+        for (i, label) in labels.into_iter().enumerate() {
+            ring.set_file(first + i as u32, label);
+        }
+    }
+}
+
+/// Start counting the bytes a call on `fd` moves, if it is a socket.
+fn begin_transfer(pid: i32, fd: i32, sent: bool, mmsgs: Option<u64>, state: &mut TracerState) {
+    let Some(endpoint) = state.fd_path(pid, fd) else {
+        return;
+    };
+    if !net::is_socket_label(&endpoint) || !state.traces(TraceCategory::Network) {
+        return;
+    }
+    let transfer = PendingTransfer {
+        endpoint,
+        sent,
+        mmsgs,
+        source: None,
+    };
+    state.pending_transfers.insert(pid, transfer);
+}
+
+/// Add what a finished socket call moved to its endpoint's counters.
+/// `result` is a byte count, or for sendmmsg/recvmmsg a message count.
+fn complete_transfer(pid: Pid, transfer: PendingTransfer, result: u64, state: &mut TracerState) {
+    let bytes = match transfer.mmsgs {
+        // struct mmsghdr: msghdr (56 bytes), then the byte count msg_len
+        Some(vec) => (0..result.min(1024))
+            .filter_map(|i| read_bytes_from_tracee(pid, vec + i * 64 + 56, 4))
+            .map(|len| u32::from_ne_bytes([len[0], len[1], len[2], len[3]]) as u64)
+            .sum(),
+        None => result,
+    };
+    let source = transfer.source.and_then(|(addr, addrlen_ptr)| {
+        let len = read_bytes_from_tracee(pid, addrlen_ptr, 4)
+            .map(|len| u32::from_ne_bytes([len[0], len[1], len[2], len[3]]) as usize)?;
+        let bytes = read_bytes_from_tracee(pid, addr, len.min(128))?;
+        net::format_sockaddr(net::label_scheme(&transfer.endpoint), &bytes)
+    });
+    let endpoint = match source {
+        Some((_, address)) => address,
+        None => transfer.endpoint,
+    };
+    // Unconnected and unnamed sockets have no endpoint to attribute bytes to
+    if endpoint.ends_with("://") {
+        return;
+    }
+    let traffic = state.network_traffic.entry(endpoint).or_default();
+    if transfer.sent {
+        traffic.bytes_sent += bytes;
+    } else {
+        traffic.bytes_received += bytes;
+    }
+}
+
+/// Note the hostname in a DNS query sent to port 53, either on a connected
+/// socket or to the sockaddr given as `dest` (address, length).
+fn record_dns_query(
+    pid: Pid,
+    fd: i32,
+    (buf, len): (u64, u64),
+    (dest, dest_len): (u64, u64),
+    state: &mut TracerState,
+) {
+    let Some(label) = state.fd_path(pid.as_raw(), fd) else {
+        return;
+    };
+    let scheme = net::label_scheme(&label);
+    let dest_len = (dest_len as usize).min(std::mem::size_of::<libc::sockaddr_storage>());
+    let server = match read_bytes_from_tracee(pid, dest, dest_len) {
+        Some(bytes) => net::format_sockaddr(scheme, &bytes).map(|(_, address)| address),
+        None => Some(label),
+    };
+    if !server.is_some_and(|server| net::is_dns_label(&server)) {
+        return;
+    }
+    // A DNS message over UDP is at most 512 bytes unless EDNS is in use
+    let message = read_bytes_from_tracee(pid, buf, (len as usize).min(512));
+    if let Some(name) = message.and_then(|m| net::dns_query_name(scheme, &m)) {
+        state.resolved_hostnames.insert(name);
+    }
+}
+
+/// Note the hostname in a DNS query sent with sendmsg: the first iovec holds it.
+fn record_dns_query_msg(pid: Pid, fd: i32, msghdr_ptr: u64, state: &mut TracerState) {
+    // struct msghdr: name, namelen(+pad), iov, iovlen, control, controllen, flags
+    let Some(hdr) = read_bytes_from_tracee(pid, msghdr_ptr, 32) else {
+        return;
+    };
+    let word = |off: usize| u64::from_ne_bytes(hdr[off..off + 8].try_into().unwrap_or([0; 8]));
+    let (name, name_len, iov, iov_len) = (word(0), word(8) & 0xffff_ffff, word(16), word(24));
+    if iov_len == 0 {
+        return;
+    }
+    let Some(first) = read_struct_from_tracee::<libc::iovec>(pid, iov) else {
+        return;
+    };
+    let (base, len) = (first.iov_base as u64, first.iov_len as u64);
+    record_dns_query(pid, fd, (base, len), (name, name_len), state);
+}
+
+/// Descriptors carried in SCM_RIGHTS control messages of a msghdr.
+fn scm_rights_fds(pid: Pid, msghdr_ptr: u64) -> Vec<i32> {
+    let mut fds = Vec::new();
+    // struct msghdr: name, namelen(+pad), iov, iovlen, control, controllen, flags
+    let Some(hdr) = read_bytes_from_tracee(pid, msghdr_ptr, 56) else {
+        return fds;
+    };
+    let word = |off: usize| u64::from_ne_bytes(hdr[off..off + 8].try_into().unwrap_or([0; 8]));
+    let (control, control_len) = (word(32), word(40) as usize);
+    if control == 0 || control_len < 16 {
+        return fds;
+    }
+    let Some(buf) = read_bytes_from_tracee(pid, control, control_len.min(4096)) else {
+        return fds;
+    };
+
+    // struct cmsghdr: len (u64), level (i32), type (i32), data aligned to 8
+    let mut off = 0;
+    while off + 16 <= buf.len() {
+        let len = u64::from_ne_bytes(buf[off..off + 8].try_into().unwrap_or([0; 8])) as usize;
+        let level = i32::from_ne_bytes(buf[off + 8..off + 12].try_into().unwrap_or([0; 4]));
+        let kind = i32::from_ne_bytes(buf[off + 12..off + 16].try_into().unwrap_or([0; 4]));
+        if len < 16 || off + len > buf.len() {
+            break;
+        }
+        if level == libc::SOL_SOCKET && kind == libc::SCM_RIGHTS {
+            for fd_bytes in buf[off + 16..off + len].chunks_exact(4) {
+                fds.push(i32::from_ne_bytes([
+                    fd_bytes[0],
+                    fd_bytes[1],
+                    fd_bytes[2],
+                    fd_bytes[3],
+                ]));
+            }
+        }
+        off += (len + 7) & !7;
+    }
+    fds
+}
+
+/// Device and inode of the open file behind a tracee's fd, the same on both
+/// ends of an SCM_RIGHTS transfer.
+fn fd_inode(pid: i32, fd: i32) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    let meta = std::fs::metadata(format!("/proc/{}/fd/{}", pid, fd)).ok()?;
+    Some((meta.dev(), meta.ino()))
+}
+
+/// Remember the sender's labels for fds it passes with SCM_RIGHTS.
+fn record_sent_fds(pid: Pid, msghdr_ptr: u64, state: &mut TracerState) {
+    for fd in scm_rights_fds(pid, msghdr_ptr) {
+        let label = state.fd_path(pid.as_raw(), fd);
+        if let (Some(label), Some(inode)) = (label, fd_inode(pid.as_raw(), fd)) {
+            state.passed_fds.insert(inode, label);
+        }
+    }
+}
+
+/// Enter fds received via SCM_RIGHTS in a msghdr into the receiver's fd table.
+fn record_received_fds(pid: Pid, msghdr_ptr: u64, state: &mut TracerState) {
+    let pid_raw = pid.as_raw();
+    for fd in scm_rights_fds(pid, msghdr_ptr) {
+        // Prefer what a traced sender knew the fd as; else ask /proc
+        let label = fd_inode(pid_raw, fd)
+            .and_then(|inode| state.passed_fds.get(&inode).cloned())
+            .or_else(|| proc_fd_label(pid_raw, fd));
+        if let Some(label) = label {
+            state.fd_table_mut(pid_raw).insert(fd, label);
+        }
+    }
+}
+
+/// Names of the open(2) flags that matter for provenance: the access mode
+/// ("rdonly", "wronly" or "rdwr") and whether the open created, truncated or appended.
+fn open_flag_names(flags: u64) -> Vec<&'static str> {
+    let flags = flags as i32;
+    let mut names = vec![match flags & libc::O_ACCMODE {
+        libc::O_WRONLY => "wronly",
+        libc::O_RDWR => "rdwr",
+        _ => "rdonly",
+    }];
+    for (flag, name) in [
+        (libc::O_CREAT, "creat"),
+        (libc::O_EXCL, "excl"),
+        (libc::O_TRUNC, "trunc"),
+        (libc::O_APPEND, "append"),
+    ] {
+        if flags & flag != 0 {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Point a duplicated fd at whatever the original refers to. dup2/dup3 close
+/// the target fd first, so an untracked original leaves it untracked too.
+fn record_dup(pid: i32, old_fd: i32, new_fd: i32, state: &mut TracerState) {
+    if old_fd == new_fd {
+        return;
+    }
+    let table = state.fd_table_mut(pid);
+    match table.get(&old_fd).cloned() {
+        Some(label) => table.insert(new_fd, label),
+        None => table.remove(&new_fd),
+    };
+}
+
+/// Label both ends of a new pipe with its "pipe:[<inode>]" name from /proc.
+fn record_pipe_fds(pid: Pid, fds_ptr: u64, state: &mut TracerState) {
+    let pid_raw = pid.as_raw();
+    let Some(bytes) = read_bytes_from_tracee(pid, fds_ptr, 8) else {
+        return;
+    };
+    for end in bytes.chunks_exact(4) {
+        let fd = i32::from_ne_bytes([end[0], end[1], end[2], end[3]]);
+        if let Ok(label) = std::fs::read_link(format!("/proc/{}/fd/{}", pid_raw, fd)) {
+            let label = label.to_string_lossy().to_string();
+            state.fd_table_mut(pid_raw).insert(fd, label);
+        }
+    }
+}
+
+/// Decode the sockaddr a socket call on `fd` names (address, length).
+fn socket_address(
+    pid: Pid,
+    fd: i32,
+    addr: u64,
+    len: u64,
+    state: &TracerState,
+) -> Option<(&'static str, String)> {
+    let pid_raw = pid.as_raw();
+    let scheme = state
+        .fd_path(pid_raw, fd)
+        .map(|label| net::label_scheme(&label))
+        .unwrap_or("ip");
+    let len = (len as usize).min(std::mem::size_of::<libc::sockaddr_storage>());
+    let (family, address) = read_bytes_from_tracee(pid, addr, len)
+        .and_then(|bytes| net::format_sockaddr(scheme, &bytes))?;
+    // Unix socket paths are relative to the cwd like any other path
+    match net::unix_socket_path(&address) {
+        Some(path) => Some((
+            family,
+            format!("unix://{}", resolve_path(path, pid_raw, state)),
+        )),
+        None => Some((family, address)),
+    }
+}
+
+/// With --deny-network, make a connect, or a send to an explicit address,
+/// that the policy doesn't allow fail with EPERM. Returns whether it was blocked.
+fn deny_network(pid: Pid, syscall_num: u64, regs: &SyscallRegs, state: &mut TracerState) -> bool {
+    // struct msghdr starts with msg_name and msg_namelen
+    let msg_name = |msghdr: u64| {
+        read_bytes_from_tracee(pid, msghdr, 12)
+            .map(|hdr| {
+                let name = u64::from_ne_bytes(hdr[0..8].try_into().unwrap_or([0; 8]));
+                let len = u32::from_ne_bytes(hdr[8..12].try_into().unwrap_or([0; 4]));
+                (name, len as u64)
+            })
+            .unwrap_or((0, 0))
+    };
+    let (operation, destinations) = match syscall_num {
+        SYS_CONNECT => ("connect", vec![(regs.rsi, regs.rdx)]),
+        SYS_SENDTO => ("sendto", vec![(regs.r8, regs.r9)]),
+        SYS_SENDMSG => ("sendmsg", vec![msg_name(regs.rsi)]),
+        SYS_SENDMMSG => {
+            let count = (regs.rdx as u32).min(1024) as u64;
+            let names = (0..count).map(|i| msg_name(regs.rsi + i * 64)).collect();
+            ("sendmmsg", names)
+        }
+        _ => return false,
+    };
+    deny_destinations(pid, regs.rdi, operation, destinations, state)
+}
+
+/// Fail the send or connect a tracee is entering on `fd` if the policy
+/// doesn't allow one of `destinations` (address and length in its memory).
+fn deny_destinations(
+    pid: Pid,
+    fd: u64,
+    operation: &'static str,
+    destinations: Vec<(u64, u64)>,
+    state: &mut TracerState,
+) -> bool {
+    let pid_raw = pid.as_raw();
+    let fd = fd as i32;
+    for (addr, len) in destinations {
+        // Sends on a connected socket were checked when it connected
+        let Some((family, address)) = socket_address(pid, fd, addr, len, state) else {
+            continue;
+        };
+        if state
+            .network_policy
+            .as_ref()
+            .is_none_or(|policy| policy.allows(&address))
+        {
+            continue;
+        }
+        skip_syscall(pid, libc::EPERM, state);
+        state.network_connections.push(NetworkConnection {
+            pid: pid_raw,
+            family,
+            operation,
+            address,
+            local_address: None,
+            success: false,
+            denied: true,
+        });
+        return true;
+    }
+    false
+}
+
+/// Stop the syscall a tracee is entering from running; it fails with `errno`.
+fn skip_syscall(pid: Pid, errno: i32, state: &mut TracerState) {
+    match Registers::read(pid).and_then(|raw| raw.skip_syscall(pid)) {
+        Ok(nr) => {
+            // Its return value is filled in at the exit stop
+            state.in_syscall.insert(pid.as_raw(), (nr, None));
+            state.denied_syscalls.insert(pid.as_raw(), errno);
+        }
+        Err(_) => stats::count_error(),
+    }
+}
+
+fn handle_socket_exit(pid: Pid, call: PendingSocketCall, ret_val: i64, state: &mut TracerState) {
+    let pid_raw = pid.as_raw();
+
+    match call {
+        PendingSocketCall::Socket { scheme } => {
+            if ret_val >= 0 {
+                state
+                    .fd_table_mut(pid_raw)
+                    .insert(ret_val as i32, net::unbound_label(scheme));
+            }
+        }
+        PendingSocketCall::Address {
+            operation,
+            fd,
+            family,
+            address,
+        } => {
+            // A non-blocking connect reports EINPROGRESS once the attempt is under way
+            let success =
+                ret_val == 0 || (operation == "connect" && ret_val == -(libc::EINPROGRESS as i64));
+            if success {
+                // connect names the peer; bind only labels a socket that has no peer yet
+                let relabel = operation == "connect"
+                    || state
+                        .fd_path(pid_raw, fd)
+                        .is_none_or(|label| label.ends_with("://"));
+                if relabel {
+                    state.fd_table_mut(pid_raw).insert(fd, address.clone());
+                }
+            }
+            let local_address = (success && operation == "connect")
+                .then(|| net::local_address(pid_raw, fd, net::label_scheme(&address)))
+                .flatten();
+            // A socket file is state outside the command, like a file it reads
+            let connected = success && operation == "connect";
+            if let Some(path) = net::unix_socket_path(&address).filter(|_| connected) {
+                state.record_unix_socket(pid_raw, path.to_string());
+            }
+            state.network_connections.push(NetworkConnection {
+                pid: pid_raw,
+                family,
+                operation,
+                address,
+                local_address,
+                success,
+                denied: false,
+            });
+        }
+        PendingSocketCall::Accept {
+            fd,
+            addr_ptr,
+            addrlen_ptr,
+        } => {
+            // Failed accepts are mostly EAGAIN polling on non-blocking servers
+            if ret_val < 0 {
+                return;
+            }
+            let listen_label = state.fd_path(pid_raw, fd).unwrap_or_default();
+            let peer = read_bytes_from_tracee(pid, addrlen_ptr, 4)
+                .map(|len| u32::from_ne_bytes([len[0], len[1], len[2], len[3]]) as usize)
+                .and_then(|len| read_bytes_from_tracee(pid, addr_ptr, len.min(128)))
+                .and_then(|bytes| net::format_sockaddr(net::label_scheme(&listen_label), &bytes));
+            let (family, address) = match peer {
+                Some(peer) => peer,
+                None => ("unknown", listen_label),
+            };
+            state
+                .fd_table_mut(pid_raw)
+                .insert(ret_val as i32, address.clone());
+            let scheme = net::label_scheme(&address);
+            state.network_connections.push(NetworkConnection {
+                pid: pid_raw,
+                family,
+                operation: "accept",
+                local_address: net::local_address(pid_raw, ret_val as i32, scheme),
+                address,
+                success: true,
+                denied: false,
+            });
+        }
+        PendingSocketCall::Listen { fd } => {
+            let Some(label) = state.fd_path(pid_raw, fd) else {
+                return;
+            };
+            // bind to port 0 leaves the real port to the kernel; report that one
+            let address =
+                net::local_address(pid_raw, fd, net::label_scheme(&label)).unwrap_or(label);
+            state.network_connections.push(NetworkConnection {
+                pid: pid_raw,
+                family: net::label_family(&address),
+                operation: "listen",
+                address,
+                local_address: None,
+                success: ret_val == 0,
+                denied: false,
+            });
+        }
+    }
+}
+
+/// Make a path argument absolute and lexically normalized (see paths::normalize).
+fn resolve_path(path: &str, pid: i32, state: &TracerState) -> String {
+    if path.starts_with('/') {
+        // Absolute paths of a chrooted process are relative to its root
+        return match state.roots.get(&pid) {
+            Some(root) => paths::normalize(&format!("{}/{}", root, path)),
+            None => paths::normalize(path),
+        };
+    }
+
+    // Resolve relative to the cwd as of the last chdir, falling back to /proc for
+    // processes that haven't changed it (the kernel reports it in our namespace,
+    // so both are already correct for chrooted processes)
+    let cwd = state.cwds.get(&state.fs_owner(pid)).cloned().or_else(|| {
+        std::fs::read_link(format!("/proc/{}/cwd", pid))
+            .ok()
+            .map(|cwd| cwd.to_string_lossy().to_string())
+    });
+    if let Some(cwd) = cwd {
+        return paths::normalize(&format!("{}/{}", cwd, path));
+    }
+
+    paths::normalize(path)
+}
+
+/// Resolve a path argument of an *at() syscall against its dirfd.
+fn resolve_path_at(dirfd: i32, path: &str, pid: i32, state: &TracerState) -> String {
+    if path.starts_with('/') || dirfd == libc::AT_FDCWD {
+        return resolve_path(path, pid, state);
+    }
+
+    let base = state
+        .fd_path(pid, dirfd)
+        .filter(|p| p.starts_with('/'))
+        .or_else(|| {
+            std::fs::read_link(format!("/proc/{}/fd/{}", pid, dirfd))
+                .ok()
+                .map(|p| p.to_string_lossy().to_string())
+        });
+
+    match base {
+        // AT_EMPTY_PATH: the call operates on dirfd itself
+        Some(base) if path.is_empty() => base,
+        Some(base) => paths::normalize(&format!("{}/{}", base, path)),
+        None => resolve_path(path, pid, state),
+    }
+}
+
+/// Read an openat2 struct open_how { u64 flags; u64 mode; u64 resolve; }.
+fn read_open_how(pid: Pid, addr: u64) -> (u64, u64, u64) {
+    read_struct_from_tracee::<libc::open_how>(pid, addr)
+        .map(|how| (how.flags, how.mode, how.resolve))
+        .unwrap_or_default()
+}
+
+/// Resolve an openat2 path, honouring the RESOLVE_* flags that change its meaning.
+fn resolve_openat2_path(
+    dirfd: i32,
+    path: String,
+    resolve: u64,
+    pid: i32,
+    state: &TracerState,
+) -> String {
+    // RESOLVE_IN_ROOT treats dirfd as "/", so absolute paths stay under it.
+    // (RESOLVE_BENEATH rejects them outright, so the open fails.)
+    let path = if resolve & RESOLVE_IN_ROOT != 0 && dirfd != libc::AT_FDCWD {
+        path.trim_start_matches('/').to_string()
+    } else {
+        path
+    };
+    resolve_path_at(dirfd, &path, pid, state)
+}
+
+// =============================================================================
+// Ptrace event handling (fork/clone/exec)
+// =============================================================================
+
+fn trace_options(exit_kill: bool, seccomp: bool, follow_forks: bool) -> ptrace::Options {
+    use nix::sys::ptrace::Options;
+    // Threads are reported as clones; fork, vfork and posix_spawn children are not
+    let mut opts = Options::PTRACE_O_TRACESYSGOOD
+        | Options::PTRACE_O_TRACECLONE
+        | Options::PTRACE_O_TRACEEXEC
+        | Options::PTRACE_O_TRACEEXIT;
+    if follow_forks {
+        opts |= Options::PTRACE_O_TRACEFORK | Options::PTRACE_O_TRACEVFORK;
+    }
+    if exit_kill {
+        // If the tracer dies, SIGKILL the tracees instead of leaving them in ptrace-stop
+        opts |= Options::PTRACE_O_EXITKILL;
+    }
+    if seccomp {
+        // The filter's RET_TRACE becomes a PTRACE_EVENT_SECCOMP stop
+        opts |= Options::PTRACE_O_TRACESECCOMP;
+    }
+    opts
+}
+
+fn setup_ptrace(pid: Pid, state: &TracerState) {
+    // Seized tracees inherit options on current kernels, but set them explicitly
+    if let Err(e) = ptrace::setoptions(
+        pid,
+        trace_options(state.exit_kill, state.seccomp, state.follow_forks),
+    ) {
+        eprintln!("Warning: ptrace setoptions failed: {}", e);
+    }
+}
+
+/// Whether a PTRACE_EVENT_STOP is a group-stop (SIGTRAP means an interrupt-stop).
+fn is_group_stop(sig: Signal) -> bool {
+    matches!(
+        sig,
+        Signal::SIGSTOP | Signal::SIGTSTP | Signal::SIGTTIN | Signal::SIGTTOU
+    )
+}
+
+/// Keep a group-stopped tracee stopped; the next SIGCONT or signal wakes it with another stop.
+fn listen(pid: Pid) {
+    // nix has no wrapper for PTRACE_LISTEN
+    unsafe { libc::ptrace(libc::PTRACE_LISTEN, pid.as_raw(), 0, 0) };
+}
+
+fn handle_ptrace_event(pid: Pid, event: i32, state: &mut TracerState) {
+    match event {
+        libc::PTRACE_EVENT_FORK | libc::PTRACE_EVENT_VFORK | libc::PTRACE_EVENT_CLONE => {
+            if let Ok(child_pid) = ptrace::getevent(pid) {
+                let child_pid_i32 = child_pid as i32;
+                let child = Pid::from_raw(child_pid_i32);
+                record_clone(pid.as_raw(), child_pid_i32, read_clone_flags(pid), state);
+                if state.early_children.remove(&child_pid_i32) {
+                    // Its initial stop was held back waiting for this event
+                    setup_ptrace(child, state);
+                    resume(child, None, state);
+                }
+            }
+        }
+        libc::PTRACE_EVENT_EXEC => {
+            // When a non-leader thread execs it takes over the leader's pid;
+            // the event message holds the tid that actually called execve.
+            let former_tid = ptrace::getevent(pid)
+                .map(|t| t as i32)
+                .unwrap_or(pid.as_raw());
+            complete_exec(pid, former_tid, state);
+        }
+        libc::PTRACE_EVENT_EXIT => {
+            // The process is about to exit; the event message is its wait status
+            if let Ok(status) = ptrace::getevent(pid) {
+                let status = status as libc::c_int;
+                if libc::WIFSIGNALED(status) {
+                    record_process_exit(pid, None, Some(libc::WTERMSIG(status)), state);
+                } else {
+                    record_process_exit(pid, Some(libc::WEXITSTATUS(status)), None, state);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Set up a new child of `parent` the way `clone_flags` shares state with it.
+fn record_clone(parent: i32, child: i32, clone_flags: u64, state: &mut TracerState) {
+    state.active_pids.insert(child);
+    // A recycled pid must not inherit a stale entry/exit state
+    state.in_syscall.remove(&child);
+    clone_fd_table(parent, child, clone_flags, state);
+    clone_mappings(parent, child, clone_flags, state);
+    clone_cwd(parent, child, clone_flags, state);
+    if let Some(root) = state.roots.get(&parent).cloned() {
+        state.roots.insert(child, root);
+    }
+    if clone_flags & libc::CLONE_THREAD as u64 != 0 {
+        capture_thread_info(child, parent, state);
+    } else {
+        capture_process_info(Pid::from_raw(child), state, Some(parent));
+    }
+    state.emit(parent, &TraceEvent::Fork { child_pid: child });
+}
+
+/// Forget what the old image of `pid` held and record the program it exec'd;
+/// `former_tid` is the thread that called execve.
+fn complete_exec(pid: Pid, former_tid: i32, state: &mut TracerState) {
+    let pid_raw = pid.as_raw();
+    // Some kernels never report the execve exit for the old image; forget
+    // the entry and let the -ENOSYS check classify the next stop.
+    state.in_syscall.remove(&pid_raw);
+    state.in_syscall.remove(&former_tid);
+    unshare_fd_table(pid_raw, state);
+    refresh_fd_table(pid_raw, state);
+    // exec replaces the whole address space of the thread group
+    let owner = state.mm_owner(pid_raw);
+    state.mappings.remove(&owner);
+    state.rings.retain(|(o, _), _| *o != owner);
+    state.registered_rings.retain(|(p, _), _| *p != pid_raw);
+    state.address_spaces.remove(&pid_raw);
+
+    let (filename, argv) = state
+        .pending_execs
+        .remove(&former_tid)
+        .or_else(|| state.pending_execs.remove(&pid_raw))
+        .unwrap_or_default();
+
+    // Process exec'd - recapture info
+    let parent = state.processes.get(&pid_raw).and_then(|p| p.parent_pid);
+    capture_process_info(pid, state, parent);
+    // argv can't be read from a process that wasn't stopped at its execve
+    let argv = match state.processes.get(&pid_raw) {
+        Some(process) if argv.is_empty() => process.command.clone(),
+        _ => argv,
+    };
+    // The root is kept: its exit is the trace's exit code
+    let unfollowed = state.root_pid != Some(pid_raw) && is_unfollowed(pid_raw, &argv, state);
+    record_exec(pid, filename, argv, state);
+    if unfollowed {
+        stop_following(pid, state);
+    }
+}
+
+/// Append an exec record for a pid stopped at (or right after) a successful exec.
+fn record_exec(pid: Pid, filename: Option<String>, argv: Vec<String>, state: &mut TracerState) {
+    if !state.traces(TraceCategory::Process) {
+        return;
+    }
+    let pid_raw = pid.as_raw();
+    let path = std::fs::read_link(format!("/proc/{}/exe", pid_raw))
+        .map(|p| p.to_string_lossy().to_string())
+        .ok()
+        .or(filename);
+
+    state.emit(pid_raw, &TraceEvent::Exec { command: &argv });
+
+    if let Some(process) = state.processes.get_mut(&pid_raw) {
+        // /proc/<pid>/cmdline can come back empty for processes that exit fast
+        if process.command.is_empty() {
+            process.command = argv.clone();
+        }
+        process.execs.push(ExecRecord {
+            timestamp: now_secs(),
+            path,
+            argv,
+        });
+    }
+}
+
+/// Whether the program a pid just exec'd is one of --no-follow, by the file
+/// name of its binary or of its argv[0] (which names the script, not the interpreter).
+fn is_unfollowed(pid: i32, argv: &[String], state: &TracerState) -> bool {
+    if state.no_follow.is_empty() {
+        return false;
+    }
+    let exe = std::fs::read_link(format!("/proc/{}/exe", pid)).ok();
+    let names = [exe.as_deref(), argv.first().map(Path::new)];
+    let unfollowed = names
+        .into_iter()
+        .flatten()
+        .filter_map(|path| path.file_name()?.to_str())
+        .any(|name| state.no_follow.iter().any(|program| program == name));
+    unfollowed
+}
+
+/// Detach a tracee at its exec; it and whatever it starts run on untraced.
+fn stop_following(pid: Pid, state: &mut TracerState) {
+    let pid_raw = pid.as_raw();
+    if ptrace::detach(pid, None).is_err() {
+        stats::count_error();
+        return;
+    }
+    state.active_pids.remove(&pid_raw);
+    release_fd_table(pid_raw, state);
+    release_mappings(pid_raw, state);
+    release_cwd(pid_raw, state);
+    state.roots.remove(&pid_raw);
+    state.in_syscall.remove(&pid_raw);
+    if let Some(process) = state.processes.get_mut(&pid_raw) {
+        process.detached = true;
+    }
+}
+
+// =============================================================================
+// Signal handling (SIGINT/SIGTERM sent to the tracer itself)
+// =============================================================================
+
+/// Signal number received by the tracer, or 0; the trace loop polls it.
+static INTERRUPT_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn record_interrupt(sig: libc::c_int) {
+    INTERRUPT_SIGNAL.store(sig, Ordering::SeqCst);
+}
+
+fn install_interrupt_handler() -> Result<(), TraceError> {
+    use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet};
+
+    // A signal left over from an earlier trace in this process must not end this one
+    INTERRUPT_SIGNAL.store(0, Ordering::SeqCst);
+    // No SA_RESTART: we want the blocking waitpid in trace_loop to return EINTR.
+    let action = SigAction::new(
+        SigHandler::Handler(record_interrupt),
+        SaFlags::empty(),
+        SigSet::empty(),
+    );
+    for sig in [Signal::SIGINT, Signal::SIGTERM] {
+        unsafe { sigaction(sig, &action) }.map_err(|e| TraceError::SignalHandler(e.into()))?;
+    }
+    Ok(())
+}
+
+fn interrupt_signal() -> Option<i32> {
+    match INTERRUPT_SIGNAL.load(Ordering::SeqCst) {
+        0 => None,
+        sig => Some(sig),
+    }
+}
+
+// =============================================================================
+// Timeout (--timeout)
+// =============================================================================
+
+/// How long the tree gets to exit after SIGTERM before it is SIGKILLed.
+const TIMEOUT_GRACE: Duration = Duration::from_secs(5);
+
+/// Exit code on timeout, matching coreutils timeout(1).
+const TIMEOUT_EXIT_CODE: i32 = 124;
+
+static TIMER_FIRED: AtomicBool = AtomicBool::new(false);
+
+/// The POSIX timer armed by arm_timer, or -1. Its id may well be 0 (a null timer_t).
+static TIMER: AtomicIsize = AtomicIsize::new(-1);
+
+extern "C" fn record_timer(_: libc::c_int) {
+    TIMER_FIRED.store(true, Ordering::SeqCst);
+}
+
+/// Install the SIGALRM handler and create a timer that signals this thread.
+fn install_timer_handler() -> Result<(), TraceError> {
+    use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet};
+
+    TIMER_FIRED.store(false, Ordering::SeqCst);
+    let action = SigAction::new(
+        SigHandler::Handler(record_timer),
+        SaFlags::empty(),
+        SigSet::empty(),
+    );
+    unsafe { sigaction(Signal::SIGALRM, &action) }
+        .map_err(|e| TraceError::SignalHandler(e.into()))?;
+
+    // A process-directed alarm could go to any thread of a library caller,
+    // leaving the trace loop's waitpid uninterrupted
+    let mut event: libc::sigevent = unsafe { std::mem::zeroed() };
+    event.sigev_notify = libc::SIGEV_THREAD_ID;
+    event.sigev_signo = libc::SIGALRM;
+    event.sigev_notify_thread_id = unsafe { libc::gettid() };
+    let mut timer: libc::timer_t = std::ptr::null_mut();
+    if unsafe { libc::timer_create(libc::CLOCK_MONOTONIC, &mut event, &mut timer) } != 0 {
+        return Err(TraceError::SignalHandler(std::io::Error::last_os_error()));
+    }
+    TIMER.store(timer as isize, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Arm the one-shot timer; its EINTR wakes the blocking waitpid without polling.
+fn arm_timer(after: Duration) {
+    let timer = TIMER.load(Ordering::SeqCst);
+    if timer == -1 {
+        return;
+    }
+    let spec = libc::itimerspec {
+        it_interval: libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        },
+        it_value: libc::timespec {
+            tv_sec: after.as_secs() as libc::time_t,
+            tv_nsec: after.subsec_nanos() as libc::c_long,
+        },
+    };
+    unsafe { libc::timer_settime(timer as libc::timer_t, 0, &spec, std::ptr::null_mut()) };
+}
+
+/// Delete the timer, so it can't go off during whatever the caller does next.
+fn disarm_timer() {
+    let timer = TIMER.swap(-1, Ordering::SeqCst);
+    if timer != -1 {
+        unsafe { libc::timer_delete(timer as libc::timer_t) };
+    }
+}
+
+/// First expiry: SIGTERM the whole tree and start the grace period. Second: SIGKILL it.
+fn handle_timer(state: &mut TracerState) {
+    if !state.timed_out {
+        state.timed_out = true;
+        // Not just the root: a hung build is often a child the root is waiting on
+        eprintln!("roar-tracer: timeout expired, sending SIGTERM to the traced processes");
+        signal_tracees(state, libc::SIGTERM);
+        arm_timer(TIMEOUT_GRACE);
+    } else {
+        eprintln!("roar-tracer: grace period expired, killing traced processes");
+        kill_tracees(state);
+    }
+}
+
+fn kill_tracees(state: &TracerState) {
+    signal_tracees(state, libc::SIGKILL);
+}
+
+fn signal_tracees(state: &TracerState, sig: libc::c_int) {
+    for pid in &state.active_pids {
+        unsafe { libc::kill(*pid, sig) };
+    }
+}
+
+// =============================================================================
+// Main tracer loop
+// =============================================================================
+
+/// Wait for the forked child to stop itself, then seize it.
+fn seize_stopped_child(child: Pid, options: ptrace::Options) -> nix::Result<()> {
+    loop {
+        match waitpid(child, Some(WaitPidFlag::WUNTRACED)) {
+            Ok(WaitStatus::Stopped(..)) => break,
+            Ok(_) => return Err(nix::errno::Errno::ESRCH),
+            Err(nix::errno::Errno::EINTR) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    ptrace::seize(child, options)?;
+    // Seizing a stopped task makes it report a PTRACE_EVENT_STOP; consume it
+    loop {
+        match waitpid(child, Some(WaitPidFlag::__WALL)) {
+            Ok(WaitStatus::PtraceEvent(..)) => return Ok(()),
+            Ok(WaitStatus::Exited(..)) | Ok(WaitStatus::Signaled(..)) => {
+                return Err(nix::errno::Errno::ESRCH)
+            }
+            Ok(_) => continue,
+            Err(nix::errno::Errno::EINTR) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn now_secs() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time before UNIX epoch")
+        .as_secs_f64()
+}
+
+/// The report for everything recorded. A partial one, for a checkpoint, copies
+/// what the final report moves out of the state.
+fn build_report(
+    state: &mut TracerState,
+    start_time: f64,
+    exit_code: i32,
+    partial: bool,
+) -> TraceReport {
+    fn take<T: Clone + Default>(value: &mut T, partial: bool) -> T {
+        if partial {
+            value.clone()
+        } else {
+            std::mem::take(value)
+        }
+    }
+    let end_time = now_secs();
+
+    let (mut processes, env_redacted) = finish_environments(state);
+    let mut process_files = take(&mut state.process_files, partial);
+    for process in &mut processes {
+        if let Some(files) = process_files.remove(&process.pid) {
+            process.opened_files = files.opened;
+            process.read_files = files.read;
+            process.written_files = files.written;
+            process.first_access = Some(files.first_access);
+            process.last_access = Some(files.last_access);
+        }
+    }
+    let mut env_accessed = state
+        .root_pid
+        .and_then(|root| state.processes.get(&root))
+        .map(|p| p.env.clone())
+        .unwrap_or_default();
+    state.env_redactor.redact(&mut env_accessed);
+
+    let mut files = take(&mut state.files, partial);
+    // Checkpoints leave this to the end: rehashing every output each time is too slow
+    if state.hash_writes && !partial {
+        for (path, stats) in files.iter_mut().filter(|(_, s)| s.written) {
+            stats.written_sha256 = digest::sha256_file(path);
+        }
+    }
+    TraceReport {
+        schema_version: SCHEMA_VERSION,
+        format_version: SCHEMA_VERSION,
+        processes,
+        opened_files: files_where(&files, |s| s.opened),
+        read_files: files_where(&files, |s| s.read),
+        written_files: files_where(&files, |s| s.written),
+        created_files: files_where(&files, |s| s.created),
+        files,
+        stat_files: take(&mut state.stat_files, partial).into_iter().collect(),
+        missing_files: take(&mut state.missing_files, partial)
+            .into_iter()
+            .collect(),
+        metadata_changed_files: take(&mut state.metadata_changed_files, partial)
+            .into_iter()
+            .collect(),
+        created_dirs: take(&mut state.created_dirs, partial).into_iter().collect(),
+        removed_dirs: take(&mut state.removed_dirs, partial).into_iter().collect(),
+        deleted_files: take(&mut state.deleted_files, partial)
+            .into_iter()
+            .collect(),
+        listed_dirs: take(&mut state.listed_dirs, partial).into_iter().collect(),
+        synced_files: take(&mut state.synced_files, partial).into_iter().collect(),
+        unix_sockets: take(&mut state.unix_sockets, partial).into_iter().collect(),
+        links: take(&mut state.links, partial),
+        readlinks: take(&mut state.readlinks, partial),
+        failed_opens: take(&mut state.failed_opens, partial),
+        env_accessed,
+        env_redacted,
+        mnt_ns: read_mnt_ns(std::process::id() as i32),
+        network_connections: take(&mut state.network_connections, partial),
+        resolved_hostnames: take(&mut state.resolved_hostnames, partial)
+            .into_iter()
+            .collect(),
+        network_traffic: take(&mut state.network_traffic, partial),
+        io_uring_used: state.processes.values().any(|p| p.io_uring_used),
+        pipe_reads: sorted_per_pid(take(&mut state.pipe_reads, partial)),
+        pipe_writes: sorted_per_pid(take(&mut state.pipe_writes, partial)),
+        shared_memory: take(&mut state.shared_memory, partial),
+        path_filters: state.filter.summary().clone(),
+        traced_categories: state.trace.clone(),
+        path_limit: state.path_limit.clone(),
+        path_aliases: take(&mut state.path_aliases, partial),
+        interrupted: state.interrupted,
+        timed_out: state.timed_out,
+        partial,
+        tracer_stats: take(&mut state.stats, partial).map(|s| s.finish(SYSCALL_NAMES)),
+        start_time,
+        end_time,
+        wall_time: end_time - start_time,
+        exit_code,
+    }
+}
+
+/// Paths whose stats satisfy `pred`, for the flat file lists.
+fn files_where(files: &HashMap<String, FileStats>, pred: fn(&FileStats) -> bool) -> Vec<String> {
+    files
+        .iter()
+        .filter(|(_, stats)| pred(stats))
+        .map(|(path, _)| path.clone())
+        .collect()
+}
+
+/// Sets of per-process labels as sorted lists, for stable output.
+fn sorted_per_pid(map: HashMap<i32, HashSet<String>>) -> HashMap<i32, Vec<String>> {
+    map.into_iter()
+        .map(|(pid, labels)| {
+            let mut labels: Vec<String> = labels.into_iter().collect();
+            labels.sort();
+            (pid, labels)
+        })
+        .collect()
+}
+
+/// Reduce each process environment to its diff against the parent and mask
+/// sensitive values. Returns the processes and every variable name masked.
+fn finish_environments(state: &TracerState) -> (Vec<ProcessInfo>, Vec<String>) {
+    let mut redacted = HashSet::new();
+    let processes = state
+        .processes
+        .values()
+        .map(|process| {
+            let mut process = process.clone();
+            let parent_env = process
+                .parent_pid
+                .and_then(|ppid| state.processes.get(&ppid))
+                // A thread's environment is its process's
+                .map(|parent| {
+                    let leader = parent.thread_of.and_then(|tgid| state.processes.get(&tgid));
+                    &leader.unwrap_or(parent).env
+                });
+            // An empty environment means /proc was unreadable, not that it was cleared
+            if let (Some(parent_env), false) = (parent_env, process.env.is_empty()) {
+                let (changed, unset) = environ::diff(&process.env, parent_env);
+                process.env = changed;
+                process.env_unset = unset;
+            }
+            redacted.extend(state.env_redactor.redact(&mut process.env));
+            process
+        })
+        .collect();
+    let mut redacted: Vec<String> = redacted.into_iter().collect();
+    redacted.sort();
+    (processes, redacted)
+}
+
+fn trace_loop(state: &mut TracerState) -> i32 {
+    let mut exit_code = 0;
+
+    while !state.active_pids.is_empty() {
+        if interrupt_signal().is_some() {
+            state.interrupted = true;
+            break;
+        }
+        if TIMER_FIRED.swap(false, Ordering::SeqCst) {
+            handle_timer(state);
+        }
+        state.checkpoint();
+
+        let status = waitpid(None, Some(WaitPidFlag::__WALL));
+        if let (Ok(_), Some(stats)) = (&status, state.stats.as_mut()) {
+            stats.count_stop();
+        }
+        match status {
+            Ok(WaitStatus::PtraceSyscall(pid)) => {
+                let sample = state.stats.as_mut().and_then(|s| s.start_handler());
+                handle_syscall(pid, state);
+                if let Some(stats) = state.stats.as_mut() {
+                    stats.end_handler(sample);
+                }
+                resume(pid, None, state);
+            }
+            // Under --seccomp, the entry of a syscall the filter traps
+            Ok(WaitStatus::PtraceEvent(pid, _sig, libc::PTRACE_EVENT_SECCOMP)) if state.seccomp => {
+                let sample = state.stats.as_mut().and_then(|s| s.start_handler());
+                handle_syscall(pid, state);
+                if let Some(stats) = state.stats.as_mut() {
+                    stats.end_handler(sample);
+                }
+                resume(pid, None, state);
+            }
+            Ok(WaitStatus::PtraceEvent(pid, sig, libc::PTRACE_EVENT_STOP)) => {
+                if !state.active_pids.contains(&pid.as_raw()) {
+                    // A new child can stop before its parent's fork event is
+                    // reported; hold it so it never runs without fd table,
+                    // process info or options set up.
+                    state.early_children.insert(pid.as_raw());
+                } else if is_group_stop(sig) {
+                    // Leave it stopped until SIGCONT, like it would be untraced
+                    listen(pid);
+                } else {
+                    // Interrupt-stop: initial stop of a new child or after attach
+                    setup_ptrace(pid, state);
+                    resume(pid, None, state);
+                }
+            }
+            Ok(WaitStatus::PtraceEvent(pid, _sig, event)) => {
+                handle_ptrace_event(pid, event, state);
+                resume(pid, None, state);
+            }
+            Ok(WaitStatus::Exited(pid, code)) => {
+                record_exited(pid, Some(code), None, state);
+                // Capture exit code of the root process
+                if state.root_pid == Some(pid.as_raw()) {
+                    exit_code = code;
+                }
+            }
+            Ok(WaitStatus::Signaled(pid, sig, _)) => {
+                record_exited(pid, None, Some(sig as i32), state);
+                // If root process was signaled, reflect that
+                if state.root_pid == Some(pid.as_raw()) {
+                    exit_code = 128 + sig as i32;
+                }
+            }
+            Ok(WaitStatus::Stopped(pid, sig)) => {
+                // Signal-delivery-stop. Under PTRACE_SEIZE, syscall stops carry the
+                // TRACESYSGOOD bit and exec is an event, so even a SIGTRAP here was
+                // sent to the tracee (int3, raise, kill -TRAP) and must reach it.
+                resume(pid, Some(sig), state);
+            }
+            Ok(_) => {}
+            Err(nix::errno::Errno::ECHILD) => break,
+            // Our own timer and interrupt handlers wake waitpid up
+            Err(nix::errno::Errno::EINTR) => {}
+            Err(_) => stats::count_error(),
+        }
+    }
+
+    exit_code
+}
+
+// =============================================================================
+// fanotify backend
+// =============================================================================
+
+/// Record file accesses until the root exits, then those still queued.
+/// Descendants that outlive the root are no longer watched.
+fn watch_loop(
+    notifier: &Notifier,
+    forks: Option<&ForkWatcher>,
+    pidfd: &OwnedFd,
+    root: Pid,
+    state: &mut TracerState,
+) -> i32 {
+    let mut tree = WatchedTree::default();
+    let mut exit_code = 0;
+
+    loop {
+        if interrupt_signal().is_some() {
+            state.interrupted = true;
+            break;
+        }
+        if TIMER_FIRED.swap(false, Ordering::SeqCst) {
+            handle_timer(state);
+        }
+        state.checkpoint();
+
+        notifier.wait(pidfd);
+        tree.record(notifier.events(), forks, state);
+
+        let (code, signal) = match waitpid(root, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::Exited(_, code)) => (Some(code), None),
+            Ok(WaitStatus::Signaled(_, sig, _)) => (None, Some(sig as i32)),
+            Err(nix::errno::Errno::ECHILD) => break,
+            _ => continue,
+        };
+        record_process_exit(root, code, signal, state);
+        state.emit(
+            root.as_raw(),
+            &TraceEvent::Exit {
+                exit_code: code,
+                signal,
+            },
+        );
+        exit_code = code.unwrap_or_else(|| 128 + signal.unwrap_or(0));
+        break;
+    }
+
+    tree.record(notifier.events(), forks, state);
+    exit_code
+}
+
+/// Which processes belong to a watched command. Members are state.active_pids.
+#[derive(Default)]
+struct WatchedTree {
+    parents: HashMap<i32, i32>, // forked member -> its parent, until it shows up in an event
+    outsiders: HashSet<i32>,    // pids known not to descend from the root
+}
+
+impl WatchedTree {
+    fn record(
+        &mut self,
+        events: Vec<fanotify::Event>,
+        forks: Option<&ForkWatcher>,
+        state: &mut TracerState,
+    ) {
+        // Events carry no time; they are read moments after they happen
+        state.now = state.started.elapsed().as_secs_f64();
+        // Read after the events, so every fork that preceded them is known
+        for (parent, child) in forks.map(ForkWatcher::forks).unwrap_or_default() {
+            if state.active_pids.contains(&parent) {
+                state.active_pids.insert(child);
+                self.parents.insert(child, parent);
+                self.outsiders.remove(&child);
+            }
+        }
+        for event in events {
+            if !self.contains(event.pid, state) {
+                continue;
+            }
+            if event.mask & (fanotify::FAN_OPEN | fanotify::FAN_OPEN_EXEC) != 0 {
+                state.record_watched_open(event.pid, event.path.clone());
+            }
+            if event.mask & fanotify::FAN_ACCESS != 0 {
+                state.record_read(event.pid, event.path.clone(), 0);
+            }
+            if event.mask & fanotify::FAN_MODIFY != 0 {
+                state.record_write(event.pid, event.path, 0);
+            }
+        }
+    }
+
+    /// Whether `pid` is the root or descends from it. Members are captured
+    /// when first seen, usually after their exec; without fork notifications,
+    /// a process that already exited can't be placed and its events are dropped.
+    fn contains(&mut self, pid: i32, state: &mut TracerState) -> bool {
+        if state.active_pids.contains(&pid) {
+            if let Some(parent) = self.parents.remove(&pid) {
+                capture_process_info(Pid::from_raw(pid), state, Some(parent));
+            }
+            return true;
+        }
+        if self.outsiders.contains(&pid) {
+            return false;
+        }
+        let Some(parent) = read_parent_pid(pid) else {
+            return false;
+        };
+        if !self.contains(parent, state) {
+            self.outsiders.insert(pid);
+            return false;
+        }
+        state.active_pids.insert(pid);
+        capture_process_info(Pid::from_raw(pid), state, Some(parent));
+        true
+    }
+}
+
+// =============================================================================
+// eBPF backend
+// =============================================================================
+
+/// Handle probe records until the root has exited and nothing is left in its
+/// cgroup, then those still queued.
+#[cfg(feature = "ebpf")]
+fn probe_loop(
+    probe: &mut ebpf::Probe,
+    cgroup: &ebpf::Cgroup,
+    pidfd: &OwnedFd,
+    root: Pid,
+    state: &mut TracerState,
+) -> i32 {
+    let mut entered = HashMap::new();
+    let mut exit_code = None;
+
+    loop {
+        if interrupt_signal().is_some() {
+            state.interrupted = true;
+            break;
+        }
+        if TIMER_FIRED.swap(false, Ordering::SeqCst) {
+            handle_timer(state);
+        }
+        state.checkpoint();
+
+        // Other processes leaving the cgroup wake nothing up: look every 100ms
+        probe.wait(exit_code.is_none().then_some(pidfd), 100);
+        for record in probe.records() {
+            record_probed(record, &mut entered, state);
+        }
+
+        if exit_code.is_none() {
+            let (code, signal) = match waitpid(root, Some(WaitPidFlag::WNOHANG)) {
+                Ok(WaitStatus::Exited(_, code)) => (Some(code), None),
+                Ok(WaitStatus::Signaled(_, sig, _)) => (None, Some(sig as i32)),
+                Err(nix::errno::Errno::ECHILD) => break,
+                _ => continue,
+            };
+            // Unless its exit_group was already seen
+            if state.active_pids.contains(&root.as_raw()) {
+                record_exited(root, code, signal, state);
+            }
+            exit_code = Some(code.unwrap_or_else(|| 128 + signal.unwrap_or(0)));
+        }
+        if !cgroup.is_populated() {
+            break;
+        }
+    }
+
+    for record in probe.records() {
+        record_probed(record, &mut entered, state);
+    }
+    let lost = probe.lost();
+    if lost > 0 {
+        eprintln!(
+            "Warning: the eBPF ring buffer overflowed; {} syscalls are missing from the trace",
+            lost
+        );
+    }
+    exit_code.unwrap_or(0)
+}
+
+/// Hand one probe record to the handlers the ptrace backend uses. `entered`
+/// holds the syscall each thread is inside of.
+#[cfg(feature = "ebpf")]
+fn record_probed(
+    record: ebpf::Record,
+    entered: &mut HashMap<i32, ebpf::Syscall>,
+    state: &mut TracerState,
+) {
+    // Records carry no time; they are read moments after they happen
+    state.now = state.started.elapsed().as_secs_f64();
+    match record {
+        ebpf::Record::Enter(call) => {
+            let pid = Pid::from_raw(call.tid);
+            if let Some(stats) = state.stats.as_mut() {
+                stats.count_syscall(call.nr);
+            }
+            if matches!(call.nr, ebpf::SYS_EXIT | ebpf::SYS_EXIT_GROUP) {
+                // Still there, but gone by the time we read /proc, more often than not
+                if state.active_pids.contains(&call.tid) {
+                    record_exited(pid, Some(call.args[0] as i32 & 0xff), None, state);
+                }
+                return;
+            }
+            if state.decodes(call.nr) {
+                let regs = call.regs();
+                CAPTURED.set(call.memory.clone());
+                state.begin_syscall_line(pid, &regs);
+                handle_syscall_entry(pid, call.nr, &regs, state);
+                CAPTURED.take();
+            }
+            entered.insert(call.tid, call);
+        }
+        ebpf::Record::Exit { tid, nr, ret } => {
+            let Some(call) = entered.remove(&tid).filter(|call| call.nr == nr) else {
+                return;
+            };
+            let pid = Pid::from_raw(tid);
+            if matches!(nr, SYS_EXECVE | SYS_EXECVEAT) && ret == 0 {
+                complete_exec(pid, tid, state);
+            }
+            if state.decodes(nr) {
+                let regs = SyscallRegs {
+                    rax: ret as u64,
+                    ..call.regs()
+                };
+                handle_syscall_exit(pid, nr, &regs, state);
+                state.end_syscall_line(tid, ret);
+            }
+        }
+        ebpf::Record::Fork { parent, child } => {
+            // Reported while the parent is inside the call that made the child
+            let flags = entered.get(&parent).map_or(0, |call| match call.nr {
+                SYS_CLONE => call.args[0],
+                SYS_CLONE3 => call
+                    .memory
+                    .bytes(call.args[0], 8)
+                    .and_then(|flags| flags.try_into().ok())
+                    .map_or(0, u64::from_ne_bytes),
+                ebpf::SYS_VFORK => (libc::CLONE_VM | libc::CLONE_VFORK) as u64,
+                _ => 0,
+            });
+            record_clone(parent, child, flags, state);
+        }
+        // Already in the memory of the execve entry that follows
+        ebpf::Record::Argv { .. } => {}
+    }
+}
+
+/// Continue a tracee to its next syscall stop, counting failures for --stats.
+/// ESRCH is expected: the tracee can be killed while we look at it.
+///
+/// Under --seccomp the filter reports syscall entries, so a tracee only needs
+/// to stop at the exit of the syscall it is inside of, if any.
+fn resume(pid: Pid, sig: Option<Signal>, state: &TracerState) {
+    let result = if state.seccomp && !state.in_syscall.contains_key(&pid.as_raw()) {
+        ptrace::cont(pid, sig)
+    } else {
+        ptrace::syscall(pid, sig)
+    };
+    match result {
+        Ok(()) | Err(nix::errno::Errno::ESRCH) => {}
+        Err(_) => stats::count_error(),
+    }
+}
+
+// =============================================================================
+// Attach mode (trace an already-running process)
+// =============================================================================
+
+fn read_proc_ids(path: &str) -> Vec<i32> {
+    std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok()?.file_name().to_str()?.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Child pids of every thread of `pid`, from /proc/<pid>/task/<tid>/children.
+fn read_proc_children(pid: i32) -> Vec<i32> {
+    read_proc_ids(&format!("/proc/{}/task", pid))
+        .into_iter()
+        .flat_map(|tid| {
+            std::fs::read_to_string(format!("/proc/{}/task/{}/children", pid, tid))
+                .unwrap_or_default()
+                .split_whitespace()
+                .filter_map(|c| c.parse().ok())
+                .collect::<Vec<i32>>()
+        })
+        .collect()
+}
+
+/// Populate a process's fd table from the file descriptors it already holds.
+fn seed_fd_table(pid: i32, state: &mut TracerState) {
+    for fd in read_proc_ids(&format!("/proc/{}/fd", pid)) {
+        if let Some(label) = proc_fd_label(pid, fd) {
+            state.fd_table_mut(pid).insert(fd, label);
+        }
+    }
+}
+
+/// Seize every thread of `pid` and, recursively, its existing children.
+fn attach_tree(pid: i32, parent_pid: Option<i32>, state: &mut TracerState) {
+    let mut attached_any = false;
+    for tid in read_proc_ids(&format!("/proc/{}/task", pid)) {
+        let task = Pid::from_raw(tid);
+        // Never EXITKILL an attached process: it was running before we came along
+        if let Err(e) = ptrace::seize(task, trace_options(false, false, state.follow_forks)) {
+            eprintln!("Warning: failed to attach to {}: {}", tid, e);
+            continue;
+        }
+        // Force a PTRACE_EVENT_STOP so the trace loop can start syscall tracing
+        let _ = ptrace::interrupt(task);
+        state.active_pids.insert(tid);
+        attached_any = true;
+
+        if tid != pid {
+            // Threads share the leader's descriptor table
+            clone_fd_table(pid, tid, libc::CLONE_FILES as u64, state);
+            clone_mappings(pid, tid, libc::CLONE_VM as u64, state);
+            clone_cwd(pid, tid, libc::CLONE_FS as u64, state);
+        }
+    }
+    if !attached_any {
+        return;
+    }
+
+    seed_fd_table(pid, state);
+    capture_process_info(Pid::from_raw(pid), state, parent_pid);
+
+    if state.follow_forks {
+        for child in read_proc_children(pid) {
+            attach_tree(child, Some(pid), state);
+        }
+    }
+}
+
+/// Stop every tracee and detach, leaving the process tree running untraced.
+fn detach_all(state: &mut TracerState) {
+    for pid_raw in state.active_pids.drain().collect::<Vec<_>>() {
+        let pid = Pid::from_raw(pid_raw);
+        // A tracee must be in a ptrace-stop to be detached
+        let _ = ptrace::interrupt(pid);
+        loop {
+            match waitpid(pid, Some(WaitPidFlag::__WALL)) {
+                Ok(WaitStatus::Stopped(_, sig)) => {
+                    // Signal-delivery-stop: hand the signal back on the way out
+                    let _ = ptrace::detach(pid, Some(sig));
+                    break;
+                }
+                Ok(WaitStatus::PtraceEvent(..)) | Ok(WaitStatus::PtraceSyscall(_)) => {
+                    let _ = ptrace::detach(pid, None);
+                    break;
+                }
+                Ok(WaitStatus::Exited(..)) | Ok(WaitStatus::Signaled(..)) => break,
+                Ok(_) => continue,
+                Err(nix::errno::Errno::EINTR) => continue,
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+// =============================================================================
+// Library API
+// =============================================================================
+
+/// How a launched command is observed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    /// Stop the command at each syscall with ptrace
+    #[default]
+    Ptrace,
+    /// Only watch which files it opens, reads and writes, with fanotify: much
+    /// cheaper, but needs CAP_SYS_ADMIN and records no sockets, execs, fds,
+    /// creations or deletions
+    Fanotify,
+    /// Have BPF programs on the syscall tracepoints report what the command
+    /// does without stopping it: needs root (CAP_BPF and CAP_PERFMON), cgroup
+    /// v2 and tracefs mounted; argv and syscall results are read after the fact
+    #[cfg(feature = "ebpf")]
+    Ebpf,
+}
+
+/// Options that shape a trace, independent of what is traced.
+#[derive(Debug)]
+pub struct TracerConfig {
+    pub backend: Backend,   // attaching always uses ptrace
+    pub filter: PathFilter, // which paths are reported (--include/--exclude)
+    pub resolve_symlinks: bool,
+    pub exit_kill: bool, // SIGKILL the tracees if the tracer dies; never applied when attaching
+    pub timeout: Option<Duration>, // SIGTERM, then SIGKILL, a launched command and its tree after this long
+    pub env_redactor: EnvRedactor,
+    pub stats: bool,                         // fill in TraceReport::tracer_stats
+    pub anon_fds: bool,    // report I/O on memfds and eventfd/timerfd/signalfd fds
+    pub hash_reads: bool,  // SHA-256 each file when first opened for reading
+    pub hash_writes: bool, // SHA-256 each written file once the trace ends
+    pub seccomp: bool, // stop a launched command only at traced syscalls; not when attaching or not following all
+    pub trace: Vec<TraceCategory>, // syscall categories to decode; network is added for deny_network
+    pub follow_forks: bool,        // trace child processes; threads are always traced
+    pub no_follow: Vec<String>,    // stop tracing processes that exec these programs (by file name)
+    pub max_paths: Option<usize>,  // paths held in files, stat_files and missing_files
+    pub spill: Option<EventLog>,   // where accesses to paths past max_paths are logged
+    pub deny_network: Option<NetworkPolicy>, // make network access fail instead of only recording it
+}
+
+impl Default for TracerConfig {
+    fn default() -> Self {
+        TracerConfig {
+            backend: Backend::Ptrace,
+            filter: PathFilter::default(),
+            resolve_symlinks: false,
+            exit_kill: true,
+            timeout: None,
+            env_redactor: EnvRedactor::default(),
+            stats: false,
+            anon_fds: false,
+            hash_reads: false,
+            hash_writes: false,
+            seccomp: false,
+            trace: TraceCategory::ALL.to_vec(),
+            follow_forks: true,
+            no_follow: Vec::new(),
+            max_paths: None,
+            spill: None,
+            deny_network: None,
+        }
+    }
+}
+
+impl TracerConfig {
+    /// The first option set that only the ptrace backend carries out: other
+    /// backends can't fail a syscall or let a process go.
+    fn ptrace_only_option(&self) -> Option<&'static str> {
+        if self.deny_network.is_some() {
+            Some("deny_network")
+        } else if self.seccomp {
+            Some("seccomp")
+        } else if !self.follow_forks {
+            Some("follow_forks = false")
+        } else if !self.no_follow.is_empty() {
+            Some("no_follow")
+        } else {
+            None
+        }
+    }
+}
+
+/// A single trace, of a command it launches or of a process tree it attaches to.
+pub struct Tracer {
+    config: TracerConfig,
+    on_event: Option<EventHook>,
+    on_checkpoint: Option<(Duration, CheckpointHook)>,
+    on_syscall: Option<SyscallHook>,
+}
+
+impl Tracer {
+    pub fn new(config: TracerConfig) -> Self {
+        Tracer {
+            config,
+            on_event: None,
+            on_checkpoint: None,
+            on_syscall: None,
+        }
+    }
+
+    /// Call `hook` with every event as it happens. It runs inside the trace
+    /// loop while the tracee is stopped, so it should be quick.
+    pub fn on_event(mut self, hook: impl FnMut(&EventRecord) + 'static) -> Self {
+        self.on_event = Some(Box::new(hook));
+        self
+    }
+
+    /// Call `hook` with a report of the trace so far, marked partial, at most
+    /// every `interval`, so a tracer that dies still leaves a usable trace.
+    /// Checkpoints are only taken while tracees are stopping, and each one
+    /// copies the whole report.
+    pub fn on_checkpoint(
+        mut self,
+        interval: Duration,
+        hook: impl FnMut(&TraceReport) + 'static,
+    ) -> Self {
+        self.on_checkpoint = Some((interval, Box::new(hook)));
+        self
+    }
+
+    /// Call `hook` with each syscall the tracer handles as it completes, with
+    /// its arguments decoded as strace would. Like on_event, it runs while the
+    /// tracee is stopped. Not called with the fanotify backend.
+    pub fn on_syscall(mut self, hook: impl FnMut(&SyscallRecord) + 'static) -> Self {
+        self.on_syscall = Some(Box::new(hook));
+        self
+    }
+
+    fn into_state(self, start_time: f64) -> TracerState {
+        let mut state = TracerState::new(self.config, self.on_event);
+        state.on_syscall = self.on_syscall;
+        state.checkpoint = self.on_checkpoint.map(|(interval, hook)| Checkpoint {
+            interval,
+            last: Instant::now(),
+            start_time,
+            hook,
+        });
+        state
+    }
+
+    /// Launch `command` and trace it and everything it forks until they all exit.
+    ///
+    /// SIGINT/SIGTERM received meanwhile end the trace early: they are passed on
+    /// to the command and the report is marked interrupted.
+    pub fn run(self, mut command: Command) -> Result<TraceReport, TraceError> {
+        if self.config.backend == Backend::Fanotify {
+            return self.watch(command);
+        }
+        #[cfg(feature = "ebpf")]
+        if self.config.backend == Backend::Ebpf {
+            return self.probe(command);
+        }
+        let start_time = now_secs();
+        let argv = command_argv(&command);
+        let mut state = self.into_state(start_time);
+        let filter = state.seccomp.then(|| {
+            let traced: Vec<u64> = SYSCALL_NAMES
+                .iter()
+                .map(|&(nr, _)| nr)
+                .filter(|&nr| state.decodes(nr))
+                .collect();
+            Filter::new(arch::AUDIT_ARCH, &arch::native_syscalls(&traced))
+        });
+
+        install_interrupt_handler()?;
+        if state.timeout.is_some() {
+            install_timer_handler()?;
+        }
+
+        // Fork and trace
+        match unsafe { fork() } {
+            Ok(ForkResult::Child) => {
+                // Child: stop until the parent has seized us, then exec
+                unsafe { libc::raise(libc::SIGSTOP) };
+
+                // Installed once seized: RET_TRACE without a tracer fails the syscall
+                if let Some(filter) = &filter {
+                    if let Err(err) = filter.install() {
+                        eprintln!("failed to install seccomp filter: {}", err);
+                        unsafe { libc::_exit(1) }
+                    }
+                }
+
+                // This replaces the child process
+                let err = command.exec();
+                eprintln!("exec failed: {}", err);
+                // _exit: the parent's atexit handlers and stdio buffers are not ours to run
+                unsafe { libc::_exit(1) }
+            }
+            Ok(ForkResult::Parent { child }) => {
+                // Parent: wait for child to stop at exec, then trace
+                let child_pid = child.as_raw();
+                state.active_pids.insert(child_pid);
+                state.root_pid = Some(child_pid);
+
+                // Seize the child while it is stopped before exec. PTRACE_SEIZE (unlike
+                // TRACEME) reports group-stops as PTRACE_EVENT_STOP, so job control in
+                // the traced command keeps working.
+                if let Err(e) = seize_stopped_child(
+                    child,
+                    trace_options(state.exit_kill, state.seccomp, state.follow_forks),
+                ) {
+                    let _ = nix::sys::signal::kill(child, nix::sys::signal::Signal::SIGKILL);
+                    return Err(TraceError::Seize(e.into()));
+                }
+                capture_process_info(child, &mut state, None);
+                // Until the exec event recaptures it, /proc shows the forked tracer
+                if let Some(process) = state.processes.get_mut(&child_pid) {
+                    process.command = argv;
+                }
+                let _ = nix::sys::signal::kill(child, nix::sys::signal::Signal::SIGCONT);
+                resume(child, None, &state);
+
+                if let Some(timeout) = state.timeout {
+                    arm_timer(timeout);
+                }
+
+                // Main event loop
+                let mut exit_code = trace_loop(&mut state);
+                disarm_timer();
+                if state.timed_out {
+                    exit_code = TIMEOUT_EXIT_CODE;
+                }
+
+                if let Some(sig) = interrupt_signal() {
+                    // Pass the interrupt on to the traced command; with EXITKILL any
+                    // tracees still around are killed when we exit.
+                    unsafe { libc::kill(child_pid, sig) };
+                    exit_code = 128 + sig;
+                }
+
+                Ok(build_report(&mut state, start_time, exit_code, false))
+            }
+            Err(e) => Err(TraceError::Fork(e.into())),
+        }
+    }
+
+    /// `run` with the fanotify backend: the command runs untraced while every
+    /// mount is watched for file access by it and its descendants.
+    fn watch(self, mut command: Command) -> Result<TraceReport, TraceError> {
+        let start_time = now_secs();
+        let argv = command_argv(&command);
+        if let Some(option) = self.config.ptrace_only_option() {
+            return Err(TraceError::Unsupported(option));
+        }
+        let mut state = self.into_state(start_time);
+        let notifier = Notifier::new().map_err(TraceError::Fanotify)?;
+        // Without it, descendants are placed from /proc when their events are read
+        let forks = ForkWatcher::new().ok();
+
+        install_interrupt_handler()?;
+        if state.timeout.is_some() {
+            install_timer_handler()?;
+        }
+
+        let exit_kill = state.exit_kill;
+        match unsafe { fork() } {
+            Ok(ForkResult::Child) => {
+                // There is no ptrace EXITKILL to rely on
+                if exit_kill {
+                    unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) };
+                }
+                let err = command.exec();
+                eprintln!("exec failed: {}", err);
+                unsafe { libc::_exit(1) }
+            }
+            Ok(ForkResult::Parent { child }) => {
+                let child_pid = child.as_raw();
+                state.active_pids.insert(child_pid);
+                state.root_pid = Some(child_pid);
+                let pidfd = match fanotify::pidfd_open(child_pid) {
+                    Ok(pidfd) => pidfd,
+                    Err(e) => {
+                        let _ = nix::sys::signal::kill(child, Signal::SIGKILL);
+                        let _ = waitpid(child, None);
+                        return Err(TraceError::Fanotify(e));
+                    }
+                };
+                capture_process_info(child, &mut state, None);
+                if let Some(process) = state.processes.get_mut(&child_pid) {
+                    process.command = argv;
+                }
+
+                if let Some(timeout) = state.timeout {
+                    arm_timer(timeout);
+                }
+                let mut exit_code =
+                    watch_loop(&notifier, forks.as_ref(), &pidfd, child, &mut state);
+                disarm_timer();
+                if state.timed_out {
+                    exit_code = TIMEOUT_EXIT_CODE;
+                }
+                if let Some(sig) = interrupt_signal() {
+                    unsafe { libc::kill(child_pid, sig) };
+                    exit_code = 128 + sig;
+                }
+
+                Ok(build_report(&mut state, start_time, exit_code, false))
+            }
+            Err(e) => Err(TraceError::Fork(e.into())),
+        }
+    }
+
+    /// `run` with the eBPF backend: the command runs unstopped in a cgroup of
+    /// its own, whose syscalls the probes report.
+    #[cfg(feature = "ebpf")]
+    fn probe(self, mut command: Command) -> Result<TraceReport, TraceError> {
+        let start_time = now_secs();
+        let argv = command_argv(&command);
+        if let Some(option) = self.config.ptrace_only_option() {
+            return Err(TraceError::Unsupported(option));
+        }
+        let mut state = self.into_state(start_time);
+        let syscalls: Vec<u64> = SYSCALL_NAMES
+            .iter()
+            .map(|&(nr, _)| nr)
+            .filter(|&nr| state.decodes(nr))
+            .chain(ebpf::LIFECYCLE)
+            .collect();
+        let cgroup = ebpf::Cgroup::create().map_err(TraceError::Ebpf)?;
+        let mut probe = ebpf::Probe::new(cgroup.id(), &syscalls).map_err(TraceError::Ebpf)?;
+
+        install_interrupt_handler()?;
+        if state.timeout.is_some() {
+            install_timer_handler()?;
+        }
+
+        let exit_kill = state.exit_kill;
+        match unsafe { fork() } {
+            Ok(ForkResult::Child) => {
+                if exit_kill {
+                    unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) };
+                }
+                // Before exec, so the probes see it from its first syscall
+                if let Err(err) = cgroup.enter() {
+                    eprintln!("failed to join the trace cgroup: {}", err);
+                    unsafe { libc::_exit(1) }
+                }
+                let err = command.exec();
+                eprintln!("exec failed: {}", err);
+                unsafe { libc::_exit(1) }
+            }
+            Ok(ForkResult::Parent { child }) => {
+                let child_pid = child.as_raw();
+                state.active_pids.insert(child_pid);
+                state.root_pid = Some(child_pid);
+                let pidfd = match fanotify::pidfd_open(child_pid) {
+                    Ok(pidfd) => pidfd,
+                    Err(e) => {
+                        let _ = nix::sys::signal::kill(child, Signal::SIGKILL);
+                        let _ = waitpid(child, None);
+                        return Err(TraceError::Ebpf(e));
+                    }
+                };
+                capture_process_info(child, &mut state, None);
+                if let Some(process) = state.processes.get_mut(&child_pid) {
+                    process.command = argv;
+                }
+
+                if let Some(timeout) = state.timeout {
+                    arm_timer(timeout);
+                }
+                let mut exit_code = probe_loop(&mut probe, &cgroup, &pidfd, child, &mut state);
+                disarm_timer();
+                if state.timed_out {
+                    exit_code = TIMEOUT_EXIT_CODE;
+                }
+                if let Some(sig) = interrupt_signal() {
+                    unsafe { libc::kill(child_pid, sig) };
+                    exit_code = 128 + sig;
+                }
+
+                Ok(build_report(&mut state, start_time, exit_code, false))
+            }
+            Err(e) => Err(TraceError::Fork(e.into())),
+        }
+    }
+
+    /// Trace an already-running process, its threads and its existing children
+    /// until they all exit. SIGINT/SIGTERM detach and leave them running.
+    pub fn attach(self, pid: i32) -> Result<TraceReport, TraceError> {
+        if pid <= 0 {
+            return Err(TraceError::InvalidPid(pid));
+        }
+        let start_time = now_secs();
+        let mut state = self.into_state(start_time);
+        // Never EXITKILL an attached tree, including children it forks while traced
+        state.exit_kill = false;
+        // A filter can only be installed by the process itself, before exec
+        state.seccomp = false;
+
+        install_interrupt_handler()?;
+        state.root_pid = Some(pid);
+        attach_tree(pid, None, &mut state);
+        if state.active_pids.is_empty() {
+            return Err(TraceError::Attach(pid));
+        }
+
+        let mut exit_code = trace_loop(&mut state);
+        if state.interrupted {
+            // Ctrl-C in attach mode ends the trace, not the target
+            detach_all(&mut state);
+            exit_code = 0;
+        }
+
+        Ok(build_report(&mut state, start_time, exit_code, false))
+    }
+}
+
+/// Program and arguments of a command, as recorded before its exec is seen.
+fn command_argv(command: &Command) -> Vec<String> {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect()
+}
@@ -1,567 +1,289 @@
-use nix::sys::ptrace;
-use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
-use nix::unistd::{fork, ForkResult, Pid};
-use serde::Serialize;
-use std::collections::{HashMap, HashSet};
+//! roar-tracer: trace a command, or attach to a running process, and write
+//! the trace to a file. All the tracing lives in the library; this only maps
+//! command-line flags onto a TracerConfig and the report onto an exit code.
+
+mod cli;
+mod config;
+
+use cli::Cli;
+use roar_tracer::{
+    check_output_path, export_otlp, write_provenance, Backend, ChromeTrace, Compression,
+    EnvRedactor, EventLog, NetworkPolicy, OutputFormat, PathFilter, StraceLog, TraceCategory,
+    Tracer, TracerConfig, DEFAULT_EXCLUDES, LIBRARY_EXCLUDES,
+};
+use std::cell::RefCell;
 use std::env;
 use std::fs::File;
-use std::io::Write;
-use std::os::unix::process::CommandExt;
-use std::process::Command;
-use std::time::{SystemTime, UNIX_EPOCH};
-
-// Syscall numbers for x86_64
-const SYS_READ: u64 = 0;
-const SYS_WRITE: u64 = 1;
-const SYS_OPEN: u64 = 2;
-const SYS_CLOSE: u64 = 3;
-const SYS_MMAP: u64 = 9;
-const SYS_PREAD64: u64 = 17; // positional read (used by pyarrow, etc.)
-const SYS_PWRITE64: u64 = 18; // positional write
-const SYS_READV: u64 = 19; // scatter read
-const SYS_WRITEV: u64 = 20; // gather write
-const SYS_SENDFILE: u64 = 40; // zero-copy file-to-file/socket
-const SYS_RENAME: u64 = 82; // rename(oldpath, newpath)
-const SYS_OPENAT: u64 = 257;
-const SYS_RENAMEAT: u64 = 264; // renameat(olddirfd, oldpath, newdirfd, newpath)
-const SYS_PREADV: u64 = 295; // positional scatter read
-const SYS_PWRITEV: u64 = 296; // positional gather write
-const SYS_RENAMEAT2: u64 = 316; // renameat2 with flags
-const SYS_COPY_FILE_RANGE: u64 = 326; // efficient file copy
-const SYS_PREADV2: u64 = 327; // preadv with flags
-const SYS_PWRITEV2: u64 = 328; // pwritev with flags
-
-// =============================================================================
-// Data Structures - designed to match what roar's Python expects
-// =============================================================================
-
-#[derive(Debug, Clone, Serialize)]
-struct ProcessInfo {
-    pid: i32,
-    parent_pid: Option<i32>,
-    command: Vec<String>,
-    env: HashMap<String, String>,
-}
-
-#[derive(Debug, Clone, Serialize)]
-struct FileAccess {
-    path: String,
-    read: bool,
-    written: bool,
+use std::io;
+use std::os::fd::{AsFd, FromRawFd};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::time::Duration;
+
+/// Exit code when the trace could not be written (the command's code is in the message).
+const OUTPUT_ERROR_EXIT_CODE: i32 = 125;
+
+/// Where the report goes: a file written atomically, or an open stream.
+enum Output {
+    Path(String),
+    Stream(File, String), // the stream and how to name it in messages
 }
 
-#[derive(Debug, Serialize)]
-struct TracerOutput {
-    processes: Vec<ProcessInfo>,
-    opened_files: Vec<String>,
-    read_files: Vec<String>,
-    written_files: Vec<String>,
-    env_accessed: HashMap<String, String>,
-    start_time: f64,
-    end_time: f64,
+/// Take over inherited fd `fd` for the report; it's closed in the traced command.
+fn open_output_fd(fd: i32) -> io::Result<File> {
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(fd) })
 }
 
-#[derive(Debug)]
-struct TracerState {
-    processes: HashMap<i32, ProcessInfo>,
-    fd_table: HashMap<(i32, i32), String>, // (pid, fd) -> path
-    in_syscall: HashMap<i32, bool>,
-    pending_opens: HashMap<i32, (String, u64)>, // pid -> (path, flags)
-    active_pids: HashSet<i32>,
-
-    // Track file access
-    opened_files: HashSet<String>,
-    read_files: HashSet<String>,
-    written_files: HashSet<String>,
-
-    // Track env vars accessed via /proc/*/environ reads
-    env_accessed: HashMap<String, String>,
+fn output_error(destination: &str, e: io::Error) -> ! {
+    eprintln!("Cannot write trace to {}: {}", destination, e);
+    std::process::exit(OUTPUT_ERROR_EXIT_CODE);
 }
 
-impl TracerState {
-    fn new() -> Self {
-        TracerState {
-            processes: HashMap::new(),
-            fd_table: HashMap::new(),
-            in_syscall: HashMap::new(),
-            pending_opens: HashMap::new(),
-            active_pids: HashSet::new(),
-            opened_files: HashSet::new(),
-            read_files: HashSet::new(),
-            written_files: HashSet::new(),
-            env_accessed: HashMap::new(),
+fn main() {
+    let cli = Cli::parse_args();
+
+    // An explicit --compress applies to everything; otherwise each file's extension decides
+    let compression = |path: &str| cli.compress.unwrap_or_else(|| Compression::from_path(path));
+    let events = match cli
+        .events
+        .as_deref()
+        .map(|path| EventLog::open(path, compression(path)))
+        .transpose()
+    {
+        Ok(events) => events,
+        Err(e) => {
+            eprintln!("Failed to open event log: {}", e);
+            std::process::exit(1);
         }
-    }
-}
-
-// =============================================================================
-// String reading from tracee memory
-// =============================================================================
+    };
+    let spill = match cli
+        .spill
+        .as_deref()
+        .map(|path| EventLog::open(path, compression(path)))
+        .transpose()
+    {
+        Ok(spill) => spill,
+        Err(e) => {
+            eprintln!("Failed to open spill log: {}", e);
+            std::process::exit(1);
+        }
+    };
 
-fn read_string_from_tracee(pid: Pid, addr: u64) -> Option<String> {
-    if addr == 0 {
-        return None;
+    let mut excludes = cli.exclude;
+    if cli.exclude_default {
+        excludes.extend(DEFAULT_EXCLUDES.iter().map(|p| p.to_string()));
     }
-
-    let mut bytes = Vec::new();
-    let mut current = addr;
-
-    loop {
-        let word = match ptrace::read(pid, current as *mut libc::c_void) {
-            Ok(w) => w,
-            Err(_) => return None,
-        };
-
-        for byte in word.to_ne_bytes() {
-            if byte == 0 {
-                return String::from_utf8(bytes).ok();
-            }
-            bytes.push(byte);
-            if bytes.len() > 4096 {
-                return None; // Safety limit
-            }
-        }
-        current += 8;
+    if cli.exclude_libs {
+        excludes.extend(LIBRARY_EXCLUDES.iter().map(|p| p.to_string()));
     }
-}
-
-// =============================================================================
-// Process info capture
-// =============================================================================
-
-fn capture_process_info(pid: Pid, state: &mut TracerState, parent_pid: Option<i32>) {
-    let pid_raw = pid.as_raw();
-
-    // Read command line
-    let cmdline_path = format!("/proc/{}/cmdline", pid_raw);
-    let command = std::fs::read_to_string(&cmdline_path)
-        .map(|s| {
-            s.split('\0')
-                .filter(|s| !s.is_empty())
-                .map(String::from)
-                .collect()
-        })
-        .unwrap_or_default();
-
-    // Read environment
-    let environ_path = format!("/proc/{}/environ", pid_raw);
-    let env: HashMap<String, String> = std::fs::read_to_string(&environ_path)
-        .map(|s| {
-            s.split('\0')
-                .filter_map(|entry| {
-                    let mut parts = entry.splitn(2, '=');
-                    match (parts.next(), parts.next()) {
-                        (Some(k), Some(v)) if !k.is_empty() => Some((k.to_string(), v.to_string())),
-                        _ => None,
-                    }
-                })
-                .collect()
-        })
-        .unwrap_or_default();
-
-    state.processes.insert(
-        pid_raw,
-        ProcessInfo {
-            pid: pid_raw,
-            parent_pid,
-            command,
-            env,
-        },
-    );
-}
-
-// =============================================================================
-// FD table management
-// =============================================================================
-
-fn clone_fd_table(parent_pid: i32, child_pid: i32, state: &mut TracerState) {
-    let entries: Vec<_> = state
-        .fd_table
-        .iter()
-        .filter(|((pid, _), _)| *pid == parent_pid)
-        .map(|((_, fd), path)| (*fd, path.clone()))
-        .collect();
-
-    for (fd, path) in entries {
-        state.fd_table.insert((child_pid, fd), path);
+    let mut filter = match PathFilter::new(&cli.include, &excludes) {
+        Ok(f) if cli.no_default_ignores => f,
+        Ok(f) => f.ignore_pseudo_filesystems(),
+        Err(e) => {
+            eprintln!("Invalid path filter: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(root) = &cli.root {
+        filter = filter.within(root);
     }
-}
-
-// =============================================================================
-// Syscall handling
-// =============================================================================
-
-fn handle_syscall(pid: Pid, state: &mut TracerState) {
-    let pid_raw = pid.as_raw();
-
-    let regs = match ptrace::getregs(pid) {
+    let env_redactor = match EnvRedactor::new(&cli.env_allow, &cli.env_deny) {
         Ok(r) => r,
-        Err(_) => return,
+        Err(e) => {
+            eprintln!("Invalid environment pattern: {}", e);
+            std::process::exit(1);
+        }
     };
-
-    let syscall_num = regs.orig_rax;
-    let is_entry = !state.in_syscall.get(&pid_raw).copied().unwrap_or(false);
-    state.in_syscall.insert(pid_raw, is_entry);
-
-    if is_entry {
-        handle_syscall_entry(pid, syscall_num, &regs, state);
-    } else {
-        handle_syscall_exit(pid, syscall_num, &regs, state);
+    let deny_network = match cli
+        .deny_network
+        .then(|| NetworkPolicy::new(cli.allow_localhost, &cli.allow_unix))
+        .transpose()
+    {
+        Ok(policy) => policy,
+        Err(e) => {
+            eprintln!("Invalid unix socket pattern: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if cli.backend != Backend::Ptrace && (cli.deny_network || cli.seccomp) {
+        eprintln!("--deny-network and --seccomp need the ptrace backend");
+        std::process::exit(1);
     }
-}
-
-fn handle_syscall_entry(
-    pid: Pid,
-    syscall_num: u64,
-    regs: &libc::user_regs_struct,
-    state: &mut TracerState,
-) {
-    let pid_raw = pid.as_raw();
+    if cli.backend != Backend::Ptrace && (cli.no_follow_forks || !cli.no_follow.is_empty()) {
+        eprintln!("--no-follow and --no-follow-forks need the ptrace backend");
+        std::process::exit(1);
+    }
+    if cli.deny_network && !cli.trace.contains(&TraceCategory::Network) {
+        eprintln!("--deny-network needs --trace to include network");
+        std::process::exit(1);
+    }
+    let config = TracerConfig {
+        backend: cli.backend,
+        filter,
+        resolve_symlinks: cli.resolve_symlinks,
+        exit_kill: !cli.no_exit_kill,
+        timeout: cli.timeout.map(Duration::from_secs_f64),
+        env_redactor,
+        stats: cli.stats,
+        anon_fds: cli.anon_fds,
+        hash_reads: cli.hash_reads,
+        hash_writes: cli.hash_writes,
+        seccomp: cli.seccomp,
+        trace: cli.trace,
+        follow_forks: !cli.no_follow_forks,
+        no_follow: cli.no_follow,
+        max_paths: cli.max_paths,
+        spill,
+        deny_network,
+    };
 
-    match syscall_num {
-        SYS_OPEN => {
-            let path_ptr = regs.rdi;
-            let flags = regs.rsi;
-            if let Some(path) = read_string_from_tracee(pid, path_ptr) {
-                let abs_path = resolve_path(&path, pid_raw);
-                state.pending_opens.insert(pid_raw, (abs_path, flags));
-            }
-        }
-        SYS_OPENAT => {
-            let path_ptr = regs.rsi;
-            let flags = regs.rdx;
-            if let Some(path) = read_string_from_tracee(pid, path_ptr) {
-                let abs_path = resolve_path(&path, pid_raw);
-                state.pending_opens.insert(pid_raw, (abs_path, flags));
-            }
-        }
-        SYS_READ | SYS_PREAD64 | SYS_READV | SYS_PREADV | SYS_PREADV2 => {
-            // All read variants have fd in rdi
-            let fd = regs.rdi as i32;
-            if let Some(path) = state.fd_table.get(&(pid_raw, fd)).cloned() {
-                state.read_files.insert(path);
-            }
-        }
-        SYS_WRITE | SYS_PWRITE64 | SYS_WRITEV | SYS_PWRITEV | SYS_PWRITEV2 => {
-            // All write variants have fd in rdi
-            let fd = regs.rdi as i32;
-            if let Some(path) = state.fd_table.get(&(pid_raw, fd)).cloned() {
-                state.written_files.insert(path);
-            }
-        }
-        SYS_SENDFILE => {
-            // sendfile(out_fd, in_fd, ...) - reads from in_fd (rsi), writes to out_fd (rdi)
-            let out_fd = regs.rdi as i32;
-            let in_fd = regs.rsi as i32;
-            if let Some(path) = state.fd_table.get(&(pid_raw, in_fd)).cloned() {
-                state.read_files.insert(path);
-            }
-            if let Some(path) = state.fd_table.get(&(pid_raw, out_fd)).cloned() {
-                state.written_files.insert(path);
+    // With -o -, the command's stdout goes to stderr so stdout carries only the trace
+    let to_stdout = cli.output.as_deref() == Some("-");
+    let output = match (cli.output_fd, cli.output) {
+        (Some(fd), _) => Output::Stream(
+            open_output_fd(fd).unwrap_or_else(|e| output_error(&format!("fd {}", fd), e)),
+            format!("fd {}", fd),
+        ),
+        (None, Some(_)) if to_stdout => Output::Stream(
+            io::stdout()
+                .as_fd()
+                .try_clone_to_owned()
+                .map(File::from)
+                .unwrap_or_else(|e| output_error("stdout", e)),
+            "stdout".to_string(),
+        ),
+        (None, path) => {
+            let path = path.unwrap_or_else(|| {
+                let path = env::temp_dir().join(format!("roar-trace-{}.json", std::process::id()));
+                let path = path.to_string_lossy().to_string();
+                eprintln!("roar-tracer: writing trace to {}", path);
+                path
+            });
+            if let Err(e) = check_output_path(&path) {
+                output_error(&path, e);
             }
+            Output::Path(path)
         }
-        SYS_COPY_FILE_RANGE => {
-            // copy_file_range(fd_in, ..., fd_out, ...) - reads from fd_in (rdi), writes to fd_out (r8)
-            let in_fd = regs.rdi as i32;
-            let out_fd = regs.r8 as i32;
-            if let Some(path) = state.fd_table.get(&(pid_raw, in_fd)).cloned() {
-                state.read_files.insert(path);
-            }
-            if let Some(path) = state.fd_table.get(&(pid_raw, out_fd)).cloned() {
-                state.written_files.insert(path);
-            }
-        }
-        SYS_MMAP => {
-            // mmap(addr, len, prot, flags, fd, offset)
-            // Args: rdi=addr, rsi=len, rdx=prot, r10=flags, r8=fd, r9=offset
-            let fd = regs.r8 as i64;
-            let prot = regs.rdx;
-            let flags = regs.r10;
+    };
 
-            // Only track if mapping a file (fd >= 0)
-            if fd >= 0 {
-                let fd_i32 = fd as i32;
-                if let Some(path) = state.fd_table.get(&(pid_raw, fd_i32)).cloned() {
-                    // PROT_READ = 1, PROT_WRITE = 2
-                    // MAP_SHARED = 1, MAP_PRIVATE = 2
-                    let is_shared = flags & 1 != 0;
+    let chrome = cli
+        .chrome_trace
+        .as_ref()
+        .map(|_| Rc::new(RefCell::new(ChromeTrace::new())));
+    let (destination, output_compression) = match &output {
+        Output::Path(path) => (path.clone(), compression(path)),
+        Output::Stream(_, name) => (name.clone(), cli.compress.unwrap_or_default()),
+    };
 
-                    // Any file-backed mmap is a read
-                    if prot & 1 != 0 {
-                        state.read_files.insert(path.clone());
-                    }
-                    // Only MAP_SHARED + PROT_WRITE is a real write (changes go to disk)
-                    // MAP_PRIVATE writes are copy-on-write and don't modify the file
-                    if is_shared && (prot & 2 != 0) {
-                        state.written_files.insert(path);
-                    }
-                }
-            }
-        }
-        SYS_RENAME => {
-            // rename(oldpath, newpath): rdi=oldpath, rsi=newpath
-            // The destination (newpath) is effectively written
-            if let Some(newpath) = read_string_from_tracee(pid, regs.rsi) {
-                let abs_path = resolve_path(&newpath, pid_raw);
-                state.written_files.insert(abs_path);
+    // strace lines are streamed as syscalls complete, in place of the report
+    let strace = match cli.format {
+        OutputFormat::Strace => {
+            if cli.backend == Backend::Fanotify || cli.flush_interval.is_some() {
+                eprintln!("--format strace needs the ptrace backend and no --flush-interval");
+                std::process::exit(1);
             }
+            let file = match &output {
+                Output::Path(path) => File::create(path),
+                Output::Stream(stream, _) => stream.try_clone(),
+            };
+            let log = file.and_then(|file| StraceLog::new(file, output_compression));
+            Some(Rc::new(RefCell::new(
+                log.unwrap_or_else(|e| output_error(&destination, e)),
+            )))
         }
-        SYS_RENAMEAT | SYS_RENAMEAT2 => {
-            // renameat(olddirfd, oldpath, newdirfd, newpath): rsi=oldpath, r10=newpath
-            // The destination (newpath) is effectively written
-            if let Some(newpath) = read_string_from_tracee(pid, regs.r10) {
-                let abs_path = resolve_path(&newpath, pid_raw);
-                state.written_files.insert(abs_path);
+        _ => None,
+    };
+
+    let mut tracer = Tracer::new(config);
+    if let Some(log) = &strace {
+        let log = Rc::clone(log);
+        tracer = tracer.on_syscall(move |record| log.borrow_mut().record(record));
+    }
+    if let Some(interval) = cli.flush_interval {
+        // A stream can't be rewritten, so checkpoints only go to files
+        let Output::Path(path) = &output else {
+            eprintln!("--flush-interval needs an output file");
+            std::process::exit(1);
+        };
+        let (path, format, compression) = (path.clone(), cli.format, output_compression);
+        tracer = tracer.on_checkpoint(Duration::from_secs_f64(interval), move |report| {
+            if let Err(e) = report.write(&path, format, compression) {
+                eprintln!("Failed to write checkpoint to {}: {}", path, e);
             }
-        }
-        _ => {}
+        });
     }
-}
-
-fn handle_syscall_exit(
-    pid: Pid,
-    syscall_num: u64,
-    regs: &libc::user_regs_struct,
-    state: &mut TracerState,
-) {
-    let pid_raw = pid.as_raw();
-    let ret_val = regs.rax as i64;
-
-    match syscall_num {
-        SYS_OPEN | SYS_OPENAT => {
-            if ret_val >= 0 {
-                if let Some((path, _flags)) = state.pending_opens.remove(&pid_raw) {
-                    let fd = ret_val as i32;
-                    state.fd_table.insert((pid_raw, fd), path.clone());
-                    state.opened_files.insert(path);
-                }
-            } else {
-                state.pending_opens.remove(&pid_raw);
+    if events.is_some() || chrome.is_some() {
+        let (mut log, timeline) = (events, chrome.clone());
+        tracer = tracer.on_event(move |record| {
+            if let Some(log) = log.as_mut() {
+                log.emit(record);
             }
-        }
-        SYS_CLOSE => {
-            if ret_val == 0 {
-                // We don't have the fd from entry, so we can't clean up properly
-                // This is a known limitation
+            if let Some(timeline) = &timeline {
+                timeline.borrow_mut().record(record);
             }
-        }
-        _ => {}
-    }
-}
-
-fn resolve_path(path: &str, pid: i32) -> String {
-    if path.starts_with('/') {
-        return path.to_string();
-    }
-
-    // Try to resolve relative to process CWD
-    let cwd_path = format!("/proc/{}/cwd", pid);
-    if let Ok(cwd) = std::fs::read_link(&cwd_path) {
-        let mut full_path = cwd;
-        full_path.push(path);
-        if let Ok(canonical) = full_path.canonicalize() {
-            return canonical.to_string_lossy().to_string();
-        }
-        return full_path.to_string_lossy().to_string();
+        });
     }
-
-    path.to_string()
-}
-
-// =============================================================================
-// Ptrace event handling (fork/clone/exec)
-// =============================================================================
-
-fn setup_ptrace(pid: Pid) {
-    use nix::sys::ptrace::Options;
-    let opts = Options::PTRACE_O_TRACESYSGOOD
-        | Options::PTRACE_O_TRACEFORK
-        | Options::PTRACE_O_TRACEVFORK
-        | Options::PTRACE_O_TRACECLONE
-        | Options::PTRACE_O_TRACEEXEC;
-
-    if let Err(e) = ptrace::setoptions(pid, opts) {
-        eprintln!("Warning: ptrace setoptions failed: {}", e);
-    }
-}
-
-fn handle_ptrace_event(pid: Pid, event: i32, state: &mut TracerState) {
-    match event {
-        libc::PTRACE_EVENT_FORK | libc::PTRACE_EVENT_VFORK | libc::PTRACE_EVENT_CLONE => {
-            if let Ok(child_pid) = ptrace::getevent(pid) {
-                let child_pid_i32 = child_pid as i32;
-                state.active_pids.insert(child_pid_i32);
-                clone_fd_table(pid.as_raw(), child_pid_i32, state);
-                capture_process_info(Pid::from_raw(child_pid_i32), state, Some(pid.as_raw()));
+    let result = match cli.attach {
+        Some(pid) => tracer.attach(pid),
+        None => {
+            let mut command = Command::new(&cli.command[0]);
+            command.args(&cli.command[1..]);
+            if to_stdout {
+                match io::stderr().as_fd().try_clone_to_owned() {
+                    Ok(stderr) => {
+                        command.stdout(Stdio::from(stderr));
+                    }
+                    Err(e) => {
+                        eprintln!("roar-tracer: {}", e);
+                        std::process::exit(1);
+                    }
+                }
             }
+            tracer.run(command)
         }
-        libc::PTRACE_EVENT_EXEC => {
-            // Process exec'd - recapture info
-            let parent = state
-                .processes
-                .get(&pid.as_raw())
-                .and_then(|p| p.parent_pid);
-            capture_process_info(pid, state, parent);
-        }
-        _ => {}
-    }
-}
-
-// =============================================================================
-// Main tracer loop
-// =============================================================================
-
-fn run_tracer(command: Vec<String>, output_file: &str) -> i32 {
-    let start_time = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("system time before UNIX epoch")
-        .as_secs_f64();
-
-    let mut state = TracerState::new();
-
-    // Fork and trace
-    match unsafe { fork() } {
-        Ok(ForkResult::Child) => {
-            // Child: request tracing and exec
-            ptrace::traceme().expect("ptrace traceme failed");
-
-            let mut cmd = Command::new(&command[0]);
-            if command.len() > 1 {
-                cmd.args(&command[1..]);
-            }
-
-            // This replaces the child process
-            let err = cmd.exec();
-            eprintln!("exec failed: {}", err);
+    };
+    let report = match result {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("roar-tracer: {}", e);
             std::process::exit(1);
         }
-        Ok(ForkResult::Parent { child }) => {
-            // Parent: wait for child to stop at exec, then trace
-            let child_pid = child.as_raw();
-            state.active_pids.insert(child_pid);
-
-            // Wait for initial stop
-            match waitpid(child, None) {
-                Ok(WaitStatus::Stopped(_, _)) => {
-                    setup_ptrace(child);
-                    capture_process_info(child, &mut state, None);
-                    let _ = ptrace::syscall(child, None);
-                }
-                _ => {
-                    eprintln!("Unexpected initial wait status");
-                    return 1;
-                }
-            }
-
-            // Main event loop
-            let exit_code = trace_loop(&mut state);
-
-            let end_time = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("system time before UNIX epoch")
-                .as_secs_f64();
-
-            // Collect env vars from the root process
-            let env_accessed = state
-                .processes
-                .values()
-                .next()
-                .map(|p| p.env.clone())
-                .unwrap_or_default();
-
-            // Build output
-            let output = TracerOutput {
-                processes: state.processes.into_values().collect(),
-                opened_files: state.opened_files.into_iter().collect(),
-                read_files: state.read_files.into_iter().collect(),
-                written_files: state.written_files.into_iter().collect(),
-                env_accessed,
-                start_time,
-                end_time,
-            };
-
-            // Write output
-            if let Ok(mut file) = File::create(output_file) {
-                if let Ok(json) = serde_json::to_string_pretty(&output) {
-                    let _ = file.write_all(json.as_bytes());
-                }
-            }
+    };
 
-            exit_code
+    if let (Some(path), Some(chrome)) = (&cli.chrome_trace, chrome) {
+        if let Err(e) = chrome.borrow().write(&report, path) {
+            eprintln!("Failed to write Chrome trace to {}: {}", path, e);
         }
-        Err(e) => {
-            eprintln!("fork failed: {}", e);
-            1
+    }
+    if let Some(endpoint) = &cli.otlp_endpoint {
+        if let Err(e) = export_otlp(&report, endpoint) {
+            eprintln!("Failed to export spans to {}: {}", endpoint, e);
         }
     }
-}
-
-fn trace_loop(state: &mut TracerState) -> i32 {
-    let mut exit_code = 0;
-
-    while !state.active_pids.is_empty() {
-        match waitpid(None, Some(WaitPidFlag::__WALL)) {
-            Ok(WaitStatus::PtraceSyscall(pid)) => {
-                handle_syscall(pid, state);
-                let _ = ptrace::syscall(pid, None);
-            }
-            Ok(WaitStatus::PtraceEvent(pid, _sig, event)) => {
-                handle_ptrace_event(pid, event, state);
-                let _ = ptrace::syscall(pid, None);
-            }
-            Ok(WaitStatus::Exited(pid, code)) => {
-                state.active_pids.remove(&pid.as_raw());
-                // Capture exit code of the root process
-                if state
-                    .processes
-                    .get(&pid.as_raw())
-                    .map(|p| p.parent_pid.is_none())
-                    .unwrap_or(false)
-                {
-                    exit_code = code;
-                }
-            }
-            Ok(WaitStatus::Signaled(pid, sig, _)) => {
-                state.active_pids.remove(&pid.as_raw());
-                // If root process was signaled, reflect that
-                if state
-                    .processes
-                    .get(&pid.as_raw())
-                    .map(|p| p.parent_pid.is_none())
-                    .unwrap_or(false)
-                {
-                    exit_code = 128 + sig as i32;
-                }
-            }
-            Ok(WaitStatus::Stopped(pid, sig)) => {
-                // Pass through signals
-                let _ = ptrace::syscall(pid, Some(sig));
-            }
-            Ok(_) => {}
-            Err(nix::errno::Errno::ECHILD) => break,
-            Err(_) => {}
+    if let Some(path) = &cli.provenance {
+        if let Err(e) = write_provenance(&report, path) {
+            eprintln!("Failed to write provenance to {}: {}", path, e);
         }
     }
-
-    exit_code
-}
-
-// =============================================================================
-// Main
-// =============================================================================
-
-fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    if args.len() < 3 {
-        eprintln!("Usage: roar-tracer <output-file> <command> [args...]");
-        eprintln!("  Traces <command> and writes syscall data to <output-file>");
-        std::process::exit(1);
+    let result = match (strace, output) {
+        // The tracer, and the hook's reference with it, is gone by now
+        (Some(log), _) => Rc::try_unwrap(log)
+            .map_err(|_| io::Error::other("syscall log still in use"))
+            .and_then(|log| log.into_inner().finish()),
+        (None, Output::Path(path)) => report.write(&path, cli.format, output_compression),
+        (None, Output::Stream(stream, _)) => {
+            report.write_to(stream, cli.format, output_compression)
+        }
+    };
+    if let Err(e) = result {
+        eprintln!(
+            "Failed to write trace to {}: {} (traced command exited with {})",
+            destination, e, report.exit_code
+        );
+        std::process::exit(OUTPUT_ERROR_EXIT_CODE);
     }
-
-    let output_file = &args[1];
-    let command: Vec<String> = args[2..].to_vec();
-
-    let exit_code = run_tracer(command, output_file);
-    std::process::exit(exit_code);
+    std::process::exit(report.exit_code);
 }
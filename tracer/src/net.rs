@@ -0,0 +1,343 @@
+//! Socket address decoding for network syscall tracing.
+//!
+//! Sockets are entered into the fd table under URL-style labels such as
+//! "tcp://93.184.216.34:443" or "unix:///var/run/docker.sock", so I/O on them
+//! can be told apart from file I/O.
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::Serialize;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NetworkConnection {
+    pub pid: i32,
+    pub family: &'static str,
+    pub operation: &'static str, // "connect", "bind", "listen", "accept", or a blocked send call
+    pub address: String,         // the peer, or for bind and listen our own address
+    pub local_address: Option<String>, // our end of a connected inet socket
+    pub success: bool,
+    pub denied: bool, // made to fail by --deny-network
+}
+
+/// What --deny-network still lets through; other connects and sends fail with EPERM.
+#[derive(Debug)]
+pub struct NetworkPolicy {
+    allow_localhost: bool,
+    allow_unix: GlobSet, // socket paths, or @name for the abstract namespace
+}
+
+impl NetworkPolicy {
+    pub fn new(allow_localhost: bool, allow_unix: &[String]) -> Result<Self, globset::Error> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in allow_unix {
+            builder.add(GlobBuilder::new(pattern).literal_separator(true).build()?);
+        }
+        Ok(NetworkPolicy {
+            allow_localhost,
+            allow_unix: builder.build()?,
+        })
+    }
+
+    /// Whether a connection to (or datagram for) a socket address may go ahead.
+    pub fn allows(&self, address: &str) -> bool {
+        match address.split_once("://") {
+            Some(("unix", path)) => self.allow_unix.is_match(path),
+            Some((_, host_port)) => self.allow_localhost && is_loopback(host_port),
+            None => false,
+        }
+    }
+}
+
+/// Whether "127.0.0.1:80" or "[::1]:80" names a loopback address.
+fn is_loopback(host_port: &str) -> bool {
+    let host = host_port
+        .rsplit_once(':')
+        .map_or(host_port, |(host, _)| host);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    // ::ffff:127.0.0.1 is loopback too
+    host.parse::<IpAddr>()
+        .is_ok_and(|ip| ip.to_canonical().is_loopback())
+}
+
+/// Bytes moved to and from one endpoint through socket fds.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct NetworkTraffic {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+/// Socket I/O seen at syscall entry; how much moved is only known at exit.
+#[derive(Debug)]
+pub struct PendingTransfer {
+    pub endpoint: String, // the socket's label, or the sendto destination
+    pub sent: bool,
+    pub mmsgs: Option<u64>, // sendmmsg/recvmmsg vector: the result counts messages
+    pub source: Option<(u64, u64)>, // recvfrom src_addr and addrlen pointers
+}
+
+/// A socket call seen at syscall entry, completed at exit.
+#[derive(Debug)]
+pub enum PendingSocketCall {
+    Socket {
+        scheme: &'static str,
+    },
+    Address {
+        operation: &'static str,
+        fd: i32,
+        family: &'static str,
+        address: String,
+    },
+    Accept {
+        fd: i32,
+        addr_ptr: u64,
+        addrlen_ptr: u64,
+    },
+    Listen {
+        fd: i32,
+    },
+}
+
+/// URL scheme for a new socket, or None for families we don't track (netlink, ...).
+pub fn socket_scheme(domain: i32, sock_type: i32) -> Option<&'static str> {
+    // SOCK_NONBLOCK / SOCK_CLOEXEC are or'd into the type argument
+    let sock_type = sock_type & 0xf;
+    match domain {
+        libc::AF_UNIX => Some("unix"),
+        libc::AF_INET | libc::AF_INET6 => Some(match sock_type {
+            libc::SOCK_STREAM => "tcp",
+            libc::SOCK_DGRAM => "udp",
+            _ => "ip",
+        }),
+        _ => None,
+    }
+}
+
+/// Label for a socket that exists but has no known address yet.
+pub fn unbound_label(scheme: &str) -> String {
+    format!("{}://", scheme)
+}
+
+/// Whether an fd-table entry refers to a socket rather than a file.
+pub fn is_socket_label(path: &str) -> bool {
+    path.contains("://")
+}
+
+/// Scheme part of a socket label ("tcp" for "tcp://1.2.3.4:80").
+pub fn label_scheme(label: &str) -> &'static str {
+    match label.split("://").next() {
+        Some("tcp") => "tcp",
+        Some("udp") => "udp",
+        Some("unix") => "unix",
+        _ => "ip",
+    }
+}
+
+/// Whether a socket label points at a DNS server (port 53).
+pub fn is_dns_label(label: &str) -> bool {
+    is_socket_label(label) && label.ends_with(":53")
+}
+
+/// The hostname asked for by a DNS query message (its first question).
+/// Over TCP each message is preceded by a two-byte length.
+pub fn dns_query_name(scheme: &str, msg: &[u8]) -> Option<String> {
+    let msg = if scheme == "tcp" { msg.get(2..)? } else { msg };
+    // Header: id, flags (QR clear for a query), qdcount, ancount, nscount, arcount
+    if msg.len() < 12 || msg[2] & 0x80 != 0 || u16::from_be_bytes([msg[4], msg[5]]) == 0 {
+        return None;
+    }
+    let mut labels = Vec::new();
+    let mut off = 12;
+    loop {
+        let len = *msg.get(off)? as usize;
+        if len == 0 {
+            break;
+        }
+        // Queries don't use compression pointers; anything else is not DNS
+        if len & 0xc0 != 0 || labels.len() >= 127 {
+            return None;
+        }
+        let label = msg.get(off + 1..off + 1 + len)?;
+        labels.push(String::from_utf8_lossy(label).to_ascii_lowercase());
+        off += 1 + len;
+    }
+    (!labels.is_empty()).then(|| labels.join("."))
+}
+
+/// Filesystem path of a unix socket label; None for unnamed and abstract sockets.
+pub fn unix_socket_path(label: &str) -> Option<&str> {
+    let path = label.strip_prefix("unix://")?;
+    (!path.is_empty() && !path.starts_with('@')).then_some(path)
+}
+
+/// Address family of a socket label ("inet6" for "tcp://[::1]:80").
+pub fn label_family(label: &str) -> &'static str {
+    match label.split_once("://") {
+        Some(("unix", _)) => "unix",
+        Some((_, rest)) if rest.starts_with('[') => "inet6",
+        Some((_, rest)) if !rest.is_empty() => "inet",
+        _ => "unknown",
+    }
+}
+
+/// Local address of an inet socket, looked up by inode in /proc/<pid>/net.
+/// The kernel only picks an ephemeral port on connect, so ask after it.
+pub fn local_address(pid: i32, fd: i32, scheme: &str) -> Option<String> {
+    let link = std::fs::read_link(format!("/proc/{}/fd/{}", pid, fd)).ok()?;
+    let link = link.to_string_lossy();
+    let inode = link.strip_prefix("socket:[")?.strip_suffix(']')?;
+    let tables: &[&str] = match scheme {
+        "tcp" => &["tcp", "tcp6"],
+        "udp" => &["udp", "udp6"],
+        _ => return None,
+    };
+    for table in tables {
+        let Ok(contents) = std::fs::read_to_string(format!("/proc/{}/net/{}", pid, table)) else {
+            continue;
+        };
+        // sl local_address rem_address st tx_queue:rx_queue tr:tm->when retrnsmt uid timeout inode
+        for line in contents.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.get(9) == Some(&inode) {
+                return parse_proc_net_address(scheme, fields.get(1)?);
+            }
+        }
+    }
+    None
+}
+
+/// Decode an address from /proc/net/{tcp,udp}[6]: hex address words in host
+/// byte order, then the port, e.g. "0100007F:0050" for 127.0.0.1:80.
+fn parse_proc_net_address(scheme: &str, field: &str) -> Option<String> {
+    let (ip, port) = field.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+    let mut octets = Vec::with_capacity(16);
+    for i in (0..ip.len()).step_by(8) {
+        let word = u32::from_str_radix(ip.get(i..i + 8)?, 16).ok()?;
+        octets.extend_from_slice(&word.to_ne_bytes());
+    }
+    match octets.len() {
+        4 => {
+            let ip = Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]);
+            Some(format!("{}://{}:{}", scheme, ip, port))
+        }
+        16 => {
+            let ip = Ipv6Addr::from(<[u8; 16]>::try_from(octets).ok()?);
+            Some(format!("{}://[{}]:{}", scheme, ip, port))
+        }
+        _ => None,
+    }
+}
+
+/// Decode a raw sockaddr into (family name, URL-style address).
+pub fn format_sockaddr(scheme: &str, bytes: &[u8]) -> Option<(&'static str, String)> {
+    if bytes.len() < 2 {
+        return None;
+    }
+    let family = u16::from_ne_bytes([bytes[0], bytes[1]]) as i32;
+
+    match family {
+        libc::AF_INET if bytes.len() >= 8 => {
+            let port = u16::from_be_bytes([bytes[2], bytes[3]]);
+            let ip = Ipv4Addr::new(bytes[4], bytes[5], bytes[6], bytes[7]);
+            Some(("inet", format!("{}://{}:{}", scheme, ip, port)))
+        }
+        libc::AF_INET6 if bytes.len() >= 24 => {
+            let port = u16::from_be_bytes([bytes[2], bytes[3]]);
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&bytes[8..24]);
+            let ip = Ipv6Addr::from(octets);
+            Some(("inet6", format!("{}://[{}]:{}", scheme, ip, port)))
+        }
+        libc::AF_UNIX => {
+            let path = &bytes[2..];
+            if path.is_empty() {
+                // Unnamed socket (e.g. from socketpair or autobind)
+                return Some(("unix", "unix://".to_string()));
+            }
+            if path[0] == 0 {
+                // Abstract namespace: leading NUL, name is the remaining bytes
+                let name = String::from_utf8_lossy(&path[1..]);
+                return Some(("unix", format!("unix://@{}", name.trim_end_matches('\0'))));
+            }
+            let end = path.iter().position(|&b| b == 0).unwrap_or(path.len());
+            Some((
+                "unix",
+                format!("unix://{}", String::from_utf8_lossy(&path[..end])),
+            ))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A UDP query whose question is the wire-format name `labels`.
+    fn query(labels: &[u8]) -> Vec<u8> {
+        let mut msg = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        msg.extend_from_slice(labels);
+        msg.extend_from_slice(&[0, 1, 0, 1]); // QTYPE A, QCLASS IN
+        msg
+    }
+
+    #[test]
+    fn reads_the_first_question() {
+        let msg = query(b"\x03www\x07Example\x03com\x00");
+        assert_eq!(
+            dns_query_name("udp", &msg).as_deref(),
+            Some("www.example.com")
+        );
+
+        let mut tcp = (msg.len() as u16).to_be_bytes().to_vec();
+        tcp.extend_from_slice(&msg);
+        assert_eq!(
+            dns_query_name("tcp", &tcp).as_deref(),
+            Some("www.example.com")
+        );
+    }
+
+    #[test]
+    fn rejects_responses_and_empty_questions() {
+        let mut response = query(b"\x03com\x00");
+        response[2] |= 0x80;
+        assert_eq!(dns_query_name("udp", &response), None);
+        let mut no_questions = query(b"\x03com\x00");
+        no_questions[5] = 0;
+        assert_eq!(dns_query_name("udp", &no_questions), None);
+        assert_eq!(dns_query_name("udp", &query(b"\x00")), None);
+    }
+
+    #[test]
+    fn rejects_compression_pointers() {
+        // A pointer back to the name's own start would loop if followed
+        assert_eq!(dns_query_name("udp", &query(b"\xc0\x0c")), None);
+        assert_eq!(dns_query_name("udp", &query(b"\x03www\xc0\x0c")), None);
+        // The reserved 0x40 and 0x80 label types too
+        assert_eq!(dns_query_name("udp", &query(b"\x41a\x00")), None);
+        assert_eq!(dns_query_name("udp", &query(b"\x81a\x00")), None);
+    }
+
+    #[test]
+    fn rejects_truncated_messages() {
+        let msg = query(b"\x03www\x07example\x03com\x00");
+        // Every prefix that ends before the root label
+        for len in 0..12 + 17 {
+            assert_eq!(dns_query_name("udp", &msg[..len]), None, "{} bytes", len);
+        }
+        assert_eq!(dns_query_name("tcp", &msg[..1]), None);
+        // A label length running past the end
+        assert_eq!(dns_query_name("udp", &query(b"\x3fabc")[..15]), None);
+    }
+
+    #[test]
+    fn bounds_the_number_of_labels() {
+        let many: Vec<u8> = b"\x01a".repeat(200).into_iter().chain([0]).collect();
+        assert_eq!(dns_query_name("udp", &query(&many)), None);
+        let most: Vec<u8> = b"\x01a".repeat(127).into_iter().chain([0]).collect();
+        assert_eq!(
+            dns_query_name("udp", &query(&most)).map(|n| n.len()),
+            Some(253)
+        );
+    }
+}
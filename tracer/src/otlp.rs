@@ -0,0 +1,217 @@
+//! OpenTelemetry export (--otlp-endpoint).
+//!
+//! The process tree becomes one trace: a span per process, parented to the
+//! span of the process that forked it, with an event for each file it
+//! opened, read or wrote. Spans are POSTed once the trace ends as OTLP/HTTP
+//! JSON, which every collector accepts on port 4318. Only plain http://
+//! endpoints are supported; put a local collector in front of a TLS one.
+
+use crate::{ProcessInfo, TraceReport};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Send the report's process tree to the collector at `endpoint`, e.g.
+/// http://localhost:4318 (/v1/traces is added when no path is given).
+pub fn export(report: &TraceReport, endpoint: &str) -> io::Result<()> {
+    let (host, path) = parse_endpoint(endpoint)?;
+    let body = spans_request(report)?.to_string();
+
+    let mut stream = TcpStream::connect(socket_addr(host))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        host,
+        body.len(),
+        body
+    )?;
+
+    let mut status = String::new();
+    BufReader::new(stream).read_line(&mut status)?;
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(io::Error::other(format!(
+            "collector replied {}",
+            status.trim_end()
+        ))),
+    }
+}
+
+/// Split http://host[:port][/path] into host and request path.
+fn parse_endpoint(endpoint: &str) -> io::Result<(&str, &str)> {
+    let rest = endpoint.strip_prefix("http://").ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "only http:// OTLP endpoints are supported",
+        )
+    })?;
+    Ok(match rest.find('/') {
+        Some(slash) if slash + 1 < rest.len() => (&rest[..slash], &rest[slash..]),
+        Some(slash) => (&rest[..slash], "/v1/traces"),
+        None => (rest, "/v1/traces"),
+    })
+}
+
+/// The host with port 80 added when it has none. An IPv6 address is
+/// bracketed, so its port is whatever follows the `]`.
+fn socket_addr(host: &str) -> String {
+    let has_port = match host.rfind(']') {
+        Some(bracket) => host[bracket + 1..].starts_with(':'),
+        None => host.contains(':'),
+    };
+    if has_port {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    }
+}
+
+/// An ExportTraceServiceRequest in OTLP's JSON encoding.
+fn spans_request(report: &TraceReport) -> io::Result<Value> {
+    let trace_id = hex(&random_bytes::<16>()?);
+    let span_ids = report
+        .processes
+        .iter()
+        .map(|p| Ok((p.pid, hex(&random_bytes::<8>()?))))
+        .collect::<io::Result<HashMap<i32, String>>>()?;
+    let nanos = |secs: f64| ((secs * 1e9) as u64).to_string();
+
+    let spans: Vec<Value> = report
+        .processes
+        .iter()
+        .map(|process| {
+            let end = process.end_time.unwrap_or(report.end_time);
+            let failed =
+                process.exit_code.is_some_and(|code| code != 0) || process.term_signal.is_some();
+            json!({
+                "traceId": trace_id,
+                "spanId": span_ids[&process.pid],
+                "parentSpanId": process
+                    .parent_pid
+                    .and_then(|ppid| span_ids.get(&ppid))
+                    .cloned()
+                    .unwrap_or_default(),
+                "name": process.command.first().cloned().unwrap_or_else(|| process.pid.to_string()),
+                "kind": 1, // SPAN_KIND_INTERNAL
+                "startTimeUnixNano": nanos(process.start_time),
+                "endTimeUnixNano": nanos(end),
+                "attributes": process_attributes(process),
+                "events": file_events(process, report, process.start_time, end)
+                    .into_iter()
+                    .map(|(time, name, path)| json!({
+                        "timeUnixNano": nanos(time),
+                        "name": name,
+                        "attributes": [string_attribute("file.path", path)],
+                    }))
+                    .collect::<Vec<_>>(),
+                "status": { "code": if failed { 2 } else { 1 } }, // ERROR or OK
+            })
+        })
+        .collect();
+
+    Ok(json!({
+        "resourceSpans": [{
+            "resource": { "attributes": [string_attribute("service.name", "roar")] },
+            "scopeSpans": [{
+                "scope": { "name": "roar-tracer", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }],
+        }],
+    }))
+}
+
+fn process_attributes(process: &ProcessInfo) -> Vec<Value> {
+    let mut attributes = vec![
+        int_attribute("process.pid", process.pid as i64),
+        string_attribute("process.command_line", &process.command.join(" ")),
+    ];
+    if let Some(ppid) = process.parent_pid {
+        attributes.push(int_attribute("process.parent_pid", ppid as i64));
+    }
+    if let Some(code) = process.exit_code {
+        attributes.push(int_attribute("process.exit.code", code as i64));
+    }
+    if let Some(signal) = process.term_signal {
+        attributes.push(int_attribute("process.exit.signal", signal as i64));
+    }
+    attributes
+}
+
+/// (time, opened/read/written, path) for a process's files. Per-process access
+/// times aren't kept, so each is placed at the file's first access, within the span.
+fn file_events<'a>(
+    process: &'a ProcessInfo,
+    report: &TraceReport,
+    start: f64,
+    end: f64,
+) -> Vec<(f64, &'static str, &'a str)> {
+    let mut events = Vec::new();
+    for (name, paths) in [
+        ("opened", &process.opened_files),
+        ("read", &process.read_files),
+        ("written", &process.written_files),
+    ] {
+        for path in paths {
+            let first = report
+                .files
+                .get(path)
+                .map_or(start, |f| report.start_time + f.first_access);
+            events.push((first.clamp(start, end), name, path.as_str()));
+        }
+    }
+    events.sort_by(|a, b| a.0.total_cmp(&b.0));
+    events
+}
+
+fn string_attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+fn int_attribute(key: &str, value: i64) -> Value {
+    // OTLP JSON encodes 64-bit integers as strings
+    json!({ "key": key, "value": { "intValue": value.to_string() } })
+}
+
+/// Random trace and span ids; collectors reject all-zero ones, so a failed
+/// read is an error rather than an id left blank.
+fn random_bytes<const N: usize>() -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    let mut filled = 0;
+    while filled < N {
+        let n = unsafe { libc::getrandom(bytes[filled..].as_mut_ptr().cast(), N - filled, 0) };
+        if n < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        filled += n as usize;
+    }
+    Ok(bytes)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_the_default_port_only_when_missing() {
+        assert_eq!(socket_addr("localhost"), "localhost:80");
+        assert_eq!(socket_addr("localhost:4318"), "localhost:4318");
+        assert_eq!(socket_addr("[::1]"), "[::1]:80");
+        assert_eq!(socket_addr("[::1]:4318"), "[::1]:4318");
+    }
+}
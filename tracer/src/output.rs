@@ -0,0 +1,138 @@
+//! Writing a TraceReport to disk or to a stream.
+//!
+//! The report is written next to its destination and renamed into place, so
+//! readers never see a truncated trace even if the tracer dies mid-write.
+//! Streams (stdout, a pipe from a parent process) get it in one pass instead.
+
+use crate::{Compression, TraceReport};
+use clap::ValueEnum;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Pretty-printed JSON
+    #[default]
+    Json,
+    /// JSON without whitespace
+    JsonCompact,
+    /// MessagePack, with the same field names as the JSON
+    Msgpack,
+    /// SQLite database with a table each for processes, files and accesses
+    Sqlite,
+    /// One comma-separated row per file: path, read, written, first_pid, bytes
+    Csv,
+    /// The csv rows, tab-separated
+    Tsv,
+    /// strace-style syscall lines, written while tracing instead of the
+    /// report (see StraceLog)
+    Strace,
+}
+
+impl TraceReport {
+    /// Atomically write the report to `path` in the given format.
+    pub fn write(
+        &self,
+        path: &str,
+        format: OutputFormat,
+        compression: Compression,
+    ) -> io::Result<()> {
+        let tmp_path = temp_output_path(path);
+        let result = serialize(self, format, compression, &tmp_path)
+            .and_then(|()| std::fs::rename(&tmp_path, path));
+        if result.is_err() {
+            let _ = std::fs::remove_file(&tmp_path);
+        }
+        result
+    }
+
+    /// Write the report to an open stream such as stdout or an inherited pipe.
+    pub fn write_to<W: Write>(
+        &self,
+        writer: W,
+        format: OutputFormat,
+        compression: Compression,
+    ) -> io::Result<()> {
+        encode(self, format, compression, writer).map(drop)
+    }
+}
+
+fn serialize(
+    report: &TraceReport,
+    format: OutputFormat,
+    compression: Compression,
+    path: &str,
+) -> io::Result<()> {
+    if let (OutputFormat::Sqlite, Compression::None) = (format, compression) {
+        return crate::sqlite::write(report, path).map_err(io::Error::other);
+    }
+    // Serialize straight into the file; traces can hold hundreds of thousands of paths
+    let file = encode(report, format, compression, File::create(path)?)?;
+    file.sync_all()
+}
+
+/// Serialize the report through the compressor into `writer`, and return it.
+fn encode<W: Write>(
+    report: &TraceReport,
+    format: OutputFormat,
+    compression: Compression,
+    writer: W,
+) -> io::Result<W> {
+    let mut writer = compression.writer(BufWriter::new(writer))?;
+    match format {
+        OutputFormat::Json => serde_json::to_writer_pretty(&mut writer, report)?,
+        OutputFormat::JsonCompact => serde_json::to_writer(&mut writer, report)?,
+        OutputFormat::Msgpack => {
+            // Named fields so the keys match the JSON output
+            rmp_serde::encode::write_named(&mut writer, report).map_err(io::Error::other)?
+        }
+        OutputFormat::Sqlite => {
+            // SQLite builds the database in a file; stream that once it's done
+            let db_path = temp_output_path(
+                &std::env::temp_dir()
+                    .join("roar-trace.sqlite")
+                    .to_string_lossy(),
+            );
+            let result = crate::sqlite::write(report, &db_path)
+                .map_err(io::Error::other)
+                .and_then(|()| io::copy(&mut File::open(&db_path)?, &mut writer));
+            let _ = std::fs::remove_file(&db_path);
+            result?;
+        }
+        OutputFormat::Csv => crate::csv::write(report, &mut writer, ',')?,
+        OutputFormat::Tsv => crate::csv::write(report, &mut writer, '\t')?,
+        OutputFormat::Strace => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "strace output is made from the syscalls, not the report",
+            ))
+        }
+    }
+    writer.finish()?.into_inner().map_err(|e| e.into_error())
+}
+
+/// Hidden sibling of the output file used while writing it.
+fn temp_output_path(output_file: &str) -> String {
+    let path = Path::new(output_file);
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Fail early if the output can't be written, rather than after a long trace.
+pub fn check_output_path(output_file: &str) -> io::Result<()> {
+    if Path::new(output_file).is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::IsADirectory,
+            "output path is a directory",
+        ));
+    }
+    let tmp_path = temp_output_path(output_file);
+    File::create(&tmp_path)?;
+    std::fs::remove_file(&tmp_path)
+}
@@ -0,0 +1,71 @@
+//! Path normalization applied before paths are recorded.
+//!
+//! Normalization is lexical by default: the file may be gone by the time we
+//! record it, so nothing here may depend on it still existing. Resolving
+//! symlinks is opt-in (--resolve-symlinks) and only ever touches the directory
+//! part, since for O_NOFOLLOW, unlink or readlink the link itself is the target.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Collapse `.`, `..` and repeated slashes, and drop trailing slashes.
+///
+/// `..` never climbs above `/`; relative paths keep leading `..` components.
+pub fn normalize(path: &str) -> String {
+    let absolute = path.starts_with('/');
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => match parts.last() {
+                Some(&last) if last != ".." => {
+                    parts.pop();
+                }
+                _ if absolute => {}
+                _ => parts.push(".."),
+            },
+            _ => parts.push(part),
+        }
+    }
+
+    let joined = parts.join("/");
+    match (absolute, joined.is_empty()) {
+        (true, _) => format!("/{}", joined),
+        (false, true) => ".".to_string(),
+        (false, false) => joined,
+    }
+}
+
+/// Resolves symlinks in the directory part of paths, caching per directory.
+#[derive(Debug, Default)]
+pub struct SymlinkResolver {
+    dirs: HashMap<String, Option<String>>, // directory -> canonical form, if resolvable
+}
+
+impl SymlinkResolver {
+    /// The path with its directory canonicalized, or None if that changes nothing
+    /// (or the directory no longer exists).
+    pub fn resolve(&mut self, path: &str) -> Option<String> {
+        let path = Path::new(path);
+        let (dir, name) = (path.parent()?, path.file_name()?);
+        let dir = dir.to_string_lossy().to_string();
+        let canonical = self
+            .dirs
+            .entry(dir.clone())
+            .or_insert_with(|| {
+                std::fs::canonicalize(&dir)
+                    .ok()
+                    .map(|p| p.to_string_lossy().to_string())
+            })
+            .clone()?;
+        if canonical == dir {
+            return None;
+        }
+        Some(
+            Path::new(&canonical)
+                .join(name)
+                .to_string_lossy()
+                .to_string(),
+        )
+    }
+}
@@ -0,0 +1,118 @@
+//! SLSA provenance (--provenance).
+//!
+//! Turns a trace into an in-toto Statement carrying a SLSA v1 provenance
+//! predicate: the files the command wrote are the subjects, the files it only
+//! read are its resolved dependencies, and the command line and environment
+//! of the root process are the build's parameters. Digests are SHA-256, as
+//! recorded with --hash-reads and --hash-writes, or else of the files as they
+//! are once the trace ends; files that are gone by then, or aren't regular
+//! files (/dev/null, pipes), are left out.
+
+use crate::digest::sha256_file;
+use crate::TraceReport;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+const PREDICATE_TYPE: &str = "https://slsa.dev/provenance/v1";
+// roar has no buildType spec of its own yet; these only need to be stable URIs
+const BUILD_TYPE: &str = "https://pypi.org/project/roar-cli/#tracer-v1";
+const BUILDER_ID: &str = "https://pypi.org/project/roar-cli/";
+
+/// Write the provenance statement for `report` to `path`.
+pub fn write(report: &TraceReport, path: &str) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, &statement(report))?;
+    writer.flush()
+}
+
+fn statement(report: &TraceReport) -> Value {
+    let written: BTreeSet<&str> = report.written_files.iter().map(String::as_str).collect();
+    let deleted: BTreeSet<&str> = report.deleted_files.iter().map(String::as_str).collect();
+
+    let subjects: Vec<Value> = written
+        .iter()
+        .filter(|path| !deleted.contains(*path))
+        .filter_map(|path| {
+            let recorded = report
+                .files
+                .get(*path)
+                .and_then(|f| f.written_sha256.clone());
+            let digest = recorded.or_else(|| sha256_file(path))?;
+            Some(json!({ "name": path, "digest": { "sha256": digest } }))
+        })
+        .collect();
+    // Files the build rewrote are its outputs, not its inputs
+    let dependencies: Vec<Value> = report
+        .read_files
+        .iter()
+        .filter(|path| !written.contains(path.as_str()))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter_map(|path| {
+            // What was read, if it was hashed at open; the file may have changed since
+            let recorded = report.files.get(path).and_then(|f| f.read_sha256.clone());
+            let digest = recorded.or_else(|| sha256_file(path))?;
+            Some(json!({
+                "uri": format!("file://{}", path),
+                "digest": { "sha256": digest },
+            }))
+        })
+        .collect();
+
+    let root = report.processes.iter().find(|p| p.parent_pid.is_none());
+    json!({
+        "_type": STATEMENT_TYPE,
+        "subject": subjects,
+        "predicateType": PREDICATE_TYPE,
+        "predicate": {
+            "buildDefinition": {
+                "buildType": BUILD_TYPE,
+                "externalParameters": {
+                    "command": root.map(|p| p.command.clone()).unwrap_or_default(),
+                },
+                "internalParameters": {
+                    "env": report.env_accessed,
+                },
+                "resolvedDependencies": dependencies,
+            },
+            "runDetails": {
+                "builder": {
+                    "id": BUILDER_ID,
+                    "version": { "roar-tracer": env!("CARGO_PKG_VERSION") },
+                },
+                "metadata": {
+                    "startedOn": rfc3339(report.start_time),
+                    "finishedOn": rfc3339(report.end_time),
+                },
+            },
+        },
+    })
+}
+
+/// Seconds since the epoch as an RFC 3339 UTC timestamp, to the second.
+fn rfc3339(secs: f64) -> String {
+    let secs = secs as i64;
+    let (days, time) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    // Howard Hinnant's civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
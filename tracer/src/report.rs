@@ -0,0 +1,192 @@
+//! The output schema: every type serialized into a TraceReport.
+//!
+//! Field names are what roar's Python (and any other consumer) reads, so the
+//! schema only grows. A field may be added, but never removed, renamed or
+//! given a different type or meaning; each release that adds fields bumps
+//! SCHEMA_VERSION and records the new field list under tests/schema/, and
+//! the schema test fails if a field disappears or appears without a bump.
+//! A change of meaning that can't be avoided also bumps the version and is
+//! noted in its history below. Types owned by other modules (NetworkConnection,
+//! NetworkTraffic, FilterSummary, TracerStats) follow the same rules.
+
+use crate::{FilterSummary, NetworkConnection, NetworkTraffic, TraceCategory, TracerStats};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+/// A traced process, from fork (or attach) to exit.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessInfo {
+    pub pid: i32,
+    pub parent_pid: Option<i32>,
+    pub command: Vec<String>,
+    // Full environment while tracing; written out as a diff against the parent
+    pub env: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub env_unset: Vec<String>, // parent variables this process no longer has
+    pub execs: Vec<ExecRecord>,
+    pub start_time: f64,
+    pub end_time: Option<f64>,
+    pub exit_code: Option<i32>,   // set when the process exited normally
+    pub term_signal: Option<i32>, // set when it was killed by a signal
+    pub cwd: Option<String>,      // working directory as of its last exec or chdir
+    pub chroot: Option<String>,   // root directory if the process chrooted
+    pub io_uring_used: bool,      // it submitted io_uring I/O we couldn't decode (e.g. SQPOLL)
+    pub mnt_ns: Option<u64>,      // mount namespace inode (/proc/<pid>/ns/mnt)
+    pub detached: bool,           // no longer traced after exec'ing a --no-follow program
+    pub thread_of: Option<i32>,   // thread-group leader, if this is a thread (CLONE_THREAD)
+    pub rusage: Option<ResourceUsage>, // read as it exited; None if that was missed
+    // What this process did to the files in the report's lists of the same names
+    pub opened_files: BTreeSet<String>,
+    pub read_files: BTreeSet<String>,
+    pub written_files: BTreeSet<String>,
+    // When it first and last touched a file, like FileStats::first_access
+    pub first_access: Option<f64>,
+    pub last_access: Option<f64>,
+}
+
+/// What a process used, from /proc as it exited. Threads share their
+/// process's figures.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceUsage {
+    pub user_time: f64,   // CPU seconds in user mode
+    pub system_time: f64, // CPU seconds in the kernel
+    pub max_rss_kb: u64,  // peak resident set size
+    pub read_bytes: u64,  // read from storage (page cache hits don't count)
+    pub write_bytes: u64, // sent to storage, or to be
+}
+
+/// One successful execve/execveat, in the order they happened for a pid.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecRecord {
+    pub timestamp: f64,
+    pub path: Option<String>, // resolved binary (/proc/<pid>/exe), if readable
+    pub argv: Vec<String>,
+}
+
+/// Version of the output schema, written as schema_version.
+///
+/// 1: first versioned output (adds format_version; earlier files have none).
+/// 2: `created` (and created_files) also covers files made by O_CREAT opens.
+/// 3: adds schema_version, partial, path_limit and traced_categories;
+///    io_uring_used, synced_files, shared_memory, resolved_hostnames,
+///    unix_sockets and network_traffic; local_address and denied in
+///    network_connections, whose operation may also be "listen"; per-process
+///    file lists, first_access, last_access, detached, rusage, thread_of and
+///    cwd; read_sha256, written_sha256, metadata, first_pid, bytes_read and
+///    bytes_written in files; ignored_mounts and root in path_filters.
+///    /dev/shm objects moved from the file lists to shared_memory, --anon-fds
+///    puts memfd:[name] and anon:[kind] pseudo-paths in them, and redacted
+///    environment values became "<redacted sha256:...>" instead of "<redacted>".
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Everything a trace recorded; what the roar-tracer binary writes to its output file.
+#[derive(Debug, Serialize)]
+pub struct TraceReport {
+    pub schema_version: u32,
+    // Versions 1 and 2 only wrote this: it stays, holding the same number, for
+    // parsers written against them that don't know schema_version
+    pub format_version: u32,
+    pub processes: Vec<ProcessInfo>,
+    pub files: HashMap<String, FileStats>,
+    pub opened_files: Vec<String>,
+    pub read_files: Vec<String>,
+    pub written_files: Vec<String>,
+    pub created_files: Vec<String>, // made by O_CREAT, or O_TMPFILE/memfd files named with linkat
+    pub stat_files: Vec<String>,
+    pub missing_files: Vec<String>,
+    pub metadata_changed_files: Vec<String>, // chmod/chown/utimes/xattr targets
+    pub created_dirs: Vec<String>,
+    pub removed_dirs: Vec<String>,
+    pub deleted_files: Vec<String>, // unlinked and not created again
+    pub listed_dirs: Vec<String>,   // directories whose entries were read (getdents)
+    pub synced_files: Vec<String>,  // flushed with fsync, fdatasync or sync_file_range
+    pub links: HashMap<String, String>, // created link path -> target (symlink or hardlink)
+    pub readlinks: HashMap<String, String>, // queried symlink -> target it pointed to
+    pub failed_opens: HashMap<String, i32>, // path -> errno, for errors other than ENOENT
+    pub env_accessed: HashMap<String, String>, // root process environment
+    pub env_redacted: Vec<String>,  // variables whose values were masked
+    pub mnt_ns: Option<u64>,        // the tracer's mount namespace, to compare with processes'
+    pub network_connections: Vec<NetworkConnection>,
+    // Hostnames looked up over DNS. Lookups answered from /etc/hosts or nscd
+    // don't appear; reads of /etc/hosts and /etc/resolv.conf are in read_files
+    pub resolved_hostnames: Vec<String>,
+    pub network_traffic: HashMap<String, NetworkTraffic>, // endpoint -> bytes moved through socket fds
+    pub unix_sockets: Vec<String>, // filesystem sockets connected to (docker.sock, database sockets)
+    pub io_uring_used: bool, // some io_uring I/O couldn't be decoded, so the lists may be incomplete
+    pub pipe_reads: HashMap<i32, Vec<String>>, // pid -> pipes it read from
+    pub pipe_writes: HashMap<i32, Vec<String>>, // pid -> pipes it wrote to
+    pub shared_memory: HashMap<String, SharedMemory>, // /dev/shm object -> its users
+    pub path_filters: FilterSummary,
+    pub traced_categories: Vec<TraceCategory>, // --trace; what the others would hold is missing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path_limit: Option<PathLimit>, // only with --max-paths
+    pub path_aliases: HashMap<String, String>, // spelling seen -> recorded path (--resolve-symlinks, --root)
+    pub interrupted: bool, // tracer was stopped by SIGINT/SIGTERM; the trace is partial
+    pub timed_out: bool,   // --timeout expired and the traced command was killed
+    pub partial: bool,     // a checkpoint (--flush-interval) written while the trace was running
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tracer_stats: Option<TracerStats>, // only with --stats
+    pub start_time: f64,
+    pub end_time: f64,
+    pub wall_time: f64,
+    // What roar-tracer exits with: the root's exit code, 128 + signal, or 124 on timeout
+    #[serde(skip)]
+    pub exit_code: i32,
+}
+
+/// When and how often a file was touched. Times are seconds since tracing
+/// started, on a monotonic clock; add the report's start_time for Unix time.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FileStats {
+    pub first_access: f64,
+    pub last_access: f64,
+    pub first_pid: i32,   // the process that touched it first
+    pub read_count: u64,  // successful read-type syscalls
+    pub write_count: u64, // successful write-type syscalls
+    // Bytes moved by the read and write syscalls that return a count (not mmap or io_uring)
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub opened: bool,
+    pub read: bool,
+    pub written: bool,
+    pub created: bool, // made by the trace: O_CREAT of an absent path, or a linkat'd unnamed file
+    pub open_flags: BTreeSet<&'static str>, // every flag it was opened with, see open_flag_names
+    pub mode: Option<u32>, // permission bits requested by the open that created it
+    // Hex SHA-256 of the contents when first opened for reading (--hash-reads)
+    // and once the trace ended (--hash-writes); unset for non-regular files
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub written_sha256: Option<String>,
+    pub metadata: Option<FileMetadata>, // stat when first opened; None if that failed
+}
+
+/// What stat said about a file when the trace first opened it, to tell
+/// whether an input changed between runs without hashing it.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileMetadata {
+    pub size: u64,
+    pub mtime: f64, // seconds since the epoch
+    pub inode: u64,
+    pub device: u64,
+    // Not created by the open, and its inode unchanged (ctime) since the trace began
+    pub existed_before: bool,
+}
+
+/// The cap on paths held in memory (--max-paths). Accesses to paths past it
+/// are left out of the path lists and files, and logged to the spill file.
+#[derive(Debug, Clone, Serialize)]
+pub struct PathLimit {
+    pub max_paths: usize,
+    pub spilled_events: u64, // accesses not held in memory; 0 if the cap was never reached
+}
+
+/// A POSIX shared memory object (shm_open, sem_open) and which processes used it.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SharedMemory {
+    pub openers: BTreeSet<i32>, // pids that opened it
+    pub readers: BTreeSet<i32>, // pids that read it or mapped it readable
+    pub writers: BTreeSet<i32>, // pids that wrote, resized or mapped it writable
+    pub created: bool,          // an O_CREAT open in the trace made it
+    pub unlinked: bool,         // shm_unlink'd by the end of the trace
+}
@@ -0,0 +1,110 @@
+//! Seccomp filter that limits syscall stops to the syscalls we trace (--seccomp).
+//!
+//! Without it a tracee stops at the entry and exit of every syscall. With it
+//! the launched command runs under a filter that returns SECCOMP_RET_TRACE
+//! for the syscalls the handlers look at and SECCOMP_RET_ALLOW for the rest:
+//! the tracer resumes with PTRACE_CONT, gets a PTRACE_EVENT_SECCOMP stop in
+//! place of a traced syscall's entry, and asks for that syscall's exit stop only.
+
+use std::io;
+
+// struct seccomp_data: int nr; __u32 arch; ...
+const DATA_NR: u32 = 0;
+const DATA_ARCH: u32 = 4;
+
+/// x32 syscall numbers have this bit set; their ABI differs, so trace them all.
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+/// A classic BPF program for PR_SET_SECCOMP.
+#[derive(Debug)]
+pub struct Filter(Vec<libc::sock_filter>);
+
+fn stmt(code: u32, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn jump(code: u32, k: u32, jt: usize) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt: u8::try_from(jt).expect("filter short enough for BPF jumps"),
+        jf: 0,
+        k,
+    }
+}
+
+impl Filter {
+    /// Trap `syscalls`, native numbers of `audit_arch`, and every syscall of
+    /// another ABI (i386 or x32 processes), whose numbers mean something else.
+    pub fn new(audit_arch: u32, syscalls: &[u64]) -> Self {
+        let load = libc::BPF_LD | libc::BPF_W | libc::BPF_ABS;
+        let ret = libc::BPF_RET | libc::BPF_K;
+        let mut program = vec![
+            stmt(load, DATA_ARCH),
+            // Native: skip the RET_TRACE below
+            libc::sock_filter {
+                code: (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16,
+                jt: 1,
+                jf: 0,
+                k: audit_arch,
+            },
+            stmt(ret, libc::SECCOMP_RET_TRACE),
+            stmt(load, DATA_NR),
+        ];
+        // Each check jumps forward to the final RET_TRACE
+        let checks = syscalls.len() + 1;
+        program.push(jump(
+            libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K,
+            X32_SYSCALL_BIT,
+            checks,
+        ));
+        for (i, &nr) in syscalls.iter().enumerate() {
+            let to_trace = checks - 1 - i;
+            program.push(jump(
+                libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+                nr as u32,
+                to_trace,
+            ));
+        }
+        program.push(stmt(ret, libc::SECCOMP_RET_ALLOW));
+        program.push(stmt(ret, libc::SECCOMP_RET_TRACE));
+        Filter(program)
+    }
+
+    /// Install the filter in the calling process; the forked child does this
+    /// right before exec, once the tracer has seized it.
+    pub fn install(&self) -> io::Result<()> {
+        let prog = libc::sock_fprog {
+            len: self.0.len() as u16,
+            filter: self.0.as_ptr() as *mut libc::sock_filter,
+        };
+        let set_filter = || unsafe {
+            libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER as libc::c_ulong,
+                &prog as *const libc::sock_fprog,
+            )
+        };
+        if set_filter() == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EACCES) {
+            return Err(err);
+        }
+        // Without CAP_SYS_ADMIN a filter needs no_new_privs, which keeps
+        // setuid programs run by the command from gaining privileges
+        if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1 as libc::c_ulong, 0, 0, 0) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if set_filter() == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
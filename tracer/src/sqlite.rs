@@ -0,0 +1,296 @@
+//! Writing a TraceReport as an SQLite database (--format sqlite).
+//!
+//! Processes, files and what each process did to each file get their own
+//! tables so large traces can be queried with SQL. Lists of strings (argv,
+//! open flags) are stored as JSON arrays; the report fields that have no
+//! table of their own are kept as one JSON object in trace.extra.
+
+use crate::TraceReport;
+use rusqlite::{params, Connection, Transaction};
+use serde_json::json;
+
+const SCHEMA: &str = "
+CREATE TABLE trace (
+    schema_version INTEGER NOT NULL,
+    format_version INTEGER NOT NULL,
+    start_time REAL NOT NULL,
+    end_time REAL NOT NULL,
+    wall_time REAL NOT NULL,
+    interrupted INTEGER NOT NULL,
+    timed_out INTEGER NOT NULL,
+    io_uring_used INTEGER NOT NULL,
+    mnt_ns INTEGER,
+    extra TEXT NOT NULL
+);
+CREATE TABLE processes (
+    pid INTEGER NOT NULL,
+    parent_pid INTEGER,
+    command TEXT NOT NULL,
+    start_time REAL NOT NULL,
+    end_time REAL,
+    exit_code INTEGER,
+    term_signal INTEGER,
+    cwd TEXT,
+    chroot TEXT,
+    io_uring_used INTEGER NOT NULL,
+    mnt_ns INTEGER,
+    thread_of INTEGER,
+    first_access REAL,
+    last_access REAL
+);
+CREATE TABLE execs (
+    pid INTEGER NOT NULL,
+    timestamp REAL NOT NULL,
+    path TEXT,
+    argv TEXT NOT NULL
+);
+-- Each process's environment as a diff against its parent's; value is NULL
+-- for a variable it no longer has
+CREATE TABLE env (
+    pid INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    value TEXT
+);
+CREATE TABLE files (
+    path TEXT PRIMARY KEY,
+    first_access REAL NOT NULL,
+    last_access REAL NOT NULL,
+    read_count INTEGER NOT NULL,
+    write_count INTEGER NOT NULL,
+    opened INTEGER NOT NULL,
+    read INTEGER NOT NULL,
+    written INTEGER NOT NULL,
+    created INTEGER NOT NULL,
+    open_flags TEXT NOT NULL,
+    mode INTEGER,
+    read_sha256 TEXT,
+    written_sha256 TEXT,
+    -- stat when first opened; all NULL if that failed
+    size INTEGER,
+    mtime REAL,
+    inode INTEGER,
+    device INTEGER,
+    existed_before INTEGER,
+    first_pid INTEGER NOT NULL,
+    bytes_read INTEGER NOT NULL,
+    bytes_written INTEGER NOT NULL
+);
+-- mode is opened, read or written
+CREATE TABLE accesses (
+    pid INTEGER NOT NULL,
+    path TEXT NOT NULL,
+    mode TEXT NOT NULL
+);
+-- list is the name of the path list in the JSON output (stat_files, deleted_files, ...)
+CREATE TABLE path_lists (
+    list TEXT NOT NULL,
+    path TEXT NOT NULL
+);
+-- kind is link or readlink
+CREATE TABLE links (
+    kind TEXT NOT NULL,
+    path TEXT NOT NULL,
+    target TEXT NOT NULL
+);
+CREATE TABLE failed_opens (
+    path TEXT PRIMARY KEY,
+    errno INTEGER NOT NULL
+);
+CREATE TABLE network_connections (
+    pid INTEGER NOT NULL,
+    family TEXT NOT NULL,
+    operation TEXT NOT NULL,
+    address TEXT NOT NULL,
+    local_address TEXT,
+    success INTEGER NOT NULL,
+    denied INTEGER NOT NULL
+);
+CREATE TABLE network_traffic (
+    endpoint TEXT PRIMARY KEY,
+    bytes_sent INTEGER NOT NULL,
+    bytes_received INTEGER NOT NULL
+);
+CREATE INDEX processes_pid ON processes (pid);
+CREATE INDEX accesses_path ON accesses (path);
+CREATE INDEX accesses_pid ON accesses (pid);
+";
+
+/// Create a database at `path` (which must not exist) holding the report.
+pub fn write(report: &TraceReport, path: &str) -> rusqlite::Result<()> {
+    let mut conn = Connection::open(path)?;
+    conn.execute_batch(SCHEMA)?;
+    // One transaction: row-at-a-time commits would take minutes on big traces
+    let tx = conn.transaction()?;
+    insert_trace(&tx, report)?;
+    insert_processes(&tx, report)?;
+    insert_files(&tx, report)?;
+    insert_network(&tx, report)?;
+    tx.commit()?;
+    conn.close().map_err(|(_, e)| e)
+}
+
+fn to_json<T: serde::Serialize + ?Sized>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+fn insert_trace(tx: &Transaction, report: &TraceReport) -> rusqlite::Result<()> {
+    let extra = json!({
+        "env_redacted": report.env_redacted,
+        "pipe_reads": report.pipe_reads,
+        "pipe_writes": report.pipe_writes,
+        "shared_memory": report.shared_memory,
+        "path_filters": report.path_filters,
+        "path_aliases": report.path_aliases,
+        "tracer_stats": report.tracer_stats,
+    });
+    tx.execute(
+        "INSERT INTO trace VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            report.schema_version,
+            report.format_version,
+            report.start_time,
+            report.end_time,
+            report.wall_time,
+            report.interrupted,
+            report.timed_out,
+            report.io_uring_used,
+            report.mnt_ns.map(|ns| ns as i64),
+            extra.to_string(),
+        ],
+    )?;
+    Ok(())
+}
+
+fn insert_processes(tx: &Transaction, report: &TraceReport) -> rusqlite::Result<()> {
+    let mut process = tx.prepare(
+        "INSERT INTO processes VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+    )?;
+    let mut exec = tx.prepare("INSERT INTO execs VALUES (?1, ?2, ?3, ?4)")?;
+    let mut env = tx.prepare("INSERT INTO env VALUES (?1, ?2, ?3)")?;
+    let mut access = tx.prepare("INSERT INTO accesses VALUES (?1, ?2, ?3)")?;
+    for p in &report.processes {
+        process.execute(params![
+            p.pid,
+            p.parent_pid,
+            to_json(&p.command),
+            p.start_time,
+            p.end_time,
+            p.exit_code,
+            p.term_signal,
+            p.cwd,
+            p.chroot,
+            p.io_uring_used,
+            p.mnt_ns.map(|ns| ns as i64),
+            p.thread_of,
+            p.first_access,
+            p.last_access,
+        ])?;
+        for e in &p.execs {
+            exec.execute(params![p.pid, e.timestamp, e.path, to_json(&e.argv)])?;
+        }
+        for (name, value) in &p.env {
+            env.execute(params![p.pid, name, value])?;
+        }
+        for name in &p.env_unset {
+            env.execute(params![p.pid, name, None::<String>])?;
+        }
+        for (mode, paths) in [
+            ("opened", &p.opened_files),
+            ("read", &p.read_files),
+            ("written", &p.written_files),
+        ] {
+            for path in paths {
+                access.execute(params![p.pid, path, mode])?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn insert_files(tx: &Transaction, report: &TraceReport) -> rusqlite::Result<()> {
+    let mut file = tx.prepare(
+        "INSERT INTO files VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, \
+         ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)",
+    )?;
+    for (path, s) in &report.files {
+        file.execute(params![
+            path,
+            s.first_access,
+            s.last_access,
+            s.read_count as i64,
+            s.write_count as i64,
+            s.opened,
+            s.read,
+            s.written,
+            s.created,
+            to_json(&s.open_flags),
+            s.mode,
+            s.read_sha256,
+            s.written_sha256,
+            s.metadata.as_ref().map(|m| m.size as i64),
+            s.metadata.as_ref().map(|m| m.mtime),
+            s.metadata.as_ref().map(|m| m.inode as i64),
+            s.metadata.as_ref().map(|m| m.device as i64),
+            s.metadata.as_ref().map(|m| m.existed_before),
+            s.first_pid,
+            s.bytes_read as i64,
+            s.bytes_written as i64,
+        ])?;
+    }
+
+    let mut listed = tx.prepare("INSERT INTO path_lists VALUES (?1, ?2)")?;
+    for (list, paths) in [
+        ("stat_files", &report.stat_files),
+        ("missing_files", &report.missing_files),
+        ("metadata_changed_files", &report.metadata_changed_files),
+        ("created_dirs", &report.created_dirs),
+        ("removed_dirs", &report.removed_dirs),
+        ("deleted_files", &report.deleted_files),
+        ("listed_dirs", &report.listed_dirs),
+        ("synced_files", &report.synced_files),
+        ("unix_sockets", &report.unix_sockets),
+        ("resolved_hostnames", &report.resolved_hostnames),
+    ] {
+        for path in paths {
+            listed.execute(params![list, path])?;
+        }
+    }
+
+    let mut link = tx.prepare("INSERT INTO links VALUES (?1, ?2, ?3)")?;
+    for (kind, links) in [("link", &report.links), ("readlink", &report.readlinks)] {
+        for (path, target) in links {
+            link.execute(params![kind, path, target])?;
+        }
+    }
+
+    let mut failed = tx.prepare("INSERT INTO failed_opens VALUES (?1, ?2)")?;
+    for (path, errno) in &report.failed_opens {
+        failed.execute(params![path, errno])?;
+    }
+    Ok(())
+}
+
+fn insert_network(tx: &Transaction, report: &TraceReport) -> rusqlite::Result<()> {
+    let mut connection =
+        tx.prepare("INSERT INTO network_connections VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")?;
+    for c in &report.network_connections {
+        connection.execute(params![
+            c.pid,
+            c.family,
+            c.operation,
+            c.address,
+            c.local_address,
+            c.success,
+            c.denied,
+        ])?;
+    }
+    let mut traffic = tx.prepare("INSERT INTO network_traffic VALUES (?1, ?2, ?3)")?;
+    for (endpoint, t) in &report.network_traffic {
+        traffic.execute(params![
+            endpoint,
+            t.bytes_sent as i64,
+            t.bytes_received as i64
+        ])?;
+    }
+    Ok(())
+}
@@ -0,0 +1,102 @@
+//! Tracer overhead statistics, reported with --stats.
+//!
+//! Tracee memory reads and swallowed ptrace errors are counted in statics, since
+//! the helpers that see them don't have the tracer state at hand. They are cheap
+//! relaxed atomics and always on; --stats only decides whether they are reported.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Only one handle_syscall call in this many is timed, to keep clock reads cheap.
+pub const SAMPLE_INTERVAL: u64 = 64;
+
+static STRING_READS: AtomicU64 = AtomicU64::new(0);
+static BYTES_READ: AtomicU64 = AtomicU64::new(0);
+static PTRACE_ERRORS: AtomicU64 = AtomicU64::new(0);
+
+/// Count a string or struct read from tracee memory.
+pub fn count_read(bytes: usize) {
+    STRING_READS.fetch_add(1, Ordering::Relaxed);
+    BYTES_READ.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+/// Count a failed ptrace or waitpid call that the trace carried on past.
+pub fn count_error() {
+    PTRACE_ERRORS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// What the tracer spent its time on.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TracerStats {
+    pub stops: u64,                      // every waitpid result handled by the trace loop
+    pub syscalls: BTreeMap<String, u64>, // syscall name (or "other") -> entries seen
+    pub string_reads: u64,
+    pub bytes_read: u64,      // from tracee memory
+    pub ptrace_errors: u64,   // failures the trace carried on past
+    pub handler_seconds: f64, // time in handle_syscall, extrapolated from samples
+    pub sample_interval: u64,
+}
+
+/// Counters kept while tracing; turned into TracerStats at the end.
+#[derive(Debug, Clone, Default)]
+pub struct StatsCollector {
+    stops: u64,
+    syscalls: HashMap<u64, u64>, // syscall number -> entries
+    handler_calls: u64,
+    sampled: Duration,
+}
+
+impl StatsCollector {
+    /// Start collecting; the global counters restart from zero for each trace.
+    pub fn new() -> Self {
+        STRING_READS.store(0, Ordering::Relaxed);
+        BYTES_READ.store(0, Ordering::Relaxed);
+        PTRACE_ERRORS.store(0, Ordering::Relaxed);
+        StatsCollector::default()
+    }
+
+    pub fn count_stop(&mut self) {
+        self.stops += 1;
+    }
+
+    pub fn count_syscall(&mut self, nr: u64) {
+        *self.syscalls.entry(nr).or_default() += 1;
+    }
+
+    /// Start timing a handle_syscall call if it is one of the sampled ones.
+    pub fn start_handler(&mut self) -> Option<Instant> {
+        self.handler_calls += 1;
+        self.handler_calls
+            .is_multiple_of(SAMPLE_INTERVAL)
+            .then(Instant::now)
+    }
+
+    pub fn end_handler(&mut self, started: Option<Instant>) {
+        if let Some(started) = started {
+            self.sampled += started.elapsed();
+        }
+    }
+
+    /// Final statistics; syscalls not in `names` are folded into "other".
+    pub fn finish(self, names: &[(u64, &str)]) -> TracerStats {
+        let mut syscalls = BTreeMap::new();
+        for (nr, count) in self.syscalls {
+            let name = names
+                .iter()
+                .find(|(known, _)| *known == nr)
+                .map_or("other", |(_, name)| name);
+            *syscalls.entry(name.to_string()).or_default() += count;
+        }
+        TracerStats {
+            stops: self.stops,
+            syscalls,
+            string_reads: STRING_READS.load(Ordering::Relaxed),
+            bytes_read: BYTES_READ.load(Ordering::Relaxed),
+            ptrace_errors: PTRACE_ERRORS.load(Ordering::Relaxed),
+            handler_seconds: self.sampled.as_secs_f64() * SAMPLE_INTERVAL as f64,
+            sample_interval: SAMPLE_INTERVAL,
+        }
+    }
+}
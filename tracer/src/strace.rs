@@ -0,0 +1,247 @@
+//! strace-style lines for the syscalls the tracer handles (--format strace).
+//!
+//! Arguments are rendered at syscall entry, while the strings they point to
+//! are still there (an execve's are gone by its exit), and the line is
+//! completed with the result at exit. Paths, argv, fds, open flags and modes
+//! are decoded; buffers and structs are shown as addresses, and syscalls
+//! without a signature below as `...`. Lines are only for the syscalls in
+//! SYSCALL_NAMES, so anything strace shows that is missing here is a syscall
+//! the tracer doesn't look at.
+
+use crate::arch::SyscallRegs;
+use crate::compress::{Compression, Compressor};
+use crate::{
+    SYS_ACCEPT, SYS_ACCEPT4, SYS_ACCESS, SYS_BIND, SYS_CHDIR, SYS_CHMOD, SYS_CHOWN, SYS_CHROOT,
+    SYS_CLOSE, SYS_CLOSE_RANGE, SYS_CONNECT, SYS_COPY_FILE_RANGE, SYS_DUP, SYS_DUP2, SYS_DUP3,
+    SYS_EXECVE, SYS_EXECVEAT, SYS_FACCESSAT, SYS_FACCESSAT2, SYS_FALLOCATE, SYS_FCHDIR, SYS_FCHMOD,
+    SYS_FCHMODAT, SYS_FCHMODAT2, SYS_FCHOWN, SYS_FCHOWNAT, SYS_FCNTL, SYS_FDATASYNC, SYS_FSTAT,
+    SYS_FSYNC, SYS_FTRUNCATE, SYS_GETDENTS, SYS_GETDENTS64, SYS_LCHOWN, SYS_LINK, SYS_LINKAT,
+    SYS_LISTEN, SYS_LSTAT, SYS_MEMFD_CREATE, SYS_MKDIR, SYS_MKDIRAT, SYS_MMAP, SYS_MPROTECT,
+    SYS_MSYNC, SYS_MUNMAP, SYS_NEWFSTATAT, SYS_OPEN, SYS_OPENAT, SYS_OPENAT2, SYS_PIPE, SYS_PIPE2,
+    SYS_PREAD64, SYS_PWRITE64, SYS_READ, SYS_READLINK, SYS_READLINKAT, SYS_READV, SYS_RECVFROM,
+    SYS_RECVMSG, SYS_RENAME, SYS_RENAMEAT, SYS_RENAMEAT2, SYS_RMDIR, SYS_SENDFILE, SYS_SENDMSG,
+    SYS_SENDTO, SYS_SOCKET, SYS_STAT, SYS_STATX, SYS_SYMLINK, SYS_SYMLINKAT, SYS_TRUNCATE,
+    SYS_UNLINK, SYS_UNLINKAT, SYS_WRITE, SYS_WRITEV,
+};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// One completed syscall, as given to `Tracer::on_syscall`.
+#[derive(Debug)]
+pub struct SyscallRecord<'a> {
+    pub timestamp: f64,
+    pub pid: i32,
+    pub name: &'a str,
+    pub args: &'a str, // rendered as strace does, without the parentheses
+    pub result: i64,   // the raw return value; -errno on failure
+}
+
+/// Callback given every completed syscall (`Tracer::on_syscall`).
+pub type SyscallHook = Box<dyn FnMut(&SyscallRecord)>;
+
+impl fmt::Display for SyscallRecord<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({}) = ", self.name, self.args)?;
+        match self.result {
+            -4095..=-1 => {
+                let errno = nix::errno::Errno::from_raw(-self.result as i32);
+                write!(f, "-1 {:?} ({})", errno, errno.desc())
+            }
+            result if self.name == "mmap" => write!(f, "{:#x}", result),
+            result => write!(f, "{}", result),
+        }
+    }
+}
+
+/// Syscall lines written as `<pid> <line>` to a file or stream while tracing.
+#[derive(Debug)]
+pub struct StraceLog {
+    writer: Compressor<BufWriter<File>>,
+    failed: Option<io::Error>, // the first write error, reported by finish
+}
+
+impl StraceLog {
+    pub fn new(file: File, compression: Compression) -> io::Result<Self> {
+        Ok(StraceLog {
+            writer: compression.writer(BufWriter::new(file))?,
+            failed: None,
+        })
+    }
+
+    pub fn record(&mut self, record: &SyscallRecord) {
+        if self.failed.is_none() {
+            if let Err(e) = writeln!(self.writer, "{} {}", record.pid, record) {
+                self.failed = Some(e);
+            }
+        }
+    }
+
+    /// Flush everything written and end the compressed stream.
+    pub fn finish(self) -> io::Result<()> {
+        if let Some(e) = self.failed {
+            return Err(e);
+        }
+        self.writer
+            .finish()?
+            .into_inner()
+            .map_err(|e| e.into_error())?;
+        Ok(())
+    }
+}
+
+/// How to show one argument.
+#[derive(Clone, Copy)]
+enum Arg {
+    Fd,
+    DirFd, // AT_FDCWD or an fd
+    Path,
+    Argv,
+    OpenFlags,
+    Mode,
+    Int,
+    Hex, // flags without names here, and pointers
+}
+
+use Arg::*;
+
+fn signature(nr: u64) -> Option<&'static [Arg]> {
+    Some(match nr {
+        SYS_READ | SYS_WRITE | SYS_GETDENTS | SYS_GETDENTS64 => &[Fd, Hex, Int],
+        SYS_PREAD64 | SYS_PWRITE64 => &[Fd, Hex, Int, Int],
+        SYS_READV | SYS_WRITEV => &[Fd, Hex, Int],
+        SYS_OPEN => &[Path, OpenFlags, Mode],
+        SYS_OPENAT => &[DirFd, Path, OpenFlags, Mode],
+        SYS_OPENAT2 => &[DirFd, Path, Hex, Int],
+        SYS_CLOSE | SYS_FSYNC | SYS_FDATASYNC | SYS_FCHDIR | SYS_DUP => &[Fd],
+        SYS_DUP2 => &[Fd, Fd],
+        SYS_DUP3 => &[Fd, Fd, Hex],
+        SYS_STAT | SYS_LSTAT => &[Path, Hex],
+        SYS_FSTAT => &[Fd, Hex],
+        SYS_NEWFSTATAT => &[DirFd, Path, Hex, Hex],
+        SYS_STATX => &[DirFd, Path, Hex, Hex, Hex],
+        SYS_ACCESS => &[Path, Int],
+        SYS_FACCESSAT => &[DirFd, Path, Int],
+        SYS_FACCESSAT2 => &[DirFd, Path, Int, Hex],
+        SYS_EXECVE => &[Path, Argv, Hex],
+        SYS_EXECVEAT => &[DirFd, Path, Argv, Hex, Hex],
+        SYS_CHDIR | SYS_RMDIR | SYS_UNLINK | SYS_CHROOT => &[Path],
+        SYS_MKDIR | SYS_CHMOD => &[Path, Mode],
+        SYS_MKDIRAT | SYS_FCHMODAT => &[DirFd, Path, Mode],
+        SYS_FCHMODAT2 => &[DirFd, Path, Mode, Hex],
+        SYS_FCHMOD => &[Fd, Mode],
+        SYS_RENAME | SYS_LINK | SYS_SYMLINK => &[Path, Path],
+        SYS_RENAMEAT => &[DirFd, Path, DirFd, Path],
+        SYS_RENAMEAT2 | SYS_LINKAT => &[DirFd, Path, DirFd, Path, Hex],
+        SYS_SYMLINKAT => &[Path, DirFd, Path],
+        SYS_READLINK => &[Path, Hex, Int],
+        SYS_READLINKAT => &[DirFd, Path, Hex, Int],
+        SYS_UNLINKAT => &[DirFd, Path, Hex],
+        SYS_CHOWN | SYS_LCHOWN => &[Path, Int, Int],
+        SYS_FCHOWN => &[Fd, Int, Int],
+        SYS_FCHOWNAT => &[DirFd, Path, Int, Int, Hex],
+        SYS_TRUNCATE => &[Path, Int],
+        SYS_FTRUNCATE => &[Fd, Int],
+        SYS_CONNECT | SYS_BIND => &[Fd, Hex, Int],
+        SYS_LISTEN => &[Fd, Int],
+        SYS_MEMFD_CREATE => &[Path, Hex],
+        SYS_MMAP => &[Hex, Int, Hex, Hex, Fd, Hex],
+        SYS_MPROTECT | SYS_MSYNC => &[Hex, Int, Hex],
+        SYS_MUNMAP => &[Hex, Int],
+        SYS_FCNTL => &[Fd, Int, Hex],
+        SYS_PIPE => &[Hex],
+        SYS_PIPE2 => &[Hex, Hex],
+        SYS_SOCKET => &[Int, Int, Int],
+        SYS_ACCEPT => &[Fd, Hex, Hex],
+        SYS_ACCEPT4 => &[Fd, Hex, Hex, Hex],
+        SYS_SENDTO | SYS_RECVFROM => &[Fd, Hex, Int, Hex, Hex, Int],
+        SYS_SENDMSG | SYS_RECVMSG => &[Fd, Hex, Hex],
+        SYS_FALLOCATE => &[Fd, Int, Int, Int],
+        SYS_CLOSE_RANGE => &[Int, Int, Hex],
+        SYS_SENDFILE => &[Fd, Fd, Hex, Int],
+        SYS_COPY_FILE_RANGE => &[Fd, Hex, Fd, Hex, Int, Hex],
+        _ => return None,
+    })
+}
+
+/// The argument list of syscall `nr`, reading strings through `read_string`
+/// and string arrays through `read_argv`.
+pub(crate) fn render_args(
+    nr: u64,
+    regs: &SyscallRegs,
+    read_string: impl Fn(u64) -> Option<String>,
+    read_argv: impl Fn(u64) -> Vec<String>,
+) -> String {
+    let Some(signature) = signature(nr) else {
+        return "...".to_string();
+    };
+    let values = [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9];
+    // Like strace, an open's mode is only shown when it can create the file
+    let creates = |flags: u64| flags as i32 & (libc::O_CREAT | libc::O_TMPFILE) != 0;
+    let mut opens_without_create = false;
+    let args: Vec<String> = signature
+        .iter()
+        .zip(values)
+        .filter(|(arg, value)| match arg {
+            OpenFlags => {
+                opens_without_create = !creates(*value);
+                true
+            }
+            Mode => !opens_without_create,
+            _ => true,
+        })
+        .map(|(arg, value)| match arg {
+            Fd => (value as i32).to_string(),
+            DirFd if value as i32 == libc::AT_FDCWD => "AT_FDCWD".to_string(),
+            DirFd => (value as i32).to_string(),
+            Path => match read_string(value) {
+                Some(path) => format!("{:?}", path),
+                None => format!("{:#x}", value),
+            },
+            Argv => format!("{:?}", read_argv(value)),
+            OpenFlags => open_flags(value as i32),
+            Mode => format!("{:04o}", value & 0o7777),
+            Int => (value as i64).to_string(),
+            Hex => format!("{:#x}", value),
+        })
+        .collect();
+    args.join(", ")
+}
+
+/// O_RDONLY|O_CREAT|... as strace writes them, with unknown bits in hex.
+fn open_flags(flags: i32) -> String {
+    let mut names = vec![match flags & libc::O_ACCMODE {
+        libc::O_WRONLY => "O_WRONLY",
+        libc::O_RDWR => "O_RDWR",
+        _ => "O_RDONLY",
+    }];
+    let mut rest = flags & !libc::O_ACCMODE;
+    // O_TMPFILE includes O_DIRECTORY's bit, so it goes first
+    for (flag, name) in [
+        (libc::O_TMPFILE, "O_TMPFILE"),
+        (libc::O_CREAT, "O_CREAT"),
+        (libc::O_EXCL, "O_EXCL"),
+        (libc::O_NOCTTY, "O_NOCTTY"),
+        (libc::O_TRUNC, "O_TRUNC"),
+        (libc::O_APPEND, "O_APPEND"),
+        (libc::O_NONBLOCK, "O_NONBLOCK"),
+        (libc::O_DSYNC, "O_DSYNC"),
+        (libc::O_DIRECT, "O_DIRECT"),
+        (libc::O_LARGEFILE, "O_LARGEFILE"),
+        (libc::O_DIRECTORY, "O_DIRECTORY"),
+        (libc::O_NOFOLLOW, "O_NOFOLLOW"),
+        (libc::O_NOATIME, "O_NOATIME"),
+        (libc::O_CLOEXEC, "O_CLOEXEC"),
+        (libc::O_PATH, "O_PATH"),
+    ] {
+        if rest & flag == flag {
+            names.push(name);
+            rest &= !flag;
+        }
+    }
+    let mut flags = names.join("|");
+    if rest != 0 {
+        flags.push_str(&format!("|{:#x}", rest));
+    }
+    flags
+}
@@ -0,0 +1,464 @@
+//! io_uring ring decoding.
+//!
+//! I/O submitted through a ring never shows up as read/write syscalls: the
+//! tracee fills submission queue entries (SQEs) in memory it shares with the
+//! kernel and calls io_uring_enter. io_uring_setup tells us the ring layout and
+//! the mmaps of the ring fd where it lives, so at each io_uring_enter the pending
+//! SQEs can be read out of tracee memory, and at its exit the completion queue
+//! entries (CQEs) carrying each operation's result.
+//!
+//! Like a syscall, an operation is only recorded once its CQE says it worked.
+//! Completions the tracee reaps straight from memory, without entering the
+//! kernel again after they were posted, are never seen, so their operations
+//! go unrecorded.
+
+use std::collections::HashMap;
+
+// Opcodes (include/uapi/linux/io_uring.h)
+pub const OP_READV: u8 = 1;
+pub const OP_WRITEV: u8 = 2;
+pub const OP_FSYNC: u8 = 3;
+pub const OP_READ_FIXED: u8 = 4;
+pub const OP_WRITE_FIXED: u8 = 5;
+pub const OP_SYNC_FILE_RANGE: u8 = 8;
+pub const OP_FALLOCATE: u8 = 17;
+pub const OP_OPENAT: u8 = 18;
+pub const OP_CLOSE: u8 = 19;
+pub const OP_STATX: u8 = 21;
+pub const OP_READ: u8 = 22;
+pub const OP_WRITE: u8 = 23;
+pub const OP_SEND: u8 = 26;
+pub const OP_RECV: u8 = 27;
+pub const OP_OPENAT2: u8 = 28;
+pub const OP_SPLICE: u8 = 30;
+pub const OP_TEE: u8 = 33;
+pub const OP_RENAMEAT: u8 = 35;
+pub const OP_UNLINKAT: u8 = 36;
+pub const OP_MKDIRAT: u8 = 37;
+pub const OP_SYMLINKAT: u8 = 38;
+pub const OP_LINKAT: u8 = 39;
+
+// SQE flags
+pub const IOSQE_FIXED_FILE: u8 = 1 << 0; // fd is an index into the registered files
+pub const IOSQE_CQE_SKIP_SUCCESS: u8 = 1 << 6; // no CQE unless the operation fails
+pub const SPLICE_F_FD_IN_FIXED: u32 = 1 << 31; // splice_fd_in is a registered file index
+
+// A direct-descriptor open with this file_index lets the kernel pick the slot
+pub const FILE_INDEX_ALLOC: u32 = u32::MAX;
+
+// io_uring_enter flags
+pub const ENTER_REGISTERED_RING: u64 = 1 << 4; // fd is an index from REGISTER_RING_FDS
+
+// io_uring_register opcodes
+pub const REGISTER_FILES: u32 = 2;
+pub const UNREGISTER_FILES: u32 = 3;
+pub const REGISTER_FILES_UPDATE: u32 = 6;
+pub const REGISTER_FILES2: u32 = 13;
+pub const REGISTER_FILES_UPDATE2: u32 = 14;
+pub const REGISTER_RING_FDS: u32 = 20;
+pub const UNREGISTER_RING_FDS: u32 = 21;
+pub const REGISTER_USE_REGISTERED_RING: u32 = 1 << 31;
+pub const RSRC_REGISTER_SPARSE: u32 = 1 << 0;
+
+// Setup flags and features that change where and how the rings are laid out
+const SETUP_SQPOLL: u32 = 1 << 1;
+const SETUP_SQE128: u32 = 1 << 10;
+const SETUP_CQE32: u32 = 1 << 11;
+const SETUP_NO_MMAP: u32 = 1 << 14;
+const SETUP_NO_SQARRAY: u32 = 1 << 16;
+const FEAT_SINGLE_MMAP: u32 = 1 << 0;
+
+// mmap offsets of the ring fd
+const OFF_SQ_RING: u64 = 0;
+const OFF_CQ_RING: u64 = 0x8000000;
+const OFF_SQES: u64 = 0x10000000;
+
+/// Size of struct io_uring_params, which io_uring_setup fills in.
+pub const PARAMS_SIZE: usize = 120;
+
+/// One io_uring instance as seen from a tracee's address space.
+#[derive(Debug, Clone)]
+pub struct Ring<T> {
+    flags: u32,
+    sq_off: [u32; 7], // head, tail, ring_mask, ring_entries, flags, dropped, array
+    cq_off: [u32; 6], // head, tail, ring_mask, ring_entries, overflow, cqes
+    single_mmap: bool,
+    sq_ring: Option<u64>,
+    cq_ring: Option<u64>,
+    sqes: Option<u64>,
+    pub files: Vec<Option<String>>, // registered file labels by index
+    pub pending: HashMap<u64, T>,   // user_data -> operation awaiting its CQE
+}
+
+/// The fields of a submission queue entry we attribute I/O with.
+#[derive(Debug)]
+pub struct Sqe {
+    pub opcode: u8,
+    pub flags: u8,
+    pub fd: i32,
+    pub off: u64, // also addr2
+    pub addr: u64,
+    pub len: u32,
+    pub op_flags: u32, // open_flags, rename_flags, splice_flags, ...
+    pub user_data: u64,
+    pub file_index: u32, // also splice_fd_in
+}
+
+/// What an SQE does to a file it names by descriptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileIo {
+    Read,
+    Write,
+    Sync,
+}
+
+impl Sqe {
+    /// Decode the first 48 bytes of an SQE, which hold every field we use.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 48 {
+            return None;
+        }
+        Some(Sqe {
+            opcode: bytes[0],
+            flags: bytes[1],
+            fd: u32_at(bytes, 4) as i32,
+            off: u64_at(bytes, 8),
+            addr: u64_at(bytes, 16),
+            len: u32_at(bytes, 24),
+            op_flags: u32_at(bytes, 28),
+            user_data: u64_at(bytes, 32),
+            file_index: u32_at(bytes, 44),
+        })
+    }
+
+    /// The open files this SQE moves data on or syncs, as (what, fd, fixed);
+    /// a fixed fd is an index into the registered files.
+    pub fn file_io(&self) -> Vec<(FileIo, i32, bool)> {
+        let fixed = self.flags & IOSQE_FIXED_FILE != 0;
+        match self.opcode {
+            OP_READV | OP_READ_FIXED | OP_READ | OP_RECV => vec![(FileIo::Read, self.fd, fixed)],
+            OP_WRITEV | OP_WRITE_FIXED | OP_WRITE | OP_SEND | OP_FALLOCATE => {
+                vec![(FileIo::Write, self.fd, fixed)]
+            }
+            OP_FSYNC | OP_SYNC_FILE_RANGE => vec![(FileIo::Sync, self.fd, fixed)],
+            // splice_fd_in shares the file_index slot; fd is the output side
+            OP_SPLICE | OP_TEE => vec![
+                (
+                    FileIo::Read,
+                    self.file_index as i32,
+                    self.op_flags & SPLICE_F_FD_IN_FIXED != 0,
+                ),
+                (FileIo::Write, self.fd, fixed),
+            ],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// A completion queue entry: which operation finished, and its result.
+#[derive(Debug)]
+pub struct Cqe {
+    pub user_data: u64,
+    pub res: i32,
+}
+
+fn u32_at(bytes: &[u8], off: usize) -> u32 {
+    u32::from_ne_bytes(bytes[off..off + 4].try_into().unwrap_or([0; 4]))
+}
+
+fn u64_at(bytes: &[u8], off: usize) -> u64 {
+    u64::from_ne_bytes(bytes[off..off + 8].try_into().unwrap_or([0; 8]))
+}
+
+impl<T> Ring<T> {
+    /// Decode the io_uring_params the kernel filled in for a new ring.
+    pub fn from_params(params: &[u8]) -> Option<Self> {
+        if params.len() < PARAMS_SIZE {
+            return None;
+        }
+        let flags = u32_at(params, 8);
+        let features = u32_at(params, 20);
+        let mut sq_off = [0; 7];
+        for (i, off) in sq_off.iter_mut().enumerate() {
+            *off = u32_at(params, 40 + i * 4);
+        }
+        let mut cq_off = [0; 6];
+        for (i, off) in cq_off.iter_mut().enumerate() {
+            *off = u32_at(params, 80 + i * 4);
+        }
+        let mut ring = Ring {
+            flags,
+            sq_off,
+            cq_off,
+            single_mmap: features & FEAT_SINGLE_MMAP != 0,
+            sq_ring: None,
+            cq_ring: None,
+            sqes: None,
+            files: Vec::new(),
+            pending: HashMap::new(),
+        };
+        if flags & SETUP_NO_MMAP != 0 {
+            // The tracee supplied the memory: sq_off.user_addr holds the SQEs,
+            // cq_off.user_addr the (single) ring
+            let rings = u64_at(params, 112);
+            ring.sq_ring = Some(rings);
+            ring.cq_ring = Some(rings);
+            ring.sqes = Some(u64_at(params, 72));
+        }
+        Some(ring)
+    }
+
+    /// SQPOLL rings are drained by a kernel thread, not at io_uring_enter.
+    pub fn sqpoll(&self) -> bool {
+        self.flags & SETUP_SQPOLL != 0
+    }
+
+    /// Note where an mmap of the ring fd at `offset` landed.
+    pub fn map(&mut self, offset: u64, addr: u64) {
+        match offset {
+            OFF_SQ_RING => {
+                self.sq_ring = Some(addr);
+                if self.single_mmap {
+                    self.cq_ring = Some(addr);
+                }
+            }
+            OFF_CQ_RING => self.cq_ring = Some(addr),
+            OFF_SQES => self.sqes = Some(addr),
+            _ => {}
+        }
+    }
+
+    /// Entries waiting in the submission queue, at most `to_submit` of them.
+    /// None if the ring's memory isn't known or can't be read.
+    pub fn submissions(
+        &self,
+        to_submit: u32,
+        read: impl Fn(u64, usize) -> Option<Vec<u8>>,
+    ) -> Option<Vec<Sqe>> {
+        let (ring, sqes) = (self.sq_ring?, self.sqes?);
+        let word = |off: u32| read(ring + off as u64, 4).map(|b| u32_at(&b, 0));
+        let head = word(self.sq_off[0])?;
+        let tail = word(self.sq_off[1])?;
+        let mask = word(self.sq_off[2])?;
+        let entries = word(self.sq_off[3])?;
+        let count = tail.wrapping_sub(head).min(to_submit).min(entries);
+        let sqe_size = if self.flags & SETUP_SQE128 != 0 {
+            128
+        } else {
+            64
+        };
+
+        let mut submitted = Vec::with_capacity(count as usize);
+        for i in 0..count {
+            let pos = head.wrapping_add(i) & mask;
+            let index = if self.flags & SETUP_NO_SQARRAY != 0 {
+                pos
+            } else {
+                word(self.sq_off[6] + pos * 4)?
+            };
+            submitted.push(Sqe::parse(&read(sqes + index as u64 * sqe_size, 48)?)?);
+        }
+        Some(submitted)
+    }
+
+    /// Entries posted to the completion queue that the tracee hasn't consumed yet.
+    pub fn completions(&self, read: impl Fn(u64, usize) -> Option<Vec<u8>>) -> Vec<Cqe> {
+        let Some(ring) = self.cq_ring else {
+            return Vec::new();
+        };
+        let word = |off: u32| read(ring + off as u64, 4).map(|b| u32_at(&b, 0));
+        let (Some(head), Some(tail), Some(mask), Some(entries)) = (
+            word(self.cq_off[0]),
+            word(self.cq_off[1]),
+            word(self.cq_off[2]),
+            word(self.cq_off[3]),
+        ) else {
+            return Vec::new();
+        };
+        let cqe_size = if self.flags & SETUP_CQE32 != 0 {
+            32
+        } else {
+            16
+        };
+        let cqes = ring + self.cq_off[5] as u64;
+
+        (0..tail.wrapping_sub(head).min(entries))
+            .map_while(|i| {
+                let pos = head.wrapping_add(i) & mask;
+                let bytes = read(cqes + pos as u64 * cqe_size, 12)?;
+                Some(Cqe {
+                    user_data: u64_at(&bytes, 0),
+                    res: u32_at(&bytes, 8) as i32,
+                })
+            })
+            .collect()
+    }
+
+    /// Label of the registered file at `index`, if any.
+    pub fn file(&self, index: u32) -> Option<String> {
+        self.files.get(index as usize).cloned().flatten()
+    }
+
+    /// Put a file (or nothing) in a registered slot, growing the table as needed.
+    pub fn set_file(&mut self, index: u32, label: Option<String>) {
+        let index = index as usize;
+        if self.files.len() <= index {
+            self.files.resize(index + 1, None);
+        }
+        self.files[index] = label;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tracee memory: regions of bytes at their addresses.
+    struct Memory(Vec<(u64, Vec<u8>)>);
+
+    impl Memory {
+        fn read(&self, addr: u64, len: usize) -> Option<Vec<u8>> {
+            self.0.iter().find_map(|(base, bytes)| {
+                let start = addr.checked_sub(*base)? as usize;
+                bytes.get(start..start + len).map(<[u8]>::to_vec)
+            })
+        }
+    }
+
+    fn put(bytes: &mut [u8], off: usize, value: &[u8]) {
+        bytes[off..off + value.len()].copy_from_slice(value);
+    }
+
+    fn sqe_bytes(opcode: u8, flags: u8, fd: i32, user_data: u64) -> Vec<u8> {
+        let mut bytes = vec![0; 64];
+        bytes[0] = opcode;
+        bytes[1] = flags;
+        put(&mut bytes, 4, &fd.to_ne_bytes());
+        put(&mut bytes, 32, &user_data.to_ne_bytes());
+        bytes
+    }
+
+    const RING: u64 = 0x1000;
+    const SQES: u64 = 0x2000;
+
+    /// A single-mmap ring of 4 entries with the SQ head at 6 and tail at 8,
+    /// so the two pending entries wrap to positions 2 and 3, and the CQ
+    /// holding two completions at positions 1 and 2.
+    fn ring_and_memory() -> (Ring<()>, Memory) {
+        let mut params = vec![0; PARAMS_SIZE];
+        put(&mut params, 20, &FEAT_SINGLE_MMAP.to_ne_bytes());
+        // sq_off: head, tail, ring_mask, ring_entries, flags, dropped, array
+        for (i, off) in [0u32, 4, 8, 12, 16, 20, 64].iter().enumerate() {
+            put(&mut params, 40 + i * 4, &off.to_ne_bytes());
+        }
+        // cq_off: head, tail, ring_mask, ring_entries, overflow, cqes
+        for (i, off) in [32u32, 36, 40, 44, 48, 128].iter().enumerate() {
+            put(&mut params, 80 + i * 4, &off.to_ne_bytes());
+        }
+        let mut ring = Ring::from_params(&params).expect("params");
+        ring.map(OFF_SQ_RING, RING);
+        ring.map(OFF_SQES, SQES);
+
+        let mut rings = vec![0; 256];
+        for (off, value) in [
+            (0, 6u32),
+            (4, 8),
+            (8, 3),
+            (12, 4),
+            (32, 1),
+            (36, 3),
+            (40, 3),
+            (44, 4),
+        ] {
+            put(&mut rings, off, &value.to_ne_bytes());
+        }
+        // The SQ array sends position 2 to SQE 1 and position 3 to SQE 0
+        put(&mut rings, 64 + 2 * 4, &1u32.to_ne_bytes());
+        put(&mut rings, 64 + 3 * 4, &0u32.to_ne_bytes());
+        put(&mut rings, 128 + 16, &11u64.to_ne_bytes());
+        put(&mut rings, 128 + 16 + 8, &100i32.to_ne_bytes());
+        put(&mut rings, 128 + 32, &12u64.to_ne_bytes());
+        put(&mut rings, 128 + 32 + 8, &(-libc::EBADF).to_ne_bytes());
+
+        let mut sqes = sqe_bytes(OP_WRITE, IOSQE_FIXED_FILE, 2, 12);
+        sqes.extend(sqe_bytes(OP_READ, 0, 5, 11));
+        sqes.resize(4 * 64, 0);
+        (ring, Memory(vec![(RING, rings), (SQES, sqes)]))
+    }
+
+    #[test]
+    fn parses_an_sqe() {
+        let mut bytes = sqe_bytes(OP_OPENAT, 0, libc::AT_FDCWD, 7);
+        put(&mut bytes, 8, &0x40u64.to_ne_bytes());
+        put(&mut bytes, 16, &0x7000u64.to_ne_bytes());
+        put(&mut bytes, 24, &0o644u32.to_ne_bytes());
+        put(&mut bytes, 28, &(libc::O_CREAT as u32).to_ne_bytes());
+        put(&mut bytes, 44, &3u32.to_ne_bytes());
+
+        let sqe = Sqe::parse(&bytes).expect("sqe");
+        assert_eq!(sqe.opcode, OP_OPENAT);
+        assert_eq!(sqe.fd, libc::AT_FDCWD);
+        assert_eq!((sqe.off, sqe.addr, sqe.len), (0x40, 0x7000, 0o644));
+        assert_eq!(sqe.op_flags, libc::O_CREAT as u32);
+        assert_eq!((sqe.user_data, sqe.file_index), (7, 3));
+        assert!(Sqe::parse(&bytes[..47]).is_none());
+    }
+
+    #[test]
+    fn maps_opcodes_to_file_io() {
+        let sqe = |opcode, flags, fd| Sqe::parse(&sqe_bytes(opcode, flags, fd, 0)).expect("sqe");
+        assert_eq!(sqe(OP_READ, 0, 3).file_io(), [(FileIo::Read, 3, false)]);
+        assert_eq!(sqe(OP_RECV, 0, 3).file_io(), [(FileIo::Read, 3, false)]);
+        assert_eq!(
+            sqe(OP_WRITE_FIXED, IOSQE_FIXED_FILE, 1).file_io(),
+            [(FileIo::Write, 1, true)]
+        );
+        assert_eq!(sqe(OP_FSYNC, 0, 4).file_io(), [(FileIo::Sync, 4, false)]);
+        assert!(sqe(OP_OPENAT, 0, 4).file_io().is_empty());
+        assert!(sqe(OP_CLOSE, 0, 4).file_io().is_empty());
+
+        // splice reads splice_fd_in, a registered file here, and writes fd
+        let mut splice = sqe(OP_SPLICE, 0, 6);
+        splice.file_index = 2;
+        splice.op_flags = SPLICE_F_FD_IN_FIXED;
+        assert_eq!(
+            splice.file_io(),
+            [(FileIo::Read, 2, true), (FileIo::Write, 6, false)]
+        );
+    }
+
+    #[test]
+    fn reads_submissions_in_ring_order() {
+        let (ring, memory) = ring_and_memory();
+        let read = |addr, len| memory.read(addr, len);
+
+        let sqes = ring.submissions(8, read).expect("submissions");
+        let seen: Vec<_> = sqes.iter().map(|s| (s.opcode, s.fd, s.user_data)).collect();
+        assert_eq!(seen, [(OP_READ, 5, 11), (OP_WRITE, 2, 12)]);
+        assert_eq!(sqes[1].file_io(), [(FileIo::Write, 2, true)]);
+
+        // No more than io_uring_enter's to_submit
+        let sqes = ring.submissions(1, read).expect("submissions");
+        assert_eq!(sqes.len(), 1);
+    }
+
+    #[test]
+    fn reads_completions() {
+        let (ring, memory) = ring_and_memory();
+        let cqes = ring.completions(|addr, len| memory.read(addr, len));
+        let seen: Vec<_> = cqes.iter().map(|c| (c.user_data, c.res)).collect();
+        assert_eq!(seen, [(11, 100), (12, -libc::EBADF)]);
+    }
+
+    #[test]
+    fn gives_up_on_unmapped_or_unreadable_rings() {
+        let (mut ring, memory) = ring_and_memory();
+        assert!(ring.submissions(8, |_, _| None).is_none());
+        assert!(ring.completions(|_, _| None).is_empty());
+
+        ring.sqes = None;
+        assert!(ring
+            .submissions(8, |addr, len| memory.read(addr, len))
+            .is_none());
+    }
+}
@@ -0,0 +1,1486 @@
+//! Library API tests: trace real commands and inspect the returned report.
+
+use roar_tracer::{
+    export_otlp, write_provenance, Backend, Compression, EventLog, NetworkPolicy, OutputFormat,
+    PathFilter, TraceCategory, TraceError, TraceEvent, Tracer, TracerConfig, SCHEMA_VERSION,
+};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::Command;
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// The trace loop waits on any child, so traces in one process must not overlap
+static TRACE_LOCK: Mutex<()> = Mutex::new(());
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("roar-tracer-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).expect("create scratch dir");
+    dir
+}
+
+#[test]
+fn traces_true() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let report = Tracer::new(TracerConfig::default())
+        .run(Command::new("/bin/true"))
+        .expect("trace /bin/true");
+
+    assert_eq!(report.exit_code, 0);
+    assert!(!report.interrupted);
+    assert!(!report.timed_out);
+    assert_eq!(report.processes.len(), 1);
+    let root = &report.processes[0];
+    assert_eq!(root.exit_code, Some(0));
+    assert_eq!(root.execs.len(), 1);
+    assert_eq!(root.execs[0].argv, ["/bin/true"]);
+}
+
+#[test]
+fn traces_script_file_io() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("script");
+    let input = dir.join("input.txt");
+    let output = dir.join("output.txt");
+    std::fs::write(&input, "data\n").expect("write input");
+    let _ = std::fs::remove_file(&output);
+
+    let mut command = Command::new("/bin/sh");
+    command
+        .arg("-c")
+        .arg("cat \"$1\" | tee \"$2\" >/dev/null; exit 3")
+        .arg("sh")
+        .arg(&input)
+        .arg(&output);
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace script");
+
+    let input = input.to_string_lossy().to_string();
+    let output = output.to_string_lossy().to_string();
+    assert_eq!(report.exit_code, 3);
+    assert!(report.processes.len() >= 3, "sh should fork cat and tee");
+    assert!(report.read_files.contains(&input));
+    assert!(report.written_files.contains(&output));
+    assert!(report.files[&output].write_count > 0);
+
+    // Each side of the pipe gets only its own files
+    let process = |name: &str| {
+        report
+            .processes
+            .iter()
+            .find(|p| p.command.first().is_some_and(|c| c == name))
+            .unwrap_or_else(|| panic!("{} process recorded", name))
+    };
+    assert!(process("cat").read_files.contains(&input));
+    assert!(!process("cat").written_files.contains(&output));
+    assert!(process("tee").written_files.contains(&output));
+    assert!(!process("tee").read_files.contains(&input));
+    let (first, last) = (process("cat").first_access, process("cat").last_access);
+    assert!(first.is_some_and(|first| last.is_some_and(|last| first <= last)));
+    assert!(first <= Some(report.files[&input].first_access));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn records_deleted_files() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("delete");
+    let doomed = dir.join("doomed.txt");
+    std::fs::write(&doomed, "old output\n").expect("write file");
+
+    let mut command = Command::new("/bin/rm");
+    command.arg(&doomed);
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace rm");
+
+    assert_eq!(report.exit_code, 0);
+    assert!(report
+        .deleted_files
+        .contains(&doomed.to_string_lossy().to_string()));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn follows_shell_redirection() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("redirect");
+    let output = dir.join("redirected.txt");
+
+    // The shell opens the file and dup2()s it onto stdout before echo writes
+    let mut command = Command::new("/bin/sh");
+    command
+        .arg("-c")
+        .arg("echo data > \"$1\"")
+        .arg("sh")
+        .arg(&output);
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace redirection");
+
+    assert!(report
+        .written_files
+        .contains(&output.to_string_lossy().to_string()));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn separates_stat_probes_from_reads() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("stat");
+    let probed = dir.join("probed.txt");
+    std::fs::write(&probed, "data\n").expect("write file");
+
+    let mut command = Command::new("/bin/sh");
+    command
+        .arg("-c")
+        .arg("test -e \"$1\"")
+        .arg("sh")
+        .arg(&probed);
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace test -e");
+
+    let probed = probed.to_string_lossy().to_string();
+    assert!(report.stat_files.contains(&probed));
+    assert!(!report.read_files.contains(&probed));
+    assert!(report.stat_files.iter().all(|p| p.starts_with('/')));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn records_created_directories() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("mkdir");
+    let nested = dir.join("a").join("b");
+    let _ = std::fs::remove_dir_all(dir.join("a"));
+
+    // mkdir -p creates each level with mkdirat relative to its parent's fd
+    let mut command = Command::new("/bin/mkdir");
+    command.arg("-p").arg(&nested);
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace mkdir -p");
+
+    for created in [dir.join("a"), nested] {
+        let created = created.to_string_lossy().to_string();
+        assert!(report.created_dirs.contains(&created), "{}", created);
+    }
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn records_links_and_readlink_targets() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("links");
+    let target = dir.join("target.txt");
+    let symlink = dir.join("sym");
+    let hardlink = dir.join("hard");
+    std::fs::write(&target, "data\n").expect("write target");
+    let _ = std::fs::remove_file(&symlink);
+    let _ = std::fs::remove_file(&hardlink);
+
+    let mut command = Command::new("/bin/sh");
+    command
+        .arg("-c")
+        .arg("cd \"$1\" && ln -s target.txt sym && ln target.txt hard && readlink sym")
+        .arg("sh")
+        .arg(&dir);
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace ln");
+
+    let path = |p: &PathBuf| p.to_string_lossy().to_string();
+    assert_eq!(report.links.get(&path(&symlink)), Some(&path(&target)));
+    assert_eq!(report.links.get(&path(&hardlink)), Some(&path(&target)));
+    assert_eq!(
+        report.readlinks.get(&path(&symlink)).map(String::as_str),
+        Some("target.txt")
+    );
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn records_metadata_changes_and_truncation() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("metadata");
+    let script = dir.join("script.sh");
+    let log = dir.join("log.txt");
+    std::fs::write(&script, "true\n").expect("write script");
+    std::fs::write(&log, "old entries\n").expect("write log");
+
+    let mut command = Command::new("/bin/sh");
+    command
+        .arg("-c")
+        .arg("chmod 755 \"$1\" && touch -m \"$1\" && truncate -s 0 \"$2\"")
+        .arg("sh")
+        .arg(&script)
+        .arg(&log);
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace chmod");
+
+    let script = script.to_string_lossy().to_string();
+    let log = log.to_string_lossy().to_string();
+    assert_eq!(report.exit_code, 0);
+    assert!(report.metadata_changed_files.contains(&script));
+    assert!(!report.written_files.contains(&script));
+    assert!(report.written_files.contains(&log));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn records_listed_directories() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("listing");
+    std::fs::write(dir.join("a.c"), "").expect("write file");
+
+    let mut command = Command::new("/bin/ls");
+    command.arg(&dir);
+    command.stdout(std::process::Stdio::null());
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace ls");
+
+    assert!(report
+        .listed_dirs
+        .contains(&dir.to_string_lossy().to_string()));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn records_open_flags_and_creation() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("flags");
+    let fresh = dir.join("fresh.txt");
+    let appended = dir.join("appended.txt");
+    let _ = std::fs::remove_file(&fresh);
+    std::fs::write(&appended, "first\n").expect("write file");
+
+    let mut command = Command::new("/bin/sh");
+    command
+        .arg("-c")
+        .arg("echo a > \"$1\"; echo b >> \"$2\"")
+        .arg("sh")
+        .arg(&fresh)
+        .arg(&appended);
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace redirections");
+
+    let fresh = &report.files[&fresh.to_string_lossy().to_string()];
+    assert!(fresh.created);
+    assert!(fresh.mode.is_some());
+    assert!(fresh.open_flags.contains("trunc"));
+    let appended = &report.files[&appended.to_string_lossy().to_string()];
+    assert!(!appended.created);
+    assert!(appended.open_flags.contains("append"));
+    assert!(appended.open_flags.contains("wronly"));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn resolves_relative_paths_after_chdir() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("chdir");
+    std::fs::create_dir_all(dir.join("sub")).expect("create subdir");
+    std::fs::write(dir.join("sub").join("input.txt"), "data\n").expect("write file");
+
+    // The shell chdirs and then forks cat, which opens a relative path
+    let mut command = Command::new("/bin/sh");
+    command
+        .arg("-c")
+        .arg("cd \"$1\" && cd sub && cat input.txt >/dev/null")
+        .arg("sh")
+        .arg(&dir);
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace cd");
+
+    let input = dir.join("sub").join("input.txt");
+    assert!(report
+        .read_files
+        .contains(&input.to_string_lossy().to_string()));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn resolves_openat_against_dirfd() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("dirfd");
+    std::fs::create_dir_all(dir.join("tree")).expect("create subdir");
+    std::fs::write(dir.join("tree").join("data.bin"), "needle\n").expect("write file");
+
+    // grep -r walks the tree with fts, opening each entry relative to its directory's fd
+    let mut command = Command::new("/bin/grep");
+    command.arg("-rq").arg("needle").arg(dir.join("tree"));
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace grep -r");
+
+    let data = dir.join("tree").join("data.bin");
+    assert!(report
+        .read_files
+        .contains(&data.to_string_lossy().to_string()));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn records_synced_files() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("sync");
+    let data = dir.join("data.db");
+    std::fs::write(&data, "committed\n").expect("write file");
+
+    // coreutils sync fsyncs each file it is given
+    let mut command = Command::new("/bin/sync");
+    command.arg(&data);
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace sync");
+
+    let data = data.to_string_lossy().to_string();
+    assert!(report.synced_files.contains(&data));
+    assert!(!report.written_files.contains(&data));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn separates_shared_memory_from_files() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let object = format!("/dev/shm/roar-tracer-{}", std::process::id());
+
+    // shm_open is an open under /dev/shm; a shell can stand in for it
+    let mut command = Command::new("/bin/sh");
+    command
+        .arg("-c")
+        .arg("echo data > \"$1\" && cat \"$1\" >/dev/null && rm \"$1\"")
+        .arg("sh")
+        .arg(&object);
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace shm use");
+
+    let shm = &report.shared_memory[&object];
+    assert!(shm.created);
+    assert!(shm.unlinked);
+    assert_eq!(shm.writers.len(), 1);
+    assert_eq!(shm.readers.len(), 1);
+    assert!(!report.written_files.contains(&object));
+    assert!(!report.read_files.contains(&object));
+    assert!(!report.deleted_files.contains(&object));
+}
+
+#[test]
+fn seccomp_filter_keeps_traced_io() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("seccomp");
+    let input = dir.join("input.txt");
+    let output = dir.join("output.txt");
+    std::fs::write(&input, "data\n").expect("write input");
+
+    let config = TracerConfig {
+        seccomp: true,
+        ..TracerConfig::default()
+    };
+    let mut command = Command::new("/bin/sh");
+    command
+        .arg("-c")
+        .arg("cat \"$1\" >/dev/null; echo done > \"$2\"; exit 3")
+        .arg("sh")
+        .arg(&input)
+        .arg(&output);
+    let report = Tracer::new(config).run(command).expect("trace script");
+
+    let input = input.to_string_lossy().to_string();
+    let output = output.to_string_lossy().to_string();
+    assert_eq!(report.exit_code, 3);
+    assert!(report.processes.len() >= 2, "sh should fork cat");
+    assert!(report.read_files.contains(&input));
+    assert!(report.written_files.contains(&output));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn deny_network_makes_connect_fail() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let config = TracerConfig {
+        deny_network: Some(NetworkPolicy::new(false, &[]).expect("no patterns")),
+        ..TracerConfig::default()
+    };
+    // bash connects for /dev/tcp redirections; the address is a documentation range
+    let mut command = Command::new("/bin/bash");
+    command.arg("-c").arg("exec 3<>/dev/tcp/192.0.2.1/9");
+    let report = Tracer::new(config).run(command).expect("trace bash");
+
+    assert_ne!(report.exit_code, 0);
+    let connection = report
+        .network_connections
+        .iter()
+        .find(|c| c.operation == "connect")
+        .expect("connect recorded");
+    assert_eq!(connection.address, "tcp://192.0.2.1:9");
+    assert!(connection.denied);
+    assert!(!connection.success);
+}
+
+/// A static i386 executable of `code`, loaded with its headers at 0x08048000
+/// so that the code starts at 0x08048054.
+fn i386_executable(code: &[u8]) -> Vec<u8> {
+    let base: u32 = 0x0804_8000;
+    let size = (52 + 32 + code.len()) as u32;
+    let mut elf = Vec::new();
+    // ELF header: 32-bit, little-endian, ET_EXEC for EM_386, one program header
+    elf.extend_from_slice(b"\x7fELF\x01\x01\x01\0\0\0\0\0\0\0\0\0");
+    for half in [2u16, 3] {
+        elf.extend_from_slice(&half.to_le_bytes());
+    }
+    for word in [1, base + 84, 52, 0, 0] {
+        elf.extend_from_slice(&word.to_le_bytes());
+    }
+    for half in [52u16, 32, 1, 40, 0, 0] {
+        elf.extend_from_slice(&half.to_le_bytes());
+    }
+    // PT_LOAD of the whole file, readable, writable and executable
+    for word in [1, 0, base, base, size, size, 7, 0x1000] {
+        elf.extend_from_slice(&word.to_le_bytes());
+    }
+    elf.extend_from_slice(code);
+    elf
+}
+
+#[test]
+fn deny_network_makes_i386_sendmsg_fail() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    #[rustfmt::skip]
+    let code = i386_executable(&[
+        0xb8, 0x67, 0x01, 0x00, 0x00,       // mov eax, 359 (socket)
+        0xbb, 0x02, 0x00, 0x00, 0x00,       // mov ebx, AF_INET
+        0xb9, 0x02, 0x00, 0x00, 0x00,       // mov ecx, SOCK_DGRAM
+        0x31, 0xd2,                         // xor edx, edx
+        0xcd, 0x80,                         // int 0x80
+        0x89, 0xc6,                         // mov esi, eax
+        0xb8, 0x72, 0x01, 0x00, 0x00,       // mov eax, 370 (sendmsg)
+        0x89, 0xf3,                         // mov ebx, esi
+        0xb9, 0xb2, 0x80, 0x04, 0x08,       // mov ecx, msg
+        0xcd, 0x80,                         // int 0x80
+        0x89, 0xc7,                         // mov edi, eax
+        0x89, 0x35, 0xce, 0x80, 0x04, 0x08, // mov [args], esi
+        0xb8, 0x66, 0x00, 0x00, 0x00,       // mov eax, 102 (socketcall)
+        0xbb, 0x10, 0x00, 0x00, 0x00,       // mov ebx, 16 (SYS_SENDMSG)
+        0xb9, 0xce, 0x80, 0x04, 0x08,       // mov ecx, args
+        0xcd, 0x80,                         // int 0x80
+        0xf7, 0xd8,                         // neg eax
+        0xc1, 0xe0, 0x04,                   // shl eax, 4
+        0xf7, 0xdf,                         // neg edi
+        0x09, 0xf8,                         // or eax, edi
+        0x89, 0xc3,                         // mov ebx, eax
+        0xb8, 0x01, 0x00, 0x00, 0x00,       // mov eax, 1 (exit)
+        0xcd, 0x80,                         // int 0x80
+        // addr: sockaddr_in for 192.0.2.1:9
+        0x02, 0x00, 0x00, 0x09, 0xc0, 0x00, 0x02, 0x01, 0, 0, 0, 0, 0, 0, 0, 0,
+        // msg: msghdr with msg_name = addr, msg_namelen = 16, no data
+        0xa2, 0x80, 0x04, 0x08, 0x10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0,
+        // args: socketcall's fd, msg and flags
+        0, 0, 0, 0, 0xb2, 0x80, 0x04, 0x08, 0, 0, 0, 0,
+    ]);
+    let dir = scratch_dir("i386");
+    let program = dir.join("sendmsg");
+    std::fs::write(&program, code).expect("write program");
+    let mode = std::os::unix::fs::PermissionsExt::from_mode(0o755);
+    std::fs::set_permissions(&program, mode).expect("make program executable");
+    if let Err(e) = Command::new(&program).status() {
+        eprintln!(
+            "skipping deny_network_makes_i386_sendmsg_fail: no i386 support: {}",
+            e
+        );
+        return;
+    }
+
+    let config = TracerConfig {
+        deny_network: Some(NetworkPolicy::new(false, &[]).expect("no patterns")),
+        ..TracerConfig::default()
+    };
+    let report = Tracer::new(config)
+        .run(Command::new(&program))
+        .expect("trace program");
+
+    // Both sends got EPERM
+    assert_eq!(report.exit_code, libc::EPERM << 4 | libc::EPERM);
+    let denied: Vec<_> = report
+        .network_connections
+        .iter()
+        .filter(|c| c.denied)
+        .map(|c| (c.operation, c.address.as_str()))
+        .collect();
+    assert_eq!(
+        denied,
+        [
+            ("sendmsg", "udp://192.0.2.1:9"),
+            ("sendmsg", "udp://192.0.2.1:9")
+        ]
+    );
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn fanotify_backend_records_file_io() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("fanotify");
+    let input = dir.join("input.txt");
+    let output = dir.join("output.txt");
+    std::fs::write(&input, "data\n").expect("write input");
+
+    let config = TracerConfig {
+        backend: Backend::Fanotify,
+        ..TracerConfig::default()
+    };
+    let mut command = Command::new("/bin/sh");
+    command
+        .arg("-c")
+        .arg("cat \"$1\" > \"$2\"; exit 3")
+        .arg("sh")
+        .arg(&input)
+        .arg(&output);
+    let report = match Tracer::new(config).run(command) {
+        Ok(report) => report,
+        // Marking mounts needs CAP_SYS_ADMIN
+        Err(TraceError::Fanotify(_)) => return,
+        Err(e) => panic!("trace script: {}", e),
+    };
+
+    let input = input.to_string_lossy().to_string();
+    let output = output.to_string_lossy().to_string();
+    assert_eq!(report.exit_code, 3);
+    assert!(report.read_files.contains(&input));
+    assert!(report.written_files.contains(&output));
+    assert!(report.opened_files.contains(&input));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn fanotify_backend_refuses_ptrace_only_options() {
+    let config = TracerConfig {
+        backend: Backend::Fanotify,
+        deny_network: Some(NetworkPolicy::new(false, &[]).expect("no patterns")),
+        ..TracerConfig::default()
+    };
+    let result = Tracer::new(config).run(Command::new("/bin/true"));
+    assert!(matches!(
+        result,
+        Err(TraceError::Unsupported("deny_network"))
+    ));
+}
+
+#[cfg(feature = "ebpf")]
+#[test]
+#[ignore = "needs root, cgroup v2 and tracefs mounted; run with --ignored"]
+fn ebpf_backend_records_processes_and_files() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("ebpf");
+    let input = dir.join("input.txt");
+    let output = dir.join("output.txt");
+    std::fs::write(&input, "data\n").expect("write input");
+
+    let config = TracerConfig {
+        backend: Backend::Ebpf,
+        ..TracerConfig::default()
+    };
+    let mut command = Command::new("/bin/sh");
+    command
+        .arg("-c")
+        .arg("cat \"$1\" | tee \"$2\" >/dev/null; exit 3")
+        .arg("sh")
+        .arg(&input)
+        .arg(&output);
+    let report = Tracer::new(config).run(command).expect("trace script");
+
+    let input = input.to_string_lossy().to_string();
+    let output = output.to_string_lossy().to_string();
+    assert_eq!(report.exit_code, 3);
+    assert!(report.read_files.contains(&input));
+    assert!(report.written_files.contains(&output));
+    let cat = report
+        .processes
+        .iter()
+        .find(|p| p.command.first().map(String::as_str) == Some("cat"))
+        .expect("cat recorded");
+    assert_eq!(cat.command, ["cat", input.as_str()]);
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn filter_applies_to_report() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let config = TracerConfig {
+        filter: PathFilter::new(&[], &["/etc/**".to_string()]).expect("valid pattern"),
+        ..TracerConfig::default()
+    };
+    let mut command = Command::new("/bin/cat");
+    command.arg("/etc/hostname");
+    let report = Tracer::new(config).run(command).expect("trace cat");
+
+    assert!(report.opened_files.iter().all(|p| !p.starts_with("/etc/")));
+    assert_eq!(report.path_filters.exclude, ["/etc/**"]);
+}
+
+#[test]
+fn include_overrides_exclude() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let config = TracerConfig {
+        filter: PathFilter::new(&["/etc/hostname".to_string()], &["**".to_string()])
+            .expect("valid patterns"),
+        ..TracerConfig::default()
+    };
+    let mut command = Command::new("/bin/cat");
+    command.arg("/etc/hostname");
+    let report = Tracer::new(config).run(command).expect("trace cat");
+
+    assert_eq!(report.opened_files, ["/etc/hostname"]);
+}
+
+#[test]
+fn traces_only_the_chosen_categories() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let trace = |category: TraceCategory, seccomp: bool| {
+        let config = TracerConfig {
+            trace: vec![category],
+            seccomp,
+            ..TracerConfig::default()
+        };
+        let file_events = Rc::new(RefCell::new(Vec::new()));
+        let seen = Rc::clone(&file_events);
+        let mut command = Command::new("/bin/sh");
+        command
+            .arg("-c")
+            .arg("test -e /etc/hostname && cat /etc/hostname | cat >/dev/null");
+        let report = Tracer::new(config)
+            .on_event(move |record| match record.event {
+                TraceEvent::Open { path, .. }
+                | TraceEvent::Read { path }
+                | TraceEvent::Write { path } => seen.borrow_mut().push(path.to_string()),
+                _ => {}
+            })
+            .run(command)
+            .expect("trace sh");
+        let file_events = file_events.take();
+        (report, file_events)
+    };
+
+    for seccomp in [false, true] {
+        let (report, file_events) = trace(TraceCategory::Metadata, seccomp);
+        assert_eq!(report.traced_categories, [TraceCategory::Metadata]);
+        assert!(report.stat_files.contains(&"/etc/hostname".to_string()));
+        assert!(report.opened_files.is_empty(), "{:?}", report.opened_files);
+        assert!(report.read_files.is_empty(), "{:?}", report.read_files);
+        assert!(report.processes.iter().all(|p| p.execs.is_empty()));
+        assert!(file_events.is_empty(), "{:?}", file_events);
+    }
+
+    // Reads and writes are decoded for sockets, but file and pipe I/O is left out
+    let (report, file_events) = trace(TraceCategory::Network, false);
+    assert!(report.read_files.is_empty(), "{:?}", report.read_files);
+    assert!(report.pipe_reads.is_empty(), "{:?}", report.pipe_reads);
+    assert!(report.pipe_writes.is_empty(), "{:?}", report.pipe_writes);
+    assert!(file_events.is_empty(), "{:?}", file_events);
+}
+
+#[test]
+fn follows_only_the_processes_asked_for() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let script = "cat /etc/hostname >/dev/null; head -c1 /etc/passwd >/dev/null; true";
+    let trace = |config: TracerConfig| {
+        let mut command = Command::new("/bin/sh");
+        command.arg("-c").arg(script);
+        Tracer::new(config).run(command).expect("trace sh")
+    };
+
+    let report = trace(TracerConfig {
+        no_follow: vec!["cat".to_string()],
+        ..TracerConfig::default()
+    });
+    let cat = report
+        .processes
+        .iter()
+        .find(|p| p.detached)
+        .expect("cat is detached");
+    assert!(cat.execs[0].argv[0].ends_with("cat"));
+    assert!(!report.read_files.contains(&"/etc/hostname".to_string()));
+    assert!(report.read_files.contains(&"/etc/passwd".to_string()));
+
+    let report = trace(TracerConfig {
+        follow_forks: false,
+        ..TracerConfig::default()
+    });
+    assert_eq!(report.processes.len(), 1);
+    assert!(!report.read_files.contains(&"/etc/passwd".to_string()));
+}
+
+#[test]
+fn times_out_the_whole_tree() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let config = TracerConfig {
+        timeout: Some(Duration::from_millis(300)),
+        ..TracerConfig::default()
+    };
+    let mut command = Command::new("/bin/sh");
+    command.arg("-c").arg("sleep 30 & sleep 30; true");
+    let report = Tracer::new(config).run(command).expect("trace sh");
+
+    assert!(report.timed_out);
+    assert_eq!(report.exit_code, 124);
+    // Everything got the SIGTERM; none had to wait for the SIGKILL
+    assert_eq!(report.processes.len(), 3);
+    for process in &report.processes {
+        assert_eq!(
+            process.term_signal,
+            Some(libc::SIGTERM),
+            "{:?}",
+            process.command
+        );
+    }
+}
+
+#[test]
+fn spills_paths_past_the_limit() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("spill");
+    let spill_path = dir.join("spill.jsonl");
+    let spill_path = spill_path.to_str().expect("utf-8 path");
+    let config = TracerConfig {
+        max_paths: Some(2),
+        spill: Some(EventLog::open(spill_path, Compression::None).expect("open spill log")),
+        ..TracerConfig::default()
+    };
+    let mut command = Command::new("/bin/cat");
+    command.arg("/etc/hostname");
+    let report = Tracer::new(config).run(command).expect("trace cat");
+
+    let held = report.files.len() + report.stat_files.len() + report.missing_files.len();
+    assert!(held <= 2, "{} paths held", held);
+    assert!(!report.read_files.contains(&"/etc/hostname".to_string()));
+    let limit = report.path_limit.expect("path_limit");
+    assert_eq!(limit.max_paths, 2);
+    let spilled: Vec<Value> = std::fs::read_to_string(spill_path)
+        .expect("read spill log")
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("JSON line"))
+        .collect();
+    assert_eq!(spilled.len() as u64, limit.spilled_events);
+    assert!(spilled
+        .iter()
+        .any(|e| e["event"] == "read" && e["path"] == "/etc/hostname"));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn records_only_under_the_root() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("root");
+    let project = dir.join("project");
+    std::fs::create_dir_all(&project).expect("create project");
+    std::fs::write(project.join("input"), "x").expect("write input");
+    let link = dir.join("link");
+    let _ = std::fs::remove_file(&link);
+    std::os::unix::fs::symlink(&project, &link).expect("symlink into project");
+    let input = project.join("input").to_string_lossy().to_string();
+    let linked = link.join("input").to_string_lossy().to_string();
+
+    let config = TracerConfig {
+        filter: PathFilter::default().within(&project.to_string_lossy()),
+        ..TracerConfig::default()
+    };
+    let mut command = Command::new("/bin/cat");
+    command.arg(&input).arg("/etc/hostname").arg(&linked);
+    let report = Tracer::new(config).run(command).expect("trace cat");
+
+    assert_eq!(report.read_files, [input.as_str()]);
+    assert_eq!(report.path_aliases.get(&linked), Some(&input));
+    assert!(report.stat_files.iter().all(|p| p.starts_with(&input)));
+    assert_eq!(
+        report.path_filters.root.as_deref(),
+        Some(&*project.to_string_lossy())
+    );
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn records_resource_usage_at_exit() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut command = Command::new("/bin/sh");
+    // Busy long enough to be sure of a clock tick
+    command
+        .arg("-c")
+        .arg("i=0; while [ $i -lt 200000 ]; do i=$((i+1)); done");
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace sh");
+
+    let rusage = report.processes[0].rusage.as_ref().expect("rusage");
+    assert!(rusage.user_time + rusage.system_time > 0.0);
+    assert!(rusage.max_rss_kb > 0);
+}
+
+#[test]
+fn marks_threads_with_their_process() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut command = Command::new("/bin/sh");
+    // GNU sort only starts threads for inputs of this size or more
+    command
+        .arg("-c")
+        .arg("seq 1000000 | sort --parallel=2 -S 100M -n -o /dev/null");
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace sort");
+
+    let sort = report
+        .processes
+        .iter()
+        .find(|p| p.command.first().map(String::as_str) == Some("sort") && p.thread_of.is_none())
+        .expect("sort process");
+    let threads: Vec<_> = report
+        .processes
+        .iter()
+        .filter(|p| p.thread_of.is_some())
+        .collect();
+    assert!(!threads.is_empty());
+    for thread in threads {
+        assert_eq!(thread.thread_of, Some(sort.pid));
+        assert_eq!(thread.command, sort.command);
+        assert!(thread.env.is_empty() && thread.env_unset.is_empty());
+    }
+}
+
+#[test]
+fn records_each_process_working_directory() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("cwd");
+    std::fs::create_dir_all(&dir).expect("create dir");
+    let dir = std::fs::canonicalize(&dir).expect("canonicalize dir");
+    let mut command = Command::new("/bin/sh");
+    command
+        .arg("-c")
+        .arg(format!("/bin/true; cd {}; /bin/true", dir.display()));
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace sh");
+
+    let start = std::env::current_dir().expect("current dir");
+    let cwds: Vec<Option<&str>> = report
+        .processes
+        .iter()
+        .filter(|p| p.command.first().map(String::as_str) == Some("/bin/true"))
+        .map(|p| p.cwd.as_deref())
+        .collect();
+    assert_eq!(cwds.len(), 2);
+    assert!(cwds.contains(&Some(&*start.to_string_lossy())));
+    assert!(cwds.contains(&Some(&*dir.to_string_lossy())));
+    let root = report.processes.iter().find(|p| p.parent_pid.is_none());
+    assert_eq!(
+        root.and_then(|p| p.cwd.as_deref()),
+        Some(&*dir.to_string_lossy())
+    );
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn delivers_sigtrap_to_the_tracee() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut command = Command::new("/bin/sh");
+    command.arg("-c").arg("kill -TRAP $$");
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace sh");
+
+    assert_eq!(report.processes[0].term_signal, Some(libc::SIGTRAP));
+    assert_eq!(report.exit_code, 128 + libc::SIGTRAP);
+}
+
+#[test]
+fn ignores_pseudo_filesystems() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let config = TracerConfig {
+        filter: PathFilter::default().ignore_pseudo_filesystems(),
+        ..TracerConfig::default()
+    };
+    let object = format!("/dev/shm/roar-tracer-ignores-{}", std::process::id());
+    let mut command = Command::new("/bin/sh");
+    command.arg("-c").arg(format!(
+        "cat /proc/self/status /etc/hostname >/dev/null; echo x > {0}; rm {0}",
+        object
+    ));
+    let report = Tracer::new(config).run(command).expect("trace sh");
+
+    assert!(report
+        .path_filters
+        .ignored_mounts
+        .iter()
+        .any(|m| m == "/proc"));
+    assert!(report.opened_files.iter().any(|p| p == "/etc/hostname"));
+    assert!(!report
+        .files
+        .keys()
+        .any(|p| p.starts_with("/proc/") || p == "/dev/null"));
+    // /dev/shm is a tmpfs of its own, not part of /dev's devtmpfs
+    assert!(report.shared_memory.contains_key(&object));
+}
+
+#[test]
+fn redacts_secrets_to_a_hash() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut command = Command::new("/bin/true");
+    command
+        .env("ROAR_TEST_TOKEN", "abc")
+        .env("ROAR_TEST_PLAIN", "abc");
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace true");
+
+    // The first 16 hex digits of sha256("abc")
+    assert_eq!(
+        report.env_accessed["ROAR_TEST_TOKEN"],
+        "<redacted sha256:ba7816bf8f01cfea>"
+    );
+    assert_eq!(report.env_accessed["ROAR_TEST_PLAIN"], "abc");
+    assert!(report.env_redacted.iter().any(|n| n == "ROAR_TEST_TOKEN"));
+}
+
+#[test]
+fn on_event_sees_exec_and_exit() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let hook_seen = Rc::clone(&seen);
+    let report = Tracer::new(TracerConfig::default())
+        .on_event(move |record| {
+            let kind = match record.event {
+                TraceEvent::Exec { .. } => "exec",
+                TraceEvent::Exit { .. } => "exit",
+                _ => return,
+            };
+            hook_seen.borrow_mut().push((record.pid, kind));
+        })
+        .run(Command::new("/bin/true"))
+        .expect("trace /bin/true");
+
+    let root = report.processes[0].pid;
+    assert_eq!(*seen.borrow(), [(root, "exec"), (root, "exit")]);
+}
+
+#[test]
+fn exports_processes_as_otlp_spans() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut command = Command::new("/bin/sh");
+    command.arg("-c").arg("cat /etc/hostname >/dev/null; true");
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace sh");
+
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind collector");
+    let endpoint = format!(
+        "http://{}",
+        listener.local_addr().expect("collector address")
+    );
+    let collector = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().expect("accept export");
+        let mut reader = BufReader::new(stream);
+        let (mut request_line, mut length) = (String::new(), 0);
+        reader.read_line(&mut request_line).expect("read request");
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).expect("read header");
+            if header == "\r\n" {
+                break;
+            }
+            if let Some(value) = header.to_lowercase().strip_prefix("content-length:") {
+                length = value.trim().parse().expect("content length");
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).expect("read body");
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .expect("reply");
+        (
+            request_line,
+            serde_json::from_slice::<serde_json::Value>(&body).expect("JSON body"),
+        )
+    });
+    export_otlp(&report, &endpoint).expect("export spans");
+    let (request_line, body) = collector.join().expect("collector thread");
+
+    assert!(request_line.starts_with("POST /v1/traces "));
+    let spans = body["resourceSpans"][0]["scopeSpans"][0]["spans"]
+        .as_array()
+        .expect("spans");
+    assert_eq!(spans.len(), report.processes.len());
+    let span = |name: &str| {
+        spans
+            .iter()
+            .find(|s| s["name"] == name)
+            .unwrap_or_else(|| panic!("{} span", name))
+    };
+    let (root, cat) = (span("/bin/sh"), span("cat"));
+    assert_eq!(root["parentSpanId"], "");
+    assert_eq!(root["traceId"].as_str().map(str::len), Some(32));
+    assert_eq!(cat["traceId"], root["traceId"]);
+    assert_eq!(cat["parentSpanId"], root["spanId"]);
+    assert!(cat["events"]
+        .as_array()
+        .expect("cat events")
+        .iter()
+        .any(|e| e["name"] == "read"
+            && e["attributes"][0]["value"]["stringValue"] == "/etc/hostname"));
+}
+
+#[test]
+fn checkpoints_report_the_trace_so_far() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let checkpoints = Rc::new(RefCell::new(Vec::new()));
+    let seen = Rc::clone(&checkpoints);
+    let mut command = Command::new("/bin/sh");
+    command
+        .arg("-c")
+        .arg("cat /etc/hostname >/dev/null; sleep 0.2; cat /etc/passwd >/dev/null");
+    let report = Tracer::new(TracerConfig::default())
+        .on_checkpoint(Duration::from_millis(50), move |report| {
+            seen.borrow_mut().push(report.read_files.clone());
+        })
+        .run(command)
+        .expect("trace sh");
+
+    assert!(!report.partial);
+    let checkpoints = checkpoints.borrow();
+    // Taken after the sleep, before the second cat has read anything
+    assert!(checkpoints.iter().any(|read| {
+        read.iter().any(|p| p == "/etc/hostname") && !read.iter().any(|p| p == "/etc/passwd")
+    }));
+}
+
+#[test]
+fn writes_report_to_a_stream() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let report = Tracer::new(TracerConfig::default())
+        .run(Command::new("/bin/true"))
+        .expect("trace /bin/true");
+
+    let written = |compression: Compression| -> Value {
+        let mut buf = Vec::new();
+        report
+            .write_to(&mut buf, OutputFormat::JsonCompact, compression)
+            .expect("write to buffer");
+        let json = match compression {
+            Compression::None => buf,
+            Compression::Gzip => {
+                let mut json = Vec::new();
+                flate2::read::GzDecoder::new(&buf[..])
+                    .read_to_end(&mut json)
+                    .expect("gunzip");
+                json
+            }
+            Compression::Zstd => zstd::decode_all(&buf[..]).expect("unzstd"),
+        };
+        serde_json::from_slice(&json).expect("JSON output")
+    };
+    for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+        let written = written(compression);
+        assert_eq!(written["processes"][0]["pid"], report.processes[0].pid);
+    }
+}
+
+#[test]
+fn writes_one_csv_row_per_file() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("csv");
+    let output = dir.join("out,put.txt");
+    let mut command = Command::new("/bin/sh");
+    command
+        .arg("-c")
+        .arg(format!("printf hello > '{}'", output.display()));
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace sh");
+
+    let mut csv = Vec::new();
+    report
+        .write_to(&mut csv, OutputFormat::Csv, Compression::None)
+        .expect("write csv");
+    let csv = String::from_utf8(csv).expect("utf-8 csv");
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("path,read,written,first_pid,bytes"));
+    let pid = report.processes[0].pid;
+    let row = format!("\"{}\",false,true,{},5", output.display(), pid);
+    assert!(lines.any(|line| line == row), "{} not in\n{}", row, csv);
+}
+
+#[test]
+fn writes_an_sqlite_database() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("sqlite");
+    let output = dir.join("output");
+    let database = dir.join("trace.db");
+    let _ = std::fs::remove_file(&database);
+    let mut command = Command::new("/bin/sh");
+    command.arg("-c").arg(format!(
+        "cat /etc/hostname > /dev/null; printf hello > '{}'",
+        output.display()
+    ));
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace sh");
+    report
+        .write(
+            &database.to_string_lossy(),
+            OutputFormat::Sqlite,
+            Compression::None,
+        )
+        .expect("write sqlite");
+
+    let db = rusqlite::Connection::open(&database).expect("open database");
+    let mut processes: Vec<(i32, Option<i32>, String)> = db
+        .prepare("SELECT pid, parent_pid, command FROM processes ORDER BY pid")
+        .expect("select processes")
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .expect("query processes")
+        .collect::<Result<_, _>>()
+        .expect("read processes");
+    let mut expected: Vec<(i32, Option<i32>, String)> = report
+        .processes
+        .iter()
+        .map(|p| {
+            let command = serde_json::to_string(&p.command).expect("command json");
+            (p.pid, p.parent_pid, command)
+        })
+        .collect();
+    processes.sort();
+    expected.sort();
+    assert_eq!(processes, expected);
+
+    let files: BTreeMap<String, (bool, bool, i64)> = db
+        .prepare("SELECT path, read, written, bytes_written FROM files")
+        .expect("select files")
+        .query_map([], |row| {
+            Ok((row.get(0)?, (row.get(1)?, row.get(2)?, row.get(3)?)))
+        })
+        .expect("query files")
+        .collect::<Result<_, _>>()
+        .expect("read files");
+    let expected: BTreeMap<String, (bool, bool, i64)> = report
+        .files
+        .iter()
+        .map(|(path, f)| (path.clone(), (f.read, f.written, f.bytes_written as i64)))
+        .collect();
+    assert_eq!(files, expected);
+    let output = output.to_string_lossy().to_string();
+    assert_eq!(files.get(&output), Some(&(false, true, 5)));
+
+    let accesses: BTreeSet<(i32, String, String)> = db
+        .prepare("SELECT pid, path, mode FROM accesses")
+        .expect("select accesses")
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .expect("query accesses")
+        .collect::<Result<_, _>>()
+        .expect("read accesses");
+    let mut expected = BTreeSet::new();
+    for p in &report.processes {
+        for (mode, paths) in [
+            ("opened", &p.opened_files),
+            ("read", &p.read_files),
+            ("written", &p.written_files),
+        ] {
+            expected.extend(
+                paths
+                    .iter()
+                    .map(|path| (p.pid, path.clone(), mode.to_string())),
+            );
+        }
+    }
+    assert_eq!(accesses, expected);
+    let shell = report
+        .processes
+        .iter()
+        .find(|p| p.parent_pid.is_none())
+        .expect("root process");
+    assert!(accesses.contains(&(shell.pid, output, "written".to_string())));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+/// Field lists per serialized type, from tests/schema/v<N>.json.
+type Schema = BTreeMap<String, BTreeSet<String>>;
+
+#[test]
+fn reports_syscalls_as_strace_lines() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let lines = Rc::new(RefCell::new(Vec::new()));
+    let seen = Rc::clone(&lines);
+    let mut command = Command::new("/bin/cat");
+    command.arg("/etc/hostname").arg("/nonexistent");
+    Tracer::new(TracerConfig::default())
+        .on_syscall(move |record| seen.borrow_mut().push(record.to_string()))
+        .run(command)
+        .expect("trace cat");
+
+    let lines = lines.borrow();
+    assert!(lines.iter().any(
+        |l| l.starts_with("openat(AT_FDCWD, \"/etc/hostname\", O_RDONLY") && l.ends_with(") = 3")
+    ));
+    assert!(lines.iter().any(|l| {
+        l.starts_with("openat(AT_FDCWD, \"/nonexistent\"")
+            && l.ends_with("= -1 ENOENT (No such file or directory)")
+    }));
+}
+
+#[test]
+fn hashes_files_as_read_and_as_written() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("hashes");
+    let file = dir.join("data.txt");
+    std::fs::write(&file, "abc").expect("write file");
+    let config = TracerConfig {
+        hash_reads: true,
+        hash_writes: true,
+        ..TracerConfig::default()
+    };
+    let mut command = Command::new("/bin/sh");
+    command
+        .arg("-c")
+        .arg(format!("read line < {0}; echo $line > {0}", file.display()));
+    let report = Tracer::new(config).run(command).expect("trace sh");
+
+    let stats = &report.files[file.to_str().expect("utf-8 path")];
+    // sha256("abc"), then sha256("abc\n")
+    assert_eq!(
+        stats.read_sha256.as_deref(),
+        Some("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+    );
+    assert_eq!(
+        stats.written_sha256.as_deref(),
+        Some("edeaaff3f1774ad2888673770c6d64097e391bc362d7d6fb34982ddf0efd18cb")
+    );
+}
+
+#[test]
+fn records_metadata_at_first_open() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("metadata");
+    let input = dir.join("input.txt");
+    std::fs::write(&input, "abc").expect("write input");
+    let (output, moved) = (dir.join("output.txt"), dir.join("moved.txt"));
+    let _ = std::fs::remove_file(&moved);
+    let mut command = Command::new("/bin/sh");
+    command.arg("-c").arg(format!(
+        "read line < {in}; echo $line > {out}; mv {out} {mv}; read line < {mv}",
+        in = input.display(),
+        out = output.display(),
+        mv = moved.display()
+    ));
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace sh");
+
+    let metadata = |path: &PathBuf| {
+        report.files[path.to_str().expect("utf-8 path")]
+            .metadata
+            .clone()
+            .expect("metadata")
+    };
+    let before = std::fs::metadata(&input).expect("stat input");
+    let input = metadata(&input);
+    assert_eq!(input.size, 3);
+    assert_eq!(input.inode, std::os::unix::fs::MetadataExt::ino(&before));
+    assert!(input.existed_before);
+    // Created by the trace, or renamed into place by it
+    assert!(!metadata(&output).existed_before);
+    assert!(!metadata(&moved).existed_before);
+}
+
+#[test]
+fn writes_slsa_provenance() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = scratch_dir("provenance");
+    let input = dir.join("input.txt");
+    let output = dir.join("output.txt");
+    std::fs::write(&input, "abc").expect("write input");
+    let mut command = Command::new("/bin/sh");
+    command.arg("-c").arg(format!(
+        "read line < {}; echo $line > {}",
+        input.display(),
+        output.display()
+    ));
+    let report = Tracer::new(TracerConfig::default())
+        .run(command)
+        .expect("trace sh");
+
+    let path = dir.join("provenance.json");
+    write_provenance(&report, path.to_str().expect("utf-8 path")).expect("write provenance");
+    let statement: Value =
+        serde_json::from_slice(&std::fs::read(&path).expect("read provenance")).expect("json");
+    assert_eq!(statement["predicateType"], "https://slsa.dev/provenance/v1");
+    assert_eq!(
+        statement["subject"][0]["name"],
+        output.to_str().expect("utf-8 path")
+    );
+    // sha256("abc\n"); the input's is sha256("abc")
+    assert_eq!(
+        statement["subject"][0]["digest"]["sha256"],
+        "edeaaff3f1774ad2888673770c6d64097e391bc362d7d6fb34982ddf0efd18cb"
+    );
+    let dependencies = statement["predicate"]["buildDefinition"]["resolvedDependencies"]
+        .as_array()
+        .expect("dependencies");
+    assert!(dependencies.iter().any(|d| {
+        d["uri"] == format!("file://{}", input.display())
+            && d["digest"]["sha256"]
+                == "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    }));
+    assert_eq!(
+        statement["predicate"]["buildDefinition"]["externalParameters"]["command"][0],
+        "/bin/sh"
+    );
+}
+
+fn schema_snapshots() -> BTreeMap<u32, Schema> {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/schema");
+    std::fs::read_dir(dir)
+        .expect("tests/schema")
+        .map(|entry| {
+            let path = entry.expect("schema entry").path();
+            let version = path
+                .file_stem()
+                .and_then(|stem| stem.to_str()?.strip_prefix('v')?.parse().ok())
+                .unwrap_or_else(|| panic!("{} should be named v<N>.json", path.display()));
+            let text = std::fs::read_to_string(&path).expect("read schema");
+            (version, serde_json::from_str(&text).expect("parse schema"))
+        })
+        .collect()
+}
+
+#[test]
+fn schema_only_grows() {
+    let snapshots = schema_snapshots();
+    assert!(
+        snapshots.contains_key(&SCHEMA_VERSION),
+        "add tests/schema/v{}.json for the current SCHEMA_VERSION",
+        SCHEMA_VERSION
+    );
+    for ((old, before), (new, after)) in snapshots.iter().zip(snapshots.iter().skip(1)) {
+        for (name, fields) in before {
+            let kept = after.get(name).expect("types aren't dropped");
+            let removed: Vec<_> = fields.difference(kept).collect();
+            assert!(
+                removed.is_empty(),
+                "v{} drops {}.{:?} from v{}",
+                new,
+                name,
+                removed,
+                old
+            );
+        }
+    }
+}
+
+#[test]
+fn report_matches_schema_snapshot() {
+    let _guard = TRACE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let config = TracerConfig {
+        stats: true,
+        max_paths: Some(100_000),
+        ..TracerConfig::default()
+    };
+    let mut command = Command::new("/bin/bash");
+    command
+        .arg("-c")
+        .arg("echo hi >/dev/udp/127.0.0.1/9; cat /etc/hostname >/dev/null; env -u HOME /bin/true");
+    let report = Tracer::new(config).run(command).expect("trace bash");
+    let report = serde_json::to_value(&report).expect("serialize report");
+    assert_eq!(report["schema_version"], SCHEMA_VERSION);
+
+    let snapshots = schema_snapshots();
+    let schema = &snapshots[&SCHEMA_VERSION];
+    // Left out of the output when empty
+    let optional: BTreeSet<&str> = [
+        "env_unset",
+        "tracer_stats",
+        "path_limit",
+        "read_sha256",
+        "written_sha256",
+    ]
+    .into();
+    let check = |name: &str, value: &Value| {
+        let expected = schema
+            .get(name)
+            .unwrap_or_else(|| panic!("{} in schema", name));
+        let fields: BTreeSet<String> = value.as_object().expect(name).keys().cloned().collect();
+        let added: Vec<_> = fields.difference(expected).collect();
+        assert!(
+            added.is_empty(),
+            "{}.{:?} isn't in tests/schema/v{}.json; bump SCHEMA_VERSION and add a snapshot",
+            name,
+            added,
+            SCHEMA_VERSION
+        );
+        let missing: Vec<_> = expected
+            .difference(&fields)
+            .filter(|f| !optional.contains(f.as_str()))
+            .collect();
+        assert!(
+            missing.is_empty(),
+            "{}.{:?} is no longer written",
+            name,
+            missing
+        );
+    };
+    let each = |value: &Value| -> Vec<Value> {
+        match value {
+            Value::Array(items) => items.clone(),
+            Value::Object(map) => map.values().cloned().collect(),
+            _ => Vec::new(),
+        }
+    };
+
+    check("TraceReport", &report);
+    check("FilterSummary", &report["path_filters"]);
+    check("TracerStats", &report["tracer_stats"]);
+    check("PathLimit", &report["path_limit"]);
+    for process in each(&report["processes"]) {
+        check("ProcessInfo", &process);
+        if !process["rusage"].is_null() {
+            check("ResourceUsage", &process["rusage"]);
+        }
+        for exec in each(&process["execs"]) {
+            check("ExecRecord", &exec);
+        }
+    }
+    for (field, name) in [
+        ("files", "FileStats"),
+        ("network_connections", "NetworkConnection"),
+        ("network_traffic", "NetworkTraffic"),
+        ("shared_memory", "SharedMemory"),
+    ] {
+        let items = each(&report[field]);
+        assert!(
+            name == "SharedMemory" || !items.is_empty(),
+            "the trace should produce {}",
+            field
+        );
+        for item in items {
+            check(name, &item);
+            if !item["metadata"].is_null() {
+                check("FileMetadata", &item["metadata"]);
+            }
+        }
+    }
+}
+
+#[test]
+fn attach_rejects_invalid_pid() {
+    let result = Tracer::new(TracerConfig::default()).attach(0);
+    assert!(matches!(result, Err(TraceError::InvalidPid(0))));
+}